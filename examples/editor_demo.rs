@@ -8,43 +8,27 @@ use gpui_editor::*;
 actions!(
     editor_demo,
     [
-        MoveUp,
-        MoveDown,
-        MoveLeft,
-        MoveRight,
-        MoveUpWithShift,
-        MoveDownWithShift,
-        MoveLeftWithShift,
-        MoveRightWithShift,
-        Backspace,
-        Delete,
-        InsertNewline,
         NextTheme,
         PreviousTheme,
         NextLanguage,
         PreviousLanguage,
-        SelectAll,
-        Escape,
-        Copy,
-        Cut,
-        Paste
+        Find,
+        ToggleSoftWrap
     ]
 );
 
-/// A complete editor view with keyboard handling and state management
-struct EditorView {
-    focus_handle: FocusHandle,
-    editor: Editor,
+/// The demo window: an `EditorView` plus a status bar and theme/language switching
+struct EditorDemo {
+    editor_view: Entity<EditorView>,
+    search_bar: Option<Entity<SearchBar>>,
     current_theme_index: usize,
     available_themes: Vec<String>,
     current_language_index: usize,
     available_languages: Vec<(String, String, String)>, // (name, extension, sample_code)
 }
 
-impl EditorView {
-    fn new(cx: &mut Context<Self>) -> Self {
-        let focus_handle = cx.focus_handle();
-
+impl EditorDemo {
+    fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
         // Initialize with sample Rust code
         let initial_code = vec![
             "// Rust sample code".to_string(),
@@ -70,7 +54,7 @@ impl EditorView {
         let mut editor = Editor::new("editor", initial_code);
 
         // Get available themes from syntax highlighter
-        let mut highlighter = SyntaxHighlighter::new();
+        let highlighter = SyntaxHighlighter::new();
         let available_themes = highlighter.available_themes();
 
         // Find and set a default theme
@@ -91,10 +75,16 @@ impl EditorView {
         ];
 
         editor.set_language("Rust".to_string());
+        editor.set_completion_provider(BufferWordsProvider);
+
+        let editor_view = cx.new(|cx| EditorView::new(editor, cx));
+        // Re-render the status bar whenever the editor changes
+        cx.observe(&editor_view, |_, _, cx| cx.notify()).detach();
+        window.focus(&editor_view.focus_handle(cx));
 
         Self {
-            focus_handle,
-            editor,
+            editor_view,
+            search_bar: None,
             current_theme_index: default_theme_index,
             available_themes,
             current_language_index: 0,
@@ -102,157 +92,79 @@ impl EditorView {
         }
     }
 
-    fn get_selected_text(&self) -> String {
-        self.editor.get_selected_text()
-    }
-
-    // Action handlers
-    fn move_up(&mut self, _: &MoveUp, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.move_up(false);
-        cx.notify();
-    }
-
-    fn move_down(&mut self, _: &MoveDown, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.move_down(false);
-        cx.notify();
-    }
-
-    fn move_left(&mut self, _: &MoveLeft, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.move_left(false);
-        cx.notify();
-    }
-
-    fn move_right(&mut self, _: &MoveRight, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.move_right(false);
-        cx.notify();
-    }
-
-    fn move_up_with_shift(
-        &mut self,
-        _: &MoveUpWithShift,
-        _window: &mut Window,
-        cx: &mut Context<Self>,
-    ) {
-        self.editor.move_up(true);
-        cx.notify();
+    fn set_theme_index(&mut self, index: usize, cx: &mut Context<Self>) {
+        self.current_theme_index = index;
+        let theme = self.available_themes[index].clone();
+        self.editor_view.update(cx, |view, cx| {
+            view.editor_mut().set_theme(&theme);
+            cx.notify();
+        });
     }
 
-    fn move_down_with_shift(
-        &mut self,
-        _: &MoveDownWithShift,
-        _window: &mut Window,
-        cx: &mut Context<Self>,
-    ) {
-        self.editor.move_down(true);
-        cx.notify();
+    fn set_language_index(&mut self, index: usize, cx: &mut Context<Self>) {
+        self.current_language_index = index;
+        let (language, _, sample_code) = self.available_languages[index].clone();
+        self.editor_view.update(cx, |view, cx| {
+            let editor = view.editor_mut();
+            editor.set_language(language);
+            editor.update_buffer(sample_code.lines().map(|s| s.to_string()).collect());
+            cx.notify();
+        });
     }
 
-    fn move_left_with_shift(
-        &mut self,
-        _: &MoveLeftWithShift,
-        _window: &mut Window,
-        cx: &mut Context<Self>,
-    ) {
-        self.editor.move_left(true);
+    fn find(&mut self, _: &Find, window: &mut Window, cx: &mut Context<Self>) {
+        let search_bar = self.search_bar.get_or_insert_with(|| {
+            let search_bar = cx.new(|cx| SearchBar::new(self.editor_view.clone(), cx));
+            // Escape in the bar hides it and returns focus to the editor
+            cx.subscribe_in(
+                &search_bar,
+                window,
+                |this, _, _: &DismissEvent, window, cx| {
+                    this.search_bar = None;
+                    window.focus(&this.editor_view.focus_handle(cx));
+                    cx.notify();
+                },
+            )
+            .detach();
+            search_bar
+        });
+        search_bar.read(cx).focus(window, cx);
         cx.notify();
     }
 
-    fn move_right_with_shift(
+    fn toggle_soft_wrap(
         &mut self,
-        _: &MoveRightWithShift,
+        _: &ToggleSoftWrap,
         _window: &mut Window,
         cx: &mut Context<Self>,
     ) {
-        self.editor.move_right(true);
-        cx.notify();
-    }
-
-    fn backspace(&mut self, _: &Backspace, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.backspace();
-        cx.notify();
-    }
-
-    fn delete(&mut self, _: &Delete, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.delete();
-        cx.notify();
-    }
-
-    fn insert_newline(&mut self, _: &InsertNewline, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.insert_newline();
-        cx.notify();
-    }
-
-    fn select_all(&mut self, _: &SelectAll, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.select_all();
-        cx.notify();
-    }
-
-    fn escape(&mut self, _: &Escape, _window: &mut Window, cx: &mut Context<Self>) {
-        self.editor.clear_selection();
-        cx.notify();
-    }
-
-    fn copy(&mut self, _: &Copy, _window: &mut Window, cx: &mut Context<Self>) {
-        let selected_text = self.get_selected_text();
-        if !selected_text.is_empty() {
-            cx.write_to_clipboard(ClipboardItem::new_string(selected_text));
-        }
-    }
-
-    fn cut(&mut self, _: &Cut, _window: &mut Window, cx: &mut Context<Self>) {
-        let selected_text = self.get_selected_text();
-        if !selected_text.is_empty() {
-            cx.write_to_clipboard(ClipboardItem::new_string(selected_text));
-            self.editor.delete_selection();
+        self.editor_view.update(cx, |view, cx| {
+            let config = view.editor_mut().config_mut();
+            config.wrap_mode = match config.wrap_mode {
+                WrapMode::None => WrapMode::EditorWidth,
+                _ => WrapMode::None,
+            };
             cx.notify();
-        }
-    }
-
-    fn paste(&mut self, _: &Paste, _window: &mut Window, cx: &mut Context<Self>) {
-        if let Some(clipboard) = cx.read_from_clipboard() {
-            if let Some(text) = clipboard.text() {
-                // Delete selection if exists
-                self.editor.delete_selection();
-
-                // Insert text character by character (simplified)
-                for ch in text.chars() {
-                    if ch == '\n' {
-                        self.editor.insert_newline();
-                    } else if ch != '\r' {
-                        self.editor.insert_char(ch);
-                    }
-                }
-                cx.notify();
-            }
-        }
+        });
     }
 
     fn next_theme(&mut self, _: &NextTheme, _window: &mut Window, cx: &mut Context<Self>) {
-        self.current_theme_index = (self.current_theme_index + 1) % self.available_themes.len();
-        self.editor
-            .set_theme(&self.available_themes[self.current_theme_index]);
-        cx.notify();
+        let index = (self.current_theme_index + 1) % self.available_themes.len();
+        self.set_theme_index(index, cx);
     }
 
     fn previous_theme(&mut self, _: &PreviousTheme, _window: &mut Window, cx: &mut Context<Self>) {
-        self.current_theme_index = if self.current_theme_index == 0 {
+        let index = if self.current_theme_index == 0 {
             self.available_themes.len() - 1
         } else {
             self.current_theme_index - 1
         };
-        self.editor
-            .set_theme(&self.available_themes[self.current_theme_index]);
-        cx.notify();
+        self.set_theme_index(index, cx);
     }
 
     fn next_language(&mut self, _: &NextLanguage, _window: &mut Window, cx: &mut Context<Self>) {
-        self.current_language_index =
-            (self.current_language_index + 1) % self.available_languages.len();
-        let (language, _, sample_code) = &self.available_languages[self.current_language_index];
-        self.editor.set_language(language.clone());
-        self.editor
-            .update_buffer(sample_code.lines().map(|s| s.to_string()).collect());
-        cx.notify();
+        let index = (self.current_language_index + 1) % self.available_languages.len();
+        self.set_language_index(index, cx);
     }
 
     fn previous_language(
@@ -261,22 +173,17 @@ impl EditorView {
         _window: &mut Window,
         cx: &mut Context<Self>,
     ) {
-        self.current_language_index = if self.current_language_index == 0 {
+        let index = if self.current_language_index == 0 {
             self.available_languages.len() - 1
         } else {
             self.current_language_index - 1
         };
-        let (language, _, sample_code) = &self.available_languages[self.current_language_index];
-        self.editor.set_language(language.clone());
-        self.editor
-            .update_buffer(sample_code.lines().map(|s| s.to_string()).collect());
-        cx.notify();
+        self.set_language_index(index, cx);
     }
 }
 
-impl Render for EditorView {
-    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let current_theme = &self.available_themes[self.current_theme_index];
+impl Render for EditorDemo {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         let (current_language, _, _) = &self.available_languages[self.current_language_index];
 
         let language = match current_language.as_str() {
@@ -284,94 +191,56 @@ impl Render for EditorView {
             _ => Language::PlainText,
         };
 
-        let cursor_position = self.editor.cursor_position();
+        let editor = self.editor_view.read(cx).editor();
+        let cursor_position = editor.cursor_position();
         let cursor_point = Point::new(cursor_position.col, cursor_position.row);
 
-        let selection = if self.editor.has_selection() {
-            let selected_text = self.get_selected_text();
+        let selection = if editor.has_selection() {
+            let selected_text = editor.get_selected_text();
             Some(Selection {
                 lines: selected_text.matches('\n').count(),
-                chars: selected_text.len(),
+                chars: selected_text.chars().count(),
             })
         } else {
             None
         };
 
         div()
-            .key_context("editor")
+            .key_context("EditorDemo")
             .size_full()
             .flex()
             .flex_col()
+            .on_action(cx.listener(Self::next_theme))
+            .on_action(cx.listener(Self::previous_theme))
+            .on_action(cx.listener(Self::next_language))
+            .on_action(cx.listener(Self::previous_language))
+            .on_action(cx.listener(Self::find))
+            .on_action(cx.listener(Self::toggle_soft_wrap))
+            .children(self.search_bar.clone())
+            .child(div().flex_grow().child(self.editor_view.clone()))
             .child(
-                div()
-                    .flex_grow()
-                    .track_focus(&self.focus_handle)
-                    .on_action(cx.listener(Self::move_up))
-                    .on_action(cx.listener(Self::move_down))
-                    .on_action(cx.listener(Self::move_left))
-                    .on_action(cx.listener(Self::move_right))
-                    .on_action(cx.listener(Self::move_up_with_shift))
-                    .on_action(cx.listener(Self::move_down_with_shift))
-                    .on_action(cx.listener(Self::move_left_with_shift))
-                    .on_action(cx.listener(Self::move_right_with_shift))
-                    .on_action(cx.listener(Self::backspace))
-                    .on_action(cx.listener(Self::delete))
-                    .on_action(cx.listener(Self::insert_newline))
-                    .on_action(cx.listener(Self::select_all))
-                    .on_action(cx.listener(Self::escape))
-                    .on_action(cx.listener(Self::copy))
-                    .on_action(cx.listener(Self::cut))
-                    .on_action(cx.listener(Self::paste))
-                    .on_action(cx.listener(Self::next_theme))
-                    .on_action(cx.listener(Self::previous_theme))
-                    .on_action(cx.listener(Self::next_language))
-                    .on_action(cx.listener(Self::previous_language))
-                    .on_key_down(cx.listener(
-                        |this: &mut Self, event: &KeyDownEvent, _window, cx| {
-                            // Handle character input
-                            if let Some(text) = &event.keystroke.key_char {
-                                if !event.keystroke.modifiers.platform
-                                    && !event.keystroke.modifiers.control
-                                    && !event.keystroke.modifiers.function
-                                {
-                                    for ch in text.chars() {
-                                        this.editor.insert_char(ch);
-                                    }
-                                    cx.notify();
-                                }
-                            }
-                        },
-                    ))
-                    .child(EditorElement::new(self.editor.clone())),
+                MetaLine::new(cursor_point, language, selection)
+                    .diagnostics(editor.diagnostic_counts()),
             )
-            .child(MetaLine::new(cursor_point, language, selection))
     }
 }
 
 fn main() {
     Application::new().run(|cx: &mut App| {
-        // Bind keyboard shortcuts
+        bind_default_keys(cx);
+
+        let cmd = if cfg!(target_os = "macos") {
+            "cmd"
+        } else {
+            "ctrl"
+        };
         cx.bind_keys([
-            KeyBinding::new("up", MoveUp, None),
-            KeyBinding::new("down", MoveDown, None),
-            KeyBinding::new("left", MoveLeft, None),
-            KeyBinding::new("right", MoveRight, None),
-            KeyBinding::new("shift-up", MoveUpWithShift, None),
-            KeyBinding::new("shift-down", MoveDownWithShift, None),
-            KeyBinding::new("shift-left", MoveLeftWithShift, None),
-            KeyBinding::new("shift-right", MoveRightWithShift, None),
-            KeyBinding::new("backspace", Backspace, None),
-            KeyBinding::new("delete", Delete, None),
-            KeyBinding::new("enter", InsertNewline, None),
-            KeyBinding::new("cmd-a", SelectAll, None),
-            KeyBinding::new("escape", Escape, None),
-            KeyBinding::new("cmd-c", Copy, None),
-            KeyBinding::new("cmd-x", Cut, None),
-            KeyBinding::new("cmd-v", Paste, None),
-            KeyBinding::new("cmd-]", NextTheme, None),
-            KeyBinding::new("cmd-[", PreviousTheme, None),
-            KeyBinding::new("cmd-shift-]", NextLanguage, None),
-            KeyBinding::new("cmd-shift-[", PreviousLanguage, None),
+            KeyBinding::new(&format!("{cmd}-]"), NextTheme, None),
+            KeyBinding::new(&format!("{cmd}-["), PreviousTheme, None),
+            KeyBinding::new(&format!("{cmd}-shift-]"), NextLanguage, None),
+            KeyBinding::new(&format!("{cmd}-shift-["), PreviousLanguage, None),
+            KeyBinding::new(&format!("{cmd}-f"), Find, None),
+            KeyBinding::new("alt-z", ToggleSoftWrap, None),
         ]);
 
         cx.open_window(
@@ -383,7 +252,7 @@ fn main() {
                 ))),
                 ..Default::default()
             },
-            |_window, cx| cx.new(EditorView::new),
+            |window, cx| cx.new(|cx| EditorDemo::new(window, cx)),
         )
         .unwrap();
     });
@@ -423,6 +292,8 @@ Features of this editor:
 - Theme switching with Cmd+[ and Cmd+]
 - Language switching with Cmd+Shift+[ and Cmd+Shift+]
 - Text selection with Shift+Arrow keys
+- Find and replace with Cmd+F
+- Soft wrap toggled with Alt+Z
 - Copy, Cut, and Paste support
 - Line numbers
 - Active line highlighting
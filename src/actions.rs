@@ -0,0 +1,332 @@
+//! Actions handled by [`EditorView`](crate::view::EditorView) and their
+//! default key bindings.
+//!
+//! Actions live in the `editor` namespace (`editor::MoveUp`, `editor::Copy`,
+//! ...) and bindings are scoped to the `Editor` key context, so they only fire
+//! while an editor is focused.
+
+use gpui::{actions, App, KeyBinding};
+
+/// Key context set by [`EditorView`](crate::view::EditorView)
+pub const KEY_CONTEXT: &str = "Editor";
+
+actions!(
+    editor,
+    [
+        MoveUp,
+        MoveDown,
+        MoveLeft,
+        MoveRight,
+        SelectUp,
+        SelectDown,
+        SelectLeft,
+        SelectRight,
+        MoveToPreviousWordStart,
+        MoveToNextWordEnd,
+        MoveToPreviousSubwordStart,
+        MoveToNextSubwordEnd,
+        MoveToLineStart,
+        MoveToLineEnd,
+        MoveToDocumentStart,
+        MoveToDocumentEnd,
+        PageUp,
+        PageDown,
+        SelectToPreviousWordStart,
+        SelectToNextWordEnd,
+        SelectToPreviousSubwordStart,
+        SelectToNextSubwordEnd,
+        SelectToLineStart,
+        SelectToLineEnd,
+        SelectToDocumentStart,
+        SelectToDocumentEnd,
+        SelectPageUp,
+        SelectPageDown,
+        SelectAll,
+        Backspace,
+        Delete,
+        DeleteToPreviousWordStart,
+        DeleteToNextWordEnd,
+        DeleteToPreviousSubwordStart,
+        DeleteToNextSubwordEnd,
+        DeleteToLineStart,
+        DeleteToLineEnd,
+        Newline,
+        Tab,
+        Backtab,
+        Indent,
+        Outdent,
+        MoveLinesUp,
+        MoveLinesDown,
+        DuplicateLines,
+        DeleteLines,
+        JoinLines,
+        SortLines,
+        SortLinesCaseInsensitive,
+        ReverseLines,
+        TrimTrailingWhitespace,
+        ToggleLineComment,
+        ToggleBlockComment,
+        Cancel,
+        Copy,
+        Cut,
+        Paste,
+        Undo,
+        Redo,
+        AddCursorAbove,
+        AddCursorBelow,
+        AddNextOccurrence,
+        MoveToMatchingBracket,
+        SelectToEnclosingBrackets,
+        NextDiagnostic,
+        PreviousDiagnostic,
+        ShowCompletions,
+        SelectNextMatch,
+        SelectPreviousMatch,
+        Fold,
+        Unfold,
+        FoldAll,
+        UnfoldAll
+    ]
+);
+
+/// Register the default editor key bindings for the current platform.
+///
+/// macOS uses `cmd` as the primary modifier; Linux and Windows use `ctrl`.
+pub fn bind_default_keys(cx: &mut App) {
+    cx.bind_keys(default_key_bindings());
+}
+
+/// The default editor key bindings for the current platform
+pub fn default_key_bindings() -> Vec<KeyBinding> {
+    if cfg!(target_os = "macos") {
+        macos_key_bindings()
+    } else {
+        linux_and_windows_key_bindings()
+    }
+}
+
+fn macos_key_bindings() -> Vec<KeyBinding> {
+    let mut bindings = common_key_bindings("cmd");
+    bindings.extend([
+        KeyBinding::new("cmd-shift-z", Redo, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-alt-up", AddCursorAbove, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-alt-down", AddCursorBelow, Some(KEY_CONTEXT)),
+        KeyBinding::new("alt-left", MoveToPreviousWordStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("alt-right", MoveToNextWordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "ctrl-alt-left",
+            MoveToPreviousSubwordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("ctrl-alt-right", MoveToNextSubwordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-left", MoveToLineStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-right", MoveToLineEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-up", MoveToDocumentStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-down", MoveToDocumentEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "alt-shift-left",
+            SelectToPreviousWordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("alt-shift-right", SelectToNextWordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "ctrl-alt-shift-left",
+            SelectToPreviousSubwordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new(
+            "ctrl-alt-shift-right",
+            SelectToNextSubwordEnd,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("cmd-shift-left", SelectToLineStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-shift-right", SelectToLineEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-shift-up", SelectToDocumentStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-shift-down", SelectToDocumentEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "alt-backspace",
+            DeleteToPreviousWordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("alt-delete", DeleteToNextWordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "ctrl-alt-backspace",
+            DeleteToPreviousSubwordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("ctrl-alt-delete", DeleteToNextSubwordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-backspace", DeleteToLineStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-k", DeleteToLineEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-g", SelectNextMatch, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-shift-g", SelectPreviousMatch, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-alt-[", Fold, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-alt-]", Unfold, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-k cmd-0", FoldAll, Some(KEY_CONTEXT)),
+        KeyBinding::new("cmd-k cmd-j", UnfoldAll, Some(KEY_CONTEXT)),
+    ]);
+    bindings
+}
+
+fn linux_and_windows_key_bindings() -> Vec<KeyBinding> {
+    let mut bindings = common_key_bindings("ctrl");
+    bindings.extend([
+        KeyBinding::new("ctrl-y", Redo, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-shift-z", Redo, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-alt-up", AddCursorAbove, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-alt-down", AddCursorBelow, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-left", MoveToPreviousWordStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-right", MoveToNextWordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("alt-left", MoveToPreviousSubwordStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("alt-right", MoveToNextSubwordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-home", MoveToDocumentStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-end", MoveToDocumentEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "ctrl-shift-left",
+            SelectToPreviousWordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("ctrl-shift-right", SelectToNextWordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "alt-shift-left",
+            SelectToPreviousSubwordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("alt-shift-right", SelectToNextSubwordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-shift-home", SelectToDocumentStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-shift-end", SelectToDocumentEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "ctrl-backspace",
+            DeleteToPreviousWordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("ctrl-delete", DeleteToNextWordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new(
+            "alt-backspace",
+            DeleteToPreviousSubwordStart,
+            Some(KEY_CONTEXT),
+        ),
+        KeyBinding::new("alt-delete", DeleteToNextSubwordEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-shift-backspace", DeleteToLineStart, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-shift-delete", DeleteToLineEnd, Some(KEY_CONTEXT)),
+        KeyBinding::new("f3", SelectNextMatch, Some(KEY_CONTEXT)),
+        KeyBinding::new("shift-f3", SelectPreviousMatch, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-shift-[", Fold, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-shift-]", Unfold, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-k ctrl-0", FoldAll, Some(KEY_CONTEXT)),
+        KeyBinding::new("ctrl-k ctrl-j", UnfoldAll, Some(KEY_CONTEXT)),
+    ]);
+    bindings
+}
+
+/// Bindings shared by every platform, with `primary` as the command modifier
+fn common_key_bindings(primary: &str) -> Vec<KeyBinding> {
+    let context = Some(KEY_CONTEXT);
+    vec![
+        KeyBinding::new("up", MoveUp, context),
+        KeyBinding::new("down", MoveDown, context),
+        KeyBinding::new("left", MoveLeft, context),
+        KeyBinding::new("right", MoveRight, context),
+        KeyBinding::new("shift-up", SelectUp, context),
+        KeyBinding::new("shift-down", SelectDown, context),
+        KeyBinding::new("shift-left", SelectLeft, context),
+        KeyBinding::new("shift-right", SelectRight, context),
+        KeyBinding::new("home", MoveToLineStart, context),
+        KeyBinding::new("end", MoveToLineEnd, context),
+        KeyBinding::new("shift-home", SelectToLineStart, context),
+        KeyBinding::new("shift-end", SelectToLineEnd, context),
+        KeyBinding::new("pageup", PageUp, context),
+        KeyBinding::new("pagedown", PageDown, context),
+        KeyBinding::new("shift-pageup", SelectPageUp, context),
+        KeyBinding::new("shift-pagedown", SelectPageDown, context),
+        KeyBinding::new("backspace", Backspace, context),
+        KeyBinding::new("shift-backspace", Backspace, context),
+        KeyBinding::new("delete", Delete, context),
+        KeyBinding::new("enter", Newline, context),
+        KeyBinding::new("tab", Tab, context),
+        KeyBinding::new("shift-tab", Backtab, context),
+        KeyBinding::new(&format!("{primary}-]"), Indent, context),
+        KeyBinding::new(&format!("{primary}-["), Outdent, context),
+        KeyBinding::new("alt-up", MoveLinesUp, context),
+        KeyBinding::new("alt-down", MoveLinesDown, context),
+        KeyBinding::new("alt-shift-down", DuplicateLines, context),
+        KeyBinding::new(&format!("{primary}-shift-k"), DeleteLines, context),
+        KeyBinding::new("ctrl-j", JoinLines, context),
+        KeyBinding::new(&format!("{primary}-/"), ToggleLineComment, context),
+        KeyBinding::new("alt-shift-a", ToggleBlockComment, context),
+        KeyBinding::new("escape", Cancel, context),
+        KeyBinding::new(&format!("{primary}-a"), SelectAll, context),
+        KeyBinding::new(&format!("{primary}-c"), Copy, context),
+        KeyBinding::new(&format!("{primary}-x"), Cut, context),
+        KeyBinding::new(&format!("{primary}-v"), Paste, context),
+        KeyBinding::new(&format!("{primary}-z"), Undo, context),
+        KeyBinding::new(&format!("{primary}-d"), AddNextOccurrence, context),
+        KeyBinding::new(
+            &format!("{primary}-shift-\\"),
+            MoveToMatchingBracket,
+            context,
+        ),
+        KeyBinding::new("ctrl-shift-m", SelectToEnclosingBrackets, context),
+        KeyBinding::new("f8", NextDiagnostic, context),
+        KeyBinding::new("shift-f8", PreviousDiagnostic, context),
+        KeyBinding::new("ctrl-space", ShowCompletions, context),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::{Action, Keystroke};
+
+    /// Normalize a keystroke string the way the current platform prints it
+    fn keys(source: &str) -> String {
+        Keystroke::parse(source).unwrap().unparse()
+    }
+
+    fn binding_for(bindings: &[KeyBinding], action: &dyn Action) -> Vec<String> {
+        bindings
+            .iter()
+            .filter(|binding| binding.action().partial_eq(action))
+            .map(|binding| {
+                binding
+                    .keystrokes()
+                    .iter()
+                    .map(|keystroke| keystroke.unparse())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_platform_modifiers() {
+        let macos = macos_key_bindings();
+        assert_eq!(binding_for(&macos, &Copy), vec![keys("cmd-c")]);
+        assert_eq!(binding_for(&macos, &Redo), vec![keys("cmd-shift-z")]);
+        assert_eq!(
+            binding_for(&macos, &MoveToLineStart),
+            vec![keys("home"), keys("cmd-left")]
+        );
+
+        let linux = linux_and_windows_key_bindings();
+        assert_eq!(binding_for(&linux, &Copy), vec![keys("ctrl-c")]);
+        assert_eq!(
+            binding_for(&linux, &Redo),
+            vec![keys("ctrl-y"), keys("ctrl-shift-z")]
+        );
+        assert_eq!(
+            binding_for(&linux, &MoveToPreviousWordStart),
+            vec![keys("ctrl-left")]
+        );
+        assert_eq!(
+            binding_for(&linux, &FoldAll),
+            vec![format!("{} {}", keys("ctrl-k"), keys("ctrl-0"))]
+        );
+    }
+
+    #[test]
+    fn test_bindings_are_scoped_to_editor() {
+        for binding in default_key_bindings() {
+            assert!(binding.predicate().is_some());
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Positions that stay on their text as the buffer is edited.
+//!
+//! An [`Anchor`] is a char offset stamped with the version of the buffer it
+//! was made at. Buffers keep an [`EditLog`] of every insertion and deletion,
+//! and resolving an anchor replays the edits made since its version. An edit
+//! costs the same however many anchors there are; the work happens when an
+//! anchor is resolved, in proportion to the edits it hasn't seen yet.
+//! Anchors kept around for a long time can be refreshed to the current
+//! version to keep that short.
+//!
+//! The log doesn't grow forever: edits older than every anchor that is still
+//! needed are dropped. The editor keeps the edits its own anchors need, and
+//! the last [`MAX_ANCHOR_AGE`] edits for anchors kept elsewhere. An anchor
+//! older than that no longer resolves, rather than resolving to the wrong
+//! place.
+//!
+//! An anchor inside deleted text moves to where the deletion was. At an
+//! insertion, its [`Bias`] decides which side of the new text it ends up on.
+
+use std::sync::Arc;
+
+/// How many edits anchors that are kept around fall behind before their
+/// holders refresh them, trading the work of a refresh against replaying
+/// more edits on every resolve
+pub const REFRESH_INTERVAL: usize = 256;
+
+/// How many edits an anchor kept outside the editor can fall behind and
+/// still resolve. Refresh it before then to keep it longer.
+pub const MAX_ANCHOR_AGE: usize = 16 * REFRESH_INTERVAL;
+
+/// Which side of text inserted exactly at an anchor it stays on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Bias {
+    /// Stay before the inserted text, like the end of a range
+    #[default]
+    Left,
+    /// Move after the inserted text, like the start of a range
+    Right,
+}
+
+/// A position in a buffer that moves with edits. Made with
+/// [`TextBuffer::anchor_at`](crate::buffer::TextBuffer::anchor_at) and turned
+/// back into a char offset with
+/// [`TextBuffer::resolve_anchor`](crate::buffer::TextBuffer::resolve_anchor).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Anchor {
+    offset: usize,
+    version: usize,
+    bias: Bias,
+}
+
+impl Anchor {
+    /// An anchor at `offset` in the text as it was at `version`
+    pub(crate) fn new(offset: usize, version: usize, bias: Bias) -> Self {
+        Self {
+            offset,
+            version,
+            bias,
+        }
+    }
+
+    pub fn bias(&self) -> Bias {
+        self.bias
+    }
+
+    /// The buffer version the anchor's offset is for
+    pub fn version(&self) -> usize {
+        self.version
+    }
+}
+
+/// A single change to the text, in chars
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LoggedEdit {
+    Insert { position: usize, len: usize },
+    Delete { start: usize, end: usize },
+}
+
+impl LoggedEdit {
+    /// Where `offset` ends up after the edit
+    fn map(&self, offset: usize, bias: Bias) -> usize {
+        match *self {
+            LoggedEdit::Insert { position, len } => {
+                if offset > position || (offset == position && bias == Bias::Right) {
+                    offset + len
+                } else {
+                    offset
+                }
+            }
+            LoggedEdit::Delete { start, end } => {
+                if offset >= end {
+                    offset - (end - start)
+                } else {
+                    offset.min(start)
+                }
+            }
+        }
+    }
+}
+
+/// The edits made to a buffer, in order. The version of the buffer is the
+/// number of edits made to it. Shared between clones of the buffer until one
+/// of them is edited.
+#[derive(Clone, Debug, Default)]
+pub struct EditLog {
+    /// The version the first edit kept was made at
+    first_version: usize,
+    edits: Arc<Vec<LoggedEdit>>,
+}
+
+impl EditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn version(&self) -> usize {
+        self.first_version + self.edits.len()
+    }
+
+    /// The oldest version whose anchors still resolve
+    pub fn first_version(&self) -> usize {
+        self.first_version
+    }
+
+    /// Drop the edits made before `version`, which anchors from before it
+    /// need. Edits are dropped [`REFRESH_INTERVAL`] or more at a time, so
+    /// calling this after every edit is cheap.
+    pub fn forget_before(&mut self, version: usize) {
+        let count = version
+            .saturating_sub(self.first_version)
+            .min(self.edits.len());
+        if count < REFRESH_INTERVAL {
+            return;
+        }
+        Arc::make_mut(&mut self.edits).drain(..count);
+        self.first_version += count;
+    }
+
+    /// Record `len` chars inserted at `position`
+    pub fn insert(&mut self, position: usize, len: usize) {
+        if len > 0 {
+            Arc::make_mut(&mut self.edits).push(LoggedEdit::Insert { position, len });
+        }
+    }
+
+    /// Record the chars in `start..end` deleted
+    pub fn delete(&mut self, start: usize, end: usize) {
+        if start < end {
+            Arc::make_mut(&mut self.edits).push(LoggedEdit::Delete { start, end });
+        }
+    }
+
+    /// An anchor at `offset` in the current version
+    pub fn anchor(&self, offset: usize, bias: Bias) -> Anchor {
+        Anchor {
+            offset,
+            version: self.version(),
+            bias,
+        }
+    }
+
+    /// The current offset of `anchor`, or `None` if it is older than
+    /// [`Self::first_version`] and the edits it missed were dropped. Anchors
+    /// from a later version than the log's, which can only come from another
+    /// buffer, resolve to their offset as it was.
+    pub fn resolve(&self, anchor: &Anchor) -> Option<usize> {
+        let start = anchor.version.checked_sub(self.first_version)?;
+        let offset = self
+            .edits
+            .get(start..)
+            .unwrap_or_default()
+            .iter()
+            .fold(anchor.offset, |offset, edit| edit.map(offset, anchor.bias));
+        Some(offset)
+    }
+
+    /// `anchor` at the same position, stamped with the current version so
+    /// resolving it again doesn't replay the edits up to now. `None` if it
+    /// no longer resolves.
+    pub fn refresh(&self, anchor: &Anchor) -> Option<Anchor> {
+        Some(self.anchor(self.resolve(anchor)?, anchor.bias))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bias_at_insertions() {
+        let mut log = EditLog::new();
+        let left = log.anchor(5, Bias::Left);
+        let right = log.anchor(5, Bias::Right);
+        log.insert(5, 3);
+        assert_eq!(log.resolve(&left).unwrap(), 5);
+        assert_eq!(log.resolve(&right).unwrap(), 8);
+
+        // Insertions before move both, insertions after move neither
+        log.insert(0, 2);
+        log.insert(20, 4);
+        assert_eq!(log.resolve(&left).unwrap(), 7);
+        assert_eq!(log.resolve(&right).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_deletions() {
+        let mut log = EditLog::new();
+        let before = log.anchor(2, Bias::Right);
+        let inside = log.anchor(6, Bias::Right);
+        let after = log.anchor(12, Bias::Left);
+        log.delete(4, 10);
+        assert_eq!(log.resolve(&before).unwrap(), 2);
+        assert_eq!(log.resolve(&inside).unwrap(), 4);
+        assert_eq!(log.resolve(&after).unwrap(), 6);
+
+        // Replacing text puts a right-biased anchor from inside it after the
+        // new text
+        log.insert(4, 3);
+        assert_eq!(log.resolve(&inside).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut log = EditLog::new();
+        log.insert(0, 10);
+        let anchor = log.anchor(4, Bias::Left);
+        assert_eq!(anchor.version(), 1);
+
+        // Empty edits aren't logged
+        log.insert(3, 0);
+        log.delete(3, 3);
+        assert_eq!(log.version(), 1);
+
+        log.delete(0, 2);
+        let refreshed = log.refresh(&anchor).unwrap();
+        assert_eq!(refreshed.version(), 2);
+        assert_eq!(log.resolve(&refreshed).unwrap(), 2);
+        log.insert(0, 1);
+        assert_eq!(log.resolve(&anchor), log.resolve(&refreshed));
+    }
+
+    #[test]
+    fn test_log_stays_bounded() {
+        let mut log = EditLog::new();
+        let mut anchor = log.anchor(0, Bias::Right);
+        let edits = 100 * REFRESH_INTERVAL;
+        for _ in 0..edits {
+            log.insert(0, 1);
+            if log.version() >= anchor.version() + REFRESH_INTERVAL {
+                anchor = log.refresh(&anchor).unwrap();
+                log.forget_before(anchor.version());
+            }
+        }
+        assert_eq!(log.version(), edits);
+        assert!(log.edits.len() < 2 * REFRESH_INTERVAL);
+        assert_eq!(log.resolve(&anchor).unwrap(), edits);
+
+        // Anchors from the versions kept still resolve
+        let kept = log.anchor(3, Bias::Left);
+        log.insert(0, 2);
+        log.forget_before(kept.version());
+        assert_eq!(log.resolve(&kept), Some(5));
+    }
+
+    #[test]
+    fn test_forgotten_anchors_dont_resolve() {
+        let mut log = EditLog::new();
+        let old = log.anchor(0, Bias::Right);
+        for _ in 0..REFRESH_INTERVAL {
+            log.insert(0, 1);
+        }
+        let kept = log.anchor(0, Bias::Right);
+        log.insert(0, 1);
+        log.forget_before(kept.version());
+        assert_eq!(log.first_version(), REFRESH_INTERVAL);
+
+        // The old anchor missed edits that are gone, so it can't be placed
+        assert_eq!(log.resolve(&old), None);
+        assert_eq!(log.refresh(&old), None);
+        assert_eq!(log.resolve(&kept), Some(1));
+    }
+}
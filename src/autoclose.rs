@@ -0,0 +1,95 @@
+//! Auto-closing bracket and quote pairs.
+//!
+//! Typing an opening character of a pair inserts its closing character too,
+//! typing the closing character right before one skips over it, and backspace
+//! between an empty pair deletes both. Which pairs apply depends on the
+//! language: Rust leaves out `'` for lifetimes and plain text leaves it out for
+//! apostrophes. The editor doesn't close pairs inside strings and comments.
+
+/// Every pair, for languages without a table of their own
+const DEFAULT_PAIRS: &[(char, char)] =
+    &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+const LANGUAGE_PAIRS: &[(&str, &[(char, char)])] = &[
+    ("Rust", &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')]),
+    (
+        "Plain Text",
+        &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')],
+    ),
+    (
+        "JavaScript",
+        &[
+            ('(', ')'),
+            ('[', ']'),
+            ('{', '}'),
+            ('"', '"'),
+            ('\'', '\''),
+            ('`', '`'),
+        ],
+    ),
+];
+
+/// The pairs that are closed automatically in `language`, as (open, close)
+pub fn pairs(language: &str) -> &'static [(char, char)] {
+    LANGUAGE_PAIRS
+        .iter()
+        .find(|(name, _)| *name == language)
+        .map_or(DEFAULT_PAIRS, |(_, pairs)| pairs)
+}
+
+/// The closing character for `open`, if it opens one of `pairs`
+pub fn closing(pairs: &[(char, char)], open: char) -> Option<char> {
+    pairs
+        .iter()
+        .find(|(pair_open, _)| *pair_open == open)
+        .map(|(_, close)| *close)
+}
+
+/// Whether `ch` closes one of `pairs`
+pub fn is_closing(pairs: &[(char, char)], ch: char) -> bool {
+    pairs.iter().any(|(_, close)| *close == ch)
+}
+
+/// Whether typing `open` between `prev` and `next` should insert its closing
+/// character too. Pairs are only closed before whitespace, closing characters
+/// or the end of the line, and quotes aren't closed right after a word, where
+/// they are more likely to be an apostrophe or to end a string.
+pub fn should_close(
+    pairs: &[(char, char)],
+    prev: Option<char>,
+    next: Option<char>,
+    open: char,
+) -> bool {
+    let Some(close) = closing(pairs, open) else {
+        return false;
+    };
+    let before_ok = open != close
+        || !prev.is_some_and(|prev| prev.is_alphanumeric() || prev == '_' || prev == open);
+    let after_ok = next.is_none_or(|next| next.is_whitespace() || is_closing(pairs, next));
+    before_ok && after_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_pairs() {
+        assert_eq!(closing(pairs("Python"), '\''), Some('\''));
+        assert_eq!(closing(pairs("Rust"), '\''), None);
+        assert_eq!(closing(pairs("Rust"), '{'), Some('}'));
+        assert!(is_closing(pairs("Rust"), ']'));
+    }
+
+    #[test]
+    fn test_should_close() {
+        let pairs = pairs("Python");
+        assert!(should_close(pairs, None, None, '('));
+        assert!(should_close(pairs, Some('f'), Some(')'), '('));
+        assert!(!should_close(pairs, None, Some('x'), '('));
+        assert!(!should_close(pairs, Some('x'), None, ')'));
+
+        assert!(should_close(pairs, Some(' '), None, '"'));
+        assert!(!should_close(pairs, Some('n'), None, '\''));
+    }
+}
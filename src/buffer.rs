@@ -17,8 +17,15 @@
 //! - Moving the gap: O(n) where n is the distance moved
 //! - Memory overhead: The gap size (typically grows as needed)
 //! - Line-based operations: Currently O(n) as they require string conversion
+//!
+//! For large documents, use [`Rope`](crate::rope::Rope) instead, which keeps
+//! line lookups and position conversion at O(log n).
 
+use crate::anchor::{Anchor, Bias, EditLog};
+use crate::coordinates;
+use crate::rope::Rope;
 use std::cmp::{max, min};
+use std::ops::Range;
 
 /// A minimal text buffer trait that supports the features we have so far
 pub trait TextBuffer {
@@ -30,7 +37,9 @@ pub trait TextBuffer {
 
     /// Get the length of a specific line in characters
     fn line_len(&self, line_idx: usize) -> usize {
-        self.get_line(line_idx).map(|s| s.len()).unwrap_or(0)
+        self.get_line(line_idx)
+            .map(|s| s.chars().count())
+            .unwrap_or(0)
     }
 
     /// Get all lines (for now, while we're simple)
@@ -44,6 +53,150 @@ pub trait TextBuffer {
 
     /// Delete backwards from a specific position (row, col)
     fn backspace_at(&mut self, row: usize, col: usize);
+
+    /// Get the length of the content in characters
+    fn len(&self) -> usize;
+
+    /// Whether the buffer contains no text
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Insert text at a linear character position
+    fn insert(&mut self, position: usize, text: &str);
+
+    /// Delete the characters in `start..end`
+    fn delete_range(&mut self, start: usize, end: usize);
+
+    /// Convert a cursor position (row, col) to a linear character position
+    fn cursor_to_position(&self, row: usize, col: usize) -> usize;
+
+    /// Convert a linear character position to a cursor position (row, col)
+    fn position_to_cursor(&self, position: usize) -> (usize, usize);
+
+    /// Get the text between two linear character positions
+    fn text_in_range(&self, start: usize, end: usize) -> String;
+
+    /// How many edits have been made to the buffer
+    fn version(&self) -> usize;
+
+    /// An anchor at a linear character position, which stays on its text
+    /// through later edits
+    fn anchor_at(&self, position: usize, bias: Bias) -> Anchor;
+
+    /// The linear character position `anchor` has moved to, or `None` if it
+    /// is older than [`Self::first_anchor_version`]
+    fn resolve_anchor(&self, anchor: &Anchor) -> Option<usize>;
+
+    /// The oldest version whose anchors still resolve
+    fn first_anchor_version(&self) -> usize;
+
+    /// Stop keeping the edits that only anchors from before `version` need
+    fn forget_edits_before(&mut self, version: usize);
+
+    /// Anchors for `range` that keep text inserted at either end outside it
+    fn anchor_range(&self, range: Range<usize>) -> Range<Anchor> {
+        self.anchor_at(range.start, Bias::Right)..self.anchor_at(range.end, Bias::Left)
+    }
+
+    /// The current positions of an anchored range, empty once its text is
+    /// deleted, or `None` if its anchors no longer resolve
+    fn resolve_anchor_range(&self, range: &Range<Anchor>) -> Option<Range<usize>> {
+        let start = self.resolve_anchor(&range.start)?;
+        Some(start..self.resolve_anchor(&range.end)?.max(start))
+    }
+
+    /// Get the full text of the buffer
+    fn text(&self) -> String {
+        self.text_in_range(0, self.len())
+    }
+
+    /// Convert a linear character position to a UTF-8 byte offset
+    fn char_to_byte(&self, position: usize) -> usize {
+        self.text_in_range(0, position.min(self.len())).len()
+    }
+
+    /// Convert a UTF-8 byte offset to a linear character position, rounding
+    /// down to the start of a char
+    fn byte_to_char(&self, offset: usize) -> usize {
+        coordinates::byte_to_char(&self.text(), offset)
+    }
+
+    /// Convert a linear character position to an offset in UTF-16 code units,
+    /// as used by platform text input
+    fn char_to_utf16(&self, position: usize) -> usize {
+        let text = self.text_in_range(0, position.min(self.len()));
+        coordinates::char_to_utf16(&text, position)
+    }
+
+    /// Convert an offset in UTF-16 code units to a linear character position.
+    ///
+    /// An offset that falls inside a surrogate pair rounds up to the next
+    /// character; offsets past the end clamp to the buffer length.
+    fn utf16_to_char(&self, offset: usize) -> usize {
+        coordinates::utf16_to_char(&self.text(), offset)
+    }
+
+    /// The linear position of the grapheme boundary before `position`.
+    ///
+    /// A line break counts as a grapheme of its own.
+    fn prev_grapheme_boundary(&self, position: usize) -> usize {
+        let (row, col) = self.position_to_cursor(position);
+        if col == 0 {
+            return position.saturating_sub(1);
+        }
+        let line = self.get_line(row).unwrap_or_default();
+        position - col + coordinates::prev_grapheme_boundary(&line, col)
+    }
+
+    /// The linear position of the grapheme boundary after `position`.
+    ///
+    /// A line break counts as a grapheme of its own.
+    fn next_grapheme_boundary(&self, position: usize) -> usize {
+        let (row, col) = self.position_to_cursor(position);
+        if col >= self.line_len(row) {
+            return (position + 1).min(self.len());
+        }
+        let line = self.get_line(row).unwrap_or_default();
+        position - col + coordinates::next_grapheme_boundary(&line, col)
+    }
+
+    /// Take an immutable copy of the buffer's lines
+    fn snapshot(&self) -> BufferSnapshot {
+        BufferSnapshot::new(&self.text())
+    }
+}
+
+/// An immutable copy of a buffer's text.
+///
+/// Snapshots are cheap to clone and can be sent to other threads, e.g. to
+/// highlight the text in the background while the buffer keeps changing. The
+/// text is kept in a [`Rope`], so a snapshot of a rope shares its tree and
+/// taking one is O(1).
+#[derive(Clone, Debug, Default)]
+pub struct BufferSnapshot {
+    text: Rope,
+}
+
+impl BufferSnapshot {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: Rope::from_text(text),
+        }
+    }
+
+    /// A snapshot of the text in `rope`
+    pub(crate) fn from_rope(rope: Rope) -> Self {
+        Self { text: rope }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.line_count()
+    }
+
+    pub fn line(&self, row: usize) -> Option<String> {
+        self.text.get_line(row)
+    }
 }
 
 /// Gap buffer implementation for efficient text editing.
@@ -68,6 +221,8 @@ pub struct GapBuffer {
     gap_start: usize,
     /// End position of the gap (exclusive)
     gap_end: usize,
+    /// Every edit, for resolving anchors
+    edits: EditLog,
 }
 
 impl GapBuffer {
@@ -79,6 +234,7 @@ impl GapBuffer {
             buffer,
             gap_start: 0,
             gap_end: initial_capacity,
+            edits: EditLog::new(),
         }
     }
 
@@ -100,6 +256,7 @@ impl GapBuffer {
             buffer,
             gap_start: text_len,
             gap_end: capacity,
+            edits: EditLog::new(),
         }
     }
 
@@ -113,6 +270,19 @@ impl GapBuffer {
         self.buffer.len() - self.gap_size()
     }
 
+    /// Whether the buffer contains no text
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the chars of the text, skipping the gap
+    fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.buffer[..self.gap_start]
+            .iter()
+            .chain(&self.buffer[self.gap_end..])
+            .copied()
+    }
+
     /// Get the size of the gap
     fn gap_size(&self) -> usize {
         self.gap_end - self.gap_start
@@ -133,7 +303,7 @@ impl GapBuffer {
             let move_count = self.gap_start - position;
 
             // Move characters right-to-left to avoid overwriting
-            for i in (0..move_count).rev() {
+            for i in 0..move_count {
                 self.buffer[self.gap_end - 1 - i] = self.buffer[self.gap_start - 1 - i];
             }
 
@@ -161,6 +331,7 @@ impl GapBuffer {
     /// by placing it at gap_start and incrementing gap_start.
     pub fn insert_char(&mut self, position: usize, ch: char) {
         self.move_gap_to(position);
+        self.edits.insert(self.gap_start, 1);
         if self.gap_start >= self.gap_end {
             self.grow_gap();
         }
@@ -171,6 +342,7 @@ impl GapBuffer {
     /// Insert text at the specified position
     pub fn insert(&mut self, position: usize, text: &str) {
         self.move_gap_to(position);
+        self.edits.insert(self.gap_start, text.chars().count());
         for ch in text.chars() {
             if self.gap_start >= self.gap_end {
                 self.grow_gap();
@@ -184,6 +356,7 @@ impl GapBuffer {
     pub fn delete_backward(&mut self, position: usize) {
         if position > 0 {
             self.move_gap_to(position);
+            self.edits.delete(self.gap_start - 1, self.gap_start);
             self.gap_start -= 1;
         }
     }
@@ -192,6 +365,7 @@ impl GapBuffer {
     pub fn delete_forward(&mut self, position: usize) {
         self.move_gap_to(position);
         if self.gap_end < self.buffer.len() {
+            self.edits.delete(self.gap_start, self.gap_start + 1);
             self.gap_end += 1;
         }
     }
@@ -206,6 +380,7 @@ impl GapBuffer {
         let end = min(end, self.len());
 
         self.move_gap_to(start);
+        self.edits.delete(start, end);
         let delete_count = end - start;
         self.gap_end = min(self.gap_end + delete_count, self.buffer.len());
     }
@@ -219,49 +394,20 @@ impl GapBuffer {
         let mut new_buffer = vec!['\0'; new_capacity];
 
         // Copy content before the gap
-        for i in 0..self.gap_start {
-            new_buffer[i] = self.buffer[i];
-        }
+        new_buffer[..self.gap_start].copy_from_slice(&self.buffer[..self.gap_start]);
 
         // Copy content after the gap
         let after_gap_start = self.gap_end;
         let after_gap_count = old_capacity - self.gap_end;
         let new_gap_end = self.gap_start + self.gap_size() + additional_capacity;
 
-        for i in 0..after_gap_count {
-            new_buffer[new_gap_end + i] = self.buffer[after_gap_start + i];
-        }
+        new_buffer[new_gap_end..new_gap_end + after_gap_count]
+            .copy_from_slice(&self.buffer[after_gap_start..]);
 
         self.buffer = new_buffer;
         self.gap_end = new_gap_end;
     }
 
-    /// Convert the buffer to a string.
-    ///
-    /// Reconstructs the text by concatenating the content before the gap
-    /// with the content after the gap, skipping the gap itself.
-    ///
-    /// # Complexity
-    /// O(n) where n is the length of the text
-    pub fn to_string(&self) -> String {
-        let mut result = String::with_capacity(self.len());
-
-        // Add content before the gap
-        for i in 0..self.gap_start {
-            result.push(self.buffer[i]);
-        }
-
-        // Add content after the gap
-        for i in self.gap_end..self.buffer.len() {
-            let ch = self.buffer[i];
-            if ch != '\0' {
-                result.push(ch);
-            }
-        }
-
-        result
-    }
-
     /// Convert the buffer to lines
     pub fn to_lines(&self) -> Vec<String> {
         let text = self.to_string();
@@ -290,7 +436,7 @@ impl GapBuffer {
         let mut current_row = 0;
         let mut current_col = 0;
 
-        for (i, ch) in text.char_indices() {
+        for (i, ch) in text.chars().enumerate() {
             if current_row == row && current_col == col {
                 return i;
             }
@@ -307,7 +453,7 @@ impl GapBuffer {
         }
 
         // Return end of text if position is beyond
-        text.len()
+        self.len()
     }
 
     /// Convert buffer position to cursor position (row, col).
@@ -319,12 +465,12 @@ impl GapBuffer {
     /// A tuple of (row, column) representing the cursor position
     pub fn position_to_cursor(&self, position: usize) -> (usize, usize) {
         let text = self.to_string();
-        let position = min(position, text.len());
+        let position = min(position, self.len());
 
         let mut row = 0;
         let mut col = 0;
 
-        for (i, ch) in text.char_indices() {
+        for (i, ch) in text.chars().enumerate() {
             if i >= position {
                 break;
             }
@@ -340,6 +486,27 @@ impl GapBuffer {
     }
 }
 
+impl std::fmt::Display for GapBuffer {
+    /// Write out the text of the buffer.
+    ///
+    /// Reconstructs the text by concatenating the content before the gap
+    /// with the content after the gap, skipping the gap itself.
+    ///
+    /// # Complexity
+    /// O(n) where n is the length of the text
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut result = String::with_capacity(self.len());
+
+        // Add content before the gap
+        result.extend(&self.buffer[..self.gap_start]);
+
+        // Add content after the gap
+        result.extend(self.buffer[self.gap_end..].iter().filter(|&&ch| ch != '\0'));
+
+        f.write_str(&result)
+    }
+}
+
 impl Default for GapBuffer {
     fn default() -> Self {
         Self::new()
@@ -367,7 +534,7 @@ impl TextBuffer for GapBuffer {
 
     fn line_len(&self, line_idx: usize) -> usize {
         let lines = self.to_lines();
-        lines.get(line_idx).map(|s| s.len()).unwrap_or(0)
+        lines.get(line_idx).map(|s| s.chars().count()).unwrap_or(0)
     }
 
     fn insert_at(&mut self, row: usize, col: usize, text: &str) {
@@ -386,6 +553,88 @@ impl TextBuffer for GapBuffer {
             self.delete_backward(position);
         }
     }
+
+    fn len(&self) -> usize {
+        GapBuffer::len(self)
+    }
+
+    fn insert(&mut self, position: usize, text: &str) {
+        GapBuffer::insert(self, position, text);
+    }
+
+    fn delete_range(&mut self, start: usize, end: usize) {
+        GapBuffer::delete_range(self, start, end);
+    }
+
+    fn cursor_to_position(&self, row: usize, col: usize) -> usize {
+        GapBuffer::cursor_to_position(self, row, col)
+    }
+
+    fn position_to_cursor(&self, position: usize) -> (usize, usize) {
+        GapBuffer::position_to_cursor(self, position)
+    }
+
+    fn text_in_range(&self, start: usize, end: usize) -> String {
+        let end = min(end, self.len());
+        (start..end)
+            .map(|i| {
+                if i < self.gap_start {
+                    self.buffer[i]
+                } else {
+                    self.buffer[i + self.gap_size()]
+                }
+            })
+            .collect()
+    }
+
+    fn char_to_byte(&self, position: usize) -> usize {
+        self.chars().take(position).map(char::len_utf8).sum()
+    }
+
+    fn byte_to_char(&self, offset: usize) -> usize {
+        self.chars()
+            .scan(0, |end, ch| {
+                *end += ch.len_utf8();
+                Some(*end)
+            })
+            .take_while(|&end| end <= offset)
+            .count()
+    }
+
+    fn char_to_utf16(&self, position: usize) -> usize {
+        self.chars().take(position).map(char::len_utf16).sum()
+    }
+
+    fn utf16_to_char(&self, offset: usize) -> usize {
+        self.chars()
+            .scan(0, |start, ch| {
+                let char_start = *start;
+                *start += ch.len_utf16();
+                Some(char_start)
+            })
+            .take_while(|&start| start < offset)
+            .count()
+    }
+
+    fn version(&self) -> usize {
+        self.edits.version()
+    }
+
+    fn anchor_at(&self, position: usize, bias: Bias) -> Anchor {
+        self.edits.anchor(min(position, self.len()), bias)
+    }
+
+    fn resolve_anchor(&self, anchor: &Anchor) -> Option<usize> {
+        self.edits.resolve(anchor)
+    }
+
+    fn first_anchor_version(&self) -> usize {
+        self.edits.first_version()
+    }
+
+    fn forget_edits_before(&mut self, version: usize) {
+        self.edits.forget_before(version);
+    }
 }
 
 #[cfg(test)]
@@ -419,6 +668,56 @@ mod tests {
         assert_eq!(buffer.position_to_cursor(6), (1, 0));
     }
 
+    #[test]
+    fn test_utf16_conversion() {
+        // "é" is one UTF-16 unit, "😀" is a surrogate pair
+        let buffer = GapBuffer::from_text("aé😀\nb");
+
+        assert_eq!(buffer.char_to_utf16(0), 0);
+        assert_eq!(buffer.char_to_utf16(2), 2);
+        assert_eq!(buffer.char_to_utf16(3), 4);
+        assert_eq!(buffer.char_to_utf16(5), 6);
+        assert_eq!(buffer.char_to_utf16(99), 6);
+
+        assert_eq!(buffer.utf16_to_char(2), 2);
+        assert_eq!(buffer.utf16_to_char(4), 3);
+        // Inside the surrogate pair
+        assert_eq!(buffer.utf16_to_char(3), 3);
+        assert_eq!(buffer.utf16_to_char(99), 5);
+
+        // The gap in the middle of the text doesn't count
+        let mut buffer = buffer;
+        buffer.move_gap_to(3);
+        assert_eq!(buffer.char_to_utf16(5), 6);
+        assert_eq!(buffer.utf16_to_char(3), 3);
+        assert_eq!(buffer.char_to_byte(4), 8);
+        assert_eq!(buffer.byte_to_char(5), 2);
+    }
+
+    #[test]
+    fn test_multibyte_lines() {
+        let buffer = GapBuffer::from_text("日本語\na👍🏽\ne\u{301}");
+        assert_eq!(buffer.line_len(0), 3);
+        assert_eq!(buffer.line_len(1), 3);
+        assert_eq!(buffer.line_len(2), 2);
+        assert_eq!(buffer.cursor_to_position(1, 1), 5);
+        assert_eq!(buffer.position_to_cursor(5), (1, 1));
+        assert_eq!(buffer.char_to_byte(5), 11);
+        assert_eq!(buffer.byte_to_char(11), 5);
+    }
+
+    #[test]
+    fn test_grapheme_boundaries_across_lines() {
+        let buffer = GapBuffer::from_text("a👍🏽\ne\u{301}");
+        assert_eq!(buffer.next_grapheme_boundary(1), 3);
+        // The line break is a boundary of its own
+        assert_eq!(buffer.next_grapheme_boundary(3), 4);
+        assert_eq!(buffer.next_grapheme_boundary(4), 6);
+        assert_eq!(buffer.prev_grapheme_boundary(6), 4);
+        assert_eq!(buffer.prev_grapheme_boundary(4), 3);
+        assert_eq!(buffer.prev_grapheme_boundary(3), 1);
+    }
+
     #[test]
     fn test_newline_insertion() {
         let mut buffer = GapBuffer::from_text("HelloWorld");
@@ -865,6 +1164,43 @@ mod tests {
         assert_eq!(buffer.to_string(), "🎭🎪");
     }
 
+    #[test]
+    fn test_move_gap_backward_with_small_gap() {
+        let mut buffer = GapBuffer::from_text("abc");
+        // Fill the gap until only a single free slot remains
+        let filler: String = "x".repeat(1024 - 4);
+        buffer.insert(3, &filler);
+        buffer.move_gap_to(0);
+        assert_eq!(buffer.to_string(), format!("abc{}", filler));
+    }
+
+    #[test]
+    fn test_move_gap_backward_further_than_gap_size() {
+        // Moving the gap back by more chars than it is wide used to copy
+        // chars over ones that hadn't been moved yet
+        let mut buffer = GapBuffer::from_text("0123456789");
+        let filler: String = "-".repeat(1024 - 13);
+        buffer.insert(10, &filler);
+        buffer.move_gap_to(2);
+        buffer.insert(2, "ab");
+        assert_eq!(buffer.to_string(), format!("01ab23456789{}", filler));
+        buffer.move_gap_to(0);
+        assert_eq!(buffer.to_string(), format!("01ab23456789{}", filler));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let buffer = GapBuffer::from_lines(vec!["one".to_string(), "two".to_string()]);
+        let snapshot = buffer.snapshot();
+        assert_eq!(snapshot.line_count(), buffer.line_count());
+        assert_eq!(snapshot.line(1), Some("two".to_string()));
+        assert_eq!(snapshot.line(2), None);
+
+        let empty = GapBuffer::new().snapshot();
+        assert_eq!(empty.line_count(), 1);
+        assert_eq!(empty.line(0), Some(String::new()));
+    }
+
     #[test]
     fn test_sequential_edits() {
         let mut buffer = GapBuffer::new();
@@ -890,4 +1226,35 @@ mod tests {
             "The quick brown cat jumped over the lazy dog"
         );
     }
+
+    #[test]
+    fn test_anchors() {
+        let mut buffer = GapBuffer::from_text("The quick fox");
+        let start = buffer.anchor_at(4, Bias::Right);
+        let end = buffer.anchor_at(9, Bias::Left);
+        let word = |buffer: &GapBuffer| {
+            buffer.text_in_range(
+                buffer.resolve_anchor(&start).unwrap(),
+                buffer.resolve_anchor(&end).unwrap(),
+            )
+        };
+
+        // Text typed at either end of the word stays outside it
+        buffer.insert(4, "very ");
+        buffer.insert_char(14, '!');
+        assert_eq!(buffer.to_string(), "The very quick! fox");
+        assert_eq!(word(&buffer), "quick");
+
+        buffer.delete_backward(4);
+        buffer.delete_forward(0);
+        buffer.delete_range(9, 11);
+        assert_eq!(buffer.to_string(), "hevery quk! fox");
+        assert_eq!(word(&buffer), "quk");
+        assert_eq!(buffer.version(), 5);
+
+        // Deleting the word collapses the anchors onto each other
+        buffer.delete_range(6, 11);
+        assert_eq!(word(&buffer), "");
+        assert_eq!(buffer.resolve_anchor(&start), Some(6));
+    }
 }
@@ -0,0 +1,263 @@
+//! Toggling comments.
+//!
+//! Comment markers come from the metadata of syntect's syntaxes (the
+//! `TM_COMMENT_START` and `TM_COMMENT_END` variables of TextMate preferences),
+//! looked up by language name. Markers usually end with a space, as in `// `;
+//! when uncommenting, a line counts as commented with or without that space.
+//!
+//! Line comments are toggled for a run of lines at a time: if every non-blank
+//! line is commented they are all uncommented, otherwise they are all
+//! commented, with the marker at the smallest indentation of the lines.
+//! Languages without line comments, like HTML and CSS, comment each line with
+//! a block comment instead.
+//!
+//! Offsets are in chars, like [`CursorPosition::col`](crate::editor::CursorPosition).
+
+/// The comment markers of a language
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommentTokens {
+    /// Starts a comment that runs to the end of the line, like `// `
+    pub line: Option<String>,
+    /// Start and end of a block comment, like `/*` and `*/`
+    pub block: Option<(String, String)>,
+}
+
+/// An edit within a line: replace `removed` chars at `col` with `text`
+pub type SpanEdit = (usize, usize, String);
+
+/// The edits that toggle line comments on `lines`, for each line in order.
+/// Edits within a line are sorted by column.
+pub fn toggle_line_comments(lines: &[String], tokens: &CommentTokens) -> Vec<Vec<SpanEdit>> {
+    let indents: Vec<Option<usize>> = lines
+        .iter()
+        .map(|line| {
+            let indent = line.chars().take_while(|ch| ch.is_whitespace()).count();
+            (indent < line.chars().count()).then_some(indent)
+        })
+        .collect();
+    let Some(min_indent) = indents.iter().flatten().min().copied() else {
+        return vec![Vec::new(); lines.len()];
+    };
+
+    let uncomment: Vec<Option<Vec<SpanEdit>>> = lines
+        .iter()
+        .zip(&indents)
+        .map(|(line, indent)| {
+            let indent = (*indent)?;
+            match (&tokens.line, &tokens.block) {
+                (Some(token), _) => uncomment_line(line, indent, token),
+                (None, Some((open, close))) => uncomment_line_block(line, indent, open, close),
+                (None, None) => None,
+            }
+        })
+        .collect();
+
+    let all_commented = uncomment
+        .iter()
+        .zip(&indents)
+        .all(|(edits, indent)| indent.is_none() || edits.is_some());
+    if all_commented {
+        return uncomment
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect();
+    }
+
+    lines
+        .iter()
+        .zip(&indents)
+        .map(|(line, indent)| {
+            if indent.is_none() {
+                return Vec::new();
+            }
+            match (&tokens.line, &tokens.block) {
+                (Some(token), _) => vec![(min_indent, 0, token.clone())],
+                (None, Some((open, close))) => {
+                    let end = line.trim_end().chars().count();
+                    vec![(min_indent, 0, open.clone()), (end, 0, close.clone())]
+                }
+                (None, None) => Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// The edit that removes the line comment marker `token` from a line whose
+/// text starts at `indent`, if it has one
+fn uncomment_line(line: &str, indent: usize, token: &str) -> Option<Vec<SpanEdit>> {
+    let text: String = line.chars().skip(indent).collect();
+    let len = marker_len(&text, token)?;
+    Some(vec![(indent, len, String::new())])
+}
+
+/// The edits that remove the block comment markers around the text of a line
+/// whose text starts at `indent`, if they are there
+fn uncomment_line_block(
+    line: &str,
+    indent: usize,
+    open: &str,
+    close: &str,
+) -> Option<Vec<SpanEdit>> {
+    let text: String = line.trim_end().chars().skip(indent).collect();
+    let (open_len, _, close_len) = block_marker_lens(&text, open, close)?;
+    let end = indent + text.chars().count();
+    Some(vec![
+        (indent, open_len, String::new()),
+        (end - close_len, close_len, String::new()),
+    ])
+}
+
+/// How many chars at the start of `text` are the marker `token`, counting the
+/// space it ends with as optional
+fn marker_len(text: &str, token: &str) -> Option<usize> {
+    token_len(token, token.trim_end(), |token| text.starts_with(token))
+}
+
+/// How many chars at the end of `text` are the marker `token`, counting the
+/// space it starts with as optional
+fn end_marker_len(text: &str, token: &str) -> Option<usize> {
+    token_len(token, token.trim_start(), |token| text.ends_with(token))
+}
+
+/// The length of `token` if `matches` finds it, or else of `trimmed`, the
+/// token without its padding
+fn token_len(token: &str, trimmed: &str, matches: impl Fn(&str) -> bool) -> Option<usize> {
+    if trimmed.is_empty() {
+        return None;
+    }
+    [token, trimmed]
+        .into_iter()
+        .find(|token| matches(token))
+        .map(|token| token.chars().count())
+}
+
+/// If `text` is a block comment from `open` to `close`: the lengths of its
+/// start marker, its contents and its end marker
+fn block_marker_lens(text: &str, open: &str, close: &str) -> Option<(usize, usize, usize)> {
+    let open_len = marker_len(text, open)?;
+    let close_len = end_marker_len(text, close)?;
+    let inner_len = text.chars().count().checked_sub(open_len + close_len)?;
+    Some((open_len, inner_len, close_len))
+}
+
+/// The text inside the block comment `text`, if it is one, along with how
+/// many chars were removed from its start. Whitespace around the comment is
+/// kept.
+pub fn unwrap_block(text: &str, open: &str, close: &str) -> Option<(String, usize)> {
+    let leading = text.len() - text.trim_start().len();
+    let trailing = text.len() - text.trim_end().len();
+    let comment = &text[leading..text.len() - trailing];
+    let (open_len, inner_len, _) = block_marker_lens(comment, open, close)?;
+    let inner: String = comment.chars().skip(open_len).take(inner_len).collect();
+    let unwrapped = format!(
+        "{}{inner}{}",
+        &text[..leading],
+        &text[text.len() - trailing..]
+    );
+    Some((unwrapped, open_len))
+}
+
+/// If `before` ends with the start of a block comment and `after` starts with
+/// its end, how many chars of each they take up
+pub fn surrounding_block(
+    before: &str,
+    after: &str,
+    open: &str,
+    close: &str,
+) -> Option<(usize, usize)> {
+    let open_len = token_len(open, open.trim_end(), |token| before.ends_with(token))?;
+    let close_len = token_len(close, close.trim_start(), |token| after.starts_with(token))?;
+    Some((open_len, close_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(str::to_string).collect()
+    }
+
+    fn slash() -> CommentTokens {
+        CommentTokens {
+            line: Some("// ".into()),
+            block: Some(("/*".into(), "*/".into())),
+        }
+    }
+
+    #[test]
+    fn test_comment_at_smallest_indent() {
+        let edits = toggle_line_comments(&lines("    a\n\n  b"), &slash());
+        assert_eq!(
+            edits,
+            vec![
+                vec![(2, 0, "// ".to_string())],
+                vec![],
+                vec![(2, 0, "// ".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uncomment_only_when_all_commented() {
+        // With and without the space after the marker
+        let edits = toggle_line_comments(&lines("  // a\n  //b"), &slash());
+        assert_eq!(
+            edits,
+            vec![vec![(2, 3, String::new())], vec![(2, 2, String::new())]]
+        );
+
+        // Mixed lines are all commented, nesting the existing comment
+        let edits = toggle_line_comments(&lines("// a\nb"), &slash());
+        assert_eq!(
+            edits,
+            vec![
+                vec![(0, 0, "// ".to_string())],
+                vec![(0, 0, "// ".to_string())]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_only_languages_comment_each_line() {
+        let html = CommentTokens {
+            line: None,
+            block: Some(("<!-- ".into(), " -->".into())),
+        };
+        let edits = toggle_line_comments(&lines("  <p>"), &html);
+        assert_eq!(
+            edits,
+            vec![vec![
+                (2, 0, "<!-- ".to_string()),
+                (5, 0, " -->".to_string())
+            ]]
+        );
+        let edits = toggle_line_comments(&lines("  <!-- <p> -->"), &html);
+        assert_eq!(
+            edits,
+            vec![vec![(2, 5, String::new()), (10, 4, String::new())]]
+        );
+    }
+
+    #[test]
+    fn test_block_comments() {
+        assert_eq!(
+            unwrap_block(" /* a */ ", "/* ", " */"),
+            Some((" a ".to_string(), 3))
+        );
+        assert_eq!(
+            unwrap_block("/*a*/", "/*", "*/"),
+            Some(("a".to_string(), 2))
+        );
+        assert_eq!(unwrap_block("a */", "/*", "*/"), None);
+        assert_eq!(
+            surrounding_block("x = /* ", " */", "/* ", " */"),
+            Some((3, 3))
+        );
+        assert_eq!(
+            surrounding_block("x = /*", "*/;", "/* ", " */"),
+            Some((2, 2))
+        );
+        assert_eq!(surrounding_block("x = ", "*/", "/*", "*/"), None);
+    }
+}
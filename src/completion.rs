@@ -0,0 +1,566 @@
+//! Completions offered for the word being typed.
+//!
+//! A [`CompletionProvider`] suggests [`CompletionItem`]s for a position in a
+//! snapshot of the text. Providers run on the background executor, so a slow
+//! one never blocks typing. The editor shows the suggestions in a
+//! [`CompletionMenu`] anchored at the start of the word before the caret, and
+//! narrows them with [`fuzzy_match`] as the user keeps typing that word. The
+//! menu closes once the caret leaves the word.
+//!
+//! [`BufferWordsProvider`] is a provider that needs no language support: it
+//! suggests the words already in the text.
+
+use crate::anchor::Anchor;
+use crate::buffer::BufferSnapshot;
+use crate::editor::CursorPosition;
+use gpui::Task;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::ops::Range;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// A suggestion for completing the text at the caret
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompletionItem {
+    /// What the suggestion is listed as
+    pub label: String,
+    /// A short note shown next to the label, like a type signature
+    pub detail: Option<String>,
+    /// Longer documentation about the suggestion
+    pub documentation: Option<String>,
+    /// The text that replaces the word being typed when the suggestion is
+    /// accepted
+    pub insert_text: String,
+    /// The text to replace instead of the word being typed, like a language
+    /// server's text edit asks for
+    pub range: Option<Range<Anchor>>,
+}
+
+impl CompletionItem {
+    /// A suggestion that inserts its label
+    pub fn new(label: impl Into<String>) -> Self {
+        let label = label.into();
+        Self {
+            insert_text: label.clone(),
+            label,
+            detail: None,
+            documentation: None,
+            range: None,
+        }
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn documentation(mut self, documentation: impl Into<String>) -> Self {
+        self.documentation = Some(documentation.into());
+        self
+    }
+
+    pub fn insert_text(mut self, insert_text: impl Into<String>) -> Self {
+        self.insert_text = insert_text.into();
+        self
+    }
+
+    pub fn range(mut self, range: Range<Anchor>) -> Self {
+        self.range = Some(range);
+        self
+    }
+}
+
+/// The completions a provider will come up with
+pub type CompletionsFuture = Pin<Box<dyn Future<Output = Vec<CompletionItem>> + Send>>;
+
+/// A source of completions, set with
+/// [`Editor::set_completion_provider`](crate::editor::Editor::set_completion_provider)
+pub trait CompletionProvider {
+    /// Suggestions for the word before `position` in `snapshot`. They don't
+    /// have to match what was typed of the word so far; the menu filters
+    /// them. The future runs on the background executor.
+    fn completions(&self, snapshot: BufferSnapshot, position: CursorPosition) -> CompletionsFuture;
+}
+
+/// Suggests the words in the text, except the one being typed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BufferWordsProvider;
+
+impl BufferWordsProvider {
+    /// Words shorter than this aren't worth suggesting
+    const MIN_WORD_LEN: usize = 3;
+}
+
+impl CompletionProvider for BufferWordsProvider {
+    fn completions(&self, snapshot: BufferSnapshot, position: CursorPosition) -> CompletionsFuture {
+        Box::pin(async move {
+            let mut words = BTreeSet::new();
+            for row in 0..snapshot.line_count() {
+                let line = snapshot.line(row).unwrap_or_default();
+                let mut col = 0;
+                for chunk in line.split(|ch: char| !is_word_char(ch)) {
+                    let len = chunk.chars().count();
+                    let typed = row == position.row && (col..=col + len).contains(&position.col);
+                    if len >= Self::MIN_WORD_LEN && !typed {
+                        words.insert(chunk.to_string());
+                    }
+                    col += len + 1;
+                }
+            }
+            words.into_iter().map(CompletionItem::new).collect()
+        })
+    }
+}
+
+/// Chars that make up the words completions replace
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// How well `query` matches `candidate`: its chars must appear in order in
+/// `candidate`, ignoring case. Matches at the start of words and runs of
+/// consecutive chars score higher. Returns the score and the char indices of
+/// `candidate` that matched, or `None` if it doesn't match.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    const MATCH: i64 = 16;
+    const WORD_START: i64 = 24;
+    const CONSECUTIVE: i64 = 16;
+    const EXACT_CASE: i64 = 1;
+
+    let query: Vec<char> = query.chars().collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    let lower = |ch: char| ch.to_lowercase().next().unwrap_or(ch);
+    let is_word_start = |index: usize| {
+        index == 0
+            || (!is_word_char(chars[index - 1]) && is_word_char(chars[index]))
+            || (chars[index - 1] == '_' && chars[index] != '_')
+            || (chars[index - 1].is_lowercase() && chars[index].is_uppercase())
+    };
+
+    // best[i][j]: the best score for matching the first i + 1 query chars with
+    // the last one at chars[j], and where the one before it matched
+    let mut best: Vec<Vec<Option<(i64, usize)>>> = vec![vec![None; chars.len()]; query.len()];
+    for (i, &query_char) in query.iter().enumerate() {
+        for j in i..chars.len() {
+            if lower(chars[j]) != lower(query_char) {
+                continue;
+            }
+            let mut score = MATCH;
+            if is_word_start(j) {
+                score += WORD_START;
+            }
+            if chars[j] == query_char {
+                score += EXACT_CASE;
+            }
+            if i == 0 {
+                // Matching later in the candidate costs a little
+                best[i][j] = Some((score - j as i64, 0));
+                continue;
+            }
+            best[i][j] = (i - 1..j)
+                .filter_map(|k| {
+                    let (previous, _) = best[i - 1][k]?;
+                    let gap = if k + 1 == j {
+                        CONSECUTIVE
+                    } else {
+                        -((j - k - 1) as i64)
+                    };
+                    Some((previous + score + gap, k))
+                })
+                .max_by_key(|(score, _)| *score);
+        }
+    }
+
+    let last = query.len() - 1;
+    let (mut j, (score, _)) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, entry)| Some((j, (*entry)?)))
+        .max_by_key(|(_, (score, _))| *score)?;
+    let mut positions = vec![0; query.len()];
+    for i in (0..query.len()).rev() {
+        positions[i] = j;
+        j = best[i][j].map_or(0, |(_, previous)| previous);
+    }
+    Some((score, positions))
+}
+
+/// An item that matches what was typed
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CompletionMatch {
+    /// Index into the menu's items
+    index: usize,
+    score: i64,
+    /// Char indices of the label that matched
+    positions: Vec<usize>,
+}
+
+/// The suggestions for the word being typed, narrowed to the ones matching
+/// it, best first, with one of them selected
+#[derive(Clone, Debug)]
+pub struct CompletionMenu {
+    word_start: CursorPosition,
+    query: String,
+    items: Rc<[CompletionItem]>,
+    matches: Vec<CompletionMatch>,
+    selected: usize,
+}
+
+impl CompletionMenu {
+    pub fn new(word_start: CursorPosition, items: Vec<CompletionItem>) -> Self {
+        let mut menu = Self {
+            word_start,
+            query: String::new(),
+            items: items.into(),
+            matches: Vec::new(),
+            selected: 0,
+        };
+        menu.filter();
+        menu
+    }
+
+    /// Where the word being completed starts
+    pub fn word_start(&self) -> CursorPosition {
+        self.word_start
+    }
+
+    /// What has been typed of the word
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Narrow the items to the ones matching `query`, selecting the best
+    pub fn set_query(&mut self, query: &str) {
+        if query != self.query {
+            self.query = query.to_string();
+            self.filter();
+        }
+    }
+
+    fn set_items(&mut self, items: Vec<CompletionItem>) {
+        self.items = items.into();
+        self.filter();
+    }
+
+    fn filter(&mut self) {
+        self.matches = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                let (score, positions) = fuzzy_match(&self.query, &item.label)?;
+                Some(CompletionMatch {
+                    index,
+                    score,
+                    positions,
+                })
+            })
+            .collect();
+        // Stable, so equally good matches keep the provider's order
+        let items = &self.items;
+        self.matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| items[a.index].label.len().cmp(&items[b.index].label.len()))
+        });
+        self.selected = 0;
+    }
+
+    /// Whether no item matches what was typed
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// The matching items, best first, with the char indices of their labels
+    /// that matched
+    pub fn matches(&self) -> impl Iterator<Item = (&CompletionItem, &[usize])> {
+        self.matches
+            .iter()
+            .map(|found| (&self.items[found.index], found.positions.as_slice()))
+    }
+
+    /// Index of the selected item among [`Self::matches`]
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_item(&self) -> Option<&CompletionItem> {
+        let found = self.matches.get(self.selected)?;
+        Some(&self.items[found.index])
+    }
+
+    /// Select the next item, wrapping around to the first
+    pub fn select_next(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + 1) % self.matches.len();
+        }
+    }
+
+    /// Select the previous item, wrapping around to the last
+    pub fn select_previous(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + self.matches.len() - 1) % self.matches.len();
+        }
+    }
+}
+
+/// The open menu and the request filling it
+struct ActiveCompletions {
+    /// Start of the word being completed, which moves with edits before it
+    word_start: Anchor,
+    menu: CompletionMenu,
+    /// Dropping the task cancels the request
+    _task: Option<Task<()>>,
+}
+
+/// The completion menu of an editor
+#[derive(Default)]
+pub(crate) struct Completions {
+    /// Incremented for every request, so results of an earlier one are
+    /// dropped
+    request: usize,
+    /// Kept in a `RefCell` so reading the menu can first close it if the
+    /// caret has left the word
+    active: RefCell<Option<ActiveCompletions>>,
+}
+
+/// A copy of the completions keeps the menu if its items have arrived. A
+/// request still running belongs to the original, so a copy taken before its
+/// results arrive has no menu.
+impl Clone for Completions {
+    fn clone(&self) -> Self {
+        let active = self.active.borrow().as_ref().and_then(|active| {
+            active._task.is_none().then(|| ActiveCompletions {
+                word_start: active.word_start,
+                menu: active.menu.clone(),
+                _task: None,
+            })
+        });
+        Self {
+            request: self.request,
+            active: RefCell::new(active),
+        }
+    }
+}
+
+impl Completions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the menu for the word starting at `word_start`, with no items
+    /// until [`Self::finish`] is called with the returned request id
+    pub fn start(&mut self, word_start: Anchor, position: CursorPosition) -> usize {
+        self.request += 1;
+        *self.active.get_mut() = Some(ActiveCompletions {
+            word_start,
+            menu: CompletionMenu::new(position, Vec::new()),
+            _task: None,
+        });
+        self.request
+    }
+
+    /// Keep the task running request `request` until it finishes
+    pub fn set_task(&mut self, request: usize, task: Task<()>) {
+        if self.request == request {
+            if let Some(active) = self.active.get_mut() {
+                active._task = Some(task);
+            }
+        }
+    }
+
+    /// Fill the menu with the results of `request`. Returns false, storing
+    /// nothing, if the menu was closed or reopened since.
+    pub fn finish(&mut self, request: usize, items: Vec<CompletionItem>) -> bool {
+        if self.request != request {
+            return false;
+        }
+        match self.active.get_mut() {
+            Some(active) => {
+                active.menu.set_items(items);
+                active._task = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Close the menu, cancelling its request. Returns whether it was
+    /// showing.
+    pub fn close(&mut self) -> bool {
+        let active = self.active.get_mut().take();
+        active.is_some_and(|active| !active.menu.is_empty())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.active.borrow().is_some()
+    }
+
+    /// The buffer version the menu's anchor was made at, if it is open
+    pub fn anchor_version(&self) -> Option<usize> {
+        let active = self.active.borrow();
+        active.as_ref().map(|active| active.word_start.version())
+    }
+
+    /// Bring the menu up to date with the text. `resolve` finds where the
+    /// anchored word starts and what has been typed of it, or `None` if the
+    /// caret left the word, which closes the menu.
+    pub fn sync(&self, resolve: impl FnOnce(&Anchor) -> Option<(CursorPosition, String)>) {
+        let mut active_slot = self.active.borrow_mut();
+        let Some(active) = active_slot.as_mut() else {
+            return;
+        };
+        match resolve(&active.word_start) {
+            Some((word_start, query)) => {
+                active.menu.word_start = word_start;
+                active.menu.set_query(&query);
+            }
+            None => *active_slot = None,
+        }
+    }
+
+    /// The menu, if it is open and has items matching what was typed
+    pub fn menu(&self) -> Option<CompletionMenu> {
+        let active = self.active.borrow();
+        let menu = &active.as_ref()?.menu;
+        (!menu.is_empty()).then(|| menu.clone())
+    }
+
+    /// Change the menu if it is showing. Returns whether it was.
+    pub fn update_menu(&mut self, f: impl FnOnce(&mut CompletionMenu)) -> bool {
+        match self.active.get_mut() {
+            Some(active) if !active.menu.is_empty() => {
+                f(&mut active.menu);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::{Context, Poll, Waker};
+
+    fn labels(menu: &CompletionMenu) -> Vec<&str> {
+        menu.matches()
+            .map(|(item, _)| item.label.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(fuzzy_match("", "anything"), Some((0, vec![])));
+        assert_eq!(fuzzy_match("xyz", "main"), None);
+        assert_eq!(fuzzy_match("mian", "main"), None);
+
+        // Word starts win over earlier matches in the middle of words
+        let (_, positions) = fuzzy_match("lo", "hello_loop").unwrap();
+        assert_eq!(positions, vec![6, 7]);
+        let (_, positions) = fuzzy_match("gV", "getValue").unwrap();
+        assert_eq!(positions, vec![0, 3]);
+
+        // Prefixes beat scattered matches, and case is only a tie-breaker
+        let score = |query, candidate| fuzzy_match(query, candidate).unwrap().0;
+        assert!(score("set", "settings") > score("set", "sort_entries"));
+        assert!(score("Set", "Settings") > score("Set", "settings"));
+        assert!(score("Set", "settings") > score("Set", "a_setting"));
+    }
+
+    #[test]
+    fn test_menu_filters_and_selects() {
+        let items = ["sort_entries", "settings", "reset", "Set"]
+            .into_iter()
+            .map(CompletionItem::new)
+            .collect();
+        let mut menu = CompletionMenu::new(CursorPosition::new(0, 0), items);
+        assert_eq!(menu.len(), 4);
+
+        menu.set_query("set");
+        assert_eq!(
+            labels(&menu),
+            vec!["settings", "Set", "sort_entries", "reset"]
+        );
+        menu.select_previous();
+        assert_eq!(menu.selected_item().unwrap().label, "reset");
+        menu.select_next();
+        assert_eq!(menu.selected_index(), 0);
+        menu.select_next();
+
+        // Typing more narrows the items and selects the best again
+        menu.set_query("sett");
+        assert_eq!(labels(&menu), vec!["settings"]);
+        assert_eq!(menu.selected_index(), 0);
+        let (_, positions) = menu.matches().next().unwrap();
+        assert_eq!(positions, &[0, 1, 2, 3]);
+
+        menu.set_query("settx");
+        assert!(menu.is_empty());
+        assert_eq!(menu.selected_item(), None);
+    }
+
+    #[test]
+    fn test_buffer_words_provider() {
+        let snapshot = BufferSnapshot::new("let total = sum(values);\nlet to\nvalues.iter()");
+        let position = CursorPosition::new(1, 6);
+        let mut future = BufferWordsProvider.completions(snapshot, position);
+        let Poll::Ready(items) = future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        else {
+            panic!("the provider doesn't wait on anything");
+        };
+        let labels: Vec<&str> = items.iter().map(|item| item.label.as_str()).collect();
+        // Short words and the word being typed aren't suggested
+        assert_eq!(labels, vec!["iter", "let", "sum", "total", "values"]);
+    }
+
+    #[test]
+    fn test_stale_results_are_dropped() {
+        let mut completions = Completions::new();
+        let anchor = crate::anchor::EditLog::new().anchor(0, crate::anchor::Bias::Left);
+        let first = completions.start(anchor, CursorPosition::new(0, 0));
+        let second = completions.start(anchor, CursorPosition::new(0, 0));
+        assert!(completions.menu().is_none());
+
+        assert!(!completions.finish(first, vec![CompletionItem::new("old")]));
+        assert!(completions.finish(second, vec![CompletionItem::new("new")]));
+        assert_eq!(labels(&completions.menu().unwrap()), vec!["new"]);
+
+        completions.sync(|_| None);
+        assert!(!completions.is_open());
+        assert!(!completions.finish(second, vec![CompletionItem::new("new")]));
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut completions = Completions::new();
+        let anchor = crate::anchor::EditLog::new().anchor(0, crate::anchor::Bias::Left);
+        let request = completions.start(anchor, CursorPosition::new(0, 0));
+        completions.set_task(request, Task::ready(()));
+        // The running request stays with the original
+        assert!(!completions.clone().is_open());
+
+        completions.finish(
+            request,
+            vec![CompletionItem::new("one"), CompletionItem::new("two")],
+        );
+        let mut copy = completions.clone();
+        copy.update_menu(CompletionMenu::select_next);
+        assert_eq!(copy.menu().unwrap().selected_index(), 1);
+        assert_eq!(completions.menu().unwrap().selected_index(), 0);
+
+        copy.close();
+        assert!(completions.is_open());
+    }
+}
@@ -0,0 +1,150 @@
+//! Text coordinate conversions.
+//!
+//! The editor deals with four units for positions inside text:
+//!
+//! - **Byte offsets** index UTF-8 data. Slicing a `str` and gpui's text layout
+//!   (`TextRun::len`, `ShapedLine` indices) use bytes.
+//! - **Char offsets** count Unicode scalar values. This is the editor's own
+//!   unit: [`CursorPosition::col`](crate::editor::CursorPosition), linear
+//!   buffer positions and undo history are all in chars.
+//! - **UTF-16 offsets** are what platform input methods speak; see
+//!   [`Editor::text_for_utf16_range`](crate::editor::Editor::text_for_utf16_range).
+//! - **Grapheme boundaries** are where a user sees one character end and the
+//!   next begin. An emoji with a skin tone or a letter with a combining accent
+//!   spans several chars, so caret movement and deletion step from boundary to
+//!   boundary and a caret never lands inside a grapheme.
+//!
+//! The functions here convert between these units within a single string,
+//! normally one line. [`TextBuffer`](crate::buffer::TextBuffer) builds on them
+//! for whole-buffer positions.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Byte offset of the char at `char_offset`, clamped to the end of `text`
+pub fn char_to_byte(text: &str, char_offset: usize) -> usize {
+    text.char_indices()
+        .nth(char_offset)
+        .map_or(text.len(), |(index, _)| index)
+}
+
+/// Char offset of `byte_offset`. Offsets inside a char round down to its
+/// start; offsets past the end clamp to the char length of `text`.
+pub fn byte_to_char(text: &str, byte_offset: usize) -> usize {
+    text.char_indices()
+        .take_while(|(index, ch)| index + ch.len_utf8() <= byte_offset)
+        .count()
+}
+
+/// UTF-16 offset of the char at `char_offset`, clamped to the end of `text`
+pub fn char_to_utf16(text: &str, char_offset: usize) -> usize {
+    text.chars().take(char_offset).map(char::len_utf16).sum()
+}
+
+/// Char offset of `utf16_offset`. An offset inside a surrogate pair rounds up
+/// to the next char; offsets past the end clamp to the char length of `text`.
+pub fn utf16_to_char(text: &str, utf16_offset: usize) -> usize {
+    let mut utf16 = 0;
+    for (index, ch) in text.chars().enumerate() {
+        if utf16 >= utf16_offset {
+            return index;
+        }
+        utf16 += ch.len_utf16();
+    }
+    text.chars().count()
+}
+
+/// The char offset of the grapheme boundary before `char_offset`, or 0
+pub fn prev_grapheme_boundary(text: &str, char_offset: usize) -> usize {
+    grapheme_boundaries(text)
+        .take_while(|&boundary| boundary < char_offset)
+        .last()
+        .unwrap_or(0)
+}
+
+/// The char offset of the grapheme boundary after `char_offset`, or the char
+/// length of `text`
+pub fn next_grapheme_boundary(text: &str, char_offset: usize) -> usize {
+    grapheme_boundaries(text)
+        .find(|&boundary| boundary > char_offset)
+        .unwrap_or_else(|| text.chars().count())
+}
+
+/// Round `char_offset` down to a grapheme boundary
+pub fn floor_grapheme_boundary(text: &str, char_offset: usize) -> usize {
+    grapheme_boundaries(text)
+        .take_while(|&boundary| boundary <= char_offset)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Char offsets where each grapheme starts, followed by the end of `text`
+fn grapheme_boundaries(text: &str) -> impl Iterator<Item = usize> + '_ {
+    let mut offset = 0;
+    text.graphemes(true)
+        .map(move |grapheme| {
+            let start = offset;
+            offset += grapheme.chars().count();
+            start
+        })
+        .chain(std::iter::once(text.chars().count()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "e" + COMBINING ACUTE ACCENT, thumbs up + skin tone, and a ZWJ family
+    const COMBINING: &str = "e\u{301}";
+    const THUMBS_UP: &str = "👍🏽";
+    const FAMILY: &str = "👨‍👩‍👧";
+
+    #[test]
+    fn test_byte_and_char_offsets() {
+        let text = "a日本😀b";
+        assert_eq!(char_to_byte(text, 0), 0);
+        assert_eq!(char_to_byte(text, 1), 1);
+        assert_eq!(char_to_byte(text, 2), 4);
+        assert_eq!(char_to_byte(text, 4), 11);
+        assert_eq!(char_to_byte(text, 99), 12);
+
+        assert_eq!(byte_to_char(text, 4), 2);
+        // Inside "本" rounds down
+        assert_eq!(byte_to_char(text, 5), 2);
+        assert_eq!(byte_to_char(text, 12), 5);
+        assert_eq!(byte_to_char(text, 99), 5);
+    }
+
+    #[test]
+    fn test_utf16_offsets() {
+        let text = "a日😀b";
+        assert_eq!(char_to_utf16(text, 2), 2);
+        assert_eq!(char_to_utf16(text, 3), 4);
+        assert_eq!(char_to_utf16(text, 99), 5);
+
+        assert_eq!(utf16_to_char(text, 2), 2);
+        // Inside the surrogate pair rounds up
+        assert_eq!(utf16_to_char(text, 3), 3);
+        assert_eq!(utf16_to_char(text, 4), 3);
+        assert_eq!(utf16_to_char(text, 99), 4);
+    }
+
+    #[test]
+    fn test_grapheme_boundaries() {
+        let text = format!("a{COMBINING}{THUMBS_UP}{FAMILY}日");
+        // a | e◌́ | 👍🏽 | 👨‍👩‍👧 | 日
+        let boundaries = [0, 1, 3, 5, 10, 11];
+        for pair in boundaries.windows(2) {
+            assert_eq!(next_grapheme_boundary(&text, pair[0]), pair[1]);
+            assert_eq!(prev_grapheme_boundary(&text, pair[1]), pair[0]);
+        }
+
+        // Offsets inside a grapheme move to its edges
+        assert_eq!(next_grapheme_boundary(&text, 2), 3);
+        assert_eq!(prev_grapheme_boundary(&text, 7), 5);
+        assert_eq!(floor_grapheme_boundary(&text, 7), 5);
+        assert_eq!(floor_grapheme_boundary(&text, 5), 5);
+
+        assert_eq!(prev_grapheme_boundary(&text, 0), 0);
+        assert_eq!(next_grapheme_boundary(&text, 11), 11);
+    }
+}
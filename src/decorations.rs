@@ -0,0 +1,369 @@
+//! Styles painted over ranges of text.
+//!
+//! Consumers decorate text under a key, like `"references"` or
+//! `"diagnostics"`, and clear everything under that key at once. Backgrounds
+//! and borders are painted behind and around the text; underlines,
+//! strikethroughs and text colors are merged into the text runs of the line,
+//! on top of the syntax highlighting.
+//!
+//! Ranges are [`Anchor`]ed to the text, so they move as it is edited. Text
+//! inserted at the start of a range goes before it and text inserted at its
+//! end goes after it, so a range never grows by typing at its edges. A range
+//! whose text is deleted entirely is dropped.
+
+use crate::anchor::{Anchor, REFRESH_INTERVAL};
+use crate::buffer::TextBuffer;
+use gpui::{px, Rgba, StrikethroughStyle, TextRun, UnderlineStyle};
+use std::ops::Range;
+use std::rc::Rc;
+
+/// How a decorated range is painted. Every part is optional, and a range can
+/// combine several.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DecorationStyle {
+    pub background: Option<Rgba>,
+    pub border: Option<Rgba>,
+    pub underline: Option<UnderlineStyle>,
+    pub strikethrough: Option<StrikethroughStyle>,
+    /// Replaces the color from syntax highlighting
+    pub text_color: Option<Rgba>,
+}
+
+impl DecorationStyle {
+    /// A style that paints nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn background(mut self, color: Rgba) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    pub fn border(mut self, color: Rgba) -> Self {
+        self.border = Some(color);
+        self
+    }
+
+    /// A straight underline
+    pub fn underline(mut self, color: Rgba) -> Self {
+        self.underline = Some(UnderlineStyle {
+            thickness: px(1.0),
+            color: Some(color.into()),
+            wavy: false,
+        });
+        self
+    }
+
+    /// A wavy underline, as for errors
+    pub fn squiggle(mut self, color: Rgba) -> Self {
+        self.underline = Some(UnderlineStyle {
+            thickness: px(1.0),
+            color: Some(color.into()),
+            wavy: true,
+        });
+        self
+    }
+
+    pub fn strikethrough(mut self, color: Rgba) -> Self {
+        self.strikethrough = Some(StrikethroughStyle {
+            thickness: px(1.0),
+            color: Some(color.into()),
+        });
+        self
+    }
+
+    pub fn text_color(mut self, color: Rgba) -> Self {
+        self.text_color = Some(color);
+        self
+    }
+
+    /// Whether the style changes the text runs rather than painting behind
+    /// or around the text
+    pub fn styles_text(&self) -> bool {
+        self.underline.is_some() || self.strikethrough.is_some() || self.text_color.is_some()
+    }
+
+    /// Apply the text parts of the style to `run`
+    pub fn apply(&self, run: &mut TextRun) {
+        if let Some(underline) = self.underline {
+            run.underline = Some(underline);
+        }
+        if let Some(strikethrough) = self.strikethrough {
+            run.strikethrough = Some(strikethrough);
+        }
+        if let Some(color) = self.text_color {
+            run.color = color.into();
+        }
+    }
+}
+
+/// Ranges decorated under one key
+#[derive(Clone, Debug)]
+struct Decoration {
+    key: String,
+    /// Anchored ranges, in order. Anchors keep their order through edits, so
+    /// the ranges stay sorted by start.
+    ranges: Vec<Range<Anchor>>,
+    style: DecorationStyle,
+}
+
+/// Every decoration in the editor, in the order they were added, so later
+/// ones are painted on top. Shared between clones of the editor until one of
+/// them changes.
+#[derive(Clone, Debug, Default)]
+pub struct Decorations {
+    decorations: Rc<Vec<Decoration>>,
+    /// The oldest buffer version of any anchor
+    version: usize,
+}
+
+impl Decorations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decorate `ranges` (char offsets) in `buffer` with `style` under
+    /// `key`. Empty ranges are ignored.
+    pub fn add(
+        &mut self,
+        buffer: &impl TextBuffer,
+        key: &str,
+        ranges: impl IntoIterator<Item = Range<usize>>,
+        style: DecorationStyle,
+    ) {
+        let mut ranges: Vec<Range<usize>> = ranges
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .collect();
+        if ranges.is_empty() {
+            return;
+        }
+        ranges.sort_by_key(|range| range.start);
+        if self.decorations.is_empty() {
+            self.version = buffer.version();
+        }
+        Rc::make_mut(&mut self.decorations).push(Decoration {
+            key: key.to_string(),
+            ranges: ranges
+                .into_iter()
+                .map(|range| buffer.anchor_range(range))
+                .collect(),
+            style,
+        });
+    }
+
+    /// Remove everything decorated under `key`
+    pub fn clear(&mut self, key: &str) {
+        if self
+            .decorations
+            .iter()
+            .any(|decoration| decoration.key == key)
+        {
+            Rc::make_mut(&mut self.decorations).retain(|decoration| decoration.key != key);
+        }
+    }
+
+    pub fn clear_all(&mut self) {
+        self.decorations = Rc::default();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decorations.is_empty()
+    }
+
+    /// The oldest buffer version of any anchor, if there are any
+    pub fn anchor_version(&self) -> Option<usize> {
+        (!self.decorations.is_empty()).then_some(self.version)
+    }
+
+    /// The current ranges decorated under `key`, sorted by start
+    pub fn ranges(&self, buffer: &impl TextBuffer, key: &str) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = self
+            .decorations
+            .iter()
+            .filter(|decoration| decoration.key == key)
+            .flat_map(|decoration| {
+                decoration
+                    .ranges
+                    .iter()
+                    .filter_map(|range| buffer.resolve_anchor_range(range))
+            })
+            .filter(|range| !range.is_empty())
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        ranges
+    }
+
+    /// Decorated ranges that overlap `offsets`, with their styles, in
+    /// painting order
+    pub fn in_range(
+        &self,
+        buffer: &impl TextBuffer,
+        offsets: Range<usize>,
+    ) -> Vec<(Range<usize>, DecorationStyle)> {
+        let mut found = Vec::new();
+        for decoration in self.decorations.iter() {
+            let end = decoration.ranges.partition_point(|range| {
+                buffer
+                    .resolve_anchor(&range.start)
+                    .is_some_and(|start| start < offsets.end)
+            });
+            found.extend(
+                decoration.ranges[..end]
+                    .iter()
+                    .filter_map(|range| buffer.resolve_anchor_range(range))
+                    .filter(|range| !range.is_empty() && range.end > offsets.start)
+                    .map(|range| (range, decoration.style)),
+            );
+        }
+        found
+    }
+
+    /// Bring the anchors up to date with `buffer` once they are
+    /// [`REFRESH_INTERVAL`] edits behind, dropping ranges whose text was
+    /// deleted
+    pub fn refresh(&mut self, buffer: &impl TextBuffer) {
+        if self.decorations.is_empty() || buffer.version() < self.version + REFRESH_INTERVAL {
+            return;
+        }
+        for decoration in Rc::make_mut(&mut self.decorations) {
+            decoration.ranges = decoration
+                .ranges
+                .iter()
+                .filter_map(|range| buffer.resolve_anchor_range(range))
+                .filter(|range| !range.is_empty())
+                .map(|range| buffer.anchor_range(range))
+                .collect();
+        }
+        Rc::make_mut(&mut self.decorations).retain(|decoration| !decoration.ranges.is_empty());
+        self.version = buffer.version();
+    }
+}
+
+/// Split `runs` so the bytes in `range` are changed by `restyle`
+pub fn restyle_runs(
+    runs: Vec<TextRun>,
+    range: Range<usize>,
+    restyle: impl Fn(&mut TextRun),
+) -> Vec<TextRun> {
+    let mut result = Vec::with_capacity(runs.len() + 2);
+    let mut offset = 0;
+    for run in runs {
+        let run_range = offset..offset + run.len;
+        offset = run_range.end;
+
+        // Split points inside this run, in order
+        let mut cuts = vec![run_range.start];
+        for cut in [range.start, range.end] {
+            if cut > run_range.start && cut < run_range.end {
+                cuts.push(cut);
+            }
+        }
+        cuts.push(run_range.end);
+
+        for piece in cuts.windows(2) {
+            let mut piece_run = run.clone();
+            piece_run.len = piece[1] - piece[0];
+            if piece[0] >= range.start && piece[1] <= range.end {
+                restyle(&mut piece_run);
+            }
+            result.push(piece_run);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::GapBuffer;
+    use gpui::{font, rgb};
+
+    fn run(len: usize) -> TextRun {
+        TextRun {
+            len,
+            font: font("Monaco"),
+            color: rgb(0xcccccc).into(),
+            background_color: None,
+            underline: None,
+            strikethrough: None,
+        }
+    }
+
+    #[test]
+    fn test_ranges_move_with_edits() {
+        let mut buffer = GapBuffer::from_text(&"x".repeat(30));
+        let mut decorations = Decorations::new();
+        decorations.add(&buffer, "k", [10..15, 20..25], DecorationStyle::new());
+
+        // Insertions before, at the start of, and at the end of a range
+        buffer.insert(0, "ab");
+        assert_eq!(decorations.ranges(&buffer, "k"), vec![12..17, 22..27]);
+        buffer.insert(12, "a");
+        assert_eq!(decorations.ranges(&buffer, "k"), vec![13..18, 23..28]);
+        buffer.insert(18, "a");
+        assert_eq!(decorations.ranges(&buffer, "k"), vec![13..18, 24..29]);
+
+        // Deleting across the start of a range trims it
+        buffer.delete_range(11, 15);
+        assert_eq!(decorations.ranges(&buffer, "k"), vec![11..14, 20..25]);
+
+        // A range whose text is replaced is dropped
+        buffer.delete_range(19, 26);
+        buffer.insert(19, "a");
+        assert_eq!(decorations.ranges(&buffer, "k"), vec![11..14]);
+    }
+
+    #[test]
+    fn test_refresh() {
+        let mut buffer = GapBuffer::from_text("abc");
+        let mut decorations = Decorations::new();
+        decorations.add(&buffer, "k", [1..2, 2..3], DecorationStyle::new());
+        buffer.delete_range(2, 3);
+        for _ in 0..REFRESH_INTERVAL {
+            buffer.insert(0, "x");
+        }
+
+        decorations.refresh(&buffer);
+        assert_eq!(decorations.version, buffer.version());
+        assert_eq!(decorations.decorations[0].ranges.len(), 1);
+        assert_eq!(
+            decorations.ranges(&buffer, "k"),
+            vec![REFRESH_INTERVAL + 1..REFRESH_INTERVAL + 2]
+        );
+    }
+
+    #[test]
+    fn test_keys() {
+        let buffer = GapBuffer::from_text("0123456789");
+        let mut decorations = Decorations::new();
+        let red = DecorationStyle::new().squiggle(rgb(0xff0000));
+        // The empty range is ignored
+        decorations.add(&buffer, "a", [0..2, 8..8], red);
+        decorations.add(&buffer, "b", [4..6, 1..3], DecorationStyle::new());
+        assert_eq!(decorations.ranges(&buffer, "b"), vec![1..3, 4..6]);
+        assert_eq!(
+            decorations.in_range(&buffer, 2..5),
+            vec![
+                (1..3, DecorationStyle::new()),
+                (4..6, DecorationStyle::new())
+            ]
+        );
+
+        decorations.clear("b");
+        assert_eq!(decorations.in_range(&buffer, 0..10), vec![(0..2, red)]);
+        decorations.clear("a");
+        assert!(decorations.is_empty());
+    }
+
+    #[test]
+    fn test_restyle_runs() {
+        let style = DecorationStyle::new().strikethrough(rgb(0xff0000));
+        let runs = restyle_runs(vec![run(4), run(4)], 2..6, |run| style.apply(run));
+        let lens: Vec<usize> = runs.iter().map(|run| run.len).collect();
+        assert_eq!(lens, vec![2, 2, 2, 2]);
+        let struck: Vec<bool> = runs.iter().map(|run| run.strikethrough.is_some()).collect();
+        assert_eq!(struck, vec![false, true, true, false]);
+    }
+}
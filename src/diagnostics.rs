@@ -0,0 +1,241 @@
+//! Diagnostics: errors, warnings and notes reported on ranges of the text.
+//!
+//! A checker, like a compiler or a language server, sets the whole list of
+//! diagnostics at once with
+//! [`Editor::set_diagnostics`](crate::editor::Editor::set_diagnostics). Their
+//! ranges are anchored to the text, so they stay on it while the user edits
+//! until the checker reports again.
+
+use crate::anchor::{Anchor, REFRESH_INTERVAL};
+use crate::buffer::TextBuffer;
+use crate::editor::CursorPosition;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// How serious a diagnostic is, from most to least
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl DiagnosticSeverity {
+    pub fn label(self) -> &'static str {
+        match self {
+            DiagnosticSeverity::Error => "error",
+            DiagnosticSeverity::Warning => "warning",
+            DiagnosticSeverity::Information => "info",
+            DiagnosticSeverity::Hint => "hint",
+        }
+    }
+}
+
+/// A problem reported on a range of the text
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: Range<CursorPosition>,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    /// What reported the diagnostic, like `rustc`
+    pub source: Option<String>,
+    /// An identifier for the kind of problem, like `E0308`
+    pub code: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(
+        range: Range<CursorPosition>,
+        severity: DiagnosticSeverity,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            range,
+            severity,
+            message: message.into(),
+            source: None,
+            code: None,
+        }
+    }
+
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    pub fn code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// The message followed by the source and code, as shown on hover
+    pub fn display_message(&self) -> String {
+        let origin: Vec<&str> = [self.source.as_deref(), self.code.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        if origin.is_empty() {
+            self.message.clone()
+        } else {
+            format!("{} ({})", self.message, origin.join(" "))
+        }
+    }
+}
+
+/// How many diagnostics there are of each severity
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiagnosticCounts {
+    pub errors: usize,
+    pub warnings: usize,
+    pub information: usize,
+    pub hints: usize,
+}
+
+impl DiagnosticCounts {
+    pub fn add(&mut self, severity: DiagnosticSeverity) {
+        match severity {
+            DiagnosticSeverity::Error => self.errors += 1,
+            DiagnosticSeverity::Warning => self.warnings += 1,
+            DiagnosticSeverity::Information => self.information += 1,
+            DiagnosticSeverity::Hint => self.hints += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.errors + self.warnings + self.information + self.hints
+    }
+}
+
+/// The diagnostics of an editor with their ranges anchored, sorted by start.
+/// Shared between clones of the editor until one of them changes.
+#[derive(Clone, Debug, Default)]
+pub struct DiagnosticSet {
+    entries: Rc<Vec<(Range<Anchor>, Diagnostic)>>,
+    /// The buffer version the anchors were made at
+    version: usize,
+}
+
+impl DiagnosticSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the diagnostics. Their ranges are char offsets in `buffer`.
+    pub fn set(
+        &mut self,
+        buffer: &impl TextBuffer,
+        diagnostics: impl IntoIterator<Item = (Range<usize>, Diagnostic)>,
+    ) {
+        let mut diagnostics: Vec<(Range<usize>, Diagnostic)> = diagnostics.into_iter().collect();
+        diagnostics.sort_by_key(|(range, _)| range.start);
+        let entries: Vec<(Range<Anchor>, Diagnostic)> = diagnostics
+            .into_iter()
+            .map(|(range, diagnostic)| (buffer.anchor_range(range), diagnostic))
+            .collect();
+        self.entries = Rc::new(entries);
+        self.version = buffer.version();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries = Rc::default();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The buffer version the anchors were made at, if there are any
+    pub fn anchor_version(&self) -> Option<usize> {
+        (!self.entries.is_empty()).then_some(self.version)
+    }
+
+    /// Every diagnostic with its current range as char offsets, in order
+    pub fn resolve(&self, buffer: &impl TextBuffer) -> Vec<(Range<usize>, &Diagnostic)> {
+        self.entries
+            .iter()
+            .filter_map(|(range, diagnostic)| {
+                Some((buffer.resolve_anchor_range(range)?, diagnostic))
+            })
+            .collect()
+    }
+
+    pub fn counts(&self) -> DiagnosticCounts {
+        let mut counts = DiagnosticCounts::default();
+        for (_, diagnostic) in self.entries.iter() {
+            counts.add(diagnostic.severity);
+        }
+        counts
+    }
+
+    /// Bring the anchors up to date with `buffer` once they are
+    /// [`REFRESH_INTERVAL`] edits behind, dropping any that no longer resolve
+    pub fn refresh(&mut self, buffer: &impl TextBuffer) {
+        if self.entries.is_empty() || buffer.version() < self.version + REFRESH_INTERVAL {
+            return;
+        }
+        Rc::make_mut(&mut self.entries).retain_mut(|(range, _)| {
+            match buffer.resolve_anchor_range(range) {
+                Some(offsets) => {
+                    *range = buffer.anchor_range(offsets);
+                    true
+                }
+                None => false,
+            }
+        });
+        self.version = buffer.version();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::GapBuffer;
+
+    fn diagnostic(severity: DiagnosticSeverity, message: &str) -> Diagnostic {
+        let start = CursorPosition::new(0, 0);
+        Diagnostic::new(start..start, severity, message)
+    }
+
+    #[test]
+    fn test_set_sorts_and_counts() {
+        let mut buffer = GapBuffer::from_text("let a = b + c;");
+        let mut set = DiagnosticSet::new();
+        set.set(
+            &buffer,
+            [
+                (12..13, diagnostic(DiagnosticSeverity::Error, "c")),
+                (8..9, diagnostic(DiagnosticSeverity::Error, "b")),
+                (4..5, diagnostic(DiagnosticSeverity::Hint, "a")),
+            ],
+        );
+        assert_eq!(
+            set.counts(),
+            DiagnosticCounts {
+                errors: 2,
+                hints: 1,
+                ..Default::default()
+            }
+        );
+
+        buffer.insert(0, "  ");
+        let resolved: Vec<(Range<usize>, &str)> = set
+            .resolve(&buffer)
+            .into_iter()
+            .map(|(range, diagnostic)| (range, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(resolved, vec![(6..7, "a"), (10..11, "b"), (14..15, "c")]);
+    }
+
+    #[test]
+    fn test_display_message() {
+        let plain = diagnostic(DiagnosticSeverity::Warning, "unused variable");
+        assert_eq!(plain.display_message(), "unused variable");
+        let full = plain.clone().source("rustc").code("W0001");
+        assert_eq!(full.display_message(), "unused variable (rustc W0001)");
+        assert_eq!(
+            plain.source("clippy").display_message(),
+            "unused variable (clippy)"
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Maps buffer rows to the visual rows they are displayed on.
+//!
+//! With soft wrap enabled a buffer line can take up several visual rows, so
+//! scrolling, painting and vertical movement work in display rows rather than
+//! buffer rows. `EditorElement` lays lines out with gpui's wrapped text layout
+//! and records where each one wraps and how wide its characters are, and the
+//! editor uses that to move between display rows and to keep the caret's goal
+//! column in pixels.
+//!
+//! Rows that haven't been laid out yet count as a single display row, and their
+//! characters are assumed to be [`DisplayMap::char_width`] wide. Rows hidden by
+//! a fold take up no display rows at all.
+
+use crate::coordinates;
+use crate::editor::CursorPosition;
+use gpui::{px, Pixels, SharedString, WrappedLine};
+use std::cell::{Ref, RefCell};
+use std::ops::Range;
+
+/// Where a laid out line wraps and where its characters are
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LineLayout {
+    /// Char offset where each visual row after the first starts
+    wrap_starts: Vec<usize>,
+    /// x of every char boundary, from the start of the unwrapped line
+    xs: Vec<Pixels>,
+}
+
+impl LineLayout {
+    #[cfg(test)]
+    pub fn new(wrap_starts: Vec<usize>, xs: Vec<Pixels>) -> Self {
+        debug_assert!(!xs.is_empty());
+        Self { wrap_starts, xs }
+    }
+
+    /// Extract the layout of a line shaped with `TextSystem::shape_text`
+    pub fn from_wrapped_line(line: &WrappedLine) -> Self {
+        let layout = &line.unwrapped_layout;
+        let glyph_index = |boundary: &gpui::WrapBoundary| {
+            layout.runs[boundary.run_ix].glyphs[boundary.glyph_ix].index
+        };
+        let wrap_starts = line
+            .wrap_boundaries()
+            .iter()
+            .map(|boundary| coordinates::byte_to_char(&line.text, glyph_index(boundary)))
+            .collect();
+
+        // A char without a glyph of its own, like a combining mark, sits where
+        // the next glyph starts
+        let mut glyphs = layout
+            .runs
+            .iter()
+            .flat_map(|run| &run.glyphs)
+            .map(|glyph| (glyph.index, glyph.position.x))
+            .peekable();
+        let mut xs: Vec<Pixels> = line
+            .text
+            .char_indices()
+            .map(|(index, _)| {
+                while glyphs
+                    .next_if(|&(glyph_index, _)| glyph_index < index)
+                    .is_some()
+                {}
+                glyphs.peek().map_or(layout.width, |&(_, x)| x)
+            })
+            .collect();
+        xs.push(layout.width);
+
+        Self { wrap_starts, xs }
+    }
+
+    /// Number of visual rows the line takes up
+    pub fn row_count(&self) -> usize {
+        self.wrap_starts.len() + 1
+    }
+
+    fn len(&self) -> usize {
+        self.xs.len() - 1
+    }
+
+    /// The visual row `col` is displayed on. A column where the line wraps
+    /// belongs to the row it starts.
+    pub fn wrap_row(&self, col: usize) -> usize {
+        self.wrap_starts.partition_point(|&start| start <= col)
+    }
+
+    fn row_start(&self, wrap_row: usize) -> usize {
+        match wrap_row.checked_sub(1) {
+            Some(index) => self.wrap_starts[index],
+            None => 0,
+        }
+    }
+
+    /// The chars displayed on `wrap_row`
+    pub fn row_range(&self, wrap_row: usize) -> Range<usize> {
+        let end = self
+            .wrap_starts
+            .get(wrap_row)
+            .copied()
+            .unwrap_or(self.len());
+        self.row_start(wrap_row)..end
+    }
+
+    /// The columns a caret can be at on `wrap_row`
+    fn row_cols(&self, wrap_row: usize) -> (usize, usize) {
+        let range = self.row_range(wrap_row);
+        let end = if wrap_row < self.wrap_starts.len() {
+            // The caret at the next row's start is drawn on that row
+            range.end.saturating_sub(1).max(range.start)
+        } else {
+            range.end
+        };
+        (range.start, end)
+    }
+
+    /// x of `col` from the start of the visual row it's on
+    pub fn x_for_col(&self, col: usize) -> Pixels {
+        let col = col.min(self.len());
+        self.xs[col] - self.xs[self.row_start(self.wrap_row(col))]
+    }
+
+    /// x of `col` from the start of `wrap_row`, which may be a column where the
+    /// row ends rather than one it contains
+    pub fn x_in_row(&self, wrap_row: usize, col: usize) -> Pixels {
+        self.xs[col.min(self.len())] - self.xs[self.row_start(wrap_row)]
+    }
+
+    /// The column on `wrap_row` closest to `x` from the start of the row
+    pub fn col_for_x(&self, wrap_row: usize, x: Pixels) -> usize {
+        let (start, end) = self.row_cols(wrap_row);
+        let x = x + self.xs[start];
+        let next = start + self.xs[start..=end].partition_point(|&boundary| boundary < x);
+        if next > end {
+            return end;
+        }
+        if next > start && x - self.xs[next - 1] < self.xs[next] - x {
+            next - 1
+        } else {
+            next
+        }
+    }
+}
+
+/// What line layouts depend on, apart from the text
+#[derive(Clone, Debug, PartialEq)]
+struct LayoutKey {
+    font_family: SharedString,
+    font_size: Pixels,
+    wrap_width: Option<Pixels>,
+}
+
+/// Display rows of the buffer: where its lines wrap and which rows folds hide
+#[derive(Clone)]
+pub(crate) struct DisplayMap {
+    key: Option<LayoutKey>,
+    char_width: Pixels,
+    /// Layout of each buffer row, if it has been laid out
+    lines: Vec<Option<LineLayout>>,
+    /// Rows hidden by folds, sorted and disjoint
+    hidden: Vec<Range<usize>>,
+    /// First display row of each row up to [`Self::len`], followed by the
+    /// display rows they take up in total. Rebuilt on demand when `None`.
+    row_starts: RefCell<Option<Vec<usize>>>,
+}
+
+impl Default for DisplayMap {
+    fn default() -> Self {
+        Self {
+            key: None,
+            // A guess until the element measures the font
+            char_width: px(8.0),
+            lines: Vec::new(),
+            hidden: Vec::new(),
+            row_starts: RefCell::new(None),
+        }
+    }
+}
+
+impl DisplayMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set what lines are laid out with. Forgets every layout if anything
+    /// changed, and returns true in that case.
+    pub fn set_text_layout(
+        &mut self,
+        font_family: SharedString,
+        font_size: Pixels,
+        wrap_width: Option<Pixels>,
+        char_width: Pixels,
+    ) -> bool {
+        let key = LayoutKey {
+            font_family,
+            font_size,
+            wrap_width,
+        };
+        self.char_width = char_width;
+        if self.key.as_ref() == Some(&key) {
+            return false;
+        }
+        self.key = Some(key);
+        self.clear();
+        true
+    }
+
+    pub fn wrap_width(&self) -> Option<Pixels> {
+        self.key.as_ref()?.wrap_width
+    }
+
+    /// Width assumed for the characters of rows that haven't been laid out
+    pub fn char_width(&self) -> Pixels {
+        self.char_width
+    }
+
+    /// Forget every layout
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.row_starts.take();
+    }
+
+    /// Hide `hidden` rows, sorted and disjoint, replacing any hidden before
+    pub fn set_folds(&mut self, hidden: Vec<Range<usize>>) {
+        if self.hidden != hidden {
+            self.hidden = hidden;
+            self.row_starts.take();
+        }
+    }
+
+    pub fn is_hidden(&self, row: usize) -> bool {
+        let index = self.hidden.partition_point(|rows| rows.end <= row);
+        self.hidden.get(index).is_some_and(|rows| rows.start <= row)
+    }
+
+    /// Account for `old_rows` rows starting at `start_row` being replaced by
+    /// `new_rows` rows. The edited rows need laying out again.
+    pub fn edit_lines(&mut self, start_row: usize, old_rows: usize, new_rows: usize) {
+        let len = self.lines.len();
+        let removed = start_row.min(len)..(start_row + old_rows.max(1)).min(len);
+        if removed.is_empty() && start_row >= len {
+            return;
+        }
+        self.lines.splice(
+            removed,
+            std::iter::repeat_with(|| None).take(new_rows.max(1)),
+        );
+        self.row_starts.take();
+    }
+
+    pub fn is_laid_out(&self, row: usize) -> bool {
+        matches!(self.lines.get(row), Some(Some(_)))
+    }
+
+    pub fn insert(&mut self, row: usize, layout: LineLayout) {
+        if self.lines.len() <= row {
+            self.lines.resize_with(row + 1, || None);
+        }
+        let old_rows = self.lines[row].as_ref().map_or(1, LineLayout::row_count);
+        if old_rows != layout.row_count() {
+            self.row_starts.take();
+        }
+        self.lines[row] = Some(layout);
+    }
+
+    pub fn layout(&self, row: usize) -> Option<&LineLayout> {
+        self.lines.get(row)?.as_ref()
+    }
+
+    /// Number of display rows for a buffer with `line_count` lines
+    pub fn display_row_count(&self, line_count: usize) -> usize {
+        self.display_row_start(line_count)
+    }
+
+    /// Number of display rows buffer `row` takes up, 0 if it is hidden
+    pub fn row_count(&self, row: usize) -> usize {
+        if self.is_hidden(row) {
+            return 0;
+        }
+        match self.lines.get(row) {
+            Some(Some(layout)) => layout.row_count(),
+            _ => 1,
+        }
+    }
+
+    /// The display row `position` is shown on
+    pub fn display_row(&self, position: CursorPosition) -> usize {
+        let wrap_row = match self.lines.get(position.row) {
+            Some(Some(layout)) => layout.wrap_row(position.col),
+            _ => 0,
+        };
+        self.display_row_start(position.row) + wrap_row
+    }
+
+    /// The buffer row shown on `display_row`, and which of its visual rows
+    /// that is
+    pub fn buffer_row(&self, display_row: usize) -> (usize, usize) {
+        let len = self.len();
+        let starts = self.row_starts();
+        if display_row >= starts[len] {
+            return (len + display_row - starts[len], 0);
+        }
+        // Hidden rows start where the next row does, so the last row starting
+        // at or before `display_row` is the visible one
+        let row = starts.partition_point(|&start| start <= display_row) - 1;
+        (row, display_row - starts[row])
+    }
+
+    /// x of `position` from the start of its visual row
+    pub fn x_for_position(&self, position: CursorPosition) -> Pixels {
+        match self.lines.get(position.row) {
+            Some(Some(layout)) => layout.x_for_col(position.col),
+            _ => self.char_width * position.col as f32,
+        }
+    }
+
+    /// The column of buffer `row` closest to `x` on its visual row `wrap_row`.
+    /// `line_len` is the row's length in chars.
+    pub fn col_for_x(&self, row: usize, wrap_row: usize, x: Pixels, line_len: usize) -> usize {
+        match self.lines.get(row) {
+            Some(Some(layout)) => layout.col_for_x(wrap_row, x),
+            _ => ((x / self.char_width).round().max(0.0) as usize).min(line_len),
+        }
+    }
+
+    /// Rows past this one are neither laid out nor hidden
+    fn len(&self) -> usize {
+        let hidden_end = self.hidden.last().map_or(0, |rows| rows.end);
+        self.lines.len().max(hidden_end)
+    }
+
+    fn row_starts(&self) -> Ref<'_, [usize]> {
+        if self.row_starts.borrow().is_none() {
+            let len = self.len();
+            let mut starts = Vec::with_capacity(len + 1);
+            let mut display_row = 0;
+            starts.push(0);
+            for row in 0..len {
+                display_row += self.row_count(row);
+                starts.push(display_row);
+            }
+            self.row_starts.replace(Some(starts));
+        }
+        Ref::map(self.row_starts.borrow(), |starts| {
+            starts.as_deref().unwrap_or_default()
+        })
+    }
+
+    /// First display row of buffer `row`
+    fn display_row_start(&self, row: usize) -> usize {
+        let len = self.len();
+        let starts = self.row_starts();
+        match starts.get(row) {
+            Some(&start) => start,
+            None => starts[len] + (row - len),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A line of `len` chars, 10px each, wrapping at `wrap_starts`
+    fn monospace(len: usize, wrap_starts: Vec<usize>) -> LineLayout {
+        LineLayout::new(
+            wrap_starts,
+            (0..=len).map(|col| px(10.0 * col as f32)).collect(),
+        )
+    }
+
+    #[test]
+    fn test_line_layout() {
+        // "hello world foo" wrapped as "hello " / "world " / "foo"
+        let layout = monospace(15, vec![6, 12]);
+        assert_eq!(layout.row_count(), 3);
+        assert_eq!(layout.wrap_row(5), 0);
+        assert_eq!(layout.wrap_row(6), 1);
+        assert_eq!(layout.x_for_col(8), px(20.0));
+        assert_eq!(layout.x_for_col(15), px(30.0));
+
+        assert_eq!(layout.col_for_x(1, px(24.0)), 8);
+        assert_eq!(layout.col_for_x(1, px(26.0)), 9);
+        // Past the end of a wrapped row, the caret stays on that row
+        assert_eq!(layout.col_for_x(0, px(500.0)), 5);
+        assert_eq!(layout.col_for_x(2, px(500.0)), 15);
+    }
+
+    #[test]
+    fn test_display_rows() {
+        let mut map = DisplayMap::new();
+        map.insert(1, monospace(15, vec![6, 12]));
+        map.insert(2, monospace(8, vec![4]));
+
+        // Row 0 and rows past the layouts take a single display row
+        assert_eq!(map.display_row_count(5), 8);
+        assert_eq!(map.display_row(CursorPosition::new(1, 7)), 2);
+        assert_eq!(map.display_row(CursorPosition::new(2, 0)), 4);
+        assert_eq!(map.display_row(CursorPosition::new(4, 3)), 7);
+        assert_eq!(map.buffer_row(0), (0, 0));
+        assert_eq!(map.buffer_row(3), (1, 2));
+        assert_eq!(map.buffer_row(5), (2, 1));
+        assert_eq!(map.buffer_row(7), (4, 0));
+    }
+
+    #[test]
+    fn test_hidden_rows() {
+        let mut map = DisplayMap::new();
+        map.insert(0, monospace(8, vec![4]));
+        map.set_folds(vec![1..3, 5..7]);
+
+        assert_eq!(map.display_row_count(10), 7);
+        assert!(map.is_hidden(2));
+        assert!(!map.is_hidden(3));
+        assert_eq!(map.row_count(1), 0);
+        assert_eq!(map.display_row(CursorPosition::new(3, 0)), 2);
+        assert_eq!(map.display_row(CursorPosition::new(7, 0)), 4);
+        assert_eq!(map.buffer_row(2), (3, 0));
+        assert_eq!(map.buffer_row(4), (7, 0));
+        assert_eq!(map.buffer_row(6), (9, 0));
+
+        // Folds stay when the layouts are forgotten
+        map.clear();
+        assert_eq!(map.display_row_count(10), 6);
+    }
+
+    #[test]
+    fn test_edit_lines_forgets_edited_rows() {
+        let mut map = DisplayMap::new();
+        for row in 0..3 {
+            map.insert(row, monospace(8, vec![4]));
+        }
+
+        // Row 1 is split in two
+        map.edit_lines(1, 1, 2);
+        assert!(map.is_laid_out(0));
+        assert!(!map.is_laid_out(1));
+        assert!(!map.is_laid_out(2));
+        assert!(map.is_laid_out(3));
+        assert_eq!(map.display_row_count(4), 6);
+
+        assert!(map.set_text_layout("Monaco".into(), px(14.0), None, px(8.0)));
+        assert!(!map.set_text_layout("Monaco".into(), px(14.0), None, px(8.0)));
+        assert!(!map.is_laid_out(0));
+    }
+}
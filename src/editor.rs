@@ -1,6 +1,32 @@
+use crate::anchor::{Anchor, Bias, MAX_ANCHOR_AGE};
+use crate::autoclose;
 use crate::buffer::{GapBuffer, TextBuffer};
-use crate::syntax_highlighter::SyntaxHighlighter;
+use crate::comment::{self, CommentTokens};
+use crate::completion::{self, CompletionItem, CompletionMenu, CompletionProvider, Completions};
+use crate::coordinates;
+use crate::decorations::{DecorationStyle, Decorations};
+use crate::diagnostics::{Diagnostic, DiagnosticCounts, DiagnosticSet, DiagnosticSeverity};
+use crate::display_map::DisplayMap;
+use crate::file::{self, FileError, FileFormat};
+use crate::fold_map::{self, FoldMap};
+use crate::highlight_cache::HighlightCache;
+use crate::history::{Edit, History};
+use crate::indent;
+use crate::line_ops::{self, SortOptions};
+use crate::movement;
+use crate::position_map::PositionMap;
+use crate::scroll::ScrollState;
+use crate::search::{self, SearchQuery, Searcher};
+use crate::selections::{SelectionRange, Selections};
+use crate::syntax_highlighter::{self, HighlightedLine, SyntaxHighlighter};
+use crate::view::EditorView;
 use gpui::*;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct EditorConfig {
@@ -13,6 +39,23 @@ pub struct EditorConfig {
     pub gutter_bg_color: Rgba,
     pub editor_bg_color: Rgba,
     pub active_line_bg_color: Rgba,
+    pub search_match_bg_color: Rgba,
+    pub matching_bracket_border_color: Rgba,
+    pub error_color: Rgba,
+    pub warning_color: Rgba,
+    pub information_color: Rgba,
+    pub hint_color: Rgba,
+    pub completion_selected_bg_color: Rgba,
+    /// The chars of completion labels that match what was typed
+    pub completion_match_color: Rgba,
+    pub show_line_numbers: bool,
+    /// Show chevrons in the gutter for folding and unfolding
+    pub show_fold_indicators: bool,
+    pub wrap_mode: WrapMode,
+    /// Columns per indentation level and between tab stops
+    pub tab_size: usize,
+    /// Indent with tab characters rather than spaces
+    pub hard_tabs: bool,
     pub font_family: SharedString,
 }
 
@@ -28,12 +71,50 @@ impl Default for EditorConfig {
             gutter_bg_color: rgb(0x252525),
             editor_bg_color: rgb(0x1e1e1e),
             active_line_bg_color: rgb(0x2a2a2a),
+            search_match_bg_color: rgba(0x6a4a1aff),
+            matching_bracket_border_color: rgba(0x888888ff),
+            error_color: rgb(0xf14c4c),
+            warning_color: rgb(0xcca700),
+            information_color: rgb(0x3794ff),
+            hint_color: rgb(0x8a8a8a),
+            completion_selected_bg_color: rgb(0x04395e),
+            completion_match_color: rgb(0x2aaaff),
+            show_line_numbers: true,
+            show_fold_indicators: true,
+            wrap_mode: WrapMode::None,
+            tab_size: 4,
+            hard_tabs: false,
             font_family: "Monaco".into(),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+impl EditorConfig {
+    /// The color diagnostics of `severity` are drawn in
+    pub fn diagnostic_color(&self, severity: DiagnosticSeverity) -> Rgba {
+        match severity {
+            DiagnosticSeverity::Error => self.error_color,
+            DiagnosticSeverity::Warning => self.warning_color,
+            DiagnosticSeverity::Information => self.information_color,
+            DiagnosticSeverity::Hint => self.hint_color,
+        }
+    }
+}
+
+/// How lines longer than the editor is wide are displayed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Lines stay on a single row and run past the right edge
+    #[default]
+    None,
+    /// Lines wrap at the right edge of the editor
+    EditorWidth,
+    /// Lines wrap after the given number of columns, measured in the width of
+    /// the font's `m`
+    Column(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CursorPosition {
     pub row: usize,
     pub col: usize,
@@ -45,40 +126,178 @@ impl CursorPosition {
     }
 }
 
+/// A change to the text: the chars in `range` replaced with `text`. The
+/// range is in the text as it was just before the change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextChange {
+    pub range: Range<CursorPosition>,
+    pub text: String,
+}
+
+/// The unit a mouse drag extends the selection by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SelectMode {
+    Character,
+    Word,
+    Line,
+}
+
+/// A selection being made with the mouse button held down
+#[derive(Clone, Copy, Debug)]
+struct PendingMouseSelection {
+    mode: SelectMode,
+    /// The range selected by the initial click: a caret, word or line
+    origin: SelectionRange,
+}
+
+/// (start, end) positions of search matches
+type SearchMatches = Rc<[(CursorPosition, CursorPosition)]>;
+
+/// (open, close) positions of a bracket pair
+type BracketPair = (CursorPosition, CursorPosition);
+
+/// A bracket pair lookup: highlighter version, caret and the pair found
+type BracketLookup = (usize, CursorPosition, Option<BracketPair>);
+
+/// The decoration key diagnostics are underlined under
+const DIAGNOSTICS_KEY: &str = "diagnostics";
+
+/// How many rows to look through for a matching bracket
+const MAX_BRACKET_SCAN_ROWS: usize = 1000;
+
+/// A replacement of whole rows, planned by a line command
+struct LineEdit {
+    /// The rows to replace
+    rows: Range<usize>,
+    /// Their replacement. Empty to delete the rows along with a line break.
+    lines: Vec<String>,
+    /// Where a position in the rows the command acted on ends up, as if
+    /// nothing above `rows` changed
+    map: Box<dyn Fn(CursorPosition) -> CursorPosition>,
+}
+
+impl LineEdit {
+    /// How many rows the edit adds, or takes away if negative
+    fn row_delta(&self) -> isize {
+        self.lines.len() as isize - self.rows.len() as isize
+    }
+}
+
+/// The active search
 #[derive(Clone)]
-pub struct Editor {
+struct SearchState {
+    query: SearchQuery,
+    searcher: Searcher,
+    /// Matches in the current text, computed on demand
+    matches: RefCell<Option<SearchMatches>>,
+}
+
+/// The core editor model, generic over the text storage.
+///
+/// `Editor` defaults to a [`GapBuffer`], which is ideal for small inputs. Use
+/// [`Editor::with_buffer`] with a [`Rope`](crate::rope::Rope) for large
+/// documents where line lookups need to stay O(log n).
+#[derive(Clone)]
+pub struct Editor<B = GapBuffer> {
     id: ElementId,
-    buffer: GapBuffer,
+    buffer: B,
     config: EditorConfig,
-    cursor_position: CursorPosition,
-    goal_column: Option<usize>,
-    selection_anchor: Option<CursorPosition>,
+    selections: Selections,
+    scroll: ScrollState,
+    position_map: PositionMap,
+    /// Where lines wrap, filled in by the element as it lays them out
+    display_map: DisplayMap,
+    fold_map: FoldMap,
+    pending_mouse_selection: Option<PendingMouseSelection>,
+    /// Text being composed by an input method, in chars
+    marked_range: Option<Range<usize>>,
     syntax_highlighter: SyntaxHighlighter,
+    /// Styled runs for painting, filled in the background
+    highlights: HighlightCache,
     language: String,
     current_theme: String,
+    history: History,
+    /// The file the text was opened from or last saved to
+    path: Option<PathBuf>,
+    file_format: FileFormat,
+    /// The history version when the text was last saved, if it matches a
+    /// saved file at all
+    saved_version: Option<usize>,
+    search: Option<SearchState>,
+    /// The last lookup of the bracket pair at the primary caret, so the
+    /// element doesn't repeat it every frame
+    matching_brackets: RefCell<Option<BracketLookup>>,
+    /// Highlights, underlines and other styles painted over ranges of text
+    decorations: Decorations,
+    diagnostics: DiagnosticSet,
+    /// Where the mouse rests over the text, for showing diagnostics there
+    hovered_position: Option<CursorPosition>,
+    /// Changes since they were last taken, if they are being tracked
+    changes: Option<Vec<TextChange>>,
+    completions: Completions,
+    completion_provider: Option<Rc<dyn CompletionProvider>>,
 }
 
 impl Editor {
     pub fn new(id: impl Into<ElementId>, lines: Vec<String>) -> Self {
+        Self::with_buffer(id, GapBuffer::from_lines(lines))
+    }
+
+    /// Open the file at `path`, keeping its encoding and line endings for
+    /// saving. The language is detected from the file extension.
+    pub fn open(id: impl Into<ElementId>, path: impl AsRef<Path>) -> Result<Self, FileError> {
+        let path = path.as_ref();
+        let (text, file_format) = file::read(path)?;
+        let mut editor = Self::with_buffer(id, GapBuffer::from_text(&text));
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if let Some(language) = editor.syntax_highlighter.detect_language(&text, extension) {
+            editor.language = language;
+        }
+        editor.path = Some(path.to_path_buf());
+        editor.file_format = file_format;
+        Ok(editor)
+    }
+}
+
+impl<B: TextBuffer> Editor<B> {
+    /// Create an editor backed by an existing buffer
+    pub fn with_buffer(id: impl Into<ElementId>, buffer: B) -> Self {
         let id = id.into();
         let syntax_highlighter = SyntaxHighlighter::new();
 
         // Auto-detect language from content
-        let full_text = lines.join("\n");
+        let full_text = buffer.text();
         let language = syntax_highlighter
             .detect_language(&full_text, Some("rs"))
             .unwrap_or_else(|| "Rust".to_string());
 
         Self {
             id,
-            buffer: GapBuffer::from_lines(lines),
+            buffer,
             config: EditorConfig::default(),
-            cursor_position: CursorPosition { row: 0, col: 0 },
-            goal_column: None,
-            selection_anchor: None,
+            selections: Selections::new(CursorPosition::new(0, 0)),
+            scroll: ScrollState::new(),
+            position_map: PositionMap::new(),
+            display_map: DisplayMap::new(),
+            fold_map: FoldMap::new(),
+            pending_mouse_selection: None,
+            marked_range: None,
             syntax_highlighter,
+            highlights: HighlightCache::new(),
             language,
             current_theme: String::new(),
+            history: History::new(),
+            path: None,
+            file_format: FileFormat::default(),
+            saved_version: Some(0),
+            search: None,
+            matching_brackets: RefCell::default(),
+            decorations: Decorations::new(),
+            diagnostics: DiagnosticSet::new(),
+            hovered_position: None,
+            changes: None,
+            completions: Completions::new(),
+            completion_provider: None,
         }
     }
 
@@ -98,31 +317,52 @@ impl Editor {
         self.config = config;
     }
 
+    /// Position of the primary caret
     pub fn cursor_position(&self) -> CursorPosition {
-        self.cursor_position
+        self.selections.primary().head
     }
 
+    /// Collapse all selections into a single caret at `position`
     pub fn set_cursor_position(&mut self, position: CursorPosition) {
-        self.cursor_position = position;
-        // Reset goal column when cursor position is explicitly set
-        self.goal_column = None;
+        self.selections.set_single(SelectionRange::caret(position));
+        self.marked_range = None;
+        self.request_autoscroll();
     }
 
     pub fn get_cursor_position(&self) -> CursorPosition {
-        self.cursor_position
+        self.cursor_position()
+    }
+
+    pub fn selections(&self) -> &Selections {
+        &self.selections
+    }
+
+    pub fn set_selections(&mut self, selections: Selections) {
+        self.selections = selections;
+        self.marked_range = None;
+        self.request_autoscroll();
     }
 
+    /// Collapse every selection to its caret, keeping all cursors
     pub fn clear_selection(&mut self) {
-        self.selection_anchor = None;
         // Reset goal column when clearing selection
-        self.goal_column = None;
+        self.selections
+            .update(|selection| *selection = SelectionRange::caret(selection.head));
+    }
+
+    /// Drop every cursor except the primary one
+    pub fn clear_extra_cursors(&mut self) {
+        self.selections.retain_primary();
     }
 
-    pub fn get_buffer(&self) -> &GapBuffer {
+    pub fn get_buffer(&self) -> &B {
         &self.buffer
     }
 
-    pub fn get_buffer_mut(&mut self) -> &mut GapBuffer {
+    /// Get mutable access to the buffer.
+    ///
+    /// Changes made directly through the buffer bypass the undo history.
+    pub fn get_buffer_mut(&mut self) -> &mut B {
         &mut self.buffer
     }
 
@@ -132,6 +372,7 @@ impl Editor {
 
     pub fn set_language(&mut self, language: String) {
         self.language = language;
+        self.highlights.invalidate();
     }
 
     pub fn current_theme(&self) -> &str {
@@ -141,6 +382,7 @@ impl Editor {
     pub fn set_theme(&mut self, theme: &str) {
         self.current_theme = theme.to_string();
         self.syntax_highlighter.set_theme(theme);
+        self.highlights.invalidate();
         // Update colors from theme
         self.config.editor_bg_color = self.syntax_highlighter.get_theme_background().into();
         self.config.text_color = self.syntax_highlighter.get_theme_foreground().into();
@@ -150,40 +392,285 @@ impl Editor {
     }
 
     pub fn update_buffer(&mut self, lines: Vec<String>) {
-        self.buffer = GapBuffer::from_lines(lines);
+        let len = self.buffer.len();
+        let text = lines.join("\n");
+        self.record_text_replaced(&text);
+        self.buffer.delete_range(0, len);
+        self.buffer.insert(0, &text);
+        self.selections = Selections::new(CursorPosition::new(0, 0));
+        self.marked_range = None;
+        self.history.clear();
+        self.text_replaced();
         // Reset highlighting state to force complete re-highlighting
         self.syntax_highlighter.reset_state();
+        self.highlights.clear();
+        self.display_map.clear();
+        self.fold_map.clear();
+        self.display_map.set_folds(Vec::new());
+        if let Some(search) = &self.search {
+            search.matches.replace(None);
+        }
+    }
+
+    /// Replace the underlying buffer
+    pub fn set_buffer(&mut self, buffer: B) {
+        self.record_text_replaced(&buffer.text());
+        self.buffer = buffer;
+        self.selections = Selections::new(CursorPosition::new(0, 0));
+        self.marked_range = None;
+        self.history.clear();
+        self.text_replaced();
+        self.syntax_highlighter.reset_state();
+        self.highlights.clear();
+        self.display_map.clear();
+        self.fold_map.clear();
+        self.display_map.set_folds(Vec::new());
+        if let Some(search) = &self.search {
+            search.matches.replace(None);
+        }
+    }
+
+    /// The text no longer has the lines it was opened or saved with
+    fn text_replaced(&mut self) {
+        self.file_format.reset_lines();
+        self.saved_version = None;
+        self.decorations.clear_all();
+        self.diagnostics.clear();
+        self.completions.close();
     }
 
     /// Update buffer content at a specific line (for future incremental updates)
     pub fn update_line(&mut self, line_index: usize, new_content: String) {
-        // Delete the old line and insert the new content
+        // Replace the old line with the new content
         if line_index < self.buffer.line_count() {
-            // Get the current line to find its length
             let line_len = self.buffer.line_len(line_index);
-
-            // Delete the entire line content
             let start_pos = self.buffer.cursor_to_position(line_index, 0);
             let end_pos = self.buffer.cursor_to_position(line_index, line_len);
-            self.buffer.delete_range(start_pos, end_pos);
 
-            // Insert the new content
-            self.buffer.insert_at(line_index, 0, &new_content);
+            self.transact(|editor| editor.edit(start_pos, end_pos, &new_content));
+        }
+    }
+
+    /// Record the whole text being replaced with `text`, if changes are
+    /// being tracked
+    fn record_text_replaced(&mut self, text: &str) {
+        if self.changes.is_some() {
+            let end = self.offset_range_to_positions(0..self.buffer.len()).end;
+            let change = TextChange {
+                range: CursorPosition::new(0, 0)..end,
+                text: text.to_string(),
+            };
+            self.changes.get_or_insert_default().push(change);
+        }
+    }
+
+    // Change tracking methods
+
+    /// Start recording every change to the text for [`Self::take_changes`],
+    /// as for keeping a language server in sync
+    pub fn track_changes(&mut self) {
+        self.changes.get_or_insert_default();
+    }
+
+    /// Stop recording changes, dropping any not taken yet
+    pub fn stop_tracking_changes(&mut self) {
+        self.changes = None;
+    }
+
+    /// The changes since the last call, in the order they were made. Empty
+    /// unless [`Self::track_changes`] was called.
+    pub fn take_changes(&mut self) -> Vec<TextChange> {
+        self.changes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // File methods
+
+    /// The file the text was opened from or last saved to
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The encoding and line endings the text is saved with
+    pub fn file_format(&self) -> &FileFormat {
+        &self.file_format
+    }
+
+    pub fn set_file_format(&mut self, file_format: FileFormat) {
+        self.file_format = file_format;
+    }
+
+    /// Whether the text changed since it was opened or last saved. Undoing
+    /// back to the saved text makes the editor clean again.
+    pub fn is_dirty(&self) -> bool {
+        self.saved_version != Some(self.history.version())
+    }
+
+    /// Save the text to the file it was opened from or last saved to
+    pub fn save(&mut self) -> Result<(), FileError> {
+        let path = self.path.clone().ok_or(FileError::NoPath)?;
+        self.save_as(path)
+    }
+
+    /// Save the text to `path`, which becomes the file [`Self::save`] saves to
+    pub fn save_as(&mut self, path: impl AsRef<Path>) -> Result<(), FileError> {
+        let path = path.as_ref();
+        file::write(path, &self.buffer.text(), &self.file_format)?;
+        self.path = Some(path.to_path_buf());
+        // Typing after saving must not join the saved version
+        self.history.finalize_last_transaction();
+        self.saved_version = Some(self.history.version());
+        Ok(())
+    }
+
+    // History methods
+
+    /// Run `f` as a single undoable transaction.
+    ///
+    /// Every edit made inside `f` is undone and redone together, and the
+    /// cursor is restored to where it was before `f` ran.
+    pub fn transact<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.start_transaction();
+        let result = f(self);
+        self.end_transaction();
+        result
+    }
+
+    /// Open a transaction manually. Must be paired with [`Self::end_transaction`].
+    pub fn start_transaction(&mut self) {
+        self.history.start_transaction(&self.selections);
+    }
+
+    pub fn end_transaction(&mut self) {
+        self.history.end_transaction(&self.selections);
+        self.forget_old_edits();
+    }
+
+    /// Stop the next typed characters from joining the last undo step
+    pub fn finalize_last_transaction(&mut self) {
+        self.history.finalize_last_transaction();
+    }
+
+    /// Set how long a pause in typing may be before a new undo step starts
+    pub fn set_undo_group_interval(&mut self, interval: Duration) {
+        self.history.set_group_interval(interval);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Revert the most recent transaction. Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(transaction) = self.history.pop_undo() else {
+            return false;
+        };
+
+        for edit in transaction.edits.iter().rev() {
+            self.replace_range(
+                edit.position,
+                edit.position + edit.inserted_len(),
+                &edit.deleted,
+            );
+        }
+        self.selections = transaction.selection_before.clone();
+        self.marked_range = None;
+        self.request_autoscroll();
+        self.history.push_redo(transaction);
+        true
+    }
+
+    /// Reapply the most recently undone transaction. Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(transaction) = self.history.pop_redo() else {
+            return false;
+        };
+
+        for edit in &transaction.edits {
+            self.replace_range(
+                edit.position,
+                edit.position + edit.deleted.chars().count(),
+                &edit.inserted,
+            );
+        }
+        self.selections = transaction.selection_after.clone();
+        self.marked_range = None;
+        self.request_autoscroll();
+        self.history.push_undo(transaction);
+        true
+    }
+
+    /// Replace the chars in `start..end` with `text`, recording the change in the
+    /// open transaction.
+    fn edit(&mut self, start: usize, end: usize, text: &str) {
+        let deleted = self.replace_range(start, end, text);
+        if deleted.is_empty() && text.is_empty() {
+            return;
+        }
+        self.history.push_edit(Edit {
+            position: start,
+            deleted,
+            inserted: text.to_string(),
+        });
+    }
+
+    /// Replace the chars in `start..end` with `text` and return the removed text
+    fn replace_range(&mut self, start: usize, end: usize, text: &str) -> String {
+        if self.changes.is_some() && (start < end || !text.is_empty()) {
+            let range = self.offset_range_to_positions(start..end.min(self.buffer.len()));
+            let change = TextChange {
+                range,
+                text: text.to_string(),
+            };
+            self.changes.get_or_insert_default().push(change);
+        }
+        let deleted = self.buffer.text_in_range(start, end);
+        self.buffer.delete_range(start, end);
+        self.buffer.insert(start, text);
 
-            // Clear highlighting state from this line onward
-            self.syntax_highlighter
-                .clear_state_from_line(line_index, &self.language);
+        // Re-highlight from the changed line until the parse state converges
+        let (row, _) = self.buffer.position_to_cursor(start);
+        let old_rows = deleted.matches('\n').count() + 1;
+        let new_rows = text.matches('\n').count() + 1;
+        self.syntax_highlighter.edit_lines(row, old_rows, new_rows);
+        self.highlights.edit_lines(row, old_rows, new_rows);
+        self.display_map.edit_lines(row, old_rows, new_rows);
+        self.fold_map.edit_lines(row, old_rows, new_rows);
+        self.file_format.edit_lines(row, old_rows, new_rows);
+        self.display_map.set_folds(self.fold_map.hidden_rows());
+        self.decorations.refresh(&self.buffer);
+        self.diagnostics.refresh(&self.buffer);
+        self.forget_old_edits();
+        self.hovered_position = None;
+        if let Some(search) = &self.search {
+            search.matches.replace(None);
         }
+
+        deleted
     }
 
-    /// Get syntax highlighting for a line
+    /// Get syntax highlighting and scopes for a line.
+    ///
+    /// Lines can be highlighted in any order; parse state for the lines above
+    /// `line_index` is computed from the buffer and cached as needed.
     pub fn highlight_line(
         &mut self,
         line: &str,
         line_index: usize,
         font_family: SharedString,
         font_size: f32,
-    ) -> Vec<TextRun> {
+    ) -> HighlightedLine {
+        let buffer = &self.buffer;
+        self.syntax_highlighter
+            .prepare_lines(&self.language, 0..line_index + 1, |row| {
+                buffer.get_line(row)
+            });
         self.syntax_highlighter.highlight_line(
             line,
             &self.language,
@@ -193,250 +680,4400 @@ impl Editor {
         )
     }
 
-    // Movement methods
-    pub fn move_left(&mut self, shift_held: bool) {
-        if shift_held && self.selection_anchor.is_none() {
-            self.selection_anchor = Some(self.cursor_position);
-        } else if !shift_held {
-            self.selection_anchor = None;
+    /// Text runs for painting `line` at `row`.
+    ///
+    /// Uses the most recent background highlighting for the row, even if the
+    /// text has changed since, and plain text if there is none yet. Call
+    /// [`Self::refresh_highlights`] to bring the rows up to date.
+    pub fn cached_text_runs(
+        &self,
+        line: &str,
+        row: usize,
+        font_family: SharedString,
+    ) -> Vec<TextRun> {
+        let runs = self.highlights.runs(row).unwrap_or_default();
+        syntax_highlighter::text_runs(&runs, line, font_family, self.config.text_color.into())
+    }
+
+    /// Highlight `rows` on the background executor if they aren't up to date.
+    ///
+    /// The view is notified when the results are ready. A request started
+    /// before a later edit is cancelled and its results are discarded.
+    pub fn refresh_highlights(&mut self, rows: Range<usize>, cx: &mut Context<EditorView<B>>)
+    where
+        B: Clone + 'static,
+    {
+        if !self.highlights.needs_highlight(rows.clone()) {
+            return;
         }
 
-        // Reset goal column when moving horizontally
-        self.goal_column = None;
+        let version = self.highlights.version();
+        let highlighter = self.syntax_highlighter.share();
+        let highlighter_version = highlighter.version();
+        let snapshot = self.buffer.snapshot();
+        let language = self.language.clone();
+        let first_row = rows.start;
+        let background_rows = rows.clone();
+        let task = cx.spawn(async move |view, cx| {
+            let lines = cx
+                .background_executor()
+                .spawn(async move {
+                    highlighter.highlight_snapshot(
+                        &snapshot,
+                        &language,
+                        highlighter_version,
+                        background_rows,
+                    )
+                })
+                .await;
+            if let Some(lines) = lines {
+                view.update(cx, |view, cx| {
+                    if view
+                        .editor_mut()
+                        .highlights
+                        .insert(version, first_row, lines)
+                    {
+                        cx.notify();
+                    }
+                })
+                .ok();
+            }
+        });
+        self.highlights.set_pending(version, rows, task);
+    }
 
-        if self.cursor_position.col > 0 {
-            self.cursor_position.col -= 1;
-        } else if self.cursor_position.row > 0 {
-            self.cursor_position.row -= 1;
-            self.cursor_position.col = self.buffer.line_len(self.cursor_position.row);
-        }
+    // Scrolling methods
+
+    /// Vertical scroll offset of the viewport
+    pub fn scroll_top(&self) -> Pixels {
+        self.scroll.scroll_top()
     }
 
-    pub fn move_right(&mut self, shift_held: bool) {
-        if shift_held && self.selection_anchor.is_none() {
-            self.selection_anchor = Some(self.cursor_position);
-        } else if !shift_held {
-            self.selection_anchor = None;
-        }
+    /// Scroll to `scroll_top`, clamped so the document stays in view
+    pub fn set_scroll_top(&mut self, scroll_top: Pixels) {
+        self.scroll
+            .set_scroll_top(scroll_top, self.max_scroll_top());
+    }
 
-        // Reset goal column when moving horizontally
-        self.goal_column = None;
+    /// Scroll by `delta`, clamped so the document stays in view. Returns true
+    /// if the scroll offset changed.
+    pub fn scroll_by(&mut self, delta: Pixels) -> bool {
+        let max_scroll_top = self.max_scroll_top();
+        self.scroll.scroll_by(delta, max_scroll_top)
+    }
 
-        let current_line_len = self.buffer.line_len(self.cursor_position.row);
+    /// Largest scroll offset at which the last line is still at the bottom of the viewport
+    pub fn max_scroll_top(&self) -> Pixels {
+        let content_height = self.config.line_height * self.display_row_count() as f32;
+        (content_height - self.scroll.viewport_height()).max(px(0.0))
+    }
 
-        if self.cursor_position.col < current_line_len {
-            self.cursor_position.col += 1;
-        } else if self.cursor_position.row < self.buffer.line_count().saturating_sub(1) {
-            // Move to start of next line
-            self.cursor_position.row += 1;
-            self.cursor_position.col = 0;
-        }
+    pub fn viewport_height(&self) -> Pixels {
+        self.scroll.viewport_height()
     }
 
-    pub fn move_up(&mut self, shift_held: bool) {
-        if shift_held && self.selection_anchor.is_none() {
-            self.selection_anchor = Some(self.cursor_position);
-        } else if !shift_held {
-            self.selection_anchor = None;
+    /// Record the height available for text. Called by `EditorElement` during layout.
+    pub fn set_viewport_height(&mut self, height: Pixels) {
+        self.scroll.set_viewport_height(height);
+        // Keep the offset valid when the viewport grows
+        self.set_scroll_top(self.scroll_top());
+    }
+
+    /// Number of whole rows that fit in the viewport
+    pub fn visible_row_count(&self) -> usize {
+        (self.scroll.viewport_height() / self.config.line_height).floor() as usize
+    }
+
+    /// Buffer rows that are at least partially inside the viewport
+    pub fn visible_rows(&self) -> Range<usize> {
+        let display_rows = self.visible_display_rows();
+        if display_rows.is_empty() {
+            let line_count = self.buffer.line_count();
+            return line_count..line_count;
         }
+        let (first, _) = self.display_map.buffer_row(display_rows.start);
+        let (last, _) = self.display_map.buffer_row(display_rows.end - 1);
+        first..last + 1
+    }
 
-        if self.cursor_position.row > 0 {
-            // Set goal column if not already set
-            if self.goal_column.is_none() {
-                self.goal_column = Some(self.cursor_position.col);
-            }
+    /// Display rows that are at least partially inside the viewport
+    pub fn visible_display_rows(&self) -> Range<usize> {
+        let line_height = self.config.line_height;
+        let scroll_top = self.scroll.scroll_top();
+        let row_count = self.display_row_count();
+        let first = (scroll_top / line_height).floor() as usize;
+        let last = ((scroll_top + self.scroll.viewport_height()) / line_height).ceil() as usize;
+        first.min(row_count)..last.min(row_count)
+    }
 
-            self.cursor_position.row -= 1;
+    /// Number of visual rows the buffer takes up, counting each wrapped line
+    /// once per row it wraps onto
+    pub fn display_row_count(&self) -> usize {
+        self.display_map.display_row_count(self.buffer.line_count())
+    }
 
-            // Try to use goal column, but clamp to line length
-            let line_len = self.buffer.line_len(self.cursor_position.row);
-            self.cursor_position.col = self
-                .goal_column
-                .unwrap_or(self.cursor_position.col)
-                .min(line_len);
-        }
+    /// The visual row `position` is displayed on
+    pub fn display_row(&self, position: CursorPosition) -> usize {
+        self.display_map.display_row(position)
     }
 
-    pub fn move_down(&mut self, shift_held: bool) {
-        if shift_held && self.selection_anchor.is_none() {
-            self.selection_anchor = Some(self.cursor_position);
-        } else if !shift_held {
-            self.selection_anchor = None;
+    /// Ask for the primary cursor to be scrolled into view on the next layout
+    pub fn request_autoscroll(&mut self) {
+        self.scroll.request_autoscroll();
+    }
+
+    /// Scroll the primary cursor into view if an autoscroll was requested.
+    /// Returns true if the scroll offset changed.
+    pub fn autoscroll(&mut self) -> bool {
+        if !self.scroll.take_autoscroll_request() {
+            return false;
         }
 
-        if self.cursor_position.row < self.buffer.line_count().saturating_sub(1) {
-            // Set goal column if not already set
-            if self.goal_column.is_none() {
-                self.goal_column = Some(self.cursor_position.col);
-            }
+        let line_height = self.config.line_height;
+        let viewport_height = self.scroll.viewport_height();
+        let scroll_top = self.scroll.scroll_top();
+        let cursor_top = line_height * self.display_row(self.cursor_position()) as f32;
+        let cursor_bottom = cursor_top + line_height;
 
-            self.cursor_position.row += 1;
+        let target = if cursor_top < scroll_top {
+            cursor_top
+        } else if cursor_bottom > scroll_top + viewport_height {
+            cursor_bottom - viewport_height
+        } else {
+            return false;
+        };
+        self.scroll.set_scroll_top(target, self.max_scroll_top())
+    }
 
-            // Try to use goal column, but clamp to line length
-            let line_len = self.buffer.line_len(self.cursor_position.row);
-            self.cursor_position.col = self
-                .goal_column
-                .unwrap_or(self.cursor_position.col)
-                .min(line_len);
-        }
+    pub(crate) fn display_map(&self) -> &DisplayMap {
+        &self.display_map
     }
 
-    pub fn select_all(&mut self) {
-        // Reset goal column when selecting all
-        self.goal_column = None;
+    pub(crate) fn display_map_mut(&mut self) -> &mut DisplayMap {
+        &mut self.display_map
+    }
 
-        // Set anchor at beginning
-        self.selection_anchor = Some(CursorPosition { row: 0, col: 0 });
+    // Mouse methods
 
-        // Move cursor to end
-        let last_row = self.buffer.line_count().saturating_sub(1);
-        let last_col = self.buffer.line_len(last_row);
-        self.cursor_position = CursorPosition {
-            row: last_row,
-            col: last_col,
-        };
+    pub(crate) fn position_map_mut(&mut self) -> &mut PositionMap {
+        &mut self.position_map
     }
 
-    pub fn has_selection(&self) -> bool {
-        self.selection_anchor.is_some()
+    /// The row whose fold indicator is under `point` in window coordinates, if
+    /// `point` is in the gutter
+    pub fn gutter_row_for_point(&self, point: Point<Pixels>) -> Option<usize> {
+        if !self.config.show_fold_indicators {
+            return None;
+        }
+        let gutter_end = self.position_map.text_origin().x - self.config.gutter_padding;
+        if point.x >= gutter_end || point.x < gutter_end - self.config.gutter_width {
+            return None;
+        }
+        Some(self.position_for_point(point)?.row)
     }
 
-    pub fn get_selection_range(&self) -> Option<(CursorPosition, CursorPosition)> {
-        self.selection_anchor.map(|anchor| {
-            // Return (start, end) positions in document order
-            if anchor.row < self.cursor_position.row
-                || (anchor.row == self.cursor_position.row && anchor.col < self.cursor_position.col)
-            {
-                (anchor, self.cursor_position)
-            } else {
-                (self.cursor_position, anchor)
+    /// The buffer position under `point` in window coordinates, based on the
+    /// lines painted in the last frame. Returns `None` before the first paint.
+    pub fn position_for_point(&self, point: Point<Pixels>) -> Option<CursorPosition> {
+        let position = self.position_map.position_for_point(
+            point,
+            self.scroll_top(),
+            &self.display_map,
+            self.buffer.line_count(),
+            |row| self.buffer.line_len(row),
+        )?;
+        Some(self.clamp_position(position))
+    }
+
+    /// Note the mouse moving to `point` in window coordinates, for showing the
+    /// diagnostics under it. Returns true if that changes which are shown.
+    pub fn mouse_hover(&mut self, point: Point<Pixels>) -> bool {
+        let text_start = self.position_map.text_origin().x - self.config.gutter_padding;
+        let position = if point.x >= text_start {
+            self.position_for_point(point)
+        } else {
+            None
+        };
+        self.hover(position)
+    }
+
+    /// Start a mouse selection at `position`.
+    ///
+    /// A single click places the caret, or extends the primary selection when
+    /// shift is held. A double click selects the word under the mouse and a
+    /// triple click selects the whole line. Dragging afterwards extends the
+    /// selection by the same unit.
+    pub fn mouse_down(&mut self, position: CursorPosition, click_count: usize, shift_held: bool) {
+        let position = self.clamp_position(position);
+        self.marked_range = None;
+        let (mode, origin) = match click_count {
+            0 | 1 if shift_held => {
+                let anchor = self.selections.primary().anchor;
+                self.selections
+                    .set_single(SelectionRange::new(anchor, position));
+                (SelectMode::Character, SelectionRange::caret(anchor))
             }
-        })
+            0 | 1 => {
+                let origin = SelectionRange::caret(position);
+                self.selections.set_single(origin);
+                (SelectMode::Character, origin)
+            }
+            2 => {
+                let origin = self.range_for_mode(SelectMode::Word, position);
+                self.selections.set_single(origin);
+                (SelectMode::Word, origin)
+            }
+            _ => {
+                let origin = self.range_for_mode(SelectMode::Line, position);
+                self.selections.set_single(origin);
+                (SelectMode::Line, origin)
+            }
+        };
+        self.pending_mouse_selection = Some(PendingMouseSelection { mode, origin });
+        self.request_autoscroll();
     }
 
-    pub fn delete_selection(&mut self) -> bool {
-        if let Some((start, end)) = self.get_selection_range() {
-            // Convert cursor positions to buffer positions
-            let start_pos = self.buffer.cursor_to_position(start.row, start.col);
-            let end_pos = self.buffer.cursor_to_position(end.row, end.col);
+    /// Extend the selection started by [`Editor::mouse_down`] to `position`
+    pub fn mouse_drag(&mut self, position: CursorPosition) {
+        let Some(pending) = self.pending_mouse_selection else {
+            return;
+        };
+        let position = self.clamp_position(position);
+        let target = self.range_for_mode(pending.mode, position);
+        let origin = pending.origin;
 
-            // Delete the range
-            self.buffer.delete_range(start_pos, end_pos);
+        let selection = if target.start() < origin.start() {
+            SelectionRange::new(origin.end(), target.start())
+        } else {
+            SelectionRange::new(origin.start(), target.end())
+        };
+        self.selections.set_single(selection);
+        self.request_autoscroll();
+    }
 
-            // Update cursor position
-            self.cursor_position = start;
-            self.selection_anchor = None;
-            self.goal_column = None;
+    /// Finish the current mouse selection
+    pub fn mouse_up(&mut self) {
+        self.pending_mouse_selection = None;
+    }
 
-            // Reset highlighting state from the changed line onward
-            self.syntax_highlighter
-                .clear_state_from_line(start.row, &self.language);
+    /// Whether a mouse selection is in progress
+    pub fn is_selecting_with_mouse(&self) -> bool {
+        self.pending_mouse_selection.is_some()
+    }
 
-            true
-        } else {
-            false
+    /// The caret, word or line containing `position`
+    fn range_for_mode(&self, mode: SelectMode, position: CursorPosition) -> SelectionRange {
+        match mode {
+            SelectMode::Character => SelectionRange::caret(position),
+            SelectMode::Word => self
+                .word_range_at(position)
+                .map(|(start, end)| SelectionRange::new(start, end))
+                .unwrap_or_else(|| SelectionRange::caret(position)),
+            SelectMode::Line => {
+                let start = CursorPosition::new(position.row, 0);
+                let end = if position.row + 1 < self.buffer.line_count() {
+                    CursorPosition::new(position.row + 1, 0)
+                } else {
+                    CursorPosition::new(position.row, self.buffer.line_len(position.row))
+                };
+                SelectionRange::new(start, end)
+            }
         }
     }
 
-    pub fn get_selected_text(&self) -> String {
-        if let Some((start, end)) = self.get_selection_range() {
-            // Convert cursor positions to buffer positions
-            let start_pos = self.buffer.cursor_to_position(start.row, start.col);
-            let end_pos = self.buffer.cursor_to_position(end.row, end.col);
-
-            // Get the full text and extract the selection
-            let text = self.buffer.to_string();
-
-            // The positions are character indices, so we can slice the chars directly
-            let chars: Vec<char> = text.chars().collect();
-            if start_pos <= chars.len() && end_pos <= chars.len() && start_pos <= end_pos {
-                chars[start_pos..end_pos].iter().collect()
+    /// Clamp `position` to a valid row and grapheme boundary in the buffer
+    fn clamp_position(&self, position: CursorPosition) -> CursorPosition {
+        let row = position.row.min(self.buffer.line_count().saturating_sub(1));
+        CursorPosition::new(row, column_for_goal(&self.buffer, row, position.col))
+    }
+
+    // Movement methods
+
+    /// Move the head of every selection with `motion`, which returns the new
+    /// head and goal column. Without shift, each selection collapses to a caret.
+    fn move_selections(
+        &mut self,
+        shift_held: bool,
+        motion: impl Fn(&B, &SelectionRange) -> (CursorPosition, Option<Pixels>),
+    ) {
+        self.move_selections_with_display_map(shift_held, |buffer, _, selection| {
+            motion(buffer, selection)
+        });
+    }
+
+    /// Like [`Self::move_selections`], for motions that go by display rows
+    fn move_selections_with_display_map(
+        &mut self,
+        shift_held: bool,
+        motion: impl Fn(&B, &DisplayMap, &SelectionRange) -> (CursorPosition, Option<Pixels>),
+    ) {
+        let buffer = &self.buffer;
+        let display_map = &self.display_map;
+        let fold_map = &self.fold_map;
+        self.selections.update(|selection| {
+            let (head, goal_column) = motion(buffer, display_map, selection);
+            // Carets step over folded rows rather than into them
+            let head = fold_map.skip_hidden(selection.head, head, buffer.line_count(), |row| {
+                buffer.line_len(row)
+            });
+            if shift_held {
+                selection.head = head;
             } else {
-                String::new()
+                *selection = SelectionRange::caret(head);
             }
-        } else {
-            String::new()
-        }
+            selection.goal_column = goal_column;
+        });
+        self.marked_range = None;
+        self.request_autoscroll();
     }
 
-    pub fn insert_char(&mut self, ch: char) {
-        // Delete selection first if there is one
-        self.delete_selection();
-
-        // Use the buffer's insert_at method directly
-        self.buffer.insert_at(
-            self.cursor_position.row,
-            self.cursor_position.col,
-            &ch.to_string(),
-        );
-        self.cursor_position.col += 1;
-        self.goal_column = None;
+    pub fn move_left(&mut self, shift_held: bool) {
+        // Reset goal column when moving horizontally
+        self.move_selections(shift_held, |buffer, selection| {
+            let mut head = selection.head;
+            if head.col > 0 {
+                let line = buffer.get_line(head.row).unwrap_or_default();
+                head.col = coordinates::prev_grapheme_boundary(&line, head.col);
+            } else if head.row > 0 {
+                head.row -= 1;
+                head.col = buffer.line_len(head.row);
+            }
+            (head, None)
+        });
+    }
 
-        // Clear highlighting state from this line onward
-        self.syntax_highlighter
-            .clear_state_from_line(self.cursor_position.row, &self.language);
+    pub fn move_right(&mut self, shift_held: bool) {
+        // Reset goal column when moving horizontally
+        self.move_selections(shift_held, |buffer, selection| {
+            let mut head = selection.head;
+            if head.col < buffer.line_len(head.row) {
+                let line = buffer.get_line(head.row).unwrap_or_default();
+                head.col = coordinates::next_grapheme_boundary(&line, head.col);
+            } else if head.row < buffer.line_count().saturating_sub(1) {
+                // Move to start of next line
+                head.row += 1;
+                head.col = 0;
+            }
+            (head, None)
+        });
     }
 
-    pub fn insert_newline(&mut self) {
-        // Delete selection first if there is one
-        self.delete_selection();
+    pub fn move_up(&mut self, shift_held: bool) {
+        self.move_vertically(shift_held, -1);
+    }
 
-        // Use the buffer's insert_at method directly
-        self.buffer
-            .insert_at(self.cursor_position.row, self.cursor_position.col, "\n");
-        self.cursor_position.row += 1;
-        self.cursor_position.col = 0;
-        self.goal_column = None;
+    pub fn move_down(&mut self, shift_held: bool) {
+        self.move_vertically(shift_held, 1);
+    }
 
-        // Clear highlighting state from this line onward
-        self.syntax_highlighter
-            .clear_state_from_line(self.cursor_position.row - 1, &self.language);
+    /// Move up by the number of rows that fit in the viewport, scrolling along
+    pub fn page_up(&mut self, shift_held: bool) {
+        let rows = self.visible_row_count().max(1);
+        self.scroll_by(-self.config.line_height * rows as f32);
+        self.move_vertically(shift_held, -(rows as isize));
     }
 
-    pub fn backspace(&mut self) {
-        // If there's a selection, delete it instead
-        if self.selection_anchor.is_some() {
-            self.delete_selection();
-            return;
-        }
+    /// Move down by the number of rows that fit in the viewport, scrolling along
+    pub fn page_down(&mut self, shift_held: bool) {
+        let rows = self.visible_row_count().max(1);
+        self.scroll_by(self.config.line_height * rows as f32);
+        self.move_vertically(shift_held, rows as isize);
+    }
 
-        // Use the buffer's backspace_at method directly
-        self.buffer
-            .backspace_at(self.cursor_position.row, self.cursor_position.col);
+    /// Move every head `rows` display rows down (or up, if negative), keeping
+    /// its goal column
+    fn move_vertically(&mut self, shift_held: bool, rows: isize) {
+        self.move_selections_with_display_map(shift_held, |buffer, display_map, selection| {
+            move_display_rows(buffer, display_map, selection, rows)
+                .unwrap_or((selection.head, selection.goal_column))
+        });
+    }
 
-        if self.cursor_position.col > 0 {
-            self.cursor_position.col -= 1;
-            // Clear highlighting state from this line onward
-            self.syntax_highlighter
-                .clear_state_from_line(self.cursor_position.row, &self.language);
-        } else if self.cursor_position.row > 0 {
-            // Move to end of previous line
-            self.cursor_position.row -= 1;
-            let line_len = self.buffer.line_len(self.cursor_position.row);
-            self.cursor_position.col = line_len;
-            // Clear highlighting state from the previous line onward
-            self.syntax_highlighter
-                .clear_state_from_line(self.cursor_position.row, &self.language);
-        }
+    pub fn move_to_previous_word_start(&mut self, shift_held: bool) {
+        self.move_selections(shift_held, |buffer, selection| {
+            (
+                previous_boundary(buffer, selection.head, movement::previous_word_start),
+                None,
+            )
+        });
+    }
 
-        self.goal_column = None;
+    pub fn move_to_next_word_end(&mut self, shift_held: bool) {
+        self.move_selections(shift_held, |buffer, selection| {
+            (
+                next_boundary(buffer, selection.head, movement::next_word_end),
+                None,
+            )
+        });
     }
 
-    pub fn delete(&mut self) {
-        // If there's a selection, delete it instead
-        if self.selection_anchor.is_some() {
-            self.delete_selection();
+    /// Like [`Self::move_to_previous_word_start`], but also stops inside
+    /// camelCase and snake_case words
+    pub fn move_to_previous_subword_start(&mut self, shift_held: bool) {
+        self.move_selections(shift_held, |buffer, selection| {
+            (
+                previous_boundary(buffer, selection.head, movement::previous_subword_start),
+                None,
+            )
+        });
+    }
+
+    /// Like [`Self::move_to_next_word_end`], but also stops inside camelCase
+    /// and snake_case words
+    pub fn move_to_next_subword_end(&mut self, shift_held: bool) {
+        self.move_selections(shift_held, |buffer, selection| {
+            (
+                next_boundary(buffer, selection.head, movement::next_subword_end),
+                None,
+            )
+        });
+    }
+
+    /// Move to the first non-whitespace character of the line, or to column 0
+    /// if already there
+    pub fn move_to_line_start(&mut self, shift_held: bool) {
+        self.move_selections(shift_held, |buffer, selection| {
+            let head = selection.head;
+            let line = buffer.get_line(head.row).unwrap_or_default();
+            let indent = movement::first_non_whitespace(&line);
+            let col = if head.col == indent { 0 } else { indent };
+            (CursorPosition::new(head.row, col), None)
+        });
+    }
+
+    pub fn move_to_line_end(&mut self, shift_held: bool) {
+        self.move_selections(shift_held, |buffer, selection| {
+            let row = selection.head.row;
+            (CursorPosition::new(row, buffer.line_len(row)), None)
+        });
+    }
+
+    pub fn move_to_document_start(&mut self, shift_held: bool) {
+        self.move_selections(shift_held, |_, _| (CursorPosition::new(0, 0), None));
+        self.selections.set_single(*self.selections.primary());
+    }
+
+    pub fn move_to_document_end(&mut self, shift_held: bool) {
+        self.move_selections(shift_held, |buffer, _| {
+            let last_row = buffer.line_count().saturating_sub(1);
+            (
+                CursorPosition::new(last_row, buffer.line_len(last_row)),
+                None,
+            )
+        });
+        self.selections.set_single(*self.selections.primary());
+    }
+
+    pub fn select_all(&mut self) {
+        let last_row = self.buffer.line_count().saturating_sub(1);
+        let last_col = self.buffer.line_len(last_row);
+        self.selections.set_single(SelectionRange::new(
+            CursorPosition::new(0, 0),
+            CursorPosition::new(last_row, last_col),
+        ));
+    }
+
+    // Multi-cursor methods
+
+    /// Add a caret on the row above every existing caret
+    pub fn add_cursor_above(&mut self) {
+        self.add_cursors_vertically(false);
+    }
+
+    /// Add a caret on the row below every existing caret
+    pub fn add_cursor_below(&mut self) {
+        self.add_cursors_vertically(true);
+    }
+
+    fn add_cursors_vertically(&mut self, below: bool) {
+        let rows = if below { 1 } else { -1 };
+        let mut new_ranges: Vec<SelectionRange> = self
+            .selections
+            .iter()
+            .filter_map(|selection| {
+                let (head, goal_column) =
+                    move_display_rows(&self.buffer, &self.display_map, selection, rows)?;
+                let mut range = SelectionRange::caret(head);
+                range.goal_column = goal_column;
+                Some(range)
+            })
+            .collect();
+
+        // The outermost new caret ends up as the primary one
+        if !below {
+            new_ranges.reverse();
+        }
+        for range in new_ranges {
+            self.selections.push(range);
+        }
+        self.request_autoscroll();
+    }
+
+    /// Select the next occurrence of the primary selection's text (cmd-d).
+    ///
+    /// With an empty primary selection, selects the word under the caret first.
+    pub fn add_next_occurrence(&mut self) {
+        let primary = *self.selections.primary();
+        if primary.is_empty() {
+            if let Some((start, end)) = self.word_range_at(primary.head) {
+                self.selections
+                    .replace_primary(SelectionRange::new(start, end));
+            }
             return;
         }
 
-        // Use the buffer's delete_at method directly
+        let (start, end) = primary.range();
+        let start_byte = self.position_to_byte(start);
+        let end_byte = self.position_to_byte(end);
+        let text = self.buffer.text();
+        let query = &text[start_byte..end_byte];
+        let selected: HashSet<usize> = self
+            .selections
+            .iter()
+            .map(|selection| self.position_to_byte(selection.start()))
+            .collect();
+
+        // The first match starting in `starts` that isn't selected yet. Matches
+        // may overlap, so a rejected one only skips its first char.
+        let step = query.chars().next().map_or(1, char::len_utf8);
+        let find_in = |starts: Range<usize>| {
+            let mut from = starts.start;
+            while let Some(found) = text[from..].find(query).map(|index| from + index) {
+                if found >= starts.end {
+                    break;
+                }
+                if !selected.contains(&found) {
+                    return Some(found);
+                }
+                from = found + step;
+            }
+            None
+        };
+
+        // Search forward from the primary selection, wrapping around once
+        let Some(found) = find_in(end_byte..text.len()).or_else(|| find_in(0..start_byte)) else {
+            return;
+        };
+        let offset = self.buffer.byte_to_char(found);
+        let (row, col) = self.buffer.position_to_cursor(offset);
+        let (end_row, end_col) = self
+            .buffer
+            .position_to_cursor(offset + query.chars().count());
+        self.selections.push(SelectionRange::new(
+            CursorPosition::new(row, col),
+            CursorPosition::new(end_row, end_col),
+        ));
+        self.request_autoscroll();
+    }
+
+    /// Byte offset of `position` in the buffer's text
+    fn position_to_byte(&self, position: CursorPosition) -> usize {
         self.buffer
-            .delete_at(self.cursor_position.row, self.cursor_position.col);
+            .char_to_byte(self.buffer.cursor_to_position(position.row, position.col))
+    }
 
-        // Clear highlighting state from this line onward
-        self.syntax_highlighter
-            .clear_state_from_line(self.cursor_position.row, &self.language);
+    /// The range of the word touching `position`, if any
+    fn word_range_at(&self, position: CursorPosition) -> Option<(CursorPosition, CursorPosition)> {
+        let line: Vec<char> = self.buffer.get_line(position.row)?.chars().collect();
+        let is_word_char = |ch: char| ch.is_alphanumeric() || ch == '_';
+
+        let col = position.col.min(line.len());
+        let mut start = col;
+        while start > 0 && is_word_char(line[start - 1]) {
+            start -= 1;
+        }
+        let mut end = col;
+        while end < line.len() && is_word_char(line[end]) {
+            end += 1;
+        }
+
+        (start < end).then(|| {
+            (
+                CursorPosition::new(position.row, start),
+                CursorPosition::new(position.row, end),
+            )
+        })
+    }
+
+    // Folding methods
+
+    /// Ranges of rows that can be folded, as `header..end`. Folding one keeps
+    /// `header` visible and hides the rows after it up to `end`.
+    pub fn foldable_ranges(&self) -> Rc<[Range<usize>]> {
+        let buffer = &self.buffer;
+        let mut highlighter = self.syntax_highlighter.share();
+        self.fold_map.foldable_ranges(
+            || (0..buffer.line_count()).map(|row| buffer.get_line(row).unwrap_or_default()),
+            |row, line| {
+                highlighter
+                    .line_scopes(&self.language, row, line, |row| buffer.get_line(row))
+                    .unwrap_or_default()
+            },
+        )
+    }
+
+    /// The foldable ranges found last, for painting.
+    ///
+    /// After an edit these are the ranges before it, moved with their text,
+    /// until [`Self::refresh_foldable_ranges`] finds the new ones.
+    pub fn cached_foldable_ranges(&self) -> Rc<[Range<usize>]> {
+        self.fold_map.cached_foldable_ranges()
+    }
+
+    /// Find the foldable ranges on the background executor if the text
+    /// changed since they were last found. The view is notified when they are
+    /// ready.
+    pub fn refresh_foldable_ranges(&mut self, cx: &mut Context<EditorView<B>>)
+    where
+        B: Clone + 'static,
+    {
+        if !self.fold_map.needs_foldable_ranges() {
+            return;
+        }
+
+        let version = self.fold_map.foldable_version();
+        let snapshot = self.buffer.snapshot();
+        // A copy, so parsing the snapshot doesn't record checkpoints for text
+        // the buffer may no longer have
+        let mut highlighter = self.syntax_highlighter.clone();
+        let language = self.language.clone();
+        let task = cx.spawn(async move |view, cx| {
+            let ranges = cx
+                .background_executor()
+                .spawn(async move {
+                    fold_map::foldable_ranges(
+                        (0..snapshot.line_count())
+                            .map(|row| snapshot.line(row).unwrap_or_default()),
+                        |row, line| {
+                            highlighter
+                                .line_scopes(&language, row, line, |row| snapshot.line(row))
+                                .unwrap_or_default()
+                        },
+                    )
+                })
+                .await;
+            view.update(cx, |view, cx| {
+                if view
+                    .editor_mut()
+                    .fold_map
+                    .insert_foldable_ranges(version, ranges)
+                {
+                    cx.notify();
+                }
+            })
+            .ok();
+        });
+        self.fold_map.set_foldable_pending(version, task);
+    }
+
+    /// The folded ranges, sorted by header
+    pub fn folds(&self) -> &[Range<usize>] {
+        self.fold_map.folds()
+    }
+
+    /// Whether `row` is the header of a fold
+    pub fn is_folded(&self, row: usize) -> bool {
+        self.fold_map.is_folded(row)
+    }
+
+    /// Whether `row` is hidden inside a fold
+    pub fn is_row_hidden(&self, row: usize) -> bool {
+        self.display_map.is_hidden(row)
+    }
+
+    /// Fold the innermost unfolded range around each caret. Returns false if
+    /// nothing was folded.
+    pub fn fold(&mut self) -> bool {
+        let ranges = self.foldable_ranges();
+        let mut folded = false;
+        for row in self.head_rows() {
+            let innermost = ranges
+                .iter()
+                .filter(|range| range.contains(&row) && !self.fold_map.is_folded(range.start))
+                .max_by_key(|range| range.start);
+            if let Some(range) = innermost {
+                folded |= self.fold_map.fold(range.clone());
+            }
+        }
+        self.folds_changed(folded)
+    }
+
+    /// Unfold every fold around each caret. Returns false if nothing was
+    /// folded.
+    pub fn unfold(&mut self) -> bool {
+        let mut unfolded = false;
+        for row in self.head_rows() {
+            unfolded |= self.fold_map.unfold_containing(row);
+        }
+        self.folds_changed(unfolded)
+    }
+
+    /// Fold or unfold the range whose header is `row`, as clicking its fold
+    /// indicator does. Returns false if `row` isn't foldable.
+    pub fn toggle_fold(&mut self, row: usize) -> bool {
+        if self.fold_map.unfold(row) {
+            return self.folds_changed(true);
+        }
+        let range = self
+            .foldable_ranges()
+            .iter()
+            .find(|range| range.start == row)
+            .cloned();
+        let folded = range.is_some_and(|range| self.fold_map.fold(range));
+        self.folds_changed(folded)
+    }
+
+    pub fn fold_all(&mut self) -> bool {
+        let mut folded = false;
+        for range in self.foldable_ranges().iter() {
+            folded |= self.fold_map.fold(range.clone());
+        }
+        self.folds_changed(folded)
+    }
+
+    pub fn unfold_all(&mut self) -> bool {
+        let unfolded = self.fold_map.unfold_all();
+        self.folds_changed(unfolded)
+    }
+
+    fn head_rows(&self) -> Vec<usize> {
+        let mut rows: Vec<usize> = self
+            .selections
+            .iter()
+            .map(|selection| selection.head.row)
+            .collect();
+        rows.dedup();
+        rows
+    }
+
+    /// Hide the folded rows and move carets out of them. Passes `changed`
+    /// through for the folding methods to return.
+    fn folds_changed(&mut self, changed: bool) -> bool {
+        if !changed {
+            return false;
+        }
+        self.display_map.set_folds(self.fold_map.hidden_rows());
+        let buffer = &self.buffer;
+        let fold_map = &self.fold_map;
+        self.selections.update(|selection| {
+            let Some(hidden) = fold_map.hidden_range(selection.head.row) else {
+                return;
+            };
+            let header = hidden.start - 1;
+            let head = CursorPosition::new(header, buffer.line_len(header));
+            if selection.is_empty() {
+                *selection = SelectionRange::caret(head);
+            } else {
+                selection.head = head;
+            }
+        });
+        self.request_autoscroll();
+        true
+    }
+
+    // Bracket methods
+
+    /// The bracket pair at the primary caret, as highlighted by the element
+    pub fn matching_brackets(&self) -> Option<BracketPair> {
+        let head = self.cursor_position();
+        let version = self.syntax_highlighter.version();
+        if let Some((cached_version, cached_head, pair)) = *self.matching_brackets.borrow() {
+            if cached_version == version && cached_head == head {
+                return pair;
+            }
+        }
+        let pair = self.bracket_pair_at(head);
+        *self.matching_brackets.borrow_mut() = Some((version, head, pair));
+        pair
+    }
+
+    /// The bracket pair with a bracket right after or before `position`, or
+    /// else the innermost pair around it. Brackets in strings and comments
+    /// are ignored.
+    pub fn bracket_pair_at(&self, position: CursorPosition) -> Option<BracketPair> {
+        let brackets = self.code_brackets(position.row);
+        let adjacent = [Some(position.col), position.col.checked_sub(1)];
+        for col in adjacent.into_iter().flatten() {
+            let Some(&(_, ch)) = brackets.iter().find(|(bracket_col, _)| *bracket_col == col)
+            else {
+                continue;
+            };
+            let bracket = CursorPosition::new(position.row, col);
+            if let Some(other) = self.matching_bracket(bracket, ch) {
+                return Some((bracket.min(other), bracket.max(other)));
+            }
+        }
+
+        let (open, ch) = self.enclosing_opening_bracket(position)?;
+        Some((open, self.matching_bracket(open, ch)?))
+    }
+
+    /// Move every caret to the other bracket of the pair at it, or to the
+    /// closing bracket of the pair around it
+    pub fn move_to_matching_bracket(&mut self) {
+        let targets: Vec<Option<CursorPosition>> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                let head = selection.head;
+                let (open, close) = self.bracket_pair_at(head)?;
+                let next_to_close =
+                    head == close || head.row == close.row && head.col == close.col + 1;
+                Some(if next_to_close { open } else { close })
+            })
+            .collect();
+        let targets = RefCell::new(targets.into_iter());
+        self.move_selections(false, |_, selection| {
+            let target = targets
+                .borrow_mut()
+                .next()
+                .flatten()
+                .unwrap_or(selection.head);
+            (target, None)
+        });
+    }
+
+    /// Select the text inside the brackets around every selection, or the
+    /// brackets too if the text inside is already selected. Repeating it
+    /// selects outwards pair by pair.
+    pub fn select_to_enclosing_brackets(&mut self) {
+        let ranges: Vec<Option<SelectionRange>> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                let (start, end) = selection.range();
+                let (open, ch) = self.enclosing_opening_bracket(start)?;
+                let close = self.matching_bracket(open, ch)?;
+                let inside_start = CursorPosition::new(open.row, open.col + 1);
+                Some(if (start, end) == (inside_start, close) {
+                    SelectionRange::new(open, CursorPosition::new(close.row, close.col + 1))
+                } else {
+                    SelectionRange::new(inside_start, close)
+                })
+            })
+            .collect();
+        let mut ranges = ranges.into_iter();
+        self.selections.update(|selection| {
+            if let Some(Some(range)) = ranges.next() {
+                *selection = range;
+            }
+        });
+        self.marked_range = None;
+        self.request_autoscroll();
+    }
+
+    /// Brackets on `row` outside strings and comments, as (col, bracket)
+    fn code_brackets(&self, row: usize) -> Vec<(usize, char)> {
+        let Some(line) = self.buffer.get_line(row) else {
+            return Vec::new();
+        };
+        let buffer = &self.buffer;
+        let spans = self
+            .syntax_highlighter
+            .share()
+            .line_scopes(&self.language, row, &line, |row| buffer.get_line(row))
+            .unwrap_or_default();
+        let in_string_or_comment = |index: usize| {
+            spans.iter().any(|span| {
+                span.range.contains(&index)
+                    && syntax_highlighter::is_string_or_comment(&span.scopes)
+            })
+        };
+
+        line.char_indices()
+            .enumerate()
+            .filter(|(_, (index, ch))| {
+                (indent::closing_bracket(*ch).is_some() || indent::is_closing_bracket(*ch))
+                    && !in_string_or_comment(*index)
+            })
+            .map(|(col, (_, ch))| (col, ch))
+            .collect()
+    }
+
+    /// The bracket matching `bracket` at `position`
+    fn matching_bracket(&self, position: CursorPosition, bracket: char) -> Option<CursorPosition> {
+        let line_count = self.buffer.line_count();
+        let mut depth = 0;
+        if let Some(close) = indent::closing_bracket(bracket) {
+            let last_row = (position.row + MAX_BRACKET_SCAN_ROWS).min(line_count);
+            for row in position.row..last_row {
+                for (col, ch) in self.code_brackets(row) {
+                    if row == position.row && col <= position.col {
+                        continue;
+                    }
+                    if ch == bracket {
+                        depth += 1;
+                    } else if ch == close {
+                        if depth == 0 {
+                            return Some(CursorPosition::new(row, col));
+                        }
+                        depth -= 1;
+                    }
+                }
+            }
+        } else {
+            let open = indent::opening_bracket(bracket)?;
+            let first_row = position.row.saturating_sub(MAX_BRACKET_SCAN_ROWS);
+            for row in (first_row..=position.row).rev() {
+                for (col, ch) in self.code_brackets(row).into_iter().rev() {
+                    if row == position.row && col >= position.col {
+                        continue;
+                    }
+                    if ch == bracket {
+                        depth += 1;
+                    } else if ch == open {
+                        if depth == 0 {
+                            return Some(CursorPosition::new(row, col));
+                        }
+                        depth -= 1;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// The innermost opening bracket before `position` that isn't closed
+    /// before it
+    fn enclosing_opening_bracket(
+        &self,
+        position: CursorPosition,
+    ) -> Option<(CursorPosition, char)> {
+        // Closing brackets seen so far without their opening one, by kind
+        let mut unmatched = [0usize; 3];
+        let kind = |close: char| match close {
+            ')' => 0,
+            ']' => 1,
+            _ => 2,
+        };
+        let first_row = position.row.saturating_sub(MAX_BRACKET_SCAN_ROWS);
+        for row in (first_row..=position.row).rev() {
+            for (col, ch) in self.code_brackets(row).into_iter().rev() {
+                if row == position.row && col >= position.col {
+                    continue;
+                }
+                match indent::closing_bracket(ch) {
+                    Some(close) if unmatched[kind(close)] == 0 => {
+                        return Some((CursorPosition::new(row, col), ch));
+                    }
+                    Some(close) => unmatched[kind(close)] -= 1,
+                    None => unmatched[kind(ch)] += 1,
+                }
+            }
+        }
+        None
+    }
+
+    // Search methods
+
+    /// Search the buffer for `query`, returning (start, end) positions of
+    /// every match. Fails if the query is an invalid regular expression.
+    pub fn find_all(
+        &self,
+        query: &SearchQuery,
+    ) -> Result<Vec<(CursorPosition, CursorPosition)>, regex::Error> {
+        let searcher = query.build()?;
+        let text = self.buffer.text();
+        Ok(search::byte_ranges_to_positions(
+            &text,
+            &searcher.find_all(&text),
+        ))
+    }
+
+    /// Make `query` the active search, whose matches are highlighted and
+    /// navigated with [`Self::select_next_match`]. An empty query clears the
+    /// search. On error the previous search is cleared as well.
+    pub fn set_search_query(&mut self, query: SearchQuery) -> Result<(), regex::Error> {
+        self.search = None;
+        if query.text.is_empty() {
+            return Ok(());
+        }
+        let searcher = query.build()?;
+        self.search = Some(SearchState {
+            query,
+            searcher,
+            matches: RefCell::default(),
+        });
+        Ok(())
+    }
+
+    pub fn search_query(&self) -> Option<&SearchQuery> {
+        self.search.as_ref().map(|search| &search.query)
+    }
+
+    pub fn clear_search(&mut self) {
+        self.search = None;
+    }
+
+    /// Every match of the active search, in order
+    pub fn search_matches(&self) -> SearchMatches {
+        let Some(search) = &self.search else {
+            return Rc::new([]);
+        };
+        search
+            .matches
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                let text = self.buffer.text();
+                search::byte_ranges_to_positions(&text, &search.searcher.find_all(&text)).into()
+            })
+            .clone()
+    }
+
+    /// Index of the match the primary selection covers exactly, if any
+    pub fn active_match_index(&self) -> Option<usize> {
+        let range = self.selections.primary().range();
+        self.search_matches()
+            .iter()
+            .position(|&match_range| match_range == range)
+    }
+
+    /// Select the first match after the primary selection, wrapping around to
+    /// the start of the buffer. Returns false if there are no matches.
+    pub fn select_next_match(&mut self) -> bool {
+        let matches = self.search_matches();
+        let (_, end) = self.selections.primary().range();
+        let Some(&(start, end)) = matches
+            .iter()
+            .find(|(start, _)| *start >= end)
+            .or(matches.first())
+        else {
+            return false;
+        };
+        self.select_match(start, end);
+        true
+    }
+
+    /// Select the last match before the primary selection, wrapping around to
+    /// the end of the buffer. Returns false if there are no matches.
+    pub fn select_previous_match(&mut self) -> bool {
+        let matches = self.search_matches();
+        let (start, _) = self.selections.primary().range();
+        let Some(&(start, end)) = matches
+            .iter()
+            .rev()
+            .find(|(_, end)| *end <= start)
+            .or(matches.last())
+        else {
+            return false;
+        };
+        self.select_match(start, end);
+        true
+    }
+
+    fn select_match(&mut self, start: CursorPosition, end: CursorPosition) {
+        self.selections.set_single(SelectionRange::new(start, end));
+        self.marked_range = None;
+        self.request_autoscroll();
+    }
+
+    /// Replace the match under the primary selection with `replacement`, then
+    /// select the next match. If the primary selection isn't on a match, only
+    /// selects the next one. Returns true if a replacement was made.
+    ///
+    /// For regex searches, `$1`-style references in `replacement` expand to
+    /// the match's capture groups.
+    pub fn replace_current(&mut self, replacement: &str) -> bool {
+        let Some(searcher) = self.search.as_ref().map(|search| search.searcher.clone()) else {
+            return false;
+        };
+        let Some(index) = self.active_match_index() else {
+            self.select_next_match();
+            return false;
+        };
+
+        let (start, end) = self.search_matches()[index];
+        let text = self.buffer.text();
+        let start = self.buffer.cursor_to_position(start.row, start.col);
+        let end = self.buffer.cursor_to_position(end.row, end.col);
+        let byte_range =
+            coordinates::char_to_byte(&text, start)..coordinates::char_to_byte(&text, end);
+        let replacement = searcher.replacement_for(&text, byte_range, replacement);
+
+        self.transact(|editor| editor.edit(start, end, &replacement));
+        let (row, col) = self
+            .buffer
+            .position_to_cursor(start + replacement.chars().count());
+        self.set_cursor_position(CursorPosition::new(row, col));
+        self.select_next_match();
+        true
+    }
+
+    /// Replace every match of the active search as a single undoable step.
+    /// Returns the number of replacements.
+    pub fn replace_all(&mut self, replacement: &str) -> usize {
+        let Some(searcher) = self.search.as_ref().map(|search| search.searcher.clone()) else {
+            return 0;
+        };
+        let text = self.buffer.text();
+        let ranges = searcher.find_all(&text);
+        if ranges.is_empty() {
+            return 0;
+        }
+
+        // Byte ranges to char offsets, in one pass over the text
+        let mut edits = Vec::with_capacity(ranges.len());
+        let (mut byte, mut char_offset) = (0, 0);
+        for range in &ranges {
+            char_offset += text[byte..range.start].chars().count();
+            let start = char_offset;
+            char_offset += text[range.clone()].chars().count();
+            byte = range.end;
+            edits.push((
+                start,
+                char_offset,
+                searcher.replacement_for(&text, range.clone(), replacement),
+            ));
+        }
+
+        let head = self.cursor_position();
+        self.transact(|editor| {
+            // Apply from the end so earlier offsets stay valid
+            for (start, end, replacement) in edits.iter().rev() {
+                editor.edit(*start, *end, replacement);
+            }
+        });
+        let head = self.clamp_position(head);
+        self.set_cursor_position(head);
+        ranges.len()
+    }
+
+    // Anchor methods
+
+    /// An anchor at `position` that stays on its text as the buffer is
+    /// edited, for positions kept across edits like bookmarks
+    pub fn anchor_at(&self, position: CursorPosition, bias: Bias) -> Anchor {
+        let position = self.clamp_position(position);
+        self.buffer.anchor_at(
+            self.buffer.cursor_to_position(position.row, position.col),
+            bias,
+        )
+    }
+
+    /// The position `anchor` has moved to, or `None` if it was made more
+    /// than [`MAX_ANCHOR_AGE`] edits ago and the edits it missed are gone
+    pub fn resolve_anchor(&self, anchor: &Anchor) -> Option<CursorPosition> {
+        let (row, col) = self
+            .buffer
+            .position_to_cursor(self.buffer.resolve_anchor(anchor)?);
+        Some(CursorPosition::new(row, col))
+    }
+
+    /// `anchor` at the same position, made at the current version. Anchors
+    /// kept for more than [`MAX_ANCHOR_AGE`] edits must be refreshed to keep
+    /// resolving; `None` if this one already doesn't.
+    pub fn refresh_anchor(&self, anchor: &Anchor) -> Option<Anchor> {
+        Some(
+            self.buffer
+                .anchor_at(self.buffer.resolve_anchor(anchor)?, anchor.bias()),
+        )
+    }
+
+    /// Drop the logged edits that neither the editor's own anchors nor
+    /// anchors younger than [`MAX_ANCHOR_AGE`] edits need. Anchors made inside
+    /// a transaction may be resolved before it ends, so nothing is dropped
+    /// until then.
+    fn forget_old_edits(&mut self) {
+        if self.history.in_transaction() {
+            return;
+        }
+        let oldest = [
+            self.decorations.anchor_version(),
+            self.diagnostics.anchor_version(),
+            self.completions.anchor_version(),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(
+            self.buffer.version().saturating_sub(MAX_ANCHOR_AGE),
+            usize::min,
+        );
+        self.buffer.forget_edits_before(oldest);
+    }
+
+    // Decoration methods
+
+    /// Paint `ranges` with `style` under `key`, on top of the syntax
+    /// highlighting and anything decorated before. The ranges move with the
+    /// text around them as it is edited.
+    pub fn add_highlight(
+        &mut self,
+        key: &str,
+        ranges: impl IntoIterator<Item = Range<CursorPosition>>,
+        style: DecorationStyle,
+    ) {
+        let ranges: Vec<Range<usize>> = ranges
+            .into_iter()
+            .map(|range| {
+                let start = self.clamp_position(range.start);
+                let end = self.clamp_position(range.end);
+                self.buffer.cursor_to_position(start.row, start.col)
+                    ..self.buffer.cursor_to_position(end.row, end.col)
+            })
+            .collect();
+        self.decorations.add(&self.buffer, key, ranges, style);
+    }
+
+    /// Remove everything added under `key` with [`Self::add_highlight`]
+    pub fn clear_highlights(&mut self, key: &str) {
+        self.decorations.clear(key);
+    }
+
+    /// The current ranges of everything added under `key`, in document order
+    pub fn highlight_ranges(&self, key: &str) -> Vec<Range<CursorPosition>> {
+        self.decorations
+            .ranges(&self.buffer, key)
+            .into_iter()
+            .map(|range| self.offset_range_to_positions(range))
+            .collect()
+    }
+
+    /// Decorated ranges that overlap `rows`, with their styles, in painting
+    /// order
+    pub fn decorations_in_rows(
+        &self,
+        rows: Range<usize>,
+    ) -> Vec<(Range<CursorPosition>, DecorationStyle)> {
+        if self.decorations.is_empty() || rows.start >= self.buffer.line_count() {
+            return Vec::new();
+        }
+        let start = self.buffer.cursor_to_position(rows.start, 0);
+        let end = if rows.end < self.buffer.line_count() {
+            self.buffer.cursor_to_position(rows.end, 0)
+        } else {
+            self.buffer.len()
+        };
+        self.decorations
+            .in_range(&self.buffer, start..end)
+            .into_iter()
+            .map(|(range, style)| (self.offset_range_to_positions(range), style))
+            .collect()
+    }
+
+    fn offset_range_to_positions(&self, range: Range<usize>) -> Range<CursorPosition> {
+        let (start_row, start_col) = self.buffer.position_to_cursor(range.start);
+        let (end_row, end_col) = self.buffer.position_to_cursor(range.end);
+        CursorPosition::new(start_row, start_col)..CursorPosition::new(end_row, end_col)
+    }
+
+    // Diagnostic methods
+
+    /// Replace the diagnostics, which are underlined in the color of their
+    /// severity, marked in the gutter and shown when hovered. Their ranges
+    /// move with the text as it is edited.
+    pub fn set_diagnostics(&mut self, diagnostics: Vec<Diagnostic>) {
+        let entries: Vec<(Range<usize>, Diagnostic)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let start = self.clamp_position(diagnostic.range.start);
+                let end = self.clamp_position(diagnostic.range.end).max(start);
+                let range = self.buffer.cursor_to_position(start.row, start.col)
+                    ..self.buffer.cursor_to_position(end.row, end.col);
+                (range, diagnostic)
+            })
+            .collect();
+
+        // Underline the char at empty ranges, or the one before at the end
+        // of a line
+        let mut squiggles: Vec<(Range<usize>, DiagnosticSeverity)> = entries
+            .iter()
+            .map(|(range, diagnostic)| {
+                let mut range = range.clone();
+                if range.is_empty() {
+                    let (row, col) = self.buffer.position_to_cursor(range.start);
+                    if col < self.buffer.line_len(row) {
+                        range.end += 1;
+                    } else if col > 0 {
+                        range.start -= 1;
+                    }
+                }
+                (range, diagnostic.severity)
+            })
+            .collect();
+        // Less severe first, so more severe squiggles are painted over them
+        squiggles.sort_by_key(|(_, severity)| std::cmp::Reverse(*severity));
+        self.decorations.clear(DIAGNOSTICS_KEY);
+        for chunk in squiggles.chunk_by(|(_, a), (_, b)| a == b) {
+            let style = DecorationStyle::new().squiggle(self.config.diagnostic_color(chunk[0].1));
+            let ranges = chunk.iter().map(|(range, _)| range.clone());
+            self.decorations
+                .add(&self.buffer, DIAGNOSTICS_KEY, ranges, style);
+        }
+
+        self.diagnostics.set(&self.buffer, entries);
+    }
+
+    pub fn clear_diagnostics(&mut self) {
+        self.diagnostics.clear();
+        self.decorations.clear(DIAGNOSTICS_KEY);
+    }
+
+    /// Every diagnostic with its current range, in document order
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics
+            .resolve(&self.buffer)
+            .into_iter()
+            .map(|(range, diagnostic)| Diagnostic {
+                range: self.offset_range_to_positions(range),
+                ..diagnostic.clone()
+            })
+            .collect()
+    }
+
+    /// How many diagnostics there are of each severity, as for a status bar
+    pub fn diagnostic_counts(&self) -> DiagnosticCounts {
+        self.diagnostics.counts()
+    }
+
+    /// The diagnostics whose range contains `position`, including at its end
+    pub fn diagnostics_at(&self, position: CursorPosition) -> Vec<Diagnostic> {
+        self.diagnostics()
+            .into_iter()
+            .filter(|diagnostic| {
+                diagnostic.range.start <= position && position <= diagnostic.range.end
+            })
+            .collect()
+    }
+
+    /// The most severe diagnostic starting on each of `rows`, as marked in
+    /// the gutter
+    pub fn row_severities(&self, rows: Range<usize>) -> Vec<Option<DiagnosticSeverity>> {
+        let mut severities: Vec<Option<DiagnosticSeverity>> = vec![None; rows.len()];
+        if self.diagnostics.is_empty() {
+            return severities;
+        }
+        for (range, diagnostic) in self.diagnostics.resolve(&self.buffer) {
+            let (row, _) = self.buffer.position_to_cursor(range.start);
+            if !rows.contains(&row) {
+                continue;
+            }
+            let severity = &mut severities[row - rows.start];
+            *severity = Some(match *severity {
+                Some(severity) => severity.min(diagnostic.severity),
+                None => diagnostic.severity,
+            });
+        }
+        severities
+    }
+
+    /// Move the caret to the start of the next diagnostic after it, wrapping
+    /// around to the first. Returns false if there are none.
+    pub fn next_diagnostic(&mut self) -> bool {
+        let head = self.cursor_position();
+        let starts: Vec<CursorPosition> = self
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.range.start)
+            .collect();
+        let Some(&start) = starts
+            .iter()
+            .find(|&&start| start > head)
+            .or(starts.first())
+        else {
+            return false;
+        };
+        self.set_cursor_position(start);
+        true
+    }
+
+    /// Move the caret to the start of the previous diagnostic before it,
+    /// wrapping around to the last. Returns false if there are none.
+    pub fn previous_diagnostic(&mut self) -> bool {
+        let head = self.cursor_position();
+        let starts: Vec<CursorPosition> = self
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| diagnostic.range.start)
+            .collect();
+        let Some(&start) = starts
+            .iter()
+            .rev()
+            .find(|&&start| start < head)
+            .or(starts.last())
+        else {
+            return false;
+        };
+        self.set_cursor_position(start);
+        true
+    }
+
+    /// Note where the mouse rests over the text, or `None` when it leaves.
+    /// Returns true if that changes which diagnostics are shown.
+    pub fn hover(&mut self, position: Option<CursorPosition>) -> bool {
+        let before = self.hovered_diagnostics();
+        self.hovered_position = position;
+        self.hovered_diagnostics() != before
+    }
+
+    /// Where the mouse rests over the text
+    pub fn hovered_position(&self) -> Option<CursorPosition> {
+        self.hovered_position
+    }
+
+    /// The diagnostics under the mouse, shown in a popover
+    pub fn hovered_diagnostics(&self) -> Vec<Diagnostic> {
+        match self.hovered_position {
+            Some(position) if !self.diagnostics.is_empty() => self.diagnostics_at(position),
+            _ => Vec::new(),
+        }
+    }
+
+    // Completion methods
+
+    /// Suggest completions from `provider` when [`Self::show_completions`]
+    /// is called
+    pub fn set_completion_provider(&mut self, provider: impl CompletionProvider + 'static) {
+        self.completion_provider = Some(Rc::new(provider));
+    }
+
+    pub fn has_completion_provider(&self) -> bool {
+        self.completion_provider.is_some()
+    }
+
+    /// Where the word before the primary caret starts and what has been typed
+    /// of it, unless the primary selection isn't empty
+    fn word_before_cursor(&self) -> Option<(CursorPosition, String)> {
+        let primary = self.selections.primary();
+        if !primary.is_empty() {
+            return None;
+        }
+        let head = primary.head;
+        let before: Vec<char> = self
+            .buffer
+            .get_line(head.row)?
+            .chars()
+            .take(head.col)
+            .collect();
+        let start = before
+            .iter()
+            .rposition(|&ch| !completion::is_word_char(ch))
+            .map_or(0, |index| index + 1);
+        Some((
+            CursorPosition::new(head.row, start),
+            before[start..].iter().collect(),
+        ))
+    }
+
+    /// Ask the completion provider for completions of the word before the
+    /// caret on the background executor. The menu shows once they arrive
+    /// and narrows them as the word is typed.
+    pub fn show_completions(&mut self, cx: &mut Context<EditorView<B>>)
+    where
+        B: Clone + 'static,
+    {
+        let Some(provider) = self.completion_provider.clone() else {
+            return;
+        };
+        let Some((word_start, _)) = self.word_before_cursor() else {
+            return;
+        };
+        let anchor = self.anchor_at(word_start, Bias::Left);
+        let request = self.completions.start(anchor, word_start);
+        let future = provider.completions(self.buffer.snapshot(), self.cursor_position());
+        let task = cx.spawn(async move |view, cx| {
+            let items = cx.background_executor().spawn(future).await;
+            view.update(cx, |view, cx| {
+                if view.editor_mut().completions.finish(request, items) {
+                    cx.notify();
+                }
+            })
+            .ok();
+        });
+        self.completions.set_task(request, task);
+    }
+
+    /// Show `items` for the word before the caret right away, as for
+    /// completions already fetched from a language server
+    pub fn show_completion_items(&mut self, items: Vec<CompletionItem>) {
+        let Some((word_start, _)) = self.word_before_cursor() else {
+            return;
+        };
+        let anchor = self.anchor_at(word_start, Bias::Left);
+        let request = self.completions.start(anchor, word_start);
+        self.completions.finish(request, items);
+    }
+
+    /// Bring the menu up to date with the word being typed, closing it if
+    /// the caret left the word
+    fn sync_completions(&self) {
+        self.completions.sync(|word_start| {
+            let word_start = self.resolve_anchor(word_start)?;
+            let (start, query) = self.word_before_cursor()?;
+            (start == word_start).then_some((start, query))
+        });
+    }
+
+    /// Whether completions were asked for and the caret is still in the word,
+    /// even if none have arrived or match
+    pub fn has_open_completions(&self) -> bool {
+        self.sync_completions();
+        self.completions.is_open()
+    }
+
+    /// The completion menu, if it is open and some items match the word
+    /// being typed
+    pub fn completion_menu(&self) -> Option<CompletionMenu> {
+        self.sync_completions();
+        self.completions.menu()
+    }
+
+    /// Select the next completion. Returns false if the menu isn't showing.
+    pub fn select_next_completion(&mut self) -> bool {
+        self.sync_completions();
+        self.completions.update_menu(CompletionMenu::select_next)
+    }
+
+    /// Select the previous completion. Returns false if the menu isn't
+    /// showing.
+    pub fn select_previous_completion(&mut self) -> bool {
+        self.sync_completions();
+        self.completions
+            .update_menu(CompletionMenu::select_previous)
+    }
+
+    /// Replace what was typed of the word with the selected completion, at
+    /// every caret after the same text, and close the menu. A completion with
+    /// its own range replaces that instead at the caret in it. Returns false
+    /// if the menu isn't showing.
+    pub fn confirm_completion(&mut self) -> bool {
+        let Some(menu) = self.completion_menu() else {
+            return false;
+        };
+        let Some(item) = menu.selected_item() else {
+            return false;
+        };
+        self.completions.close();
+        let query = menu.query();
+        let query_len = query.chars().count();
+        let range = item.range.as_ref().and_then(|range| {
+            let start = self.buffer.resolve_anchor(&range.start)?;
+            Some(start..self.buffer.resolve_anchor(&range.end)?.max(start))
+        });
+        self.edit_selections(|buffer, _, start, end| {
+            // The item's own range, at the caret it was asked for
+            if let Some(range) = range.as_ref() {
+                if start == end && (range.start..=range.end).contains(&start) {
+                    return Some((range.start, range.end, item.insert_text.clone()));
+                }
+            }
+            if start != end || end < query_len {
+                return None;
+            }
+            let word_start = end - query_len;
+            (buffer.text_in_range(word_start, end) == query)
+                .then(|| (word_start, end, item.insert_text.clone()))
+        });
+        true
+    }
+
+    /// Close the completion menu, cancelling a request for it that hasn't
+    /// finished. Returns whether the menu was showing.
+    pub fn cancel_completions(&mut self) -> bool {
+        self.completions.close()
+    }
+
+    // Selection queries
+
+    pub fn has_selection(&self) -> bool {
+        self.selections
+            .iter()
+            .any(|selection| !selection.is_empty())
+    }
+
+    /// The primary selection as (start, end) in document order, if it is not empty
+    pub fn get_selection_range(&self) -> Option<(CursorPosition, CursorPosition)> {
+        let primary = self.selections.primary();
+        (!primary.is_empty()).then(|| primary.range())
+    }
+
+    /// Every non-empty selection as (start, end) in document order
+    pub fn selection_ranges(&self) -> Vec<(CursorPosition, CursorPosition)> {
+        self.selections
+            .iter()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| selection.range())
+            .collect()
+    }
+
+    /// The text of every non-empty selection, one per line
+    pub fn get_selected_text(&self) -> String {
+        self.selection_ranges()
+            .into_iter()
+            .map(|(start, end)| {
+                // Convert cursor positions to buffer positions
+                let start_pos = self.buffer.cursor_to_position(start.row, start.col);
+                let end_pos = self.buffer.cursor_to_position(end.row, end.col);
+                self.buffer.text_in_range(start_pos, end_pos)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Editing methods
+
+    /// Replace each range with its text as a single transaction, as for
+    /// applying formatting. The ranges must not overlap. Selections stay on
+    /// the same text.
+    pub fn apply_text_edits(&mut self, edits: Vec<(Range<CursorPosition>, String)>) {
+        let mut edits: Vec<(usize, usize, String)> = edits
+            .into_iter()
+            .map(|(range, text)| {
+                let start = self.clamp_position(range.start);
+                let end = self.clamp_position(range.end).max(start);
+                (
+                    self.buffer.cursor_to_position(start.row, start.col),
+                    self.buffer.cursor_to_position(end.row, end.col),
+                    text,
+                )
+            })
+            .collect();
+        if edits.is_empty() {
+            return;
+        }
+        edits.sort_by_key(|(start, end, _)| (*start, *end));
+
+        let anchors: Vec<(Anchor, Anchor)> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                (
+                    self.anchor_at(selection.anchor, Bias::Right),
+                    self.anchor_at(selection.head, Bias::Right),
+                )
+            })
+            .collect();
+        self.transact(|editor| {
+            // Apply from the end so earlier offsets stay valid
+            for (start, end, text) in edits.iter().rev() {
+                editor.edit(*start, *end, text);
+            }
+            // Nothing is forgotten during a transaction, so these resolve
+            let resolved: Vec<(CursorPosition, CursorPosition)> = anchors
+                .iter()
+                .filter_map(|(anchor, head)| {
+                    Some((editor.resolve_anchor(anchor)?, editor.resolve_anchor(head)?))
+                })
+                .collect();
+            let mut resolved = resolved.into_iter();
+            editor.selections.update(|selection| {
+                if let Some((anchor, head)) = resolved.next() {
+                    *selection = SelectionRange::new(anchor, head);
+                }
+            });
+        });
+        self.marked_range = None;
+        self.request_autoscroll();
+    }
+
+    /// Apply one edit per selection as a single transaction.
+    ///
+    /// `f` returns the char range to replace and its replacement for each
+    /// selection, or `None` to leave that selection's text alone. Edited
+    /// selections collapse to a caret after the inserted text; the others are
+    /// shifted to stay on the same text.
+    fn edit_selections(
+        &mut self,
+        mut f: impl FnMut(&B, &SelectionRange, usize, usize) -> Option<(usize, usize, String)>,
+    ) {
+        struct PlannedEdit {
+            selection: SelectionRange,
+            anchor: usize,
+            head: usize,
+            edit: Option<(usize, usize, String)>,
+        }
+
+        let mut prev_end = 0;
+        let planned: Vec<PlannedEdit> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                let anchor = self
+                    .buffer
+                    .cursor_to_position(selection.anchor.row, selection.anchor.col);
+                let head = self
+                    .buffer
+                    .cursor_to_position(selection.head.row, selection.head.col);
+                let edit = f(&self.buffer, selection, anchor.min(head), anchor.max(head)).map(
+                    |(start, end, text)| {
+                        // Never let two edits overlap
+                        let start = start.max(prev_end);
+                        let end = end.max(start);
+                        prev_end = end;
+                        (start, end, text)
+                    },
+                );
+                PlannedEdit {
+                    selection: *selection,
+                    anchor,
+                    head,
+                    edit,
+                }
+            })
+            .collect();
+
+        if planned.iter().all(|planned| planned.edit.is_none()) {
+            return;
+        }
+        self.marked_range = None;
+
+        let primary = self.selections.primary_index();
+        self.transact(|editor| {
+            // Apply from the end so earlier offsets stay valid
+            for planned in planned.iter().rev() {
+                if let Some((start, end, text)) = &planned.edit {
+                    editor.edit(*start, *end, text);
+                }
+            }
+
+            let mut delta: isize = 0;
+            let mut ranges = Vec::with_capacity(planned.len());
+            for planned in planned {
+                let shift = |offset: usize| (offset as isize + delta) as usize;
+                match planned.edit {
+                    Some((start, end, text)) => {
+                        let inserted = text.chars().count();
+                        let (row, col) = editor.buffer.position_to_cursor(shift(start) + inserted);
+                        ranges.push(SelectionRange::caret(CursorPosition::new(row, col)));
+                        delta += inserted as isize - (end - start) as isize;
+                    }
+                    None => {
+                        let (anchor_row, anchor_col) =
+                            editor.buffer.position_to_cursor(shift(planned.anchor));
+                        let (head_row, head_col) =
+                            editor.buffer.position_to_cursor(shift(planned.head));
+                        let mut range = SelectionRange::new(
+                            CursorPosition::new(anchor_row, anchor_col),
+                            CursorPosition::new(head_row, head_col),
+                        );
+                        range.goal_column = planned.selection.goal_column;
+                        ranges.push(range);
+                    }
+                }
+            }
+            editor.selections = Selections::from_ranges(ranges, primary);
+        });
+        self.request_autoscroll();
+    }
+
+    /// Delete the text of every non-empty selection. Returns false if nothing was selected.
+    pub fn delete_selection(&mut self) -> bool {
+        if !self.has_selection() {
+            return false;
+        }
+        self.edit_selections(|_, selection, start, end| {
+            (!selection.is_empty()).then(|| (start, end, String::new()))
+        });
+        true
+    }
+
+    /// Replace every selection with `text`
+    pub fn insert_text(&mut self, text: &str) {
+        self.edit_selections(|_, _, start, end| Some((start, end, text.to_string())));
+    }
+
+    /// Paste clipboard text.
+    ///
+    /// When the text has exactly one line per cursor, each cursor receives its
+    /// own line; otherwise the whole text is inserted at every cursor.
+    pub fn paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "");
+        let lines: Vec<&str> = text.split('\n').collect();
+        if self.selections.count() > 1 && lines.len() == self.selections.count() {
+            let mut lines = lines.into_iter();
+            self.edit_selections(|_, _, start, end| {
+                Some((start, end, lines.next().unwrap_or_default().to_string()))
+            });
+        } else {
+            self.insert_text(&text);
+        }
+    }
+
+    /// Type `ch` at every selection.
+    ///
+    /// An opening bracket or quote also inserts its closing character, unless
+    /// the caret is in a string or comment, and wraps a selection rather than
+    /// replacing it. A closing character typed right before the same one skips
+    /// over it. A closing bracket typed on a line with nothing but indentation
+    /// before the caret takes away one level of it.
+    pub fn insert_char(&mut self, ch: char) {
+        let tab_size = self.config.tab_size.max(1);
+        let hard_tabs = self.config.hard_tabs;
+        let pairs = autoclose::pairs(&self.language);
+        let closing = autoclose::closing(pairs, ch);
+        let in_string_or_comment: Vec<bool> = self
+            .selections
+            .iter()
+            .map(|selection| closing.is_some() && self.is_in_string_or_comment(selection.head))
+            .collect();
+
+        self.transact(|editor| {
+            // Consecutive typing is undone as a single step
+            editor.history.set_mergeable();
+            let mut in_string_or_comment = in_string_or_comment.into_iter();
+            let mut reselect = Vec::new();
+            editor.edit_selections(|buffer, selection, start, end| {
+                let in_string_or_comment = in_string_or_comment.next().unwrap_or(false);
+                let head = selection.head;
+                let line: Vec<char> = buffer
+                    .get_line(head.row)
+                    .unwrap_or_default()
+                    .chars()
+                    .collect();
+                let prev = head
+                    .col
+                    .checked_sub(1)
+                    .and_then(|col| line.get(col))
+                    .copied();
+                let next = line.get(head.col).copied();
+
+                if let (Some(close), false) = (closing, selection.is_empty()) {
+                    // Keep the wrapped text selected
+                    let text = buffer.text_in_range(start, end);
+                    let len = text.chars().count();
+                    reselect.push(Some((len + 1, 1)));
+                    return Some((start, end, format!("{ch}{text}{close}")));
+                }
+                if selection.is_empty() && next == Some(ch) && autoclose::is_closing(pairs, ch) {
+                    // A quote only closes the string the caret is in
+                    if closing != Some(ch) || in_string_or_comment {
+                        reselect.push(None);
+                        return Some((start, start + 1, ch.to_string()));
+                    }
+                }
+                if let Some(close) = closing {
+                    if !in_string_or_comment && autoclose::should_close(pairs, prev, next, ch) {
+                        reselect.push(Some((1, 1)));
+                        return Some((start, end, format!("{ch}{close}")));
+                    }
+                }
+                reselect.push(None);
+
+                if selection.is_empty() && indent::is_closing_bracket(ch) {
+                    let before: String = line[..head.col.min(line.len())].iter().collect();
+                    if !before.is_empty() && before.trim().is_empty() {
+                        let width =
+                            indent::indent_width(&before, tab_size).saturating_sub(tab_size);
+                        let mut text = indent::indent_of_width(width, tab_size, hard_tabs);
+                        text.push(ch);
+                        return Some((start - head.col, start, text));
+                    }
+                }
+                Some((start, end, ch.to_string()))
+            });
+            editor.move_carets_back(reselect);
+        });
+    }
+
+    /// Break the line at every selection, keeping the line's indentation and
+    /// adding a level after a line that opens a block. Between a pair of
+    /// brackets, the closing bracket moves to a line of its own.
+    pub fn insert_newline(&mut self) {
+        let language = self.language.clone();
+        let unit = indent::indent_unit(self.config.tab_size.max(1), self.config.hard_tabs);
+        let mut reselect = Vec::new();
+        self.edit_selections(|buffer, selection, start, end| {
+            let (first, last) = selection.range();
+            let line = buffer.get_line(first.row).unwrap_or_default();
+            let before: String = line.chars().take(first.col).collect();
+            let after = buffer.get_line(last.row).unwrap_or_default();
+            let after: String = after.chars().skip(last.col).collect();
+
+            let indent = indent::leading_whitespace(&before);
+            let mut text = format!("\n{indent}");
+            if indent::increases_indent(&language, &before) {
+                text.push_str(&unit);
+            }
+            let split = before
+                .trim_end()
+                .chars()
+                .next_back()
+                .and_then(indent::closing_bracket)
+                .is_some_and(|close| after.trim_start().starts_with(close));
+            if split {
+                // The caret goes on the new line between the brackets
+                let closing_line = format!("\n{indent}");
+                let len = closing_line.chars().count();
+                text.push_str(&closing_line);
+                reselect.push(Some((len, len)));
+            } else {
+                reselect.push(None);
+            }
+            Some((start, end, text))
+        });
+        self.move_carets_back(reselect);
+    }
+
+    /// Adjust the carets left by [`Self::edit_selections`]. For each caret,
+    /// `offsets` can give how many chars before it the selection's anchor and
+    /// head should be instead.
+    fn move_carets_back(&mut self, offsets: Vec<Option<(usize, usize)>>) {
+        if offsets.iter().all(Option::is_none) {
+            return;
+        }
+        let buffer = &self.buffer;
+        let position = |offset: usize| {
+            let (row, col) = buffer.position_to_cursor(offset);
+            CursorPosition::new(row, col)
+        };
+        let mut offsets = offsets.into_iter();
+        self.selections.update(|selection| {
+            if let Some(Some((anchor_back, head_back))) = offsets.next() {
+                let caret = buffer.cursor_to_position(selection.head.row, selection.head.col);
+                *selection =
+                    SelectionRange::new(position(caret - anchor_back), position(caret - head_back));
+            }
+        });
+    }
+
+    /// Whether the text around `position` is inside a string or a comment
+    fn is_in_string_or_comment(&self, position: CursorPosition) -> bool {
+        let Some(line) = self.buffer.get_line(position.row) else {
+            return false;
+        };
+        let index = coordinates::char_to_byte(&line, position.col);
+        let mut highlighter = self.syntax_highlighter.share();
+        let buffer = &self.buffer;
+        // Both the text before the caret and the text after it, so a caret
+        // right outside a string's quotes isn't in the string
+        [index, index + 1].into_iter().all(|index| {
+            highlighter
+                .scopes_at(&self.language, position.row, &line, index, |row| {
+                    buffer.get_line(row)
+                })
+                .is_some_and(|scopes| syntax_highlighter::is_string_or_comment(&scopes))
+        })
+    }
+
+    /// Insert a tab at every caret, or indent the selected lines if a
+    /// selection spans several lines. Soft tabs insert spaces up to the next
+    /// tab stop.
+    pub fn tab(&mut self) {
+        let spans_lines = self
+            .selections
+            .iter()
+            .any(|selection| selection.start().row != selection.end().row);
+        if spans_lines {
+            self.indent_selection();
+            return;
+        }
+
+        let tab_size = self.config.tab_size.max(1);
+        let hard_tabs = self.config.hard_tabs;
+        self.edit_selections(|buffer, selection, start, end| {
+            if hard_tabs {
+                return Some((start, end, "\t".to_string()));
+            }
+            let first = selection.start();
+            let line = buffer.get_line(first.row).unwrap_or_default();
+            let before: String = line.chars().take(first.col).collect();
+            let spaces = tab_size - indent::indent_width(&before, tab_size) % tab_size;
+            Some((start, end, " ".repeat(spaces)))
+        });
+    }
+
+    /// Take away one level of indentation from the selected lines
+    pub fn backtab(&mut self) {
+        self.outdent_selection();
+    }
+
+    /// Add one level of indentation to every non-empty selected line
+    pub fn indent_selection(&mut self) {
+        let unit = indent::indent_unit(self.config.tab_size.max(1), self.config.hard_tabs);
+        self.edit_line_starts(|line| (!line.is_empty()).then(|| (0, unit.clone())));
+    }
+
+    /// Take away one level of indentation from every selected line
+    pub fn outdent_selection(&mut self) {
+        let tab_size = self.config.tab_size.max(1);
+        self.edit_line_starts(|line| {
+            let len = indent::outdent_len(line, tab_size);
+            (len > 0).then(|| (len, String::new()))
+        });
+    }
+
+    /// Every row touched by a selection, in order. A selection ending at the
+    /// start of a row doesn't touch that row.
+    fn selected_rows(&self) -> Vec<usize> {
+        let mut rows: Vec<usize> = Vec::new();
+        for selection in self.selections.iter() {
+            let (start, end) = selection.range();
+            let last = if end.col == 0 && end.row > start.row {
+                end.row - 1
+            } else {
+                end.row
+            };
+            let first = match rows.last() {
+                Some(&row) => start.row.max(row + 1),
+                None => start.row,
+            };
+            rows.extend(first..=last);
+        }
+        rows
+    }
+
+    /// Runs of consecutive selected rows
+    fn selected_row_blocks(&self) -> Vec<Range<usize>> {
+        let mut blocks: Vec<Range<usize>> = Vec::new();
+        for row in self.selected_rows() {
+            match blocks.last_mut() {
+                Some(block) if block.end == row => block.end += 1,
+                _ => blocks.push(row..row + 1),
+            }
+        }
+        blocks
+    }
+
+    /// Replace the start of every selected line as a single transaction.
+    /// `f` returns the number of chars to remove from the start of a line and
+    /// the text to put in their place, or `None` to leave the line alone.
+    /// Selections stay on the same text.
+    fn edit_line_starts(&mut self, f: impl Fn(&str) -> Option<(usize, String)>) {
+        let edits = self
+            .selected_rows()
+            .into_iter()
+            .filter_map(|row| {
+                let line = self.buffer.get_line(row).unwrap_or_default();
+                let (removed, text) = f(&line)?;
+                Some((row, (0, removed, text)))
+            })
+            .collect();
+        self.edit_within_rows(edits);
+    }
+
+    /// Make edits within rows as a single transaction. Each edit is a row and
+    /// the [`comment::SpanEdit`] to make in it, sorted by row and column.
+    /// Selections stay on the same text.
+    fn edit_within_rows(&mut self, edits: Vec<(usize, comment::SpanEdit)>) {
+        if edits.is_empty() {
+            return;
+        }
+        self.marked_range = None;
+
+        // Positions inside removed text end up after the text replacing it
+        let shift = |position: &mut CursorPosition| {
+            let mut delta = 0;
+            for (_, (col, removed, text)) in edits.iter().filter(|(row, _)| *row == position.row) {
+                let len = text.chars().count() as isize;
+                if col + removed <= position.col {
+                    delta += len - *removed as isize;
+                } else {
+                    if *col <= position.col {
+                        delta += col.cast_signed() + len - position.col.cast_signed();
+                    }
+                    break;
+                }
+            }
+            position.col = position.col.saturating_add_signed(delta);
+        };
+
+        self.transact(|editor| {
+            for (row, (col, removed, text)) in edits.iter().rev() {
+                let start = editor.buffer.cursor_to_position(*row, *col);
+                editor.edit(start, start + removed, text);
+            }
+            editor.selections.update(|selection| {
+                shift(&mut selection.anchor);
+                shift(&mut selection.head);
+                selection.goal_column = None;
+            });
+        });
+        self.request_autoscroll();
+    }
+
+    /// Delete the grapheme before every caret, and the closing character
+    /// after it too if the caret is between an empty pair
+    pub fn backspace(&mut self) {
+        let pairs = autoclose::pairs(&self.language);
+        self.edit_selections(|buffer, selection, start, end| {
+            let head = selection.head;
+            let line: Vec<char> = buffer
+                .get_line(head.row)
+                .unwrap_or_default()
+                .chars()
+                .collect();
+            let prev = head
+                .col
+                .checked_sub(1)
+                .and_then(|col| line.get(col))
+                .copied();
+            let next = line.get(head.col).copied();
+            if !selection.is_empty() {
+                // If there's a selection, delete it instead
+                Some((start, end, String::new()))
+            } else if prev
+                .and_then(|prev| autoclose::closing(pairs, prev))
+                .is_some_and(|close| Some(close) == next)
+            {
+                Some((start - 1, start + 1, String::new()))
+            } else if start > 0 {
+                Some((buffer.prev_grapheme_boundary(start), start, String::new()))
+            } else {
+                None
+            }
+        });
+    }
+
+    pub fn delete(&mut self) {
+        self.edit_selections(|buffer, selection, start, end| {
+            if !selection.is_empty() {
+                // If there's a selection, delete it instead
+                Some((start, end, String::new()))
+            } else if start < buffer.len() {
+                Some((start, buffer.next_grapheme_boundary(start), String::new()))
+            } else {
+                None
+            }
+        });
+    }
+
+    pub fn delete_to_previous_word_start(&mut self) {
+        self.delete_to(|buffer, head| {
+            previous_boundary(buffer, head, movement::previous_word_start)
+        });
+    }
+
+    pub fn delete_to_next_word_end(&mut self) {
+        self.delete_to(|buffer, head| next_boundary(buffer, head, movement::next_word_end));
+    }
+
+    pub fn delete_to_previous_subword_start(&mut self) {
+        self.delete_to(|buffer, head| {
+            previous_boundary(buffer, head, movement::previous_subword_start)
+        });
+    }
+
+    pub fn delete_to_next_subword_end(&mut self) {
+        self.delete_to(|buffer, head| next_boundary(buffer, head, movement::next_subword_end));
+    }
+
+    /// Delete from each caret back to column 0
+    pub fn delete_to_line_start(&mut self) {
+        self.delete_to(|_, head| CursorPosition::new(head.row, 0));
+    }
+
+    /// Delete from each caret to the end of its line
+    pub fn delete_to_line_end(&mut self) {
+        self.delete_to(|buffer, head| CursorPosition::new(head.row, buffer.line_len(head.row)));
+    }
+
+    /// Delete every selection, or for carets, the text between the caret and
+    /// `target(caret)`
+    fn delete_to(&mut self, target: impl Fn(&B, CursorPosition) -> CursorPosition) {
+        self.edit_selections(|buffer, selection, start, end| {
+            if !selection.is_empty() {
+                return Some((start, end, String::new()));
+            }
+            let target = target(buffer, selection.head);
+            let target = buffer.cursor_to_position(target.row, target.col);
+            (target != start).then(|| (start.min(target), start.max(target), String::new()))
+        });
+    }
+
+    // Line methods
+
+    /// Swap the selected lines with the line above them
+    pub fn move_lines_up(&mut self) {
+        self.edit_selected_lines(|buffer, block| {
+            let above = block.start.checked_sub(1)?;
+            let mut lines = block_lines(buffer, block.clone());
+            lines.push(buffer.get_line(above).unwrap_or_default());
+            Some(LineEdit {
+                rows: above..block.end,
+                lines,
+                map: Box::new(|position| CursorPosition::new(position.row - 1, position.col)),
+            })
+        });
+    }
+
+    /// Swap the selected lines with the line below them
+    pub fn move_lines_down(&mut self) {
+        self.edit_selected_lines(|buffer, block| {
+            let below = block.end;
+            if below >= buffer.line_count() {
+                return None;
+            }
+            let mut lines = vec![buffer.get_line(below).unwrap_or_default()];
+            lines.extend(block_lines(buffer, block.clone()));
+            Some(LineEdit {
+                rows: block.start..below + 1,
+                lines,
+                map: Box::new(|position| CursorPosition::new(position.row + 1, position.col)),
+            })
+        });
+    }
+
+    /// Insert a copy of the selected lines below them, selecting the copy
+    pub fn duplicate_lines(&mut self) {
+        self.edit_selected_lines(|buffer, block| {
+            let lines = block_lines(buffer, block.clone());
+            let len = lines.len();
+            Some(LineEdit {
+                rows: block,
+                lines: [lines.clone(), lines].concat(),
+                map: Box::new(move |position| {
+                    CursorPosition::new(position.row + len, position.col)
+                }),
+            })
+        });
+    }
+
+    /// Delete the selected lines
+    pub fn delete_lines(&mut self) {
+        self.edit_selected_lines(|buffer, block| {
+            let line_count = buffer.line_count();
+            if block == (0..line_count) {
+                return Some(LineEdit {
+                    rows: block,
+                    lines: vec![String::new()],
+                    map: Box::new(|_| CursorPosition::new(0, 0)),
+                });
+            }
+            // Carets stay on the line that takes the place of the deleted
+            // ones, or the line above at the end of the text
+            let row = if block.end < line_count {
+                block.start
+            } else {
+                block.start - 1
+            };
+            Some(LineEdit {
+                rows: block,
+                lines: Vec::new(),
+                map: Box::new(move |position| CursorPosition::new(row, position.col)),
+            })
+        });
+    }
+
+    /// Join the selected lines into one, or a line with the line below it if
+    /// only one line is selected. See [`line_ops::join`] for the spacing.
+    pub fn join_lines(&mut self) {
+        self.edit_selected_lines(|buffer, block| {
+            let rows = if block.len() == 1 {
+                block.start..block.end + 1
+            } else {
+                block
+            };
+            if rows.end > buffer.line_count() {
+                return None;
+            }
+            let (joined, starts) = line_ops::join(&block_lines(buffer, rows.clone()));
+            let first = rows.start;
+            let removed_rows = rows.len() - 1;
+            Some(LineEdit {
+                rows,
+                lines: vec![joined],
+                map: Box::new(move |position| match starts.get(position.row - first) {
+                    Some(&(start, removed)) => {
+                        CursorPosition::new(first, start + position.col.saturating_sub(removed))
+                    }
+                    None => CursorPosition::new(position.row - removed_rows, position.col),
+                }),
+            })
+        });
+    }
+
+    /// Sort the selected lines. Selections keep covering the sorted rows.
+    pub fn sort_lines(&mut self, options: SortOptions) {
+        self.edit_selected_lines(|buffer, block| {
+            let lines = line_ops::sort(&block_lines(buffer, block.clone()), options);
+            let last_row = block.start + lines.len() - 1;
+            let removed_rows = block.len() - lines.len();
+            let end = block.end;
+            Some(LineEdit {
+                rows: block,
+                lines,
+                map: Box::new(move |position| {
+                    let row = if position.row < end {
+                        position.row.min(last_row)
+                    } else {
+                        position.row - removed_rows
+                    };
+                    CursorPosition::new(row, position.col)
+                }),
+            })
+        });
+    }
+
+    /// Reverse the order of the selected lines. Selections keep covering the
+    /// reversed rows.
+    pub fn reverse_lines(&mut self) {
+        self.edit_selected_lines(|buffer, block| {
+            let mut lines = block_lines(buffer, block.clone());
+            lines.reverse();
+            Some(LineEdit {
+                rows: block,
+                lines,
+                map: Box::new(|position| position),
+            })
+        });
+    }
+
+    /// Remove whitespace from the end of every line
+    pub fn trim_trailing_whitespace(&mut self) {
+        let blocks = (0..self.buffer.line_count())
+            .map(|row| row..row + 1)
+            .collect();
+        self.edit_lines(blocks, |buffer, block| {
+            let line = buffer.get_line(block.start).unwrap_or_default();
+            let trimmed = line.trim_end();
+            (trimmed.len() < line.len()).then(|| LineEdit {
+                rows: block,
+                lines: vec![trimmed.to_string()],
+                map: Box::new(|position| position),
+            })
+        });
+    }
+
+    /// The comment markers of the editor's language
+    pub fn comment_tokens(&self) -> CommentTokens {
+        self.syntax_highlighter.comment_tokens(&self.language)
+    }
+
+    /// Comment or uncomment the selected lines. Each run of consecutive lines
+    /// is uncommented if all of its non-blank lines are comments, and
+    /// commented otherwise. See the [`comment`] module for the details.
+    pub fn toggle_line_comment(&mut self) {
+        let tokens = self.comment_tokens();
+        let mut edits = Vec::new();
+        for block in self.selected_row_blocks() {
+            let lines = block_lines(&self.buffer, block.clone());
+            for (row, line_edits) in block.zip(comment::toggle_line_comments(&lines, &tokens)) {
+                edits.extend(line_edits.into_iter().map(|edit| (row, edit)));
+            }
+        }
+        self.edit_within_rows(edits);
+    }
+
+    /// Wrap every selection in a block comment, or unwrap it if it is one or
+    /// is the inside of one. An empty selection toggles a block comment
+    /// around the text of its line. Languages without block comments toggle
+    /// line comments instead.
+    pub fn toggle_block_comment(&mut self) {
+        let Some((open, close)) = self.comment_tokens().block else {
+            self.toggle_line_comment();
+            return;
+        };
+        let open_len = open.chars().count();
+        let close_len = close.chars().count();
+
+        // Where each selection goes within its replacement, as offsets back
+        // from the end of it
+        let mut offsets = Vec::new();
+        self.edit_selections(|buffer, selection, start, end| {
+            let (start, end) = if selection.is_empty() {
+                let row = selection.head.row;
+                let line = buffer.get_line(row).unwrap_or_default();
+                let indent = line.chars().take_while(|ch| ch.is_whitespace()).count();
+                let end = line.trim_end().chars().count().max(indent);
+                let line_start = buffer.cursor_to_position(row, 0);
+                (line_start + indent, line_start + end)
+            } else {
+                (start, end)
+            };
+            let text = buffer.text_in_range(start, end);
+            let text_len = text.chars().count();
+            // The caret's offset into the text, for empty selections
+            let caret = buffer
+                .cursor_to_position(selection.head.row, selection.head.col)
+                .saturating_sub(start)
+                .min(text_len);
+
+            let before = buffer.text_in_range(start.saturating_sub(open_len), start);
+            let after = buffer.text_in_range(end, (end + close_len).min(buffer.len()));
+            let surrounding = (!selection.is_empty())
+                .then(|| comment::surrounding_block(&before, &after, &open, &close))
+                .flatten();
+            let (edit, selected) = if let Some((before_len, after_len)) = surrounding {
+                // The selection is the inside of a comment
+                ((start - before_len, end + after_len, text), 0..text_len)
+            } else if let Some((inner, removed)) = comment::unwrap_block(&text, &open, &close) {
+                let inner_len = inner.chars().count();
+                let caret = caret.saturating_sub(removed).min(inner_len);
+                let selected = if selection.is_empty() {
+                    caret..caret
+                } else {
+                    0..inner_len
+                };
+                ((start, end, inner), selected)
+            } else {
+                let caret = open_len + caret;
+                let selected = if selection.is_empty() {
+                    caret..caret
+                } else {
+                    open_len..open_len + text_len
+                };
+                ((start, end, format!("{open}{text}{close}")), selected)
+            };
+
+            let len = edit.2.chars().count();
+            let (anchor, head) = if selection.is_reversed() {
+                (selected.end, selected.start)
+            } else {
+                (selected.start, selected.end)
+            };
+            offsets.push(Some((len - anchor, len - head)));
+            Some(edit)
+        });
+        self.move_carets_back(offsets);
+    }
+
+    /// Apply a line command to each run of consecutive selected rows
+    fn edit_selected_lines(&mut self, plan: impl Fn(&B, Range<usize>) -> Option<LineEdit>) {
+        let blocks = self.selected_row_blocks();
+        self.edit_lines(blocks, plan);
+    }
+
+    /// Replace rows as a single transaction. `plan` gets each block of rows a
+    /// command acts on, in order, and returns how to edit it; selections in a
+    /// block move as its [`LineEdit::map`] says and the others stay on the
+    /// same text.
+    fn edit_lines(
+        &mut self,
+        blocks: Vec<Range<usize>>,
+        plan: impl Fn(&B, Range<usize>) -> Option<LineEdit>,
+    ) {
+        let edits: Vec<(Range<usize>, LineEdit)> = blocks
+            .into_iter()
+            .filter_map(|block| {
+                let edit = plan(&self.buffer, block.clone())?;
+                let changed = edit.lines != block_lines(&self.buffer, edit.rows.clone());
+                changed.then_some((block, edit))
+            })
+            .collect();
+        if edits.is_empty() {
+            return;
+        }
+        self.marked_range = None;
+
+        // Where each selection ends up, before clamping to the new text
+        let shift = |position: CursorPosition, delta: isize| {
+            CursorPosition::new(position.row.saturating_add_signed(delta), position.col)
+        };
+        let selections: Vec<SelectionRange> = self
+            .selections
+            .iter()
+            .map(|selection| {
+                let start_row = selection.start().row;
+                match edits
+                    .iter()
+                    .position(|(block, _)| block.contains(&start_row))
+                {
+                    Some(index) => {
+                        let delta = edits[..index]
+                            .iter()
+                            .map(|(_, edit)| edit.row_delta())
+                            .sum();
+                        let map = &edits[index].1.map;
+                        SelectionRange::new(
+                            shift(map(selection.anchor), delta),
+                            shift(map(selection.head), delta),
+                        )
+                    }
+                    None => {
+                        let delta = edits
+                            .iter()
+                            .filter(|(_, edit)| edit.rows.end <= start_row)
+                            .map(|(_, edit)| edit.row_delta())
+                            .sum();
+                        SelectionRange::new(
+                            shift(selection.anchor, delta),
+                            shift(selection.head, delta),
+                        )
+                    }
+                }
+            })
+            .collect();
+
+        self.transact(|editor| {
+            // Apply from the end so earlier rows stay valid
+            for (_, edit) in edits.iter().rev() {
+                let buffer = &editor.buffer;
+                let line_end = |row: usize| buffer.cursor_to_position(row, buffer.line_len(row));
+                let (start, end) = if !edit.lines.is_empty() {
+                    (
+                        buffer.cursor_to_position(edit.rows.start, 0),
+                        line_end(edit.rows.end - 1),
+                    )
+                } else if edit.rows.end < buffer.line_count() {
+                    (
+                        buffer.cursor_to_position(edit.rows.start, 0),
+                        buffer.cursor_to_position(edit.rows.end, 0),
+                    )
+                } else {
+                    // The last rows take the line break before them instead
+                    (line_end(edit.rows.start - 1), buffer.len())
+                };
+                editor.edit(start, end, &edit.lines.join("\n"));
+            }
+
+            let buffer = &editor.buffer;
+            let clamp = |position: CursorPosition| {
+                let row = position.row.min(buffer.line_count() - 1);
+                CursorPosition::new(row, position.col.min(buffer.line_len(row)))
+            };
+            let mut selections = selections.into_iter();
+            editor.selections.update(|selection| {
+                if let Some(moved) = selections.next() {
+                    *selection = SelectionRange::new(clamp(moved.anchor), clamp(moved.head));
+                }
+            });
+        });
+        self.request_autoscroll();
+    }
+
+    // Text input methods
+    //
+    // These back gpui's `EntityInputHandler`. Platform input methods address
+    // the document in UTF-16 code units, while the editor works in chars.
+
+    /// The marked (composing) text range in chars, if an input method is composing
+    pub fn marked_range(&self) -> Option<Range<usize>> {
+        self.marked_range.clone()
+    }
+
+    pub fn marked_utf16_range(&self) -> Option<Range<usize>> {
+        self.marked_range
+            .as_ref()
+            .map(|range| self.char_range_to_utf16(range))
+    }
+
+    /// Commit the marked text as-is
+    pub fn unmark_text(&mut self) {
+        self.marked_range = None;
+    }
+
+    /// The text in the UTF-16 `range`, along with the range actually covered
+    /// once it is clamped to the document and to char boundaries
+    pub fn text_for_utf16_range(&self, range: Range<usize>) -> (String, Range<usize>) {
+        let range = self.utf16_range_to_char(&range);
+        let text = self.buffer.text_in_range(range.start, range.end);
+        (text, self.char_range_to_utf16(&range))
+    }
+
+    /// The primary selection in UTF-16 units, and whether it is reversed
+    pub fn selected_utf16_range(&self) -> (Range<usize>, bool) {
+        let selection = self.selections.primary();
+        let (start, end) = selection.range();
+        let start = self.buffer.cursor_to_position(start.row, start.col);
+        let end = self.buffer.cursor_to_position(end.row, end.col);
+        (
+            self.char_range_to_utf16(&(start..end)),
+            selection.is_reversed(),
+        )
+    }
+
+    /// Insert text coming from the platform, ending any composition.
+    ///
+    /// Replaces the UTF-16 `range` if given, otherwise the marked text, otherwise
+    /// every selection. Consecutive input is grouped into one undo step like typing.
+    pub fn replace_text_in_utf16_range(&mut self, range: Option<Range<usize>>, text: &str) {
+        let range = range
+            .map(|range| self.utf16_range_to_char(&range))
+            .or_else(|| self.marked_range.clone());
+        self.transact(|editor| {
+            editor.history.set_mergeable();
+            let mut chars = text.chars();
+            match (range, chars.next(), chars.next()) {
+                (Some(range), _, _) => {
+                    editor.replace_and_select(range, text, None);
+                }
+                // A typed character gets bracket pairing and indentation
+                (None, Some(ch), None) => editor.insert_char(ch),
+                (None, _, _) => editor.insert_text(text),
+            }
+        });
+        self.marked_range = None;
+    }
+
+    /// Replace text and mark the result as being composed by an input method.
+    ///
+    /// Replaces the UTF-16 `range` if given, otherwise the current marked text,
+    /// otherwise the primary selection; composition only happens at the primary
+    /// cursor. `new_selected_range` is relative to `text`, in UTF-16 units.
+    pub fn replace_and_mark_text_in_utf16_range(
+        &mut self,
+        range: Option<Range<usize>>,
+        text: &str,
+        new_selected_range: Option<Range<usize>>,
+    ) {
+        let range = range
+            .map(|range| self.utf16_range_to_char(&range))
+            .or_else(|| self.marked_range.clone())
+            .unwrap_or_else(|| {
+                let (start, end) = self.selections.primary().range();
+                self.buffer.cursor_to_position(start.row, start.col)
+                    ..self.buffer.cursor_to_position(end.row, end.col)
+            });
+        let selected = new_selected_range.map(|selected| {
+            coordinates::utf16_to_char(text, selected.start)
+                ..coordinates::utf16_to_char(text, selected.end)
+        });
+
+        let start = range.start;
+        self.transact(|editor| {
+            // A whole composition is undone as one step
+            editor.history.set_mergeable();
+            editor.replace_and_select(range, text, selected);
+        });
+        self.marked_range = (!text.is_empty()).then(|| start..start + text.chars().count());
+    }
+
+    /// Window bounds of the text in the UTF-16 `range`, used to position the
+    /// input method's candidate window. Returns `None` if the range is not on screen.
+    pub fn bounds_for_utf16_range(&self, range: Range<usize>) -> Option<Bounds<Pixels>> {
+        let range = self.utf16_range_to_char(&range);
+        let (row, col) = self.buffer.position_to_cursor(range.start);
+        let scroll_top = self.scroll_top();
+        let start = self.position_map.point_for_position(
+            CursorPosition::new(row, col),
+            scroll_top,
+            &self.display_map,
+        )?;
+
+        let (end_row, end_col) = self.buffer.position_to_cursor(range.end);
+        let end = CursorPosition::new(end_row, end_col);
+        let end_x = if self.display_row(end) == self.display_row(CursorPosition::new(row, col)) {
+            self.position_map
+                .point_for_position(end, scroll_top, &self.display_map)
+                .map_or(start.x, |end| end.x)
+        } else {
+            start.x
+        };
+        Some(Bounds::from_corners(
+            start,
+            point(end_x, start.y + self.position_map.line_height()),
+        ))
+    }
+
+    /// The UTF-16 offset of the character under `point` in window coordinates
+    pub fn utf16_offset_for_point(&self, point: Point<Pixels>) -> Option<usize> {
+        let position = self.position_for_point(point)?;
+        let offset = self.buffer.cursor_to_position(position.row, position.col);
+        Some(self.buffer.char_to_utf16(offset))
+    }
+
+    /// Replace the chars in `range` with `text` and leave a single selection:
+    /// `selected` (relative to `text`, in chars) or a caret after the text.
+    /// Must be called inside a transaction.
+    fn replace_and_select(
+        &mut self,
+        range: Range<usize>,
+        text: &str,
+        selected: Option<Range<usize>>,
+    ) {
+        self.edit(range.start, range.end, text);
+        let inserted = text.chars().count();
+        let selected = selected.unwrap_or(inserted..inserted);
+        let to_cursor = |editor: &Self, offset: usize| {
+            let (row, col) = editor
+                .buffer
+                .position_to_cursor(range.start + offset.min(inserted));
+            CursorPosition::new(row, col)
+        };
+        let selection = SelectionRange::new(
+            to_cursor(self, selected.start),
+            to_cursor(self, selected.end),
+        );
+        self.selections.set_single(selection);
+        self.request_autoscroll();
+    }
+
+    fn utf16_range_to_char(&self, range: &Range<usize>) -> Range<usize> {
+        let start = self.buffer.utf16_to_char(range.start);
+        let end = self.buffer.utf16_to_char(range.end);
+        start..end.max(start)
+    }
+
+    fn char_range_to_utf16(&self, range: &Range<usize>) -> Range<usize> {
+        self.buffer.char_to_utf16(range.start)..self.buffer.char_to_utf16(range.end)
+    }
+}
+
+/// The column closest to `goal` on `row` that is not inside a grapheme
+/// Apply a backward in-line `motion` to `head`, moving to the end of the
+/// previous line from column 0
+/// The text of `rows`
+fn block_lines<B: TextBuffer>(buffer: &B, rows: Range<usize>) -> Vec<String> {
+    rows.map(|row| buffer.get_line(row).unwrap_or_default())
+        .collect()
+}
+
+fn previous_boundary<B: TextBuffer>(
+    buffer: &B,
+    head: CursorPosition,
+    motion: fn(&str, usize) -> usize,
+) -> CursorPosition {
+    if head.col == 0 {
+        return match head.row.checked_sub(1) {
+            Some(row) => CursorPosition::new(row, buffer.line_len(row)),
+            None => head,
+        };
+    }
+    let line = buffer.get_line(head.row).unwrap_or_default();
+    CursorPosition::new(head.row, motion(&line, head.col))
+}
+
+/// Apply a forward in-line `motion` to `head`, moving to the start of the next
+/// line from the end of a line
+fn next_boundary<B: TextBuffer>(
+    buffer: &B,
+    head: CursorPosition,
+    motion: fn(&str, usize) -> usize,
+) -> CursorPosition {
+    if head.col >= buffer.line_len(head.row) {
+        return if head.row + 1 < buffer.line_count() {
+            CursorPosition::new(head.row + 1, 0)
+        } else {
+            head
+        };
+    }
+    let line = buffer.get_line(head.row).unwrap_or_default();
+    CursorPosition::new(head.row, motion(&line, head.col))
+}
+
+/// Where `selection`'s head ends up `rows` display rows down (or up, if
+/// negative), and the goal column to keep for the next vertical move. `None`
+/// if the head is already on the first or last display row.
+fn move_display_rows<B: TextBuffer>(
+    buffer: &B,
+    display_map: &DisplayMap,
+    selection: &SelectionRange,
+    rows: isize,
+) -> Option<(CursorPosition, Option<Pixels>)> {
+    let head = selection.head;
+    let display_row = display_map.display_row(head);
+    let last_display_row = display_map
+        .display_row_count(buffer.line_count())
+        .saturating_sub(1);
+    let target = display_row
+        .saturating_add_signed(rows)
+        .min(last_display_row);
+    if target == display_row {
+        return None;
+    }
+
+    let goal = selection
+        .goal_column
+        .unwrap_or_else(|| display_map.x_for_position(head));
+    let (row, wrap_row) = display_map.buffer_row(target);
+    let col = display_map.col_for_x(row, wrap_row, goal, buffer.line_len(row));
+    Some((
+        CursorPosition::new(row, column_for_goal(buffer, row, col)),
+        Some(goal),
+    ))
+}
+
+fn column_for_goal<B: TextBuffer>(buffer: &B, row: usize, goal: usize) -> usize {
+    let line = buffer.get_line(row).unwrap_or_default();
+    coordinates::floor_grapheme_boundary(&line, goal)
+}
+
+#[cfg(test)]
+mod tests {
+    // Import explicitly: a glob would pull in gpui's `test` attribute macro
+    use super::{
+        px, Bias, CursorPosition, DecorationStyle, Diagnostic, DiagnosticSeverity, Duration,
+        Editor, SearchQuery, SelectionRange, Selections, TextChange,
+    };
+    use crate::anchor::{MAX_ANCHOR_AGE, REFRESH_INTERVAL};
+    use crate::buffer::TextBuffer;
+    use crate::completion::CompletionItem;
+    use crate::display_map::LineLayout;
+    use crate::file::{Encoding, FileError};
+    use crate::line_ops::SortOptions;
+    use crate::syntax_highlighter;
+    use std::path::PathBuf;
+
+    fn editor(text: &str) -> Editor {
+        let mut editor = Editor::new("test", text.lines().map(|s| s.to_string()).collect());
+        editor.set_undo_group_interval(Duration::from_secs(60));
+        editor
+    }
+
+    fn type_text(editor: &mut Editor, text: &str) {
+        for ch in text.chars() {
+            if ch == '\n' {
+                editor.insert_newline();
+            } else {
+                editor.insert_char(ch);
+            }
+        }
+    }
+
+    #[test]
+    fn test_undo_redo_typing_group() {
+        let mut editor = editor("fn main");
+        editor.set_cursor_position(CursorPosition::new(0, 7));
+        type_text(&mut editor, "() {}");
+        assert_eq!(editor.get_buffer().to_string(), "fn main() {}");
+
+        assert!(editor.undo());
+        assert_eq!(editor.get_buffer().to_string(), "fn main");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 7));
+        assert!(!editor.can_undo());
+
+        assert!(editor.redo());
+        assert_eq!(editor.get_buffer().to_string(), "fn main() {}");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 12));
+        assert!(!editor.can_redo());
+    }
+
+    #[test]
+    fn test_cursor_movement_breaks_typing_group() {
+        let mut editor = editor("ac");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+        type_text(&mut editor, "b");
+        editor.move_right(false);
+        type_text(&mut editor, "d");
+        assert_eq!(editor.get_buffer().to_string(), "abcd");
+
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "abc");
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "ac");
+    }
+
+    #[test]
+    fn test_newline_and_deletions_are_separate_steps() {
+        let mut editor = editor("hello");
+        editor.set_cursor_position(CursorPosition::new(0, 5));
+        editor.insert_newline();
+        editor.backspace();
+        editor.backspace();
+        assert_eq!(editor.get_buffer().to_string(), "hell");
+
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "hello");
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "hello\n");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 0));
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "hello");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 5));
+    }
+
+    #[test]
+    fn test_undo_restores_selection() {
+        let mut editor = editor("hello world");
+        editor.set_cursor_position(CursorPosition::new(0, 5));
+        for _ in 0..6 {
+            editor.move_right(true);
+        }
+        editor.insert_char('!');
+        assert_eq!(editor.get_buffer().to_string(), "hello!");
+
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "hello world");
+        assert_eq!(editor.get_selected_text(), " world");
+    }
+
+    #[test]
+    fn test_transact_groups_edits() {
+        let mut editor = editor("one\ntwo");
+        editor.transact(|editor| {
+            editor.update_line(0, "1".to_string());
+            editor.update_line(1, "2".to_string());
+        });
+        assert_eq!(editor.get_buffer().to_string(), "1\n2");
+
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "one\ntwo");
+        assert!(!editor.can_undo());
+    }
+
+    #[test]
+    fn test_edit_after_undo_clears_redo() {
+        let mut editor = editor("");
+        type_text(&mut editor, "a");
+        editor.undo();
+        assert!(editor.can_redo());
+
+        type_text(&mut editor, "b");
+        assert!(!editor.can_redo());
+        assert!(!editor.redo());
+    }
+
+    fn heads(editor: &Editor) -> Vec<(usize, usize)> {
+        editor
+            .selections()
+            .iter()
+            .map(|selection| (selection.head.row, selection.head.col))
+            .collect()
+    }
+
+    #[test]
+    fn test_add_cursor_below_and_type() {
+        let mut editor = editor("let a = 1;\nlet b = 2;\nlet c = 3;");
+        editor.set_cursor_position(CursorPosition::new(0, 4));
+        editor.add_cursor_below();
+        editor.add_cursor_below();
+        assert_eq!(heads(&editor), vec![(0, 4), (1, 4), (2, 4)]);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(2, 4));
+
+        type_text(&mut editor, "x");
+        assert_eq!(
+            editor.get_buffer().to_string(),
+            "let xa = 1;\nlet xb = 2;\nlet xc = 3;"
+        );
+        assert_eq!(heads(&editor), vec![(0, 5), (1, 5), (2, 5)]);
+
+        editor.undo();
+        assert_eq!(
+            editor.get_buffer().to_string(),
+            "let a = 1;\nlet b = 2;\nlet c = 3;"
+        );
+        assert_eq!(heads(&editor), vec![(0, 4), (1, 4), (2, 4)]);
+    }
+
+    #[test]
+    fn test_add_cursor_above_preserves_goal_column() {
+        let mut editor = editor("long line\nab\nlong line");
+        editor.set_cursor_position(CursorPosition::new(2, 7));
+        editor.add_cursor_above();
+        editor.add_cursor_above();
+        assert_eq!(heads(&editor), vec![(0, 7), (1, 2), (2, 7)]);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 7));
+    }
+
+    #[test]
+    fn test_multi_cursor_movement_merges() {
+        let mut editor = editor("abc");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+        editor.set_selections(crate::selections::Selections::from_ranges(
+            vec![
+                SelectionRange::caret(CursorPosition::new(0, 1)),
+                SelectionRange::caret(CursorPosition::new(0, 2)),
+            ],
+            1,
+        ));
+        editor.move_left(false);
+        assert_eq!(heads(&editor), vec![(0, 0), (0, 1)]);
+        editor.move_left(false);
+        assert_eq!(heads(&editor), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_multi_cursor_backspace_and_delete() {
+        let mut editor = editor("ab\ncd\nef");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+        editor.add_cursor_below();
+        editor.add_cursor_below();
+        editor.backspace();
+        assert_eq!(editor.get_buffer().to_string(), "b\nd\nf");
+        assert_eq!(heads(&editor), vec![(0, 0), (1, 0), (2, 0)]);
+
+        editor.delete();
+        assert_eq!(editor.get_buffer().to_string(), "\n\n");
+    }
+
+    #[test]
+    fn test_add_next_occurrence() {
+        let mut editor = editor("foo bar foo\nfoo");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+
+        editor.add_next_occurrence();
+        assert_eq!(editor.get_selected_text(), "foo");
+
+        editor.add_next_occurrence();
+        editor.add_next_occurrence();
+        assert_eq!(editor.selections().count(), 3);
+        assert_eq!(
+            editor.get_selection_range(),
+            Some((CursorPosition::new(1, 0), CursorPosition::new(1, 3)))
+        );
+
+        // Every occurrence is already selected
+        editor.add_next_occurrence();
+        assert_eq!(editor.selections().count(), 3);
+
+        type_text(&mut editor, "baz");
+        assert_eq!(editor.get_buffer().to_string(), "baz bar baz\nbaz");
+    }
+
+    #[test]
+    fn test_add_next_occurrence_wraps_around_multibyte_text() {
+        let mut editor = editor("😀 日本 x\n日本 日本");
+        editor.set_cursor_position(CursorPosition::new(1, 0));
+        editor.add_next_occurrence();
+        editor.add_next_occurrence();
+        assert_eq!(
+            editor.get_selection_range(),
+            Some((CursorPosition::new(1, 3), CursorPosition::new(1, 5)))
+        );
+
+        // Past the end of the buffer, the search continues from the start
+        editor.add_next_occurrence();
+        assert_eq!(
+            editor.get_selection_range(),
+            Some((CursorPosition::new(0, 2), CursorPosition::new(0, 4)))
+        );
+        editor.add_next_occurrence();
+        assert_eq!(editor.selections().count(), 3);
+
+        type_text(&mut editor, "ab");
+        assert_eq!(editor.get_buffer().to_string(), "😀 ab x\nab ab");
+    }
+
+    #[test]
+    fn test_multi_cursor_selection_replace() {
+        let mut editor = editor("one two one");
+        editor.set_cursor_position(CursorPosition::new(0, 0));
+        editor.add_next_occurrence();
+        editor.add_next_occurrence();
+        assert_eq!(editor.get_selected_text(), "one\none");
+
+        editor.insert_newline();
+        assert_eq!(editor.get_buffer().to_string(), "\n two \n");
+        assert_eq!(heads(&editor), vec![(1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn test_paste_distributes_lines() {
+        let mut editor = editor("a\nb");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+        editor.add_cursor_below();
+        editor.paste("1\r\n2");
+        assert_eq!(editor.get_buffer().to_string(), "a1\nb2");
+
+        editor.paste("xy");
+        assert_eq!(editor.get_buffer().to_string(), "a1xy\nb2xy");
+    }
+
+    #[test]
+    fn test_scroll_is_clamped() {
+        let lines: Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::new("test", lines);
+        editor.set_viewport_height(px(200.0));
+
+        // 100 lines of 20px in a 200px viewport
+        assert_eq!(editor.max_scroll_top(), px(1800.0));
+        editor.set_scroll_top(px(-50.0));
+        assert_eq!(editor.scroll_top(), px(0.0));
+        editor.scroll_by(px(5000.0));
+        assert_eq!(editor.scroll_top(), px(1800.0));
+        assert_eq!(editor.visible_rows(), 90..100);
+    }
+
+    #[test]
+    fn test_visible_rows() {
+        let lines: Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::new("test", lines);
+        editor.set_viewport_height(px(200.0));
+        assert_eq!(editor.visible_row_count(), 10);
+        assert_eq!(editor.visible_rows(), 0..10);
+
+        editor.set_scroll_top(px(30.0));
+        assert_eq!(editor.visible_rows(), 1..12);
+    }
+
+    #[test]
+    fn test_autoscroll_follows_cursor() {
+        let lines: Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::new("test", lines);
+        editor.set_viewport_height(px(200.0));
+
+        for _ in 0..15 {
+            editor.move_down(false);
+        }
+        assert!(editor.autoscroll());
+        // Row 15 sits on the bottom edge of the viewport
+        assert_eq!(editor.scroll_top(), px(120.0));
+
+        // No request, no scroll
+        assert!(!editor.autoscroll());
+
+        editor.set_cursor_position(CursorPosition::new(2, 0));
+        assert!(editor.autoscroll());
+        assert_eq!(editor.scroll_top(), px(40.0));
+    }
+
+    #[test]
+    fn test_click_and_drag() {
+        let mut editor = editor("hello world\nsecond line");
+        editor.mouse_down(CursorPosition::new(0, 3), 1, false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 3));
+        assert!(!editor.has_selection());
+
+        editor.mouse_drag(CursorPosition::new(1, 2));
+        assert_eq!(editor.get_selected_text(), "lo world\nse");
+
+        // Dragging back past the origin reverses the selection
+        editor.mouse_drag(CursorPosition::new(0, 1));
+        assert_eq!(editor.get_selected_text(), "el");
+        assert!(editor.selections().primary().is_reversed());
+
+        editor.mouse_up();
+        editor.mouse_drag(CursorPosition::new(1, 5));
+        assert_eq!(editor.get_selected_text(), "el");
+    }
+
+    #[test]
+    fn test_click_clamps_and_collapses_cursors() {
+        let mut editor = editor("one\ntwo\nthree");
+        editor.add_cursor_below();
+        editor.mouse_down(CursorPosition::new(9, 9), 1, false);
+        assert_eq!(editor.selections().count(), 1);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(2, 5));
+    }
+
+    #[test]
+    fn test_shift_click_extends_selection() {
+        let mut editor = editor("hello world");
+        editor.set_cursor_position(CursorPosition::new(0, 2));
+        editor.mouse_down(CursorPosition::new(0, 8), 1, true);
+        assert_eq!(editor.get_selected_text(), "llo wo");
+
+        editor.mouse_drag(CursorPosition::new(0, 0));
+        assert_eq!(editor.get_selected_text(), "he");
+    }
+
+    #[test]
+    fn test_double_click_selects_words() {
+        let mut editor = editor("let some_value = other;");
+        editor.mouse_down(CursorPosition::new(0, 6), 2, false);
+        assert_eq!(editor.get_selected_text(), "some_value");
+
+        // Dragging extends by whole words in both directions
+        editor.mouse_drag(CursorPosition::new(0, 19));
+        assert_eq!(editor.get_selected_text(), "some_value = other");
+        editor.mouse_drag(CursorPosition::new(0, 1));
+        assert_eq!(editor.get_selected_text(), "let some_value");
+    }
+
+    #[test]
+    fn test_triple_click_selects_lines() {
+        let mut editor = editor("first\nsecond\nthird");
+        editor.mouse_down(CursorPosition::new(1, 3), 3, false);
+        assert_eq!(editor.get_selected_text(), "second\n");
+
+        editor.mouse_drag(CursorPosition::new(2, 1));
+        assert_eq!(editor.get_selected_text(), "second\nthird");
+    }
+
+    #[test]
+    fn test_drag_requests_autoscroll() {
+        let lines: Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::new("test", lines);
+        editor.set_viewport_height(px(200.0));
+        editor.mouse_down(CursorPosition::new(5, 0), 1, false);
+        editor.autoscroll();
+
+        // Dragging below the viewport scrolls the head into view
+        editor.mouse_drag(CursorPosition::new(12, 0));
+        assert!(editor.autoscroll());
+        assert_eq!(editor.scroll_top(), px(60.0));
+    }
+
+    #[test]
+    fn test_utf16_ranges() {
+        let mut editor = editor("a😀b\nc");
+        editor.set_cursor_position(CursorPosition::new(0, 2));
+        assert_eq!(editor.selected_utf16_range(), (3..3, false));
+
+        let (text, range) = editor.text_for_utf16_range(1..4);
+        assert_eq!(text, "😀b");
+        assert_eq!(range, 1..4);
+
+        // Ranges are clamped to the document
+        let (text, range) = editor.text_for_utf16_range(4..99);
+        assert_eq!(text, "\nc");
+        assert_eq!(range, 4..6);
+    }
+
+    #[test]
+    fn test_ime_composition() {
+        let mut editor = editor("ab");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+
+        // Compose "に" then "にほ", then commit "日本"
+        editor.replace_and_mark_text_in_utf16_range(None, "に", Some(1..1));
+        assert_eq!(editor.get_buffer().to_string(), "aにb");
+        assert_eq!(editor.marked_utf16_range(), Some(1..2));
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 2));
+
+        editor.replace_and_mark_text_in_utf16_range(None, "にほ", Some(0..2));
+        assert_eq!(editor.get_buffer().to_string(), "aにほb");
+        assert_eq!(editor.marked_utf16_range(), Some(1..3));
+        assert_eq!(editor.get_selected_text(), "にほ");
+
+        editor.replace_text_in_utf16_range(None, "日本");
+        assert_eq!(editor.get_buffer().to_string(), "a日本b");
+        assert_eq!(editor.marked_utf16_range(), None);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 3));
+
+        // The whole composition is one undo step
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "ab");
+    }
+
+    #[test]
+    fn test_dead_key_replaces_marked_text() {
+        let mut editor = editor("");
+        editor.replace_and_mark_text_in_utf16_range(None, "´", None);
+        assert_eq!(editor.marked_utf16_range(), Some(0..1));
+        editor.replace_text_in_utf16_range(None, "é");
+        assert_eq!(editor.get_buffer().to_string(), "é");
+        assert_eq!(editor.marked_utf16_range(), None);
+
+        // Moving the cursor ends a composition
+        editor.replace_and_mark_text_in_utf16_range(None, "´", None);
+        editor.move_left(false);
+        assert_eq!(editor.marked_utf16_range(), None);
+    }
+
+    #[test]
+    fn test_replace_text_in_explicit_range() {
+        let mut editor = editor("hello 😀 world");
+        editor.replace_text_in_utf16_range(Some(6..8), "🙂");
+        assert_eq!(editor.get_buffer().to_string(), "hello 🙂 world");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 7));
+    }
+
+    #[test]
+    fn test_input_without_range_uses_all_cursors() {
+        let mut editor = editor("a\nb");
+        editor.add_cursor_below();
+        editor.replace_text_in_utf16_range(None, "x");
+        assert_eq!(editor.get_buffer().to_string(), "xa\nxb");
+    }
+
+    #[test]
+    fn test_movement_steps_over_graphemes() {
+        // "e" + combining acute, a ZWJ family, then CJK
+        let mut editor = editor("e\u{301}👨‍👩‍👧日本");
+        let mut cols = Vec::new();
+        for _ in 0..4 {
+            editor.move_right(false);
+            cols.push(editor.cursor_position().col);
+        }
+        assert_eq!(cols, vec![2, 7, 8, 9]);
+
+        editor.move_left(false);
+        editor.move_left(false);
+        assert_eq!(editor.cursor_position().col, 7);
+        editor.move_left(false);
+        assert_eq!(editor.cursor_position().col, 2);
+    }
+
+    #[test]
+    fn test_deletion_removes_whole_graphemes() {
+        let mut editor = editor("a👍🏽b e\u{301}");
+        editor.set_cursor_position(CursorPosition::new(0, 3));
+        editor.backspace();
+        assert_eq!(editor.get_buffer().to_string(), "ab e\u{301}");
+
+        editor.set_cursor_position(CursorPosition::new(0, 3));
+        editor.delete();
+        assert_eq!(editor.get_buffer().to_string(), "ab ");
+    }
+
+    #[test]
+    fn test_vertical_movement_with_wide_chars() {
+        let mut editor = editor("日本語テキスト\n👍🏽👍🏽\nabc");
+        editor.set_cursor_position(CursorPosition::new(0, 3));
+        editor.move_down(false);
+        // Column 3 is inside the second thumbs up, so the caret snaps back
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 2));
+        editor.move_down(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(2, 3));
+        editor.move_up(false);
+        editor.move_up(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 3));
+    }
+
+    #[test]
+    fn test_selection_text_with_multibyte_chars() {
+        let mut editor = editor("héllo 世界\n😀!");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+        for _ in 0..9 {
+            editor.move_right(true);
+        }
+        assert_eq!(editor.get_selected_text(), "éllo 世界\n😀");
+        assert_eq!(editor.selected_utf16_range(), (1..11, false));
+    }
+
+    #[test]
+    fn test_word_movement_crosses_lines() {
+        let mut editor = editor("let fooBar = 1;\n  baz");
+        editor.set_cursor_position(CursorPosition::new(0, 0));
+        editor.move_to_next_word_end(false);
+        editor.move_to_next_word_end(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 10));
+        editor.move_to_previous_subword_start(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 7));
+
+        editor.set_cursor_position(CursorPosition::new(0, 15));
+        editor.move_to_next_word_end(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 0));
+        editor.move_to_next_word_end(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 5));
+        editor.move_to_previous_word_start(false);
+        editor.move_to_previous_word_start(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 0));
+        editor.move_to_previous_word_start(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 15));
+    }
+
+    #[test]
+    fn test_smart_line_start() {
+        let mut editor = editor("    indented");
+        editor.set_cursor_position(CursorPosition::new(0, 8));
+        editor.move_to_line_start(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 4));
+        editor.move_to_line_start(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 0));
+        editor.move_to_line_start(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 4));
+
+        editor.move_to_line_end(true);
+        assert_eq!(editor.get_selected_text(), "indented");
+    }
+
+    #[test]
+    fn test_document_boundaries() {
+        let mut editor = editor("one\ntwo\nthree");
+        editor.set_cursor_position(CursorPosition::new(1, 1));
+        editor.add_cursor_below();
+        editor.move_to_document_end(true);
+        assert_eq!(editor.selections().count(), 1);
+        assert_eq!(editor.get_selected_text(), "wo\nthree");
+
+        editor.move_to_document_start(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 0));
+        assert!(!editor.has_selection());
+    }
+
+    #[test]
+    fn test_page_up_and_down() {
+        let lines: Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::new("test", lines);
+        editor.set_viewport_height(px(200.0));
+        editor.set_cursor_position(CursorPosition::new(0, 3));
+
+        // 10 rows fit in the viewport
+        editor.page_down(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(10, 3));
+        assert_eq!(editor.scroll_top(), px(200.0));
+        editor.page_down(true);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(20, 3));
+        assert!(editor.has_selection());
+
+        editor.page_up(false);
+        editor.page_up(false);
+        editor.page_up(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 3));
+        assert_eq!(editor.scroll_top(), px(0.0));
+    }
+
+    #[test]
+    fn test_delete_to_word_and_line_boundaries() {
+        let mut editor = editor("let foo_bar = 1;");
+        editor.set_cursor_position(CursorPosition::new(0, 11));
+        editor.delete_to_previous_subword_start();
+        assert_eq!(editor.get_buffer().to_string(), "let foo_ = 1;");
+        editor.delete_to_previous_word_start();
+        assert_eq!(editor.get_buffer().to_string(), "let  = 1;");
+        editor.delete_to_next_word_end();
+        assert_eq!(editor.get_buffer().to_string(), "let  1;");
+        editor.delete_to_line_end();
+        assert_eq!(editor.get_buffer().to_string(), "let ");
+
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "let  1;");
+        editor.delete_to_line_start();
+        assert_eq!(editor.get_buffer().to_string(), " 1;");
+    }
+
+    #[test]
+    fn test_select_matches_wraps_around() {
+        let mut editor = editor("foo bar\nFoo baz foo");
+        editor.set_search_query(SearchQuery::new("foo")).unwrap();
+        assert_eq!(editor.search_matches().len(), 3);
+
+        editor.set_cursor_position(CursorPosition::new(0, 5));
+        assert!(editor.select_next_match());
+        assert_eq!(editor.active_match_index(), Some(1));
+        assert!(editor.select_next_match());
+        assert!(editor.select_next_match());
+        assert_eq!(editor.active_match_index(), Some(0));
+        assert!(editor.select_previous_match());
+        assert_eq!(editor.active_match_index(), Some(2));
+
+        // Matches follow edits
+        editor.move_to_document_start(false);
+        editor.insert_text("foo ");
+        assert_eq!(editor.search_matches().len(), 4);
+
+        editor.clear_search();
+        assert!(!editor.select_next_match());
+    }
+
+    #[test]
+    fn test_replace_current_with_captures() {
+        let mut editor = editor("let a = 1;\nlet b = 2;");
+        editor
+            .set_search_query(SearchQuery::new(r"let (\w+)").regex(true))
+            .unwrap();
+
+        // Off a match, the first replace only selects one
+        assert!(!editor.replace_current("const $1"));
+        assert_eq!(editor.active_match_index(), Some(0));
+        assert!(editor.replace_current("const $1"));
+        assert_eq!(editor.get_buffer().to_string(), "const a = 1;\nlet b = 2;");
+        assert_eq!(editor.get_selected_text(), "let b");
+        assert!(editor
+            .set_search_query(SearchQuery::new("(").regex(true))
+            .is_err());
+        assert!(editor.search_query().is_none());
+    }
+
+    #[test]
+    fn test_replace_all_is_one_undo_step() {
+        let mut editor = editor("a.b a.b\nab a.b");
+        editor.set_cursor_position(CursorPosition::new(1, 5));
+        editor.set_search_query(SearchQuery::new("a.b")).unwrap();
+        assert_eq!(editor.replace_all("x"), 3);
+        assert_eq!(editor.get_buffer().to_string(), "x x\nab x");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 4));
+        assert!(editor.search_matches().is_empty());
+
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "a.b a.b\nab a.b");
+    }
+
+    /// Lay out `row` as if its `len` chars were 10px wide and it wrapped at
+    /// `wrap_starts`
+    fn wrap_row(editor: &mut Editor, row: usize, len: usize, wrap_starts: Vec<usize>) {
+        let xs = (0..=len).map(|col| px(10.0 * col as f32)).collect();
+        editor
+            .display_map_mut()
+            .insert(row, LineLayout::new(wrap_starts, xs));
+    }
+
+    #[test]
+    fn test_vertical_movement_through_wrapped_rows() {
+        let mut editor = editor("aaaa bbbb cccc\nxy\nlonger line");
+        editor.display_map_mut().set_text_layout(
+            "Monaco".into(),
+            px(14.0),
+            Some(px(50.0)),
+            px(10.0),
+        );
+        // "aaaa " / "bbbb " / "cccc"
+        wrap_row(&mut editor, 0, 14, vec![5, 10]);
+        assert_eq!(editor.display_row_count(), 5);
+
+        editor.set_cursor_position(CursorPosition::new(0, 7));
+        editor.move_down(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 12));
+        editor.move_down(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 2));
+        // The goal is kept in pixels, 20px into the row
+        editor.move_down(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(2, 2));
+        editor.move_up(false);
+        editor.move_up(false);
+        editor.move_up(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 7));
+
+        // Past the end of a wrapped row, the caret stops before the wrap
+        editor.set_cursor_position(CursorPosition::new(2, 11));
+        editor.move_up(false);
+        editor.move_up(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 14));
+        editor.move_up(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 9));
+    }
+
+    #[test]
+    fn test_scrolling_counts_wrapped_rows() {
+        let lines: Vec<String> = (0..20).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::new("test", lines);
+        editor.set_viewport_height(px(200.0));
+        for row in 0..5 {
+            wrap_row(&mut editor, row, 6, vec![3]);
+        }
+
+        // 25 display rows of 20px in a 200px viewport
+        assert_eq!(editor.max_scroll_top(), px(300.0));
+        editor.set_scroll_top(px(60.0));
+        assert_eq!(editor.visible_display_rows(), 3..13);
+        assert_eq!(editor.visible_rows(), 1..8);
+
+        editor.set_cursor_position(CursorPosition::new(4, 4));
+        assert_eq!(editor.display_row(editor.cursor_position()), 9);
+        // Row 12 is display row 17, the last one in view at 160px
+        editor.set_cursor_position(CursorPosition::new(12, 0));
+        assert!(editor.autoscroll());
+        assert_eq!(editor.scroll_top(), px(160.0));
+    }
+
+    #[test]
+    fn test_clone_has_its_own_layout_scroll_and_highlighting() {
+        let lines: Vec<String> = (0..20).map(|i| format!("line {}", i)).collect();
+        let mut editor = Editor::new("test", lines);
+        editor.set_viewport_height(px(200.0));
+        editor.set_search_query(SearchQuery::new("line 1")).unwrap();
+        assert_eq!(editor.search_matches().len(), 11);
+
+        let mut copy = editor.clone();
+        wrap_row(&mut copy, 0, 6, vec![3]);
+        copy.scroll_by(px(60.0));
+        copy.move_to_document_end(false);
+        copy.insert_text(" line 1");
+        assert_eq!(copy.display_row_count(), 21);
+        assert_eq!(copy.search_matches().len(), 12);
+
+        assert_eq!(editor.display_row_count(), 20);
+        assert_eq!(editor.scroll_top(), px(0.0));
+        assert_eq!(editor.search_matches().len(), 11);
+
+        // Opening a comment in the copy leaves the original's code alone
+        let lines = (0..4).map(|i| format!("let v{i} = {i};")).collect();
+        let mut code = Editor::new("code", lines);
+        let in_comment = |editor: &mut Editor, row: usize| {
+            let line = editor.get_buffer().get_line(row).unwrap();
+            let highlighted = editor.highlight_line(&line, row, "Mono".into(), 14.0);
+            syntax_highlighter::is_string_or_comment(&highlighted.scopes[0].scopes)
+        };
+        assert!(!in_comment(&mut code, 3));
+        let mut copy = code.clone();
+        copy.set_cursor_position(CursorPosition::new(0, 0));
+        copy.insert_text("/*");
+        assert!(in_comment(&mut copy, 3));
+        assert!(!in_comment(&mut code, 3));
+    }
+
+    const FOLDABLE: &str = "fn main() {\n    let a = 1;\n    let b = 2;\n}\nfn end() {}";
+
+    #[test]
+    fn test_brackets_in_strings_and_comments_dont_fold() {
+        let text = "fn f() {\n    let s = \"{\";\n    // (\n    g();\n    // )\n}";
+        let editor = editor(text);
+        assert_eq!(editor.foldable_ranges().first(), Some(&(0..5)));
+        assert_eq!(editor.foldable_ranges().len(), 1);
+    }
+
+    #[test]
+    fn test_fold_hides_rows_and_moves_caret() {
+        let mut editor = editor(FOLDABLE);
+        assert_eq!(editor.foldable_ranges().first(), Some(&(0..3)));
+        assert_eq!(editor.foldable_ranges().len(), 1);
+
+        editor.set_cursor_position(CursorPosition::new(2, 4));
+        assert!(editor.fold());
+        assert!(editor.is_folded(0));
+        assert_eq!(editor.folds().len(), 1);
+        assert!(editor.is_row_hidden(1));
+        assert!(!editor.is_row_hidden(3));
+        assert_eq!(editor.display_row_count(), 3);
+        // The caret leaves the hidden rows for the end of the header
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 11));
+
+        assert!(editor.unfold());
+        assert!(editor.folds().is_empty());
+        assert!(!editor.unfold());
+        assert!(editor.toggle_fold(0));
+        assert!(editor.is_folded(0));
+        assert!(!editor.toggle_fold(3));
+    }
+
+    #[test]
+    fn test_movement_skips_folded_rows() {
+        let mut editor = editor(FOLDABLE);
+        editor.toggle_fold(0);
+
+        editor.set_cursor_position(CursorPosition::new(0, 11));
+        editor.move_right(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(3, 0));
+        editor.move_left(false);
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 11));
+
+        editor.move_down(false);
+        assert_eq!(editor.cursor_position().row, 3);
+        editor.move_up(false);
+        assert_eq!(editor.cursor_position().row, 0);
+    }
+
+    #[test]
+    fn test_edits_inside_a_fold_unfold_it() {
+        let mut editor = editor(FOLDABLE);
+        editor.toggle_fold(0);
+
+        // Edits above a fold move it along
+        editor.set_cursor_position(CursorPosition::new(0, 0));
+        editor.insert_newline();
+        assert_eq!(editor.folds().first(), Some(&(1..4)));
+
+        // Joining the closing line with a hidden one unfolds
+        editor.set_cursor_position(CursorPosition::new(4, 0));
+        editor.backspace();
+        assert!(editor.folds().is_empty());
+        assert!(!editor.is_row_hidden(2));
+    }
+
+    #[test]
+    fn test_newline_keeps_and_increases_indentation() {
+        let mut editor = editor("fn main() {\n    let a = 1;");
+        editor.set_cursor_position(CursorPosition::new(1, 14));
+        editor.insert_newline();
+        assert_eq!(
+            editor.get_buffer().to_string(),
+            "fn main() {\n    let a = 1;\n    "
+        );
+
+        editor.set_cursor_position(CursorPosition::new(0, 11));
+        editor.insert_newline();
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 4));
+
+        editor.set_language("Python".to_string());
+        editor.update_buffer(vec!["def f(x):".to_string()]);
+        editor.set_cursor_position(CursorPosition::new(0, 9));
+        editor.insert_newline();
+        assert_eq!(editor.get_buffer().to_string(), "def f(x):\n    ");
+    }
+
+    #[test]
+    fn test_newline_between_brackets() {
+        let mut editor = editor("  f({})");
+        editor.set_cursor_position(CursorPosition::new(0, 5));
+        editor.insert_newline();
+        assert_eq!(editor.get_buffer().to_string(), "  f({\n      \n  })");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 6));
+    }
+
+    #[test]
+    fn test_closing_bracket_dedents() {
+        let mut editor = editor("if x {\n        ");
+        editor.set_cursor_position(CursorPosition::new(1, 8));
+        editor.insert_char('}');
+        assert_eq!(editor.get_buffer().to_string(), "if x {\n    }");
+
+        // Not after other text on the line
+        editor.insert_char(')');
+        assert_eq!(editor.get_buffer().to_string(), "if x {\n    })");
+    }
+
+    #[test]
+    fn test_tab_inserts_to_next_tab_stop() {
+        let mut editor = editor("ab");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+        editor.tab();
+        assert_eq!(editor.get_buffer().to_string(), "a   b");
+
+        editor.config_mut().hard_tabs = true;
+        editor.tab();
+        assert_eq!(editor.get_buffer().to_string(), "a   \tb");
+    }
+
+    #[test]
+    fn test_indent_and_outdent_selection() {
+        let mut editor = editor("a\n\n  b\nc");
+        editor.config_mut().tab_size = 2;
+        editor.set_selections(Selections::from_ranges(
+            vec![SelectionRange::new(
+                CursorPosition::new(0, 1),
+                CursorPosition::new(3, 0),
+            )],
+            0,
+        ));
+
+        // Tab with a multi-line selection indents; empty lines stay empty
+        editor.tab();
+        assert_eq!(editor.get_buffer().to_string(), "  a\n\n    b\nc");
+        // The selection stays on the same text
+        assert_eq!(editor.get_selected_text(), "\n\n    b\n");
+
+        editor.backtab();
+        editor.backtab();
+        assert_eq!(editor.get_buffer().to_string(), "a\n\nb\nc");
+        assert!(editor.undo());
+        assert_eq!(editor.get_buffer().to_string(), "a\n\n  b\nc");
+    }
+
+    #[test]
+    fn test_brackets_and_quotes_close_and_type_over() {
+        let mut editor = editor("");
+        type_text(&mut editor, "f(\"a");
+        assert_eq!(editor.get_buffer().to_string(), "f(\"a\")");
+        type_text(&mut editor, "\")");
+        assert_eq!(editor.get_buffer().to_string(), "f(\"a\")");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 6));
+
+        // Not before a word, and Rust doesn't pair single quotes
+        editor.set_cursor_position(CursorPosition::new(0, 0));
+        type_text(&mut editor, "['");
+        assert_eq!(editor.get_buffer().to_string(), "['f(\"a\")");
+    }
+
+    #[test]
+    fn test_no_pairs_in_strings_and_comments() {
+        let mut editor = editor("let s = \"ab\"; // c");
+        editor.set_cursor_position(CursorPosition::new(0, 10));
+        editor.insert_char('(');
+        assert_eq!(editor.get_buffer().to_string(), "let s = \"a(b\"; // c");
+
+        editor.move_to_line_end(false);
+        editor.insert_char('[');
+        assert_eq!(editor.get_buffer().to_string(), "let s = \"a(b\"; // c[");
+    }
+
+    #[test]
+    fn test_pairs_wrap_selections() {
+        let mut editor = editor("let x = a + b;");
+        editor.set_selections(Selections::from_ranges(
+            vec![SelectionRange::new(
+                CursorPosition::new(0, 8),
+                CursorPosition::new(0, 13),
+            )],
+            0,
+        ));
+        editor.insert_char('(');
+        editor.insert_char('[');
+        assert_eq!(editor.get_buffer().to_string(), "let x = ([a + b]);");
+        assert_eq!(editor.get_selected_text(), "a + b");
+    }
+
+    #[test]
+    fn test_backspace_deletes_empty_pairs() {
+        let mut editor = editor("");
+        type_text(&mut editor, "x = {");
+        assert_eq!(editor.get_buffer().to_string(), "x = {}");
+        editor.backspace();
+        assert_eq!(editor.get_buffer().to_string(), "x = ");
+    }
+
+    #[test]
+    fn test_backspace_at_line_and_buffer_end() {
+        let mut editor = editor("ab\ncd");
+        editor.set_cursor_position(CursorPosition::new(0, 2));
+        editor.backspace();
+        assert_eq!(editor.get_buffer().to_string(), "a\ncd");
+
+        editor.set_cursor_position(CursorPosition::new(1, 2));
+        editor.backspace();
+        assert_eq!(editor.get_buffer().to_string(), "a\nc");
+
+        // At the start of a line, the newline goes
+        editor.set_cursor_position(CursorPosition::new(1, 0));
+        editor.backspace();
+        assert_eq!(editor.get_buffer().to_string(), "ac");
+    }
+
+    #[test]
+    fn test_matching_brackets() {
+        let mut editor = editor("fn f() {\n    g(a[0], (b));\n}");
+        let at = CursorPosition::new;
+
+        // A bracket after or before the caret, or else the innermost pair
+        editor.set_cursor_position(at(1, 5));
+        assert_eq!(editor.matching_brackets(), Some((at(1, 5), at(1, 15))));
+        editor.set_cursor_position(at(1, 16));
+        assert_eq!(editor.matching_brackets(), Some((at(1, 5), at(1, 15))));
+        editor.set_cursor_position(at(1, 13));
+        assert_eq!(editor.matching_brackets(), Some((at(1, 12), at(1, 14))));
+        editor.set_cursor_position(at(1, 1));
+        assert_eq!(editor.matching_brackets(), Some((at(0, 7), at(2, 0))));
+
+        editor.set_cursor_position(at(0, 7));
+        editor.move_to_matching_bracket();
+        assert_eq!(editor.cursor_position(), at(2, 0));
+        editor.move_to_matching_bracket();
+        assert_eq!(editor.cursor_position(), at(0, 7));
+
+        // Inside a pair, the caret jumps to its closing bracket
+        editor.set_cursor_position(at(1, 8));
+        editor.move_to_matching_bracket();
+        assert_eq!(editor.cursor_position(), at(1, 9));
+    }
+
+    #[test]
+    fn test_brackets_in_strings_and_comments_are_ignored() {
+        let mut editor = editor("f(\")\", // )\n)");
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+        assert_eq!(
+            editor.matching_brackets(),
+            Some((CursorPosition::new(0, 1), CursorPosition::new(1, 0)))
+        );
+    }
+
+    #[test]
+    fn test_select_to_enclosing_brackets() {
+        let mut editor = editor("f(a, [b, c])");
+        editor.set_cursor_position(CursorPosition::new(0, 7));
+        editor.select_to_enclosing_brackets();
+        assert_eq!(editor.get_selected_text(), "b, c");
+        editor.select_to_enclosing_brackets();
+        assert_eq!(editor.get_selected_text(), "[b, c]");
+        editor.select_to_enclosing_brackets();
+        assert_eq!(editor.get_selected_text(), "a, [b, c]");
+        editor.select_to_enclosing_brackets();
+        assert_eq!(editor.get_selected_text(), "(a, [b, c])");
+        // There is nothing more to select
+        editor.select_to_enclosing_brackets();
+        assert_eq!(editor.get_selected_text(), "(a, [b, c])");
+    }
+
+    /// A path in the temp dir for `name`, unique to this process
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("gpui-editor-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_open_and_save_keep_bytes() {
+        let path = temp_path("round-trip.py");
+        let bytes = b"\xef\xbb\xbfdef f():\r\n    pass\n\r\n";
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut editor = Editor::open("test", &path).unwrap();
+        assert_eq!(editor.language(), "Python");
+        assert_eq!(editor.file_format().encoding, Encoding::Utf8Bom);
+        assert_eq!(editor.get_buffer().to_string(), "def f():\n    pass\n\n");
+        editor.save().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+        // Lines added by editing get the most common ending
+        editor.set_cursor_position(CursorPosition::new(1, 8));
+        editor.insert_newline();
+        type_text(&mut editor, "x");
+        editor.save().unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            b"\xef\xbb\xbfdef f():\r\n    pass\r\n    x\n\r\n"
+        );
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_dirty_flag_follows_saved_version() {
+        let path = temp_path("dirty.txt");
+        std::fs::write(&path, "a").unwrap();
+        let mut editor = Editor::open("test", &path).unwrap();
+        assert!(!editor.is_dirty());
+
+        editor.set_cursor_position(CursorPosition::new(0, 1));
+        type_text(&mut editor, "b");
+        assert!(editor.is_dirty());
+        editor.save().unwrap();
+        assert!(!editor.is_dirty());
+
+        // Typing right after saving starts a new undo step
+        type_text(&mut editor, "c");
+        assert!(editor.is_dirty());
+        assert!(editor.undo());
+        assert!(!editor.is_dirty());
+        assert!(editor.undo());
+        assert!(editor.is_dirty());
+        assert!(editor.redo());
+        assert!(!editor.is_dirty());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_open_rejects_binary_files() {
+        let path = temp_path("binary.bin");
+        std::fs::write(&path, b"\x7fELF\x02\x01\x00\x00").unwrap();
+        assert!(matches!(
+            Editor::open("test", &path),
+            Err(FileError::Binary)
+        ));
+        std::fs::remove_file(&path).ok();
+
+        assert!(matches!(editor("").save(), Err(FileError::NoPath)));
+    }
+
+    fn select(editor: &mut Editor, anchor: (usize, usize), head: (usize, usize)) {
+        editor.set_selections(Selections::from_ranges(
+            vec![SelectionRange::new(
+                CursorPosition::new(anchor.0, anchor.1),
+                CursorPosition::new(head.0, head.1),
+            )],
+            0,
+        ));
+    }
+
+    #[test]
+    fn test_move_lines_keeps_selection_on_them() {
+        let mut editor = editor("a\nb\nc\nd");
+        select(&mut editor, (1, 0), (2, 1));
+        editor.move_lines_up();
+        assert_eq!(editor.get_buffer().to_string(), "b\nc\na\nd");
+        assert_eq!(editor.get_selected_text(), "b\nc");
+
+        // Nothing moves past the first line
+        editor.move_lines_up();
+        assert_eq!(editor.get_buffer().to_string(), "b\nc\na\nd");
+
+        editor.move_lines_down();
+        editor.move_lines_down();
+        assert_eq!(editor.get_buffer().to_string(), "a\nd\nb\nc");
+        assert_eq!(editor.get_selected_text(), "b\nc");
+
+        // Each move is one undo step
+        assert!(editor.undo());
+        assert_eq!(editor.get_buffer().to_string(), "a\nb\nc\nd");
+        assert_eq!(editor.get_selected_text(), "b\nc");
+    }
+
+    #[test]
+    fn test_duplicate_and_delete_lines() {
+        let mut editor = editor("a\nb\nc");
+        editor.set_cursor_position(CursorPosition::new(1, 1));
+        editor.duplicate_lines();
+        assert_eq!(editor.get_buffer().to_string(), "a\nb\nb\nc");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(2, 1));
+
+        select(&mut editor, (0, 0), (1, 0));
+        editor.delete_lines();
+        assert_eq!(editor.get_buffer().to_string(), "b\nb\nc");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 0));
+
+        editor.set_cursor_position(CursorPosition::new(2, 1));
+        editor.delete_lines();
+        assert_eq!(editor.get_buffer().to_string(), "b\nb");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(1, 1));
+    }
+
+    #[test]
+    fn test_join_lines() {
+        let mut editor = editor("f(\n    a,\n    b\n)");
+        editor.set_cursor_position(CursorPosition::new(0, 2));
+        editor.join_lines();
+        assert_eq!(editor.get_buffer().to_string(), "f( a,\n    b\n)");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 2));
+
+        select(&mut editor, (0, 3), (2, 1));
+        editor.join_lines();
+        assert_eq!(editor.get_buffer().to_string(), "f( a, b )");
+        assert_eq!(editor.get_selected_text(), "a, b )");
+    }
+
+    #[test]
+    fn test_sort_reverse_and_trim_lines() {
+        let mut editor = editor("x\nc\nB\na\nb\nz");
+        select(&mut editor, (1, 0), (4, 1));
+        editor.sort_lines(SortOptions::new());
+        assert_eq!(editor.get_buffer().to_string(), "x\nB\na\nb\nc\nz");
+        editor.sort_lines(SortOptions::new().ignore_case(true).unique(true));
+        assert_eq!(editor.get_buffer().to_string(), "x\na\nB\nc\nz");
+
+        select(&mut editor, (1, 0), (3, 1));
+        editor.reverse_lines();
+        assert_eq!(editor.get_buffer().to_string(), "x\nc\nB\na\nz");
+
+        editor.update_buffer(vec!["a  ".into(), "b".into(), "\t".into()]);
+        editor.trim_trailing_whitespace();
+        assert_eq!(editor.get_buffer().to_string(), "a\nb\n");
+        assert!(editor.undo());
+        assert_eq!(editor.get_buffer().to_string(), "a  \nb\n\t");
+    }
+
+    #[test]
+    fn test_toggle_line_comment() {
+        let mut editor = editor("fn f() {\n    a();\n\n    // b();\n}");
+        select(&mut editor, (1, 6), (3, 2));
+        editor.toggle_line_comment();
+        assert_eq!(
+            editor.get_buffer().to_string(),
+            "fn f() {\n    // a();\n\n    // // b();\n}"
+        );
+        // The selection stays on the same text
+        assert_eq!(editor.get_selected_text(), ");\n\n  ");
+
+        editor.toggle_line_comment();
+        assert_eq!(
+            editor.get_buffer().to_string(),
+            "fn f() {\n    a();\n\n    // b();\n}"
+        );
+        assert_eq!(editor.get_selected_text(), ");\n\n  ");
+    }
+
+    #[test]
+    fn test_line_comments_follow_the_language() {
+        let mut python = editor("x = 1");
+        python.set_language("Python".to_string());
+        python.toggle_line_comment();
+        assert_eq!(python.get_buffer().to_string(), "# x = 1");
+
+        // HTML only has block comments, so every line gets one
+        let mut html = editor("<p>\n  <b>");
+        html.set_language("HTML".to_string());
+        select(&mut html, (0, 0), (1, 1));
+        html.toggle_line_comment();
+        assert_eq!(
+            html.get_buffer().to_string(),
+            "<!-- <p> -->\n<!--   <b> -->"
+        );
+        html.toggle_line_comment();
+        assert_eq!(html.get_buffer().to_string(), "<p>\n  <b>");
+    }
+
+    #[test]
+    fn test_toggle_block_comment() {
+        let mut editor = editor("let x = a + b;");
+        select(&mut editor, (0, 8), (0, 13));
+        editor.toggle_block_comment();
+        assert_eq!(editor.get_buffer().to_string(), "let x = /*a + b*/;");
+        assert_eq!(editor.get_selected_text(), "a + b");
+        editor.toggle_block_comment();
+        assert_eq!(editor.get_buffer().to_string(), "let x = a + b;");
+        assert_eq!(editor.get_selected_text(), "a + b");
+
+        // A caret comments its line, and uncomments it again
+        editor.set_cursor_position(CursorPosition::new(0, 4));
+        editor.toggle_block_comment();
+        assert_eq!(editor.get_buffer().to_string(), "/*let x = a + b;*/");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 6));
+        editor.toggle_block_comment();
+        assert_eq!(editor.get_buffer().to_string(), "let x = a + b;");
+        assert_eq!(editor.cursor_position(), CursorPosition::new(0, 4));
+    }
+
+    #[test]
+    fn test_line_comments_round_trip_in_every_language() {
+        let syntax_set = syntect::parsing::SyntaxSet::load_defaults_newlines();
+        let mut editor = editor("");
+        for syntax in syntax_set.syntaxes() {
+            editor.update_buffer(vec!["  x".to_string(), "    y".to_string()]);
+            editor.set_language(syntax.name.clone());
+            select(&mut editor, (0, 0), (1, 1));
+            editor.toggle_line_comment();
+            let commented = editor.get_buffer().to_string();
+            editor.toggle_line_comment();
+            assert_eq!(
+                editor.get_buffer().to_string(),
+                "  x\n    y",
+                "{}",
+                syntax.name
+            );
+
+            let tokens = editor.comment_tokens();
+            if tokens.line.is_some() || tokens.block.is_some() {
+                assert_ne!(commented, "  x\n    y", "{}", syntax.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_highlights_follow_edits() {
+        let mut editor = editor("let value = 1;\nvalue += 1;");
+        let style = DecorationStyle::new().background(gpui::rgb(0x333333));
+        let pos = CursorPosition::new;
+        editor.add_highlight("refs", [pos(0, 4)..pos(0, 9), pos(1, 0)..pos(1, 5)], style);
+        editor.add_highlight(
+            "errors",
+            [pos(1, 6)..pos(1, 8)],
+            style.squiggle(gpui::rgb(0xff0000)),
+        );
+
+        // Typing before a range moves it, typing at its edges doesn't grow it
+        editor.set_cursor_position(pos(0, 0));
+        type_text(&mut editor, "pub ");
+        editor.set_cursor_position(pos(0, 8));
+        type_text(&mut editor, "x");
+        editor.set_cursor_position(pos(1, 0));
+        editor.insert_newline();
+        assert_eq!(
+            editor.highlight_ranges("refs"),
+            vec![pos(0, 9)..pos(0, 14), pos(2, 0)..pos(2, 5)]
+        );
+
+        // Undo moves them back
+        while editor.undo() {}
+        assert_eq!(
+            editor.highlight_ranges("refs"),
+            vec![pos(0, 4)..pos(0, 9), pos(1, 0)..pos(1, 5)]
+        );
+
+        let row_styles: Vec<_> = editor
+            .decorations_in_rows(1..2)
+            .into_iter()
+            .map(|(range, style)| (range, style.underline.is_some()))
+            .collect();
+        assert_eq!(
+            row_styles,
+            vec![(pos(1, 0)..pos(1, 5), false), (pos(1, 6)..pos(1, 8), true)]
+        );
+
+        editor.clear_highlights("refs");
+        assert!(editor.highlight_ranges("refs").is_empty());
+        assert_eq!(editor.decorations_in_rows(0..2).len(), 1);
+
+        // Replacing the text drops them all
+        editor.update_buffer(vec!["fn main() {}".to_string()]);
+        assert!(editor.decorations_in_rows(0..1).is_empty());
+    }
+
+    #[test]
+    fn test_anchors_follow_edits_and_undo() {
+        let mut editor = editor("fn main() {\n    run();\n}");
+        let pos = CursorPosition::new;
+        let left = editor.anchor_at(pos(1, 4), Bias::Left);
+        let right = editor.anchor_at(pos(1, 4), Bias::Right);
+
+        editor.set_cursor_position(pos(1, 4));
+        type_text(&mut editor, "let x = ");
+        assert_eq!(editor.resolve_anchor(&left), Some(pos(1, 4)));
+        assert_eq!(editor.resolve_anchor(&right), Some(pos(1, 12)));
+
+        // Lines added above move both down
+        editor.set_cursor_position(pos(0, 0));
+        editor.insert_newline();
+        assert_eq!(editor.resolve_anchor(&left), Some(pos(2, 4)));
+        assert_eq!(editor.resolve_anchor(&right), Some(pos(2, 12)));
+
+        // Undoing replays edits too, so anchors come back
+        while editor.undo() {}
+        assert_eq!(editor.resolve_anchor(&left), Some(pos(1, 4)));
+        assert_eq!(editor.resolve_anchor(&right), Some(pos(1, 4)));
+    }
+
+    #[test]
+    fn test_edit_log_stays_bounded() {
+        // Every replacement on the first line is an edit before the highlight
+        let words = 512;
+        let mut editor = editor(&format!("{}\nfn main() {{}}", "x ".repeat(words)));
+        let pos = CursorPosition::new;
+        let style = DecorationStyle::new().background(gpui::rgb(0x333333));
+        editor.add_highlight("name", [pos(1, 3)..pos(1, 7)], style);
+        let mut long = false;
+        let mut replace_words = |editor: &mut Editor, times: usize| {
+            for _ in 0..times {
+                let (from, to) = if long { ("yy", "x") } else { ("x", "yy") };
+                editor.set_search_query(SearchQuery::new(from)).unwrap();
+                assert_eq!(editor.replace_all(to), words);
+                long = !long;
+            }
+        };
+
+        replace_words(&mut editor, 2 * MAX_ANCHOR_AGE / words);
+        let buffer = editor.get_buffer();
+        assert!(buffer.version() >= 2 * MAX_ANCHOR_AGE);
+        assert!(
+            buffer.version() - buffer.first_anchor_version() <= MAX_ANCHOR_AGE + REFRESH_INTERVAL
+        );
+        assert_eq!(editor.highlight_ranges("name"), vec![pos(1, 3)..pos(1, 7)]);
+
+        // Anchors kept elsewhere resolve for `MAX_ANCHOR_AGE` edits, and
+        // refreshing them starts that over
+        let mut bookmark = editor.anchor_at(pos(1, 12), Bias::Left);
+        let forgotten = bookmark;
+        for _ in 0..2 {
+            replace_words(&mut editor, MAX_ANCHOR_AGE / (2 * words));
+            assert_eq!(editor.resolve_anchor(&bookmark), Some(pos(1, 12)));
+            bookmark = editor.refresh_anchor(&bookmark).unwrap();
+        }
+
+        // One that wasn't refreshed in time doesn't resolve at all
+        replace_words(&mut editor, 2 * REFRESH_INTERVAL / words);
+        assert_eq!(editor.resolve_anchor(&forgotten), None);
+        assert_eq!(editor.refresh_anchor(&forgotten), None);
+        assert_eq!(editor.resolve_anchor(&bookmark), Some(pos(1, 12)));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut editor = editor("let a = b;\nlet c = a\nlet d = 1;");
+        let pos = CursorPosition::new;
+        editor.set_diagnostics(vec![
+            Diagnostic::new(
+                pos(1, 9)..pos(1, 9),
+                DiagnosticSeverity::Error,
+                "expected `;`",
+            )
+            .source("rustc"),
+            Diagnostic::new(
+                pos(0, 8)..pos(0, 9),
+                DiagnosticSeverity::Error,
+                "cannot find `b`",
+            ),
+            Diagnostic::new(
+                pos(0, 4)..pos(0, 5),
+                DiagnosticSeverity::Warning,
+                "unused `a`",
+            ),
+        ]);
+
+        let counts = editor.diagnostic_counts();
+        assert_eq!((counts.errors, counts.warnings, counts.total()), (2, 1, 3));
+        assert_eq!(
+            editor.row_severities(0..3),
+            vec![
+                Some(DiagnosticSeverity::Error),
+                Some(DiagnosticSeverity::Error),
+                None
+            ]
+        );
+
+        // Each gets a squiggle; the empty one underlines the char before it
+        let squiggles: Vec<_> = editor
+            .decorations_in_rows(0..3)
+            .into_iter()
+            .filter(|(_, style)| style.underline.is_some_and(|underline| underline.wavy))
+            .map(|(range, _)| range)
+            .collect();
+        assert_eq!(squiggles.len(), 3);
+        assert!(squiggles.contains(&(pos(1, 8)..pos(1, 9))));
+
+        // Navigation wraps around in both directions
+        editor.set_cursor_position(pos(0, 6));
+        assert!(editor.next_diagnostic());
+        assert_eq!(editor.cursor_position(), pos(0, 8));
+        assert!(editor.next_diagnostic());
+        assert_eq!(editor.cursor_position(), pos(1, 9));
+        assert!(editor.next_diagnostic());
+        assert_eq!(editor.cursor_position(), pos(0, 4));
+        assert!(editor.previous_diagnostic());
+        assert_eq!(editor.cursor_position(), pos(1, 9));
+
+        // Ranges follow edits above them
+        editor.set_cursor_position(pos(0, 0));
+        editor.insert_newline();
+        assert_eq!(editor.diagnostics()[2].range, pos(2, 9)..pos(2, 9));
+        let messages: Vec<String> = editor
+            .diagnostics_at(pos(1, 9))
+            .iter()
+            .map(|diagnostic| diagnostic.display_message())
+            .collect();
+        assert_eq!(messages, vec!["cannot find `b`"]);
+
+        // Hovering shows the diagnostics under the mouse
+        assert!(editor.hover(Some(pos(2, 9))));
+        assert_eq!(
+            editor.hovered_diagnostics()[0].display_message(),
+            "expected `;` (rustc)"
+        );
+        assert!(!editor.hover(Some(pos(2, 9))));
+        assert!(editor.hover(Some(pos(3, 0))));
+        assert!(editor.hovered_diagnostics().is_empty());
+
+        editor.clear_diagnostics();
+        assert!(editor.diagnostics().is_empty());
+        assert!(editor.decorations_in_rows(0..4).is_empty());
+        assert!(!editor.next_diagnostic());
+    }
+
+    #[test]
+    fn test_track_changes() {
+        let mut editor = editor("ab\ncd");
+        let pos = CursorPosition::new;
+        editor.insert_char('x');
+        assert!(editor.take_changes().is_empty());
+
+        editor.track_changes();
+        editor.set_cursor_position(pos(1, 2));
+        type_text(&mut editor, "é");
+        select(&mut editor, (0, 1), (1, 1));
+        editor.backspace();
+        let changes = editor.take_changes();
+        assert_eq!(
+            changes,
+            vec![
+                TextChange {
+                    range: pos(1, 2)..pos(1, 2),
+                    text: "é".to_string(),
+                },
+                TextChange {
+                    range: pos(0, 1)..pos(1, 1),
+                    text: String::new(),
+                },
+            ]
+        );
+        assert!(editor.take_changes().is_empty());
+
+        // Undo is reported like any other change
+        editor.undo();
+        assert_eq!(
+            editor.take_changes(),
+            vec![TextChange {
+                range: pos(0, 1)..pos(0, 1),
+                text: "ab\nc".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_apply_text_edits() {
+        let mut editor = editor("fn a(){\nlet b=1;\n}");
+        let pos = CursorPosition::new;
+        editor.set_cursor_position(pos(1, 6));
+        editor.apply_text_edits(vec![
+            (pos(1, 0)..pos(1, 0), "    ".to_string()),
+            (pos(0, 6)..pos(0, 6), " ".to_string()),
+            (pos(1, 5)..pos(1, 6), " = ".to_string()),
+        ]);
+        assert_eq!(
+            editor.get_buffer().to_string(),
+            "fn a() {\n    let b = 1;\n}"
+        );
+        // The caret stays before the `1`
+        assert_eq!(editor.cursor_position(), pos(1, 12));
+
+        // All edits undo together
+        editor.undo();
+        assert_eq!(editor.get_buffer().to_string(), "fn a(){\nlet b=1;\n}");
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut editor = editor("let settings = 1;\nlet value = se");
+        let pos = CursorPosition::new;
+        let items = ["settings", "self", "reset"]
+            .into_iter()
+            .map(CompletionItem::new)
+            .collect::<Vec<_>>();
+        editor.set_cursor_position(pos(1, 14));
+        editor.show_completion_items(items.clone());
+        let menu = editor.completion_menu().unwrap();
+        assert_eq!(menu.word_start(), pos(1, 12));
+        assert_eq!(menu.len(), 3);
+
+        // Typing narrows the items, and the selection wraps around
+        type_text(&mut editor, "t");
+        assert_eq!(editor.completion_menu().unwrap().len(), 2);
+        assert!(editor.select_previous_completion());
+        assert_eq!(
+            editor
+                .completion_menu()
+                .unwrap()
+                .selected_item()
+                .unwrap()
+                .label,
+            "reset"
+        );
+        assert!(editor.select_next_completion());
+        assert!(editor.confirm_completion());
+        assert_eq!(
+            editor.get_buffer().to_string(),
+            "let settings = 1;\nlet value = settings"
+        );
+        assert_eq!(editor.cursor_position(), pos(1, 20));
+        assert!(editor.completion_menu().is_none());
+        assert!(!editor.confirm_completion());
+
+        // Leaving the word closes the menu
+        editor.undo();
+        editor.show_completion_items(items.clone());
+        assert!(editor.has_open_completions());
+        type_text(&mut editor, " ");
+        assert!(!editor.has_open_completions());
+        editor.backspace();
+        assert!(!editor.select_next_completion());
+
+        // No item matching keeps the menu open but hidden
+        editor.show_completion_items(items.clone());
+        type_text(&mut editor, "x");
+        assert!(editor.completion_menu().is_none());
+        assert!(editor.has_open_completions());
+        assert!(!editor.cancel_completions());
+        assert!(!editor.has_open_completions());
 
-        self.goal_column = None;
+        // Every caret after the same prefix is completed
+        let mut carets = self::editor("xe\nse\nse");
+        carets.set_cursor_position(pos(0, 2));
+        carets.add_cursor_below();
+        carets.add_cursor_below();
+        carets.show_completion_items(items);
+        assert!(carets.confirm_completion());
+        assert_eq!(carets.get_buffer().to_string(), "xe\nself\nself");
     }
 }
@@ -1,82 +1,225 @@
 //! GPUI Element implementation for rendering an Editor
 
-use crate::buffer::TextBuffer;
-use crate::editor::Editor;
+use crate::buffer::{GapBuffer, TextBuffer};
+use crate::completion::CompletionItem;
+use crate::coordinates::char_to_byte;
+use crate::decorations;
+use crate::diagnostics::DiagnosticSeverity;
+use crate::display_map::LineLayout;
+use crate::editor::{CursorPosition, Editor, WrapMode};
+use crate::view::EditorView;
 use gpui::*;
+use std::ops::Range;
 
-/// A GPUI Element that renders an Editor
-pub struct EditorElement {
-    editor: Editor,
+/// Most completions listed at once; the list scrolls to keep the selected one
+/// in view
+const MAX_VISIBLE_COMPLETIONS: usize = 8;
+/// Width the documentation of the selected completion wraps at
+const COMPLETION_DOCUMENTATION_WIDTH: Pixels = px(320.0);
+
+/// A GPUI Element that renders the editor of an [`EditorView`]
+pub struct EditorElement<B = GapBuffer> {
+    id: ElementId,
+    view: Entity<EditorView<B>>,
+    /// Platform text input goes to the view while this is focused
+    focus_handle: Option<FocusHandle>,
 }
 
-impl EditorElement {
-    /// Create a new EditorElement from an Editor
-    pub fn new(editor: Editor) -> Self {
-        Self { editor }
+impl<B: TextBuffer + Clone + 'static> EditorElement<B> {
+    /// Create an EditorElement that lays out and paints the editor of `view`
+    pub fn new(id: impl Into<ElementId>, view: Entity<EditorView<B>>) -> Self {
+        Self {
+            id: id.into(),
+            view,
+            focus_handle: None,
+        }
     }
 
-    /// Get a reference to the underlying Editor
-    pub fn editor(&self) -> &Editor {
-        &self.editor
+    /// Route platform text input (typing, dead keys, IME composition) to the
+    /// view while `focus_handle` is focused
+    pub fn with_input_handler(mut self, focus_handle: &FocusHandle) -> Self {
+        self.focus_handle = Some(focus_handle.clone());
+        self
     }
 
-    /// Get a mutable reference to the underlying Editor
-    pub fn editor_mut(&mut self) -> &mut Editor {
-        &mut self.editor
+    fn register_scroll_handler(&self, window: &mut Window, bounds: Bounds<Pixels>) {
+        let view = self.view.clone();
+        window.on_mouse_event(move |event: &ScrollWheelEvent, phase, _window, cx| {
+            if phase == DispatchPhase::Bubble && bounds.contains(&event.position) {
+                view.update(cx, |view, cx| {
+                    let editor = view.editor_mut();
+                    let delta = event.delta.pixel_delta(editor.config().line_height);
+                    if editor.scroll_by(-delta.y) {
+                        cx.notify();
+                    }
+                });
+            }
+        });
     }
+}
 
-    fn line_bounds(&self, row: usize, bounds: Bounds<Pixels>) -> Bounds<Pixels> {
+/// The editor being drawn, borrowed from the view for one phase of drawing it
+struct EditorPainter<'a, B> {
+    editor: &'a mut Editor<B>,
+}
+
+impl<B: TextBuffer + Clone + 'static> EditorPainter<'_, B> {
+    /// Bounds of display row `display_row`, right of the gutter
+    fn display_row_bounds(&self, display_row: usize, bounds: Bounds<Pixels>) -> Bounds<Pixels> {
         let config = self.editor.config();
         Bounds {
             origin: point(
                 bounds.origin.x + config.gutter_width,
-                bounds.origin.y + config.line_height * row as f32,
+                bounds.origin.y + config.line_height * display_row as f32
+                    - self.editor.scroll_top(),
             ),
             size: size(bounds.size.width - config.gutter_width, config.line_height),
         }
     }
 
-    fn cursor_position_px(&self, bounds: Bounds<Pixels>, window: &mut Window) -> Point<Pixels> {
+    /// Bounds of every visual row of buffer `row`, right of the gutter
+    fn line_bounds(&self, row: usize, bounds: Bounds<Pixels>) -> Bounds<Pixels> {
+        let display_map = self.editor.display_map();
+        let display_row = self.editor.display_row(CursorPosition::new(row, 0));
+        let mut line_bounds = self.display_row_bounds(display_row, bounds);
+        line_bounds.size.height *= display_map.row_count(row) as f32;
+        line_bounds
+    }
+
+    fn cursor_position_px(
+        &self,
+        cursor_pos: CursorPosition,
+        bounds: Bounds<Pixels>,
+    ) -> Point<Pixels> {
         let config = self.editor.config();
-        let cursor_pos = self.editor.get_cursor_position();
-        let line = self
-            .editor
-            .get_buffer()
-            .get_line(cursor_pos.row)
-            .unwrap_or_else(|| String::new());
+        let row_bounds = self.display_row_bounds(self.editor.display_row(cursor_pos), bounds);
+        let offset_x = self.editor.display_map().x_for_position(cursor_pos);
+        point(
+            row_bounds.origin.x + config.gutter_padding + offset_x,
+            row_bounds.origin.y,
+        )
+    }
 
-        let text_before_cursor = &line[..cursor_pos.col.min(line.len())];
-        let text_x = bounds.origin.x + config.gutter_width + config.gutter_padding;
+    /// Shape `line` with `runs`, wrapped at the display map's wrap width
+    fn shape_line(&self, line: SharedString, runs: &[TextRun], window: &mut Window) -> WrappedLine {
+        let font_size = self.editor.config().font_size;
+        let wrap_width = self.editor.display_map().wrap_width();
+        window
+            .text_system()
+            .shape_text(line, font_size, runs, wrap_width, None)
+            .ok()
+            .and_then(|lines| lines.into_iter().next())
+            .unwrap_or_default()
+    }
 
-        let offset_x = if !text_before_cursor.is_empty() {
-            let shaped = window.text_system().shape_line(
-                SharedString::from(text_before_cursor.to_string()),
-                config.font_size,
-                &[TextRun {
-                    len: text_before_cursor.len(),
-                    font: Font {
-                        family: config.font_family.clone(),
-                        features: Default::default(),
-                        weight: FontWeight::NORMAL,
-                        style: FontStyle::Normal,
-                        fallbacks: Default::default(),
-                    },
-                    color: config.text_color.into(),
-                    background_color: None,
-                    underline: None,
-                    strikethrough: None,
-                }],
-                None,
-            );
-            shaped.width
-        } else {
-            px(0.0)
-        };
+    /// Bring the display map up to date with the font and wrap width. When
+    /// wrapping, every line is laid out so the display rows of the whole
+    /// buffer are known.
+    fn layout_rows(&mut self, bounds: Bounds<Pixels>, window: &mut Window) {
+        let config = self.editor.config();
+        let text_system = window.text_system();
+        let font_id = text_system.resolve_font(&font(config.font_family.clone()));
+        let char_width = text_system
+            .em_advance(font_id, config.font_size)
+            .unwrap_or_else(|_| self.editor.display_map().char_width());
 
-        point(
-            text_x + offset_x,
-            bounds.origin.y + config.line_height * cursor_pos.row as f32,
-        )
+        let wrap_width = match config.wrap_mode {
+            WrapMode::None => None,
+            WrapMode::EditorWidth => {
+                Some(bounds.size.width - config.gutter_width - config.gutter_padding * 2.0)
+            }
+            WrapMode::Column(columns) => Some(char_width * columns as f32),
+        }
+        // Always fit at least one character on a row
+        .map(|width| width.max(char_width));
+        let font_family = config.font_family.clone();
+        let font_size = config.font_size;
+        self.editor.display_map_mut().set_text_layout(
+            font_family.clone(),
+            font_size,
+            wrap_width,
+            char_width,
+        );
+        if wrap_width.is_none() {
+            return;
+        }
+
+        for row in 0..self.editor.get_buffer().line_count() {
+            if self.editor.display_map().is_laid_out(row) {
+                continue;
+            }
+            let line = self.editor.get_buffer().get_line(row).unwrap_or_default();
+            let runs = self
+                .editor
+                .cached_text_runs(&line, row, font_family.clone());
+            let shaped = self.shape_line(line.into(), &runs, window);
+            self.editor
+                .display_map_mut()
+                .insert(row, LineLayout::from_wrapped_line(&shaped));
+        }
+    }
+
+    /// Shape the visible lines with their highlighting, recording their
+    /// layouts in the display map
+    fn layout_lines(
+        &mut self,
+        window: &mut Window,
+        cx: &mut Context<EditorView<B>>,
+    ) -> Vec<(usize, WrappedLine)> {
+        let visible_rows = self.editor.visible_rows();
+        self.editor.refresh_highlights(visible_rows.clone(), cx);
+        if self.editor.config().show_fold_indicators {
+            self.editor.refresh_foldable_ranges(cx);
+        }
+
+        let font_family = self.editor.config().font_family.clone();
+        let mut lines = Vec::with_capacity(visible_rows.len());
+        for row in visible_rows {
+            if self.editor.is_row_hidden(row) {
+                continue;
+            }
+            let line = self.editor.get_buffer().get_line(row).unwrap_or_default();
+            let mut text_runs = self
+                .editor
+                .cached_text_runs(&line, row, font_family.clone());
+
+            for (range, style) in self.editor.decorations_in_rows(row..row + 1) {
+                if !style.styles_text() {
+                    continue;
+                }
+                let start_col = if range.start.row == row {
+                    range.start.col
+                } else {
+                    0
+                };
+                let end_col = if range.end.row == row {
+                    range.end.col
+                } else {
+                    usize::MAX
+                };
+                let bytes = char_to_byte(&line, start_col)..char_to_byte(&line, end_col);
+                text_runs = decorations::restyle_runs(text_runs, bytes, |run| style.apply(run));
+            }
+
+            // Underline text that an input method is still composing
+            if let Some(marked) = self.marked_byte_range(row, &line) {
+                let underline = UnderlineStyle {
+                    thickness: px(1.0),
+                    color: Some(self.editor.config().text_color.into()),
+                    wavy: false,
+                };
+                text_runs = decorations::restyle_runs(text_runs, marked, |run| {
+                    run.underline = Some(underline)
+                });
+            }
+
+            let shaped = self.shape_line(line.into(), &text_runs, window);
+            self.editor
+                .display_map_mut()
+                .insert(row, LineLayout::from_wrapped_line(&shaped));
+            lines.push((row, shaped));
+        }
+        lines
     }
 
     fn paint_editor_background(&self, window: &mut Window, bounds: Bounds<Pixels>) {
@@ -121,15 +264,133 @@ impl EditorElement {
 
     fn paint_active_line_background(&self, window: &mut Window, bounds: Bounds<Pixels>) {
         let config = self.editor.config();
-        let cursor_pos = self.editor.get_cursor_position();
         let bg_color: Hsla = config.active_line_bg_color.into();
 
         if bg_color.is_opaque() {
-            let active_line_bounds = self.line_bounds(cursor_pos.row, bounds);
+            let visible_rows = self.editor.visible_rows();
+            let mut rows: Vec<usize> = self
+                .editor
+                .selections()
+                .iter()
+                .map(|selection| selection.head.row)
+                .filter(|row| visible_rows.contains(row))
+                .collect();
+            rows.dedup();
+
+            for row in rows {
+                let active_line_bounds = self.line_bounds(row, bounds);
+                window.paint_quad(PaintQuad {
+                    bounds: active_line_bounds,
+                    corner_radii: (0.0).into(),
+                    background: config.active_line_bg_color.into(),
+                    border_color: transparent_black(),
+                    border_widths: (0.0).into(),
+                    border_style: BorderStyle::Solid,
+                });
+            }
+        }
+    }
+
+    fn paint_selection(&self, window: &mut Window, bounds: Bounds<Pixels>) {
+        let selection_color = rgba(0x264f78ff);
+        self.paint_range_backgrounds(
+            &self.editor.selection_ranges(),
+            selection_color,
+            window,
+            bounds,
+        );
+    }
+
+    fn paint_search_matches(&self, window: &mut Window, bounds: Bounds<Pixels>) {
+        let matches = self.editor.search_matches();
+        if matches.is_empty() {
+            return;
+        }
+
+        // Matches are sorted, so only look at the ones that can be visible
+        let visible_rows = self.editor.visible_rows();
+        let first = matches.partition_point(|(_, end)| end.row < visible_rows.start);
+        let last = matches.partition_point(|(start, _)| start.row < visible_rows.end);
+        self.paint_range_backgrounds(
+            &matches[first..last.max(first)],
+            self.editor.config().search_match_bg_color,
+            window,
+            bounds,
+        );
+    }
+
+    /// Outline the bracket pair at the primary caret
+    fn paint_matching_brackets(&self, window: &mut Window, bounds: Bounds<Pixels>) {
+        let Some((open, close)) = self.editor.matching_brackets() else {
+            return;
+        };
+        let config = self.editor.config();
+        let display_map = self.editor.display_map();
+        let visible_rows = self.editor.visible_rows();
+        for bracket in [open, close] {
+            if !visible_rows.contains(&bracket.row) || display_map.is_hidden(bracket.row) {
+                continue;
+            }
+            let Some(layout) = display_map.layout(bracket.row) else {
+                continue;
+            };
+            let wrap_row = layout.wrap_row(bracket.col);
+            let start_x = layout.x_in_row(wrap_row, bracket.col);
+            let end_x = layout.x_in_row(wrap_row, bracket.col + 1);
+            let display_row =
+                self.editor.display_row(CursorPosition::new(bracket.row, 0)) + wrap_row;
+            let row_bounds = self.display_row_bounds(display_row, bounds);
+
+            window.paint_quad(PaintQuad {
+                bounds: Bounds {
+                    origin: point(
+                        row_bounds.origin.x + config.gutter_padding + start_x,
+                        row_bounds.origin.y,
+                    ),
+                    size: size(end_x - start_x, config.line_height),
+                },
+                corner_radii: (0.0).into(),
+                background: transparent_black().into(),
+                border_color: config.matching_bracket_border_color.into(),
+                border_widths: px(1.0).into(),
+                border_style: BorderStyle::Solid,
+            });
+        }
+    }
+
+    /// Paint the backgrounds and borders of decorated ranges in the viewport
+    fn paint_decorations(&self, window: &mut Window, bounds: Bounds<Pixels>) {
+        let decorations = self.editor.decorations_in_rows(self.editor.visible_rows());
+        for (range, style) in decorations {
+            if style.background.is_none() && style.border.is_none() {
+                continue;
+            }
+            for range_bounds in self.range_bounds(&[(range.start, range.end)], bounds) {
+                window.paint_quad(PaintQuad {
+                    bounds: range_bounds,
+                    corner_radii: (0.0).into(),
+                    background: style.background.unwrap_or_default().into(),
+                    border_color: style.border.unwrap_or_default().into(),
+                    border_widths: px(if style.border.is_some() { 1.0 } else { 0.0 }).into(),
+                    border_style: BorderStyle::Solid,
+                });
+            }
+        }
+    }
+
+    /// Fill the text of each (start, end) range in the viewport with `color`
+    fn paint_range_backgrounds(
+        &self,
+        ranges: &[(CursorPosition, CursorPosition)],
+        color: Rgba,
+        window: &mut Window,
+        bounds: Bounds<Pixels>,
+    ) {
+        for range_bounds in self.range_bounds(ranges, bounds) {
             window.paint_quad(PaintQuad {
-                bounds: active_line_bounds,
+                bounds: range_bounds,
                 corner_radii: (0.0).into(),
-                background: config.active_line_bg_color.into(),
+                background: color.into(),
                 border_color: transparent_black(),
                 border_widths: (0.0).into(),
                 border_style: BorderStyle::Solid,
@@ -137,139 +398,146 @@ impl EditorElement {
         }
     }
 
-    fn paint_selection(&self, window: &mut Window, bounds: Bounds<Pixels>) {
+    /// Bounds of the text of each (start, end) range in the viewport, one
+    /// for every visual row a range is on
+    fn range_bounds(
+        &self,
+        ranges: &[(CursorPosition, CursorPosition)],
+        bounds: Bounds<Pixels>,
+    ) -> Vec<Bounds<Pixels>> {
         let config = self.editor.config();
 
-        if let Some((start, end)) = self.editor.get_selection_range() {
-            let selection_color = rgba(0x264f78ff);
-
-            for row in start.row..=end.row {
-                if let Some(line) = self.editor.get_buffer().get_line(row) {
-                    let line_bounds = self.line_bounds(row, bounds);
-
-                    let start_col = if row == start.row { start.col } else { 0 };
-                    let end_col = if row == end.row { end.col } else { line.len() };
-
-                    let text_x_start = line_bounds.origin.x + config.gutter_padding;
-
-                    let start_x = if start_col > 0 {
-                        let text_before =
-                            SharedString::from(line[..start_col.min(line.len())].to_string());
-                        let shaped = window.text_system().shape_line(
-                            text_before.clone(),
-                            config.font_size,
-                            &[TextRun {
-                                len: text_before.len(),
-                                font: Font {
-                                    family: config.font_family.clone(),
-                                    features: Default::default(),
-                                    weight: FontWeight::NORMAL,
-                                    style: FontStyle::Normal,
-                                    fallbacks: Default::default(),
-                                },
-                                color: config.text_color.into(),
-                                background_color: None,
-                                underline: None,
-                                strikethrough: None,
-                            }],
-                            None,
-                        );
-                        shaped.width
-                    } else {
-                        px(0.0)
-                    };
-
-                    let end_x = if end_col > 0 {
-                        let text_to_end =
-                            SharedString::from(line[..end_col.min(line.len())].to_string());
-                        let shaped = window.text_system().shape_line(
-                            text_to_end.clone(),
-                            config.font_size,
-                            &[TextRun {
-                                len: text_to_end.len(),
-                                font: Font {
-                                    family: config.font_family.clone(),
-                                    features: Default::default(),
-                                    weight: FontWeight::NORMAL,
-                                    style: FontStyle::Normal,
-                                    fallbacks: Default::default(),
-                                },
-                                color: config.text_color.into(),
-                                background_color: None,
-                                underline: None,
-                                strikethrough: None,
-                            }],
-                            None,
-                        );
-                        shaped.width
-                    } else {
-                        px(0.0)
-                    };
-
-                    let selection_bounds = Bounds {
-                        origin: point(text_x_start + start_x, line_bounds.origin.y),
+        let display_map = self.editor.display_map();
+        let visible_rows = self.editor.visible_rows();
+        let mut range_bounds = Vec::new();
+        for &(start, end) in ranges {
+            let first_row = start.row.max(visible_rows.start);
+            let last_row = end.row.min(visible_rows.end.saturating_sub(1));
+            for row in first_row..=last_row {
+                if display_map.is_hidden(row) {
+                    continue;
+                }
+                let Some(layout) = display_map.layout(row) else {
+                    continue;
+                };
+                let start_col = if row == start.row { start.col } else { 0 };
+                let end_col = if row == end.row {
+                    end.col
+                } else {
+                    self.editor.get_buffer().line_len(row)
+                };
+
+                // A range on a wrapped line has bounds on each visual row
+                let first_display_row = self.editor.display_row(CursorPosition::new(row, 0));
+                for wrap_row in layout.wrap_row(start_col)..=layout.wrap_row(end_col) {
+                    let cols = layout.row_range(wrap_row);
+                    let start_x = layout.x_in_row(wrap_row, start_col.max(cols.start));
+                    let end_x = layout.x_in_row(wrap_row, end_col.min(cols.end));
+                    if end_x <= start_x {
+                        continue;
+                    }
+
+                    let row_bounds = self.display_row_bounds(first_display_row + wrap_row, bounds);
+                    range_bounds.push(Bounds {
+                        origin: point(
+                            row_bounds.origin.x + config.gutter_padding + start_x,
+                            row_bounds.origin.y,
+                        ),
                         size: size(end_x - start_x, config.line_height),
-                    };
-
-                    window.paint_quad(PaintQuad {
-                        bounds: selection_bounds,
-                        corner_radii: (0.0).into(),
-                        background: selection_color.into(),
-                        border_color: transparent_black(),
-                        border_widths: (0.0).into(),
-                        border_style: BorderStyle::Solid,
                     });
                 }
             }
         }
+        range_bounds
     }
 
-    fn paint_lines(&mut self, cx: &mut App, window: &mut Window, bounds: Bounds<Pixels>) {
-        let _config = self.editor.config();
-        let lines = self.editor.get_buffer().all_lines();
-
-        for (i, line) in lines.iter().enumerate() {
-            let line_bounds = self.line_bounds(i, bounds);
-            self.paint_line_number(cx, window, i + 1, line_bounds, bounds);
-            self.paint_line(cx, window, line, i, line_bounds);
+    fn paint_lines(
+        &mut self,
+        lines: &[(usize, WrappedLine)],
+        cx: &mut App,
+        window: &mut Window,
+        bounds: Bounds<Pixels>,
+    ) {
+        let config = self.editor.config();
+        let foldable = self.editor.cached_foldable_ranges();
+        let visible_rows = self.editor.visible_rows();
+        let severities = self.editor.row_severities(visible_rows.clone());
+        for (row, line) in lines {
+            let line_bounds = self.line_bounds(*row, bounds);
+            // Wrapped lines are numbered on their first visual row only
+            if config.show_line_numbers {
+                let severity = severities[row - visible_rows.start];
+                self.paint_line_number(cx, window, row + 1, severity, line_bounds, bounds);
+            }
+            if config.show_fold_indicators {
+                if self.editor.is_folded(*row) {
+                    self.paint_fold_indicator(cx, window, "▸", line_bounds, bounds);
+                    self.paint_fold_placeholder(cx, window, *row, bounds);
+                } else if foldable.iter().any(|range| range.start == *row) {
+                    self.paint_fold_indicator(cx, window, "▾", line_bounds, bounds);
+                }
+            }
+            let origin = point(
+                line_bounds.origin.x + config.gutter_padding,
+                line_bounds.origin.y,
+            );
+            let _ = line.paint(
+                origin,
+                config.line_height,
+                TextAlign::Left,
+                None,
+                window,
+                cx,
+            );
         }
+
+        // Record what was painted so mouse events can be mapped back to positions
+        let text_origin = point(
+            bounds.origin.x + config.gutter_width + config.gutter_padding,
+            bounds.origin.y,
+        );
+        let first_row = lines.first().map_or(0, |(row, _)| *row);
+        let line_height = config.line_height;
+        self.editor
+            .position_map_mut()
+            .update(text_origin, line_height, first_row);
     }
 
+    /// Paint a line number, with a dot in the color of `severity` before it
+    /// if the line has diagnostics
     fn paint_line_number(
         &self,
         cx: &mut App,
         window: &mut Window,
         line_number: usize,
+        severity: Option<DiagnosticSeverity>,
         line_bounds: Bounds<Pixels>,
         editor_bounds: Bounds<Pixels>,
     ) {
         let config = self.editor.config();
-        let line_number_str = SharedString::new(line_number.to_string());
-        let line_number_len = line_number_str.len();
         let gutter_padding = px(10.0);
         let line_number_x =
             editor_bounds.origin.x + config.gutter_width - gutter_padding - px(20.0);
 
-        let shaped_line_number = window.text_system().shape_line(
-            line_number_str,
-            config.font_size,
-            &[TextRun {
-                len: line_number_len,
-                font: Font {
-                    family: config.font_family.clone(),
-                    features: Default::default(),
-                    weight: FontWeight::NORMAL,
-                    style: FontStyle::Normal,
-                    fallbacks: Default::default(),
+        if let Some(severity) = severity {
+            let size = px(6.0);
+            window.paint_quad(PaintQuad {
+                bounds: Bounds {
+                    origin: point(
+                        line_number_x - size - px(2.0),
+                        line_bounds.origin.y + (config.line_height - size) / 2.0,
+                    ),
+                    size: gpui::size(size, size),
                 },
-                color: config.line_number_color.into(),
-                background_color: None,
-                underline: None,
-                strikethrough: None,
-            }],
-            None,
-        );
+                corner_radii: (size / 2.0).into(),
+                background: config.diagnostic_color(severity).into(),
+                border_color: transparent_black(),
+                border_widths: (0.0).into(),
+                border_style: BorderStyle::Solid,
+            });
+        }
 
+        let shaped_line_number = self.shape_gutter_text(line_number.to_string().into(), window);
         let _ = shaped_line_number.paint(
             point(line_number_x, line_bounds.origin.y),
             config.line_height,
@@ -278,56 +546,411 @@ impl EditorElement {
         );
     }
 
-    fn paint_line(
-        &mut self,
+    /// Paint the chevron that folds or unfolds a row at the gutter's left edge
+    fn paint_fold_indicator(
+        &self,
         cx: &mut App,
         window: &mut Window,
-        line: impl Into<SharedString>,
-        line_index: usize,
+        chevron: &'static str,
         line_bounds: Bounds<Pixels>,
+        editor_bounds: Bounds<Pixels>,
     ) {
-        let gutter_padding = px(10.0);
-        let text_x = line_bounds.origin.x + gutter_padding;
-        let line = line.into();
+        let shaped = self.shape_gutter_text(chevron.into(), window);
+        let _ = shaped.paint(
+            point(editor_bounds.origin.x + px(4.0), line_bounds.origin.y),
+            self.editor.config().line_height,
+            window,
+            cx,
+        );
+    }
 
-        // Get syntax highlighted text runs
+    /// Paint "⋯" after the text of folded `row` to stand in for the hidden rows
+    fn paint_fold_placeholder(
+        &self,
+        cx: &mut App,
+        window: &mut Window,
+        row: usize,
+        bounds: Bounds<Pixels>,
+    ) {
         let config = self.editor.config();
-        let font_family = config.font_family.clone();
-        let font_size = config.font_size;
-        let line_height = config.line_height;
-        let font_size_f32: f32 = font_size.into();
-        let text_runs = self
-            .editor
-            .highlight_line(&line, line_index, font_family, font_size_f32);
+        let display_map = self.editor.display_map();
+        let Some(layout) = display_map.layout(row) else {
+            return;
+        };
+        let last_wrap_row = layout.row_count() - 1;
+        let line_len = self.editor.get_buffer().line_len(row);
+        let text_end = layout.x_in_row(last_wrap_row, line_len);
+        let display_row = self.editor.display_row(CursorPosition::new(row, 0)) + last_wrap_row;
+        let row_bounds = self.display_row_bounds(display_row, bounds);
+
+        let shaped = self.shape_gutter_text("⋯".into(), window);
+        let padding = px(4.0);
+        let placeholder_bounds = Bounds {
+            origin: point(
+                row_bounds.origin.x + config.gutter_padding + text_end + display_map.char_width(),
+                row_bounds.origin.y + px(2.0),
+            ),
+            size: size(shaped.width + padding * 2.0, config.line_height - px(4.0)),
+        };
+        window.paint_quad(PaintQuad {
+            bounds: placeholder_bounds,
+            corner_radii: px(3.0).into(),
+            background: config.active_line_bg_color.into(),
+            border_color: transparent_black(),
+            border_widths: (0.0).into(),
+            border_style: BorderStyle::Solid,
+        });
+        let _ = shaped.paint(
+            point(placeholder_bounds.origin.x + padding, row_bounds.origin.y),
+            config.line_height,
+            window,
+            cx,
+        );
+    }
+
+    /// Shape `text` in the line number color
+    fn shape_gutter_text(&self, text: SharedString, window: &mut Window) -> ShapedLine {
+        let color = self.editor.config().line_number_color;
+        self.shape_plain_text(text, color, window)
+    }
 
-        let shaped_line =
-            window
-                .text_system()
-                .shape_line(line.clone(), font_size, &text_runs, None);
+    /// Shape `text` in the editor font and `color`
+    fn shape_plain_text(&self, text: SharedString, color: Rgba, window: &mut Window) -> ShapedLine {
+        let run = self.plain_run(text.len(), color);
+        window
+            .text_system()
+            .shape_line(text, self.editor.config().font_size, &[run], None)
+    }
 
-        let _ = shaped_line.paint(point(text_x, line_bounds.origin.y), line_height, window, cx);
+    /// A run of `len` bytes in the editor font
+    fn plain_run(&self, len: usize, color: Rgba) -> TextRun {
+        TextRun {
+            len,
+            font: Font {
+                family: self.editor.config().font_family.clone(),
+                features: Default::default(),
+                weight: FontWeight::NORMAL,
+                style: FontStyle::Normal,
+                fallbacks: Default::default(),
+            },
+            color: color.into(),
+            background_color: None,
+            underline: None,
+            strikethrough: None,
+        }
     }
 
-    fn paint_cursor(&self, window: &mut Window, bounds: Bounds<Pixels>) {
+    /// The byte range of `line` covered by marked text, if any
+    fn marked_byte_range(&self, row: usize, line: &str) -> Option<Range<usize>> {
+        let marked = self.editor.marked_range()?;
+        let buffer = self.editor.get_buffer();
+        let (start_row, start_col) = buffer.position_to_cursor(marked.start);
+        let (end_row, end_col) = buffer.position_to_cursor(marked.end);
+        if row < start_row || row > end_row {
+            return None;
+        }
+
+        let start_col = if row == start_row { start_col } else { 0 };
+        let end_col = if row == end_row { end_col } else { usize::MAX };
+        let range = char_to_byte(line, start_col)..char_to_byte(line, end_col);
+        (!range.is_empty()).then_some(range)
+    }
+
+    /// Show the messages of the diagnostics under the mouse in a box below
+    /// the line, or above it near the bottom of the editor
+    fn paint_diagnostic_popover(&self, cx: &mut App, window: &mut Window, bounds: Bounds<Pixels>) {
+        let diagnostics = self.editor.hovered_diagnostics();
+        let Some(first) = diagnostics.first() else {
+            return;
+        };
         let config = self.editor.config();
-        let cursor_pos = self.cursor_position_px(bounds, window);
-        let cursor_bounds = Bounds {
-            origin: cursor_pos,
-            size: size(px(2.0), config.line_height),
+        let lines: Vec<(ShapedLine, Rgba)> = diagnostics
+            .iter()
+            .flat_map(|diagnostic| {
+                let color = config.diagnostic_color(diagnostic.severity);
+                diagnostic
+                    .display_message()
+                    .lines()
+                    .map(|line| (line.to_string(), color))
+                    .collect::<Vec<_>>()
+            })
+            .map(|(line, color)| {
+                let shaped = self.shape_plain_text(line.into(), config.text_color, window);
+                (shaped, color)
+            })
+            .collect();
+
+        let padding = px(6.0);
+        let marker_width = px(3.0);
+        let width = lines
+            .iter()
+            .map(|(line, _)| line.width)
+            .fold(px(0.0), Pixels::max)
+            + padding * 3.0
+            + marker_width;
+        let height = config.line_height * lines.len() as f32 + padding * 2.0;
+
+        let anchor = self.cursor_position_px(first.range.start, bounds);
+        let below = anchor.y + config.line_height;
+        let y = if below + height > bounds.origin.y + bounds.size.height {
+            anchor.y - height
+        } else {
+            below
         };
+        let x = anchor
+            .x
+            .min(bounds.origin.x + bounds.size.width - width)
+            .max(bounds.origin.x);
+        window.paint_quad(PaintQuad {
+            bounds: Bounds {
+                origin: point(x, y),
+                size: size(width, height),
+            },
+            corner_radii: px(3.0).into(),
+            background: config.gutter_bg_color.into(),
+            border_color: config.line_number_color.into(),
+            border_widths: px(1.0).into(),
+            border_style: BorderStyle::Solid,
+        });
 
+        // Each line gets a bar in the color of its diagnostic's severity
+        for (index, (line, color)) in lines.iter().enumerate() {
+            let line_y = y + padding + config.line_height * index as f32;
+            window.paint_quad(PaintQuad {
+                bounds: Bounds {
+                    origin: point(x + padding, line_y),
+                    size: size(marker_width, config.line_height),
+                },
+                corner_radii: (0.0).into(),
+                background: (*color).into(),
+                border_color: transparent_black(),
+                border_widths: (0.0).into(),
+                border_style: BorderStyle::Solid,
+            });
+            let _ = line.paint(
+                point(x + padding * 2.0 + marker_width, line_y),
+                config.line_height,
+                window,
+                cx,
+            );
+        }
+    }
+
+    /// Shape a completion label with the chars at `positions`, which matched
+    /// what was typed, in the match color
+    fn shape_completion_label(
+        &self,
+        label: &str,
+        positions: &[usize],
+        window: &mut Window,
+    ) -> ShapedLine {
+        let config = self.editor.config();
+        let mut runs: Vec<TextRun> = Vec::new();
+        for (index, ch) in label.chars().enumerate() {
+            let color = if positions.contains(&index) {
+                config.completion_match_color
+            } else {
+                config.text_color
+            };
+            match runs.last_mut() {
+                Some(run) if run.color == Hsla::from(color) => run.len += ch.len_utf8(),
+                _ => runs.push(self.plain_run(ch.len_utf8(), color)),
+            }
+        }
+        window
+            .text_system()
+            .shape_line(label.to_string().into(), config.font_size, &runs, None)
+    }
+
+    /// List the completions matching the word being typed in a box below
+    /// its start, or above it near the bottom of the editor, with the
+    /// documentation of the selected one beside it
+    fn paint_completion_menu(&self, cx: &mut App, window: &mut Window, bounds: Bounds<Pixels>) {
+        let Some(menu) = self.editor.completion_menu() else {
+            return;
+        };
+        let config = self.editor.config();
+        let selected = menu.selected_index();
+        let first = (selected + 1).saturating_sub(MAX_VISIBLE_COMPLETIONS);
+        let rows: Vec<(ShapedLine, Option<ShapedLine>)> = menu
+            .matches()
+            .skip(first)
+            .take(MAX_VISIBLE_COMPLETIONS)
+            .map(|(item, positions)| {
+                let label = self.shape_completion_label(&item.label, positions, window);
+                let detail = item.detail.as_ref().map(|detail| {
+                    self.shape_plain_text(detail.clone().into(), config.line_number_color, window)
+                });
+                (label, detail)
+            })
+            .collect();
+
+        let padding = px(6.0);
+        let label_width = rows
+            .iter()
+            .map(|(label, _)| label.width)
+            .fold(px(0.0), Pixels::max);
+        let detail_width = rows
+            .iter()
+            .filter_map(|(_, detail)| Some(detail.as_ref()?.width))
+            .fold(px(0.0), Pixels::max);
+        let gap = if detail_width > px(0.0) {
+            padding * 4.0
+        } else {
+            px(0.0)
+        };
+        let width = label_width + gap + detail_width + padding * 2.0;
+        let height = config.line_height * rows.len() as f32 + padding * 2.0;
+
+        let anchor = self.cursor_position_px(menu.word_start(), bounds);
+        let below = anchor.y + config.line_height;
+        let y = if below + height > bounds.origin.y + bounds.size.height {
+            anchor.y - height
+        } else {
+            below
+        };
+        let x = anchor
+            .x
+            .min(bounds.origin.x + bounds.size.width - width)
+            .max(bounds.origin.x);
+        let menu_bounds = Bounds {
+            origin: point(x, y),
+            size: size(width, height),
+        };
         window.paint_quad(PaintQuad {
-            bounds: cursor_bounds,
-            corner_radii: (0.0).into(),
-            background: rgb(0xffffff).into(),
-            border_color: transparent_black(),
-            border_widths: (0.0).into(),
+            bounds: menu_bounds,
+            corner_radii: px(3.0).into(),
+            background: config.gutter_bg_color.into(),
+            border_color: config.line_number_color.into(),
+            border_widths: px(1.0).into(),
+            border_style: BorderStyle::Solid,
+        });
+
+        for (index, (label, detail)) in rows.iter().enumerate() {
+            let row_y = y + padding + config.line_height * index as f32;
+            if first + index == selected {
+                window.paint_quad(fill(
+                    Bounds {
+                        origin: point(x + px(1.0), row_y),
+                        size: size(width - px(2.0), config.line_height),
+                    },
+                    config.completion_selected_bg_color,
+                ));
+            }
+            let _ = label.paint(point(x + padding, row_y), config.line_height, window, cx);
+            if let Some(detail) = detail {
+                let detail_x = x + width - padding - detail.width;
+                let _ = detail.paint(point(detail_x, row_y), config.line_height, window, cx);
+            }
+        }
+
+        if let Some(item) = menu.selected_item() {
+            self.paint_completion_documentation(item, menu_bounds, cx, window, bounds);
+        }
+    }
+
+    /// Show the detail and documentation of `item` in a box beside the
+    /// completion menu, or on its left if there is no room on its right
+    fn paint_completion_documentation(
+        &self,
+        item: &CompletionItem,
+        menu_bounds: Bounds<Pixels>,
+        cx: &mut App,
+        window: &mut Window,
+        bounds: Bounds<Pixels>,
+    ) {
+        let Some(documentation) = &item.documentation else {
+            return;
+        };
+        let config = self.editor.config();
+        let padding = px(6.0);
+        let wrap_width = COMPLETION_DOCUMENTATION_WIDTH - padding * 2.0;
+        let paragraphs = item
+            .detail
+            .iter()
+            .map(|detail| (detail.as_str(), config.line_number_color))
+            .chain(documentation.lines().map(|line| (line, config.text_color)));
+        let mut lines = Vec::new();
+        for (text, color) in paragraphs {
+            let run = self.plain_run(text.len(), color);
+            let shaped = window.text_system().shape_text(
+                text.to_string().into(),
+                config.font_size,
+                &[run],
+                Some(wrap_width),
+                None,
+            );
+            lines.extend(shaped.into_iter().flatten());
+        }
+        let height = lines
+            .iter()
+            .map(|line| line.size(config.line_height).height)
+            .fold(px(0.0), |total, height| total + height)
+            + padding * 2.0;
+
+        let right = menu_bounds.origin.x + menu_bounds.size.width + px(2.0);
+        let x = if right + COMPLETION_DOCUMENTATION_WIDTH <= bounds.origin.x + bounds.size.width {
+            right
+        } else {
+            menu_bounds.origin.x - px(2.0) - COMPLETION_DOCUMENTATION_WIDTH
+        };
+        let y = menu_bounds.origin.y;
+        window.paint_quad(PaintQuad {
+            bounds: Bounds {
+                origin: point(x, y),
+                size: size(COMPLETION_DOCUMENTATION_WIDTH, height),
+            },
+            corner_radii: px(3.0).into(),
+            background: config.gutter_bg_color.into(),
+            border_color: config.line_number_color.into(),
+            border_widths: px(1.0).into(),
             border_style: BorderStyle::Solid,
         });
+        let mut line_y = y + padding;
+        for line in &lines {
+            let _ = line.paint(
+                point(x + padding, line_y),
+                config.line_height,
+                TextAlign::Left,
+                None,
+                window,
+                cx,
+            );
+            line_y += line.size(config.line_height).height;
+        }
+    }
+
+    fn paint_cursors(&self, window: &mut Window, bounds: Bounds<Pixels>) {
+        let config = self.editor.config();
+        let visible_rows = self.editor.visible_rows();
+        let heads: Vec<CursorPosition> = self
+            .editor
+            .selections()
+            .iter()
+            .map(|selection| selection.head)
+            .filter(|head| visible_rows.contains(&head.row))
+            .collect();
+
+        for head in heads {
+            let cursor_pos = self.cursor_position_px(head, bounds);
+            let cursor_bounds = Bounds {
+                origin: cursor_pos,
+                size: size(px(2.0), config.line_height),
+            };
+
+            window.paint_quad(PaintQuad {
+                bounds: cursor_bounds,
+                corner_radii: (0.0).into(),
+                background: rgb(0xffffff).into(),
+                border_color: transparent_black(),
+                border_widths: (0.0).into(),
+                border_style: BorderStyle::Solid,
+            });
+        }
     }
 }
 
-impl IntoElement for EditorElement {
+impl<B: TextBuffer + Clone + 'static> IntoElement for EditorElement<B> {
     type Element = Self;
 
     fn into_element(self) -> Self::Element {
@@ -335,12 +958,13 @@ impl IntoElement for EditorElement {
     }
 }
 
-impl Element for EditorElement {
+impl<B: TextBuffer + Clone + 'static> Element for EditorElement<B> {
     type RequestLayoutState = ();
-    type PrepaintState = ();
+    /// The visible rows, shaped for painting
+    type PrepaintState = Vec<(usize, WrappedLine)>;
 
     fn id(&self) -> Option<ElementId> {
-        Some(self.editor.id().clone())
+        Some(self.id.clone())
     }
 
     fn source_location(&self) -> Option<&'static core::panic::Location<'static>> {
@@ -354,10 +978,14 @@ impl Element for EditorElement {
         window: &mut Window,
         cx: &mut App,
     ) -> (LayoutId, Self::RequestLayoutState) {
-        let mut style = Style::default();
-        style.flex_grow = 1.0;
-        style.size.width = relative(1.0).into();
-        style.size.height = relative(1.0).into();
+        let style = Style {
+            flex_grow: 1.0,
+            size: Size {
+                width: relative(1.0).into(),
+                height: relative(1.0).into(),
+            },
+            ..Default::default()
+        };
         let layout_id = window.request_layout(style, None, cx);
         (layout_id, ())
     }
@@ -366,12 +994,20 @@ impl Element for EditorElement {
         &mut self,
         _: Option<&GlobalElementId>,
         _: Option<&gpui::InspectorElementId>,
-        _: Bounds<Pixels>,
+        bounds: Bounds<Pixels>,
         _: &mut Self::RequestLayoutState,
-        _window: &mut Window,
-        _cx: &mut App,
+        window: &mut Window,
+        cx: &mut App,
     ) -> Self::PrepaintState {
-        ()
+        self.view.update(cx, |view, cx| {
+            let mut painter = EditorPainter {
+                editor: view.editor_mut(),
+            };
+            painter.layout_rows(bounds, window);
+            painter.editor.set_viewport_height(bounds.size.height);
+            painter.editor.autoscroll();
+            painter.layout_lines(window, cx)
+        })
     }
 
     fn paint(
@@ -380,15 +1016,34 @@ impl Element for EditorElement {
         _: Option<&gpui::InspectorElementId>,
         bounds: Bounds<Pixels>,
         _: &mut Self::RequestLayoutState,
-        _: &mut Self::PrepaintState,
+        lines: &mut Self::PrepaintState,
         window: &mut Window,
         cx: &mut App,
     ) {
-        self.paint_gutter_background(window, bounds);
-        self.paint_editor_background(window, bounds);
-        self.paint_active_line_background(window, bounds);
-        self.paint_selection(window, bounds);
-        self.paint_lines(cx, window, bounds);
-        self.paint_cursor(window, bounds);
+        self.register_scroll_handler(window, bounds);
+        if let Some(focus_handle) = &self.focus_handle {
+            let input_handler = ElementInputHandler::new(bounds, self.view.clone());
+            window.handle_input(focus_handle, input_handler, cx);
+        }
+
+        self.view.update(cx, |view, cx| {
+            let mut painter = EditorPainter {
+                editor: view.editor_mut(),
+            };
+            painter.paint_gutter_background(window, bounds);
+            painter.paint_editor_background(window, bounds);
+
+            window.with_content_mask(Some(ContentMask { bounds }), |window| {
+                painter.paint_active_line_background(window, bounds);
+                painter.paint_decorations(window, bounds);
+                painter.paint_search_matches(window, bounds);
+                painter.paint_selection(window, bounds);
+                painter.paint_matching_brackets(window, bounds);
+                painter.paint_lines(lines, cx, window, bounds);
+                painter.paint_cursors(window, bounds);
+                painter.paint_diagnostic_popover(cx, window, bounds);
+                painter.paint_completion_menu(cx, window, bounds);
+            });
+        });
     }
 }
@@ -0,0 +1,391 @@
+//! Reading and writing text files.
+//!
+//! Files are decoded to text with `\n` line breaks, and the [`FileFormat`] they
+//! were read with remembers everything needed to write them back byte for
+//! byte: the encoding, whether there was a byte order mark, and the line
+//! ending after every line. Lines added by editing get the most common ending
+//! of the file.
+//!
+//! The encoding is UTF-16 if the file starts with a UTF-16 byte order mark,
+//! UTF-8 (with or without a byte order mark) if it is valid UTF-8, and Latin-1
+//! otherwise, since every byte sequence is valid Latin-1. Files with NUL bytes
+//! are rejected as binary. A lone `\r` isn't a line ending and stays in the
+//! text as it is.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const UTF16_LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF16_BE_BOM: &[u8] = &[0xfe, 0xff];
+
+/// How the text of a file is stored as bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark
+    Utf8Bom,
+    /// UTF-16 little endian, starting with a byte order mark
+    Utf16Le,
+    /// UTF-16 big endian, starting with a byte order mark
+    Utf16Be,
+    /// ISO 8859-1: one byte per char, for chars up to U+00FF
+    Latin1,
+}
+
+impl Encoding {
+    pub fn label(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 with BOM",
+            Encoding::Utf16Le => "UTF-16 LE",
+            Encoding::Utf16Be => "UTF-16 BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+
+    /// Detect the encoding of `bytes` and decode them
+    fn decode(bytes: &[u8]) -> Result<(Encoding, String), FileError> {
+        if let Some(bytes) = bytes.strip_prefix(UTF16_LE_BOM) {
+            return decode_utf16(bytes, u16::from_le_bytes).map(|text| (Encoding::Utf16Le, text));
+        }
+        if let Some(bytes) = bytes.strip_prefix(UTF16_BE_BOM) {
+            return decode_utf16(bytes, u16::from_be_bytes).map(|text| (Encoding::Utf16Be, text));
+        }
+        if bytes.contains(&0) {
+            return Err(FileError::Binary);
+        }
+        if let Some(bytes) = bytes.strip_prefix(UTF8_BOM) {
+            if let Ok(text) = std::str::from_utf8(bytes) {
+                return Ok((Encoding::Utf8Bom, text.to_string()));
+            }
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Ok((Encoding::Utf8, text.to_string())),
+            Err(_) => Ok((
+                Encoding::Latin1,
+                bytes.iter().map(|&byte| char::from(byte)).collect(),
+            )),
+        }
+    }
+
+    fn encode(self, text: &str) -> Result<Vec<u8>, FileError> {
+        let bytes = match self {
+            Encoding::Utf8 => text.as_bytes().to_vec(),
+            Encoding::Utf8Bom => [UTF8_BOM, text.as_bytes()].concat(),
+            Encoding::Utf16Le => UTF16_LE_BOM
+                .iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect(),
+            Encoding::Utf16Be => UTF16_BE_BOM
+                .iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+                .collect(),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|ch| {
+                    u8::try_from(ch).map_err(|_| FileError::Unencodable { encoding: self, ch })
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(bytes)
+    }
+}
+
+/// UTF-16 without its byte order mark. Text that isn't valid UTF-16 or that
+/// has NUL chars is binary.
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, FileError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(FileError::Binary);
+    }
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let text = char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|_| FileError::Binary)?;
+    if text.contains('\0') {
+        return Err(FileError::Binary);
+    }
+    Ok(text)
+}
+
+/// The end of a single line
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// The line endings of a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineEndings {
+    Uniform(LineEnding),
+    /// The ending after each line but the last, in order
+    Mixed(Vec<LineEnding>),
+}
+
+impl Default for LineEndings {
+    fn default() -> Self {
+        LineEndings::Uniform(LineEnding::default())
+    }
+}
+
+impl LineEndings {
+    /// The line endings of `text`, and `text` with every line ending
+    /// replaced by `\n`
+    fn detect(text: &str) -> (LineEndings, String) {
+        let endings: Vec<LineEnding> = text
+            .split_inclusive('\n')
+            .filter(|line| line.ends_with('\n'))
+            .map(|line| {
+                if line.ends_with("\r\n") {
+                    LineEnding::CrLf
+                } else {
+                    LineEnding::Lf
+                }
+            })
+            .collect();
+        let endings = match endings.first() {
+            None => LineEndings::default(),
+            Some(&first) if endings.iter().all(|&ending| ending == first) => {
+                LineEndings::Uniform(first)
+            }
+            Some(_) => LineEndings::Mixed(endings),
+        };
+        (endings, text.replace("\r\n", "\n"))
+    }
+
+    /// The ending for lines the file didn't have: the most common one, or LF
+    /// when there are as many of each
+    pub fn preferred(&self) -> LineEnding {
+        match self {
+            LineEndings::Uniform(ending) => *ending,
+            LineEndings::Mixed(endings) => {
+                let crlf = endings
+                    .iter()
+                    .filter(|&&ending| ending == LineEnding::CrLf)
+                    .count();
+                if crlf * 2 > endings.len() {
+                    LineEnding::CrLf
+                } else {
+                    LineEnding::Lf
+                }
+            }
+        }
+    }
+
+    /// `text` with each `\n` replaced by the ending of its line
+    fn apply(&self, text: &str) -> String {
+        match self {
+            LineEndings::Uniform(LineEnding::Lf) => text.to_string(),
+            LineEndings::Uniform(LineEnding::CrLf) => text.replace('\n', "\r\n"),
+            LineEndings::Mixed(endings) => {
+                let preferred = self.preferred();
+                let mut result = String::with_capacity(text.len() + endings.len());
+                for (row, line) in text.split('\n').enumerate() {
+                    if row > 0 {
+                        let ending = endings.get(row - 1).copied().unwrap_or(preferred);
+                        result.push_str(ending.as_str());
+                    }
+                    result.push_str(line);
+                }
+                result
+            }
+        }
+    }
+}
+
+/// How a file's text is stored, so it can be written back the way it was read
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_endings: LineEndings,
+}
+
+impl FileFormat {
+    /// Decode the contents of a file, returning its text with `\n` line
+    /// breaks and the format it was stored in
+    pub fn decode(bytes: &[u8]) -> Result<(String, FileFormat), FileError> {
+        let (encoding, text) = Encoding::decode(bytes)?;
+        let (line_endings, text) = LineEndings::detect(&text);
+        let format = FileFormat {
+            encoding,
+            line_endings,
+        };
+        Ok((text, format))
+    }
+
+    /// Encode `text`, which has `\n` line breaks, in this format
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, FileError> {
+        self.encoding.encode(&self.line_endings.apply(text))
+    }
+
+    /// Account for `old_rows` rows starting at `start_row` being replaced by
+    /// `new_rows` rows, both at least 1. New lines end with the preferred
+    /// ending, and lines after the edit keep theirs.
+    pub fn edit_lines(&mut self, start_row: usize, old_rows: usize, new_rows: usize) {
+        let preferred = self.line_endings.preferred();
+        if let LineEndings::Mixed(endings) = &mut self.line_endings {
+            // The endings within the edited rows, clamped for edits past the
+            // lines the file had
+            let start = start_row.min(endings.len());
+            let end = (start_row + old_rows - 1).min(endings.len());
+            let added = std::iter::repeat_n(preferred, new_rows - 1);
+            endings.splice(start..end, added);
+        }
+    }
+
+    /// Forget the ending of each line, for when the whole text is replaced
+    pub fn reset_lines(&mut self) {
+        self.line_endings = LineEndings::Uniform(self.line_endings.preferred());
+    }
+}
+
+/// Why a file couldn't be read or written
+#[derive(Debug)]
+pub enum FileError {
+    Io(io::Error),
+    /// The file has NUL bytes, or isn't valid UTF-16 despite starting with a
+    /// UTF-16 byte order mark
+    Binary,
+    /// The text has a char that the file's encoding can't store
+    Unencodable {
+        encoding: Encoding,
+        ch: char,
+    },
+    /// The editor has no file to save to; use `save_as`
+    NoPath,
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(error) => error.fmt(f),
+            FileError::Binary => write!(f, "file is binary"),
+            FileError::Unencodable { encoding, ch } => {
+                write!(f, "{ch:?} can't be saved as {}", encoding.label())
+            }
+            FileError::NoPath => write!(f, "no file to save to"),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FileError {
+    fn from(error: io::Error) -> Self {
+        FileError::Io(error)
+    }
+}
+
+/// Read the file at `path`, returning its text with `\n` line breaks and the
+/// format it was stored in
+pub fn read(path: &Path) -> Result<(String, FileFormat), FileError> {
+    FileFormat::decode(&fs::read(path)?)
+}
+
+/// Write `text`, which has `\n` line breaks, to `path` in `format`. Nothing is
+/// written if the text can't be encoded.
+pub fn write(path: &Path, text: &str, format: &FileFormat) -> Result<(), FileError> {
+    fs::write(path, format.encode(text)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> (String, FileFormat) {
+        let (text, format) = FileFormat::decode(bytes).unwrap();
+        assert_eq!(format.encode(&text).unwrap(), bytes);
+        (text, format)
+    }
+
+    #[test]
+    fn test_encodings_round_trip() {
+        let (text, format) = round_trip(b"caf\xc3\xa9\n");
+        assert_eq!((text.as_str(), format.encoding), ("café\n", Encoding::Utf8));
+
+        let (text, format) = round_trip(b"\xef\xbb\xbfa");
+        assert_eq!((text.as_str(), format.encoding), ("a", Encoding::Utf8Bom));
+
+        let (text, format) = round_trip(b"\xff\xfea\x00\n\x00");
+        assert_eq!((text.as_str(), format.encoding), ("a\n", Encoding::Utf16Le));
+
+        let (text, format) = round_trip(b"\xfe\xff\x00a\xd8\x3d\xde\x00");
+        assert_eq!((text.as_str(), format.encoding), ("a😀", Encoding::Utf16Be));
+
+        let (text, format) = round_trip(b"caf\xe9");
+        assert_eq!((text.as_str(), format.encoding), ("café", Encoding::Latin1));
+    }
+
+    #[test]
+    fn test_line_endings_round_trip() {
+        let (text, format) = round_trip(b"a\r\nb\r\n");
+        assert_eq!(text, "a\nb\n");
+        assert_eq!(format.line_endings, LineEndings::Uniform(LineEnding::CrLf));
+
+        let (text, format) = round_trip(b"a\r\nb\nc\r\nd\re");
+        assert_eq!(text, "a\nb\nc\nd\re");
+        assert_eq!(
+            format.line_endings,
+            LineEndings::Mixed(vec![LineEnding::CrLf, LineEnding::Lf, LineEnding::CrLf])
+        );
+        assert_eq!(format.line_endings.preferred(), LineEnding::CrLf);
+
+        let (_, format) = round_trip(b"no line breaks");
+        assert_eq!(format.line_endings, LineEndings::Uniform(LineEnding::Lf));
+    }
+
+    #[test]
+    fn test_mixed_line_endings_follow_edits() {
+        let (_, mut format) = FileFormat::decode(b"a\r\nb\nc\r\nd").unwrap();
+        // "b" is split in two, then "a" is joined with the line after it
+        format.edit_lines(1, 1, 2);
+        assert_eq!(
+            format.encode("a\nb\nx\nc\nd").unwrap(),
+            b"a\r\nb\r\nx\nc\r\nd"
+        );
+        format.edit_lines(0, 2, 1);
+        assert_eq!(format.encode("ab\nx\nc\nd").unwrap(), b"ab\r\nx\nc\r\nd");
+    }
+
+    #[test]
+    fn test_binary_and_unencodable() {
+        assert!(matches!(
+            FileFormat::decode(b"\x89PNG\r\n\x1a\n\x00\x00"),
+            Err(FileError::Binary)
+        ));
+        assert!(matches!(
+            FileFormat::decode(b"\xff\xfea"),
+            Err(FileError::Binary)
+        ));
+
+        let (_, format) = FileFormat::decode(b"caf\xe9").unwrap();
+        assert!(matches!(
+            format.encode("€"),
+            Err(FileError::Unencodable { ch: '€', .. })
+        ));
+    }
+}
@@ -0,0 +1,497 @@
+//! Code folding.
+//!
+//! A fold collapses a range of rows to its first row, which stays visible with
+//! a "⋯" placeholder after its text. Ranges are written `header..end`: the
+//! `header` row stays visible and rows `header + 1..end` are hidden.
+//!
+//! Foldable ranges come from two places. Indentation makes every run of lines
+//! indented deeper than the line before it foldable, which covers languages
+//! without brackets. Bracket pairs spanning more than two lines are foldable up
+//! to the line with the closing bracket, so that line stays visible. Brackets
+//! inside strings and comments don't count. When both give a range for the
+//! same header, the bracket range wins.
+//!
+//! Finding them takes a pass over the whole text, so after an edit the element
+//! paints fold indicators from the last ranges found, moved with the edit,
+//! while the editor finds the new ones on the background executor.
+
+use crate::editor::CursorPosition;
+use crate::syntax_highlighter::{self, ScopeSpan};
+use gpui::Task;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Foldable ranges, shared rather than copied for every frame
+type FoldableRanges = Rc<[Range<usize>]>;
+
+/// A search for foldable ranges that hasn't finished yet
+struct PendingFoldable {
+    version: usize,
+    /// Dropping the task cancels it
+    _task: Task<()>,
+}
+
+/// The foldable ranges found last and whether they match the current text
+#[derive(Default)]
+struct FoldableCache {
+    /// Incremented whenever the text changes
+    version: usize,
+    ranges: FoldableRanges,
+    /// Whether `ranges` were found in the current text rather than moved
+    /// with edits since
+    up_to_date: bool,
+    pending: Option<PendingFoldable>,
+}
+
+/// A copy of the cache doesn't take over its pending search, whose results
+/// are for the original
+impl Clone for FoldableCache {
+    fn clone(&self) -> Self {
+        Self {
+            version: self.version,
+            ranges: self.ranges.clone(),
+            up_to_date: self.up_to_date,
+            pending: None,
+        }
+    }
+}
+
+impl FoldableCache {
+    /// Forget the ranges and anything pending for the old text
+    fn text_changed(&mut self, ranges: FoldableRanges) {
+        self.version += 1;
+        self.ranges = ranges;
+        self.up_to_date = false;
+        self.pending = None;
+    }
+}
+
+/// The folded ranges of an editor, kept in sync with edits
+#[derive(Clone, Default)]
+pub(crate) struct FoldMap {
+    /// Sorted by header, possibly nested
+    folds: Vec<Range<usize>>,
+    /// Kept in a `RefCell` so asking for the ranges can find them on demand
+    foldable: RefCell<FoldableCache>,
+}
+
+impl FoldMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn folds(&self) -> &[Range<usize>] {
+        &self.folds
+    }
+
+    /// The foldable ranges of the text, finding them with `lines` and
+    /// `line_scopes` if the text changed since they were last found
+    pub fn foldable_ranges<I: IntoIterator<Item = String>>(
+        &self,
+        lines: impl FnOnce() -> I,
+        line_scopes: impl FnMut(usize, &str) -> Vec<ScopeSpan>,
+    ) -> FoldableRanges {
+        let mut foldable = self.foldable.borrow_mut();
+        if !foldable.up_to_date {
+            foldable.ranges = foldable_ranges(lines(), line_scopes).into();
+            foldable.up_to_date = true;
+            foldable.pending = None;
+        }
+        foldable.ranges.clone()
+    }
+
+    /// The foldable ranges found last, moved with any edits since
+    pub fn cached_foldable_ranges(&self) -> FoldableRanges {
+        self.foldable.borrow().ranges.clone()
+    }
+
+    pub fn foldable_version(&self) -> usize {
+        self.foldable.borrow().version
+    }
+
+    /// Whether the foldable ranges are out of date and no search for them is
+    /// running
+    pub fn needs_foldable_ranges(&self) -> bool {
+        let foldable = self.foldable.borrow();
+        !foldable.up_to_date
+            && foldable
+                .pending
+                .as_ref()
+                .is_none_or(|pending| pending.version != foldable.version)
+    }
+
+    /// Remember the search for the foldable ranges at `version`, cancelling
+    /// any earlier one
+    pub fn set_foldable_pending(&mut self, version: usize, task: Task<()>) {
+        self.foldable.get_mut().pending = Some(PendingFoldable {
+            version,
+            _task: task,
+        });
+    }
+
+    /// Store the foldable ranges found at `version`. Returns false, storing
+    /// nothing, if the text has changed since.
+    pub fn insert_foldable_ranges(&mut self, version: usize, ranges: Vec<Range<usize>>) -> bool {
+        let foldable = self.foldable.get_mut();
+        if foldable.version != version {
+            return false;
+        }
+        foldable.ranges = ranges.into();
+        foldable.up_to_date = true;
+        foldable.pending = None;
+        true
+    }
+
+    /// Fold `range`. Returns false if it is already folded or hides nothing.
+    pub fn fold(&mut self, range: Range<usize>) -> bool {
+        if range.end <= range.start + 1 {
+            return false;
+        }
+        match self
+            .folds
+            .binary_search_by_key(&range.start, |fold| fold.start)
+        {
+            Ok(_) => false,
+            Err(index) => {
+                self.folds.insert(index, range);
+                true
+            }
+        }
+    }
+
+    /// Unfold the fold whose header is `row`. Returns false if there is none.
+    pub fn unfold(&mut self, row: usize) -> bool {
+        let len = self.folds.len();
+        self.folds.retain(|fold| fold.start != row);
+        self.folds.len() != len
+    }
+
+    /// Unfold every fold that contains `row`, header included
+    pub fn unfold_containing(&mut self, row: usize) -> bool {
+        let len = self.folds.len();
+        self.folds.retain(|fold| !fold.contains(&row));
+        self.folds.len() != len
+    }
+
+    pub fn unfold_all(&mut self) -> bool {
+        let folded = !self.folds.is_empty();
+        self.folds.clear();
+        folded
+    }
+
+    pub fn is_folded(&self, header: usize) -> bool {
+        self.folds.iter().any(|fold| fold.start == header)
+    }
+
+    /// Hidden rows, sorted and merged
+    pub fn hidden_rows(&self) -> Vec<Range<usize>> {
+        let mut hidden: Vec<Range<usize>> = Vec::new();
+        for fold in &self.folds {
+            let rows = fold.start + 1..fold.end;
+            match hidden.last_mut() {
+                Some(last) if rows.start <= last.end => last.end = last.end.max(rows.end),
+                _ => hidden.push(rows),
+            }
+        }
+        hidden
+    }
+
+    /// The run of hidden rows `row` is in, if it is hidden
+    pub fn hidden_range(&self, row: usize) -> Option<Range<usize>> {
+        self.hidden_rows()
+            .into_iter()
+            .find(|rows| rows.contains(&row))
+    }
+
+    /// Where a caret that moved from `from` onto `position` belongs if
+    /// `position` is hidden: past the fold when moving forward, at the end of
+    /// its header otherwise. `line_len` gives the char length of a row.
+    pub fn skip_hidden(
+        &self,
+        from: CursorPosition,
+        position: CursorPosition,
+        line_count: usize,
+        line_len: impl Fn(usize) -> usize,
+    ) -> CursorPosition {
+        let Some(hidden) = self.hidden_range(position.row) else {
+            return position;
+        };
+        if position > from && hidden.end < line_count {
+            CursorPosition::new(hidden.end, 0)
+        } else {
+            let header = hidden.start - 1;
+            CursorPosition::new(header, line_len(header))
+        }
+    }
+
+    /// Account for `old_rows` rows starting at `start_row` being replaced by
+    /// `new_rows` rows, both at least 1. Folds hiding an edited row are
+    /// unfolded, and folds after the edit move with their text.
+    pub fn edit_lines(&mut self, start_row: usize, old_rows: usize, new_rows: usize) {
+        let foldable = self.foldable.get_mut();
+        let moved = edit_ranges(&foldable.ranges, start_row, old_rows, new_rows);
+        foldable.text_changed(moved);
+
+        let edited = start_row..start_row + old_rows;
+        self.folds.retain_mut(|fold| {
+            let hidden = fold.start + 1..fold.end;
+            if hidden.start < edited.end && edited.start < hidden.end {
+                return false;
+            }
+            // Text after an edit on the header row ends up on its last new row
+            if fold.start >= edited.end - 1 {
+                fold.start = fold.start + new_rows - old_rows;
+                fold.end = fold.end + new_rows - old_rows;
+            }
+            true
+        });
+    }
+
+    /// Forget every fold
+    pub fn clear(&mut self) {
+        self.foldable.get_mut().text_changed(Rc::new([]));
+        self.folds.clear();
+    }
+}
+
+/// Move `ranges` with their text after `old_rows` rows starting at
+/// `start_row` were replaced by `new_rows` rows, both at least 1, dropping
+/// the ones that start or end on a row that was removed
+fn edit_ranges(
+    ranges: &[Range<usize>],
+    start_row: usize,
+    old_rows: usize,
+    new_rows: usize,
+) -> FoldableRanges {
+    let removed = start_row + 1..start_row + old_rows - 1;
+    // Text after an edit on the first edited row ends up on its last new row
+    let move_row = |row: usize| {
+        if row + 1 >= start_row + old_rows {
+            row + new_rows - old_rows
+        } else {
+            row
+        }
+    };
+    ranges
+        .iter()
+        .filter(|range| !removed.contains(&range.start) && !removed.contains(&range.end))
+        .map(|range| move_row(range.start)..move_row(range.end))
+        .collect()
+}
+
+/// Foldable ranges of the text with `lines`, sorted by header.
+///
+/// `line_scopes` returns the scopes of a row given its text. It is only called
+/// for rows with brackets, in order. Without scopes, every bracket counts.
+pub fn foldable_ranges(
+    lines: impl IntoIterator<Item = String>,
+    line_scopes: impl FnMut(usize, &str) -> Vec<ScopeSpan>,
+) -> Vec<Range<usize>> {
+    let lines: Vec<String> = lines.into_iter().collect();
+    let mut ranges = bracket_ranges(&lines, line_scopes);
+    for range in indent_ranges(&lines) {
+        if let Err(index) = ranges.binary_search_by_key(&range.start, |range| range.start) {
+            ranges.insert(index, range);
+        }
+    }
+    ranges
+}
+
+/// Runs of lines indented deeper than the line before them. Blank lines don't
+/// end a run, but a run doesn't end on one either.
+fn indent_ranges(lines: &[String]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    // Headers whose run hasn't ended yet, with their indentation
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut last_non_blank = 0;
+
+    for (row, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = line.chars().take_while(|ch| ch.is_whitespace()).count();
+        while let Some(&(header, header_indent)) = open.last() {
+            if indent > header_indent {
+                break;
+            }
+            open.pop();
+            if last_non_blank > header {
+                ranges.push(header..last_non_blank + 1);
+            }
+        }
+        open.push((row, indent));
+        last_non_blank = row;
+    }
+    for (header, _) in open.into_iter().rev() {
+        if last_non_blank > header {
+            ranges.push(header..last_non_blank + 1);
+        }
+    }
+
+    ranges.sort_by_key(|range| range.start);
+    ranges
+}
+
+/// Bracket pairs outside strings and comments that hide at least one line,
+/// ending at the closing bracket's line. Only the widest range is kept for each
+/// header.
+fn bracket_ranges(
+    lines: &[String],
+    mut line_scopes: impl FnMut(usize, &str) -> Vec<ScopeSpan>,
+) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut open: Vec<(char, usize)> = Vec::new();
+
+    for (row, line) in lines.iter().enumerate() {
+        let is_bracket = |ch: char| matches!(ch, '(' | '[' | '{' | ')' | ']' | '}');
+        if !line.contains(is_bracket) {
+            continue;
+        }
+        let spans = line_scopes(row, line);
+        let in_string_or_comment = |index: usize| {
+            spans.iter().any(|span| {
+                span.range.contains(&index)
+                    && syntax_highlighter::is_string_or_comment(&span.scopes)
+            })
+        };
+
+        let brackets = line
+            .char_indices()
+            .filter(|&(index, ch)| is_bracket(ch) && !in_string_or_comment(index));
+        for (_, ch) in brackets {
+            let opening = match ch {
+                '(' | '[' | '{' => {
+                    open.push((ch, row));
+                    continue;
+                }
+                ')' => '(',
+                ']' => '[',
+                '}' => '{',
+                _ => continue,
+            };
+            // Skip unbalanced closing brackets
+            let Some(index) = open.iter().rposition(|&(ch, _)| ch == opening) else {
+                continue;
+            };
+            let (_, header) = open[index];
+            open.truncate(index);
+            if row > header + 1 {
+                ranges.push(header..row);
+            }
+        }
+    }
+
+    ranges.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    ranges.dedup_by_key(|range| range.start);
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    fn no_scopes(_row: usize, _line: &str) -> Vec<ScopeSpan> {
+        Vec::new()
+    }
+
+    #[test]
+    fn test_indent_ranges() {
+        let text = "def a():\n    x = 1\n\n    if x:\n        y\nz\n";
+        assert_eq!(indent_ranges(&lines(text)), vec![0..5, 3..5]);
+    }
+
+    #[test]
+    fn test_bracket_ranges() {
+        let text = "fn main() {\n    let v = vec![\n        1,\n    ];\n    f(a, b);\n}";
+        assert_eq!(bracket_ranges(&lines(text), no_scopes), vec![0..5, 1..3]);
+        // Brackets that only span two lines hide nothing
+        assert!(bracket_ranges(&lines("f(\n)"), no_scopes).is_empty());
+    }
+
+    #[test]
+    fn test_bracket_ranges_win() {
+        let text = "if x {\n    a\n    b\n}\nfoo:\n  bar";
+        assert_eq!(foldable_ranges(lines(text), no_scopes), vec![0..3, 4..6]);
+    }
+
+    #[test]
+    fn test_hidden_rows_merge_nested_folds() {
+        let mut folds = FoldMap::new();
+        assert!(folds.fold(2..5));
+        assert!(folds.fold(0..10));
+        assert!(!folds.fold(2..5));
+        assert!(!folds.fold(7..8));
+        assert_eq!(folds.hidden_rows(), vec![1..10]);
+        assert!(folds.unfold(0));
+        assert_eq!(folds.hidden_rows(), vec![3..5]);
+    }
+
+    #[test]
+    fn test_skip_hidden() {
+        let mut folds = FoldMap::new();
+        folds.fold(1..4);
+        let line_len = |_| 3;
+        let at = CursorPosition::new;
+
+        assert_eq!(folds.skip_hidden(at(1, 3), at(2, 0), 6, line_len), at(4, 0));
+        assert_eq!(folds.skip_hidden(at(4, 0), at(3, 3), 6, line_len), at(1, 3));
+        assert_eq!(folds.skip_hidden(at(0, 0), at(0, 1), 6, line_len), at(0, 1));
+        // A fold that runs to the end of the text has nothing after it
+        assert_eq!(folds.skip_hidden(at(1, 3), at(2, 0), 4, line_len), at(1, 3));
+    }
+
+    #[test]
+    fn test_edits_move_and_unfold() {
+        let mut folds = FoldMap::new();
+        folds.fold(2..5);
+        folds.fold(10..15);
+
+        // A line is inserted above both folds
+        folds.edit_lines(0, 1, 2);
+        assert_eq!(folds.folds(), &[3..6, 11..16]);
+
+        // Typing on a header keeps its fold
+        folds.edit_lines(3, 1, 1);
+        assert_eq!(folds.folds(), &[3..6, 11..16]);
+
+        // Splitting a header moves it to the row the rest of it ends up on
+        folds.edit_lines(3, 1, 2);
+        assert_eq!(folds.folds(), &[4..7, 12..17]);
+
+        // Editing a hidden row unfolds
+        folds.edit_lines(14, 1, 1);
+        assert_eq!(folds.folds().first(), Some(&(4..7)));
+        assert_eq!(folds.folds().len(), 1);
+    }
+
+    #[test]
+    fn test_foldable_ranges_move_with_edits() {
+        let mut folds = FoldMap::new();
+        let text = lines("a {\n  b\n  c\n}\nd {\n  e\n  f\n}");
+        assert_eq!(&*folds.foldable_ranges(|| text, no_scopes), &[0..3, 4..7]);
+        assert!(!folds.needs_foldable_ranges());
+
+        // A line is inserted inside the first range
+        folds.edit_lines(1, 1, 2);
+        assert!(folds.needs_foldable_ranges());
+        assert_eq!(&*folds.cached_foldable_ranges(), &[0..4, 5..8]);
+
+        // Removing rows drops the ranges ending on them
+        folds.edit_lines(3, 3, 1);
+        let moved = folds.cached_foldable_ranges().to_vec();
+        assert_eq!(moved.first(), Some(&(3..6)));
+        assert_eq!(moved.len(), 1);
+
+        // Ranges found before another edit are dropped
+        let version = folds.foldable_version();
+        folds.edit_lines(0, 1, 1);
+        assert!(!folds.insert_foldable_ranges(version, moved.clone()));
+        assert!(folds.insert_foldable_ranges(folds.foldable_version(), moved));
+        assert!(!folds.needs_foldable_ranges());
+    }
+}
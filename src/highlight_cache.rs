@@ -0,0 +1,212 @@
+//! Highlighted lines computed in the background.
+//!
+//! Painting never runs syntect. `EditorElement` paints whatever styled runs are
+//! cached for a row, and when some visible rows are missing or out of date it
+//! asks the editor to highlight them on the background executor. Until the
+//! results arrive, rows keep their last known styling (or none), and the
+//! view is notified once they are stored.
+
+use crate::syntax_highlighter::StyledRun;
+use gpui::Task;
+use std::ops::Range;
+
+#[derive(Clone)]
+struct CachedLine {
+    /// Cache version the runs were computed for
+    version: usize,
+    runs: Vec<StyledRun>,
+}
+
+/// A highlight request that hasn't finished yet
+struct PendingHighlight {
+    version: usize,
+    rows: Range<usize>,
+    /// Dropping the task cancels it
+    _task: Task<()>,
+}
+
+#[derive(Default)]
+pub(crate) struct HighlightCache {
+    /// Incremented whenever the text, language or theme changes
+    version: usize,
+    lines: Vec<Option<CachedLine>>,
+    pending: Option<PendingHighlight>,
+}
+
+/// A copy of the cache doesn't take over its pending request, whose results
+/// are for the original
+impl Clone for HighlightCache {
+    fn clone(&self) -> Self {
+        Self {
+            version: self.version,
+            lines: self.lines.clone(),
+            pending: None,
+        }
+    }
+}
+
+impl HighlightCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Mark every row out of date, keeping the runs to paint in the meantime
+    pub fn invalidate(&mut self) {
+        self.version += 1;
+        self.pending = None;
+    }
+
+    /// Forget every row
+    pub fn clear(&mut self) {
+        self.version += 1;
+        self.lines.clear();
+        self.pending = None;
+    }
+
+    /// Account for `old_rows` rows starting at `start_row` being replaced by
+    /// `new_rows` rows.
+    ///
+    /// Rows after the edit move with their text. An edit can change the
+    /// highlighting of any row below it, so those rows become out of date,
+    /// while the rows above it stay up to date.
+    pub fn edit_lines(&mut self, start_row: usize, old_rows: usize, new_rows: usize) {
+        let len = self.lines.len();
+        let removed = (start_row + 1).min(len)..(start_row + old_rows.max(1)).min(len);
+        self.lines.splice(
+            removed,
+            std::iter::repeat_with(|| None).take(new_rows.saturating_sub(1)),
+        );
+        let old_version = self.version;
+        self.version += 1;
+        let version = self.version;
+        let above = start_row.min(self.lines.len());
+        for line in self.lines[..above].iter_mut().flatten() {
+            if line.version == old_version {
+                line.version = version;
+            }
+        }
+        self.pending = None;
+    }
+
+    /// The last runs computed for `row`, which may be out of date
+    pub fn runs(&self, row: usize) -> Option<Vec<StyledRun>> {
+        let line = self.lines.get(row)?.as_ref()?;
+        Some(line.runs.clone())
+    }
+
+    /// Whether `rows` need highlighting and no request for them is running
+    pub fn needs_highlight(&self, rows: Range<usize>) -> bool {
+        if let Some(pending) = &self.pending {
+            if pending.version == self.version
+                && pending.rows.start <= rows.start
+                && rows.end <= pending.rows.end
+            {
+                return false;
+            }
+        }
+        rows.into_iter().any(
+            |row| !matches!(self.lines.get(row), Some(Some(line)) if line.version == self.version),
+        )
+    }
+
+    /// Remember the request highlighting `rows` at `version`, cancelling any
+    /// earlier one
+    pub fn set_pending(&mut self, version: usize, rows: Range<usize>, task: Task<()>) {
+        self.pending = Some(PendingHighlight {
+            version,
+            rows,
+            _task: task,
+        });
+    }
+
+    /// Store runs for the rows starting at `first_row`, computed at `version`.
+    /// Returns false, storing nothing, if the cache has changed since.
+    pub fn insert(&mut self, version: usize, first_row: usize, lines: Vec<Vec<StyledRun>>) -> bool {
+        if self.version != version {
+            return false;
+        }
+
+        let end = first_row + lines.len();
+        if self.lines.len() < end {
+            self.lines.resize_with(end, || None);
+        }
+        for (row, runs) in (first_row..end).zip(lines) {
+            self.lines[row] = Some(CachedLine { version, runs });
+        }
+        self.pending = None;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::Style;
+
+    fn run(len: usize) -> Vec<StyledRun> {
+        vec![StyledRun {
+            len,
+            style: Style::default(),
+        }]
+    }
+
+    #[test]
+    fn test_insert_and_staleness() {
+        let mut cache = HighlightCache::new();
+        assert!(cache.needs_highlight(0..2));
+
+        let version = cache.version();
+        assert!(cache.insert(version, 0, vec![run(1), run(2)]));
+        assert!(!cache.needs_highlight(0..2));
+        assert!(cache.needs_highlight(0..3));
+
+        // Results computed before a change are dropped
+        cache.invalidate();
+        assert!(!cache.insert(version, 0, vec![run(5)]));
+        assert_eq!(cache.runs(0), Some(run(1)));
+        assert!(cache.needs_highlight(0..1));
+    }
+
+    #[test]
+    fn test_edit_moves_rows() {
+        let mut cache = HighlightCache::new();
+        let version = cache.version();
+        cache.insert(version, 0, vec![run(1), run(2), run(3)]);
+
+        // Row 0 is split in two
+        cache.edit_lines(0, 1, 2);
+        assert_eq!(cache.runs(0), Some(run(1)));
+        assert_eq!(cache.runs(1), None);
+        assert_eq!(cache.runs(2), Some(run(2)));
+        assert_eq!(cache.runs(3), Some(run(3)));
+
+        // Rows 1 through 3 are joined
+        cache.edit_lines(1, 3, 1);
+        assert_eq!(cache.runs(1), None);
+        assert_eq!(cache.runs(2), None);
+        assert!(cache.needs_highlight(0..1));
+    }
+
+    #[test]
+    fn test_edit_keeps_rows_above_up_to_date() {
+        let mut cache = HighlightCache::new();
+        let version = cache.version();
+        cache.insert(version, 0, vec![run(1), run(2), run(3), run(4)]);
+
+        cache.edit_lines(2, 1, 1);
+        assert!(!cache.needs_highlight(0..2));
+        assert!(cache.needs_highlight(2..3));
+        assert!(cache.needs_highlight(3..4));
+
+        // Rows highlighted since stay up to date through the next edit too
+        let version = cache.version();
+        cache.insert(version, 2, vec![run(5)]);
+        cache.edit_lines(3, 1, 1);
+        assert!(!cache.needs_highlight(0..3));
+        assert!(cache.needs_highlight(3..4));
+    }
+}
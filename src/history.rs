@@ -0,0 +1,354 @@
+//! Undo/redo history for the editor.
+//!
+//! Every change to the buffer is recorded as an [`Edit`] (the text that was
+//! removed and the text that replaced it at a char position). Edits are
+//! grouped into [`Transaction`]s together with the selections before and
+//! after, so undoing a transaction restores both the text and the carets.
+//!
+//! Consecutive typing is merged into a single transaction as long as the
+//! keystrokes arrive within the group interval of each other and the
+//! cursor did not move in between.
+
+use crate::selections::Selections;
+use std::time::{Duration, Instant};
+
+/// Default time window in which consecutive typing is grouped together
+pub const DEFAULT_GROUP_INTERVAL: Duration = Duration::from_millis(300);
+
+/// A single replacement in the buffer, in char offsets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    /// Char offset where the edit starts
+    pub position: usize,
+    /// Text that was removed from the buffer
+    pub deleted: String,
+    /// Text that was inserted in its place
+    pub inserted: String,
+}
+
+impl Edit {
+    /// Char length of the inserted text
+    pub fn inserted_len(&self) -> usize {
+        self.inserted.chars().count()
+    }
+}
+
+/// A group of edits that is undone and redone as one step
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    pub edits: Vec<Edit>,
+    pub selection_before: Selections,
+    pub selection_after: Selections,
+    /// Identifies the state of the text after this transaction
+    id: usize,
+    /// Whether later typing may be merged into this transaction
+    mergeable: bool,
+    last_edited_at: Instant,
+}
+
+/// Undo and redo stacks plus the transaction currently being built
+#[derive(Clone, Debug)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    pending: Option<Transaction>,
+    depth: usize,
+    group_interval: Duration,
+    /// Id for the next committed transaction. Ids are never reused, so 0
+    /// only ever means "no transactions".
+    next_id: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            pending: None,
+            depth: 0,
+            group_interval: DEFAULT_GROUP_INTERVAL,
+            next_id: 1,
+        }
+    }
+
+    pub fn set_group_interval(&mut self, interval: Duration) {
+        self.group_interval = interval;
+    }
+
+    /// Open a transaction. Nested calls join the outermost transaction.
+    pub fn start_transaction(&mut self, selections: &Selections) {
+        if self.depth == 0 {
+            self.pending = Some(Transaction {
+                edits: Vec::new(),
+                selection_before: selections.clone(),
+                selection_after: selections.clone(),
+                id: 0,
+                mergeable: false,
+                last_edited_at: Instant::now(),
+            });
+        }
+        self.depth += 1;
+    }
+
+    /// Whether a transaction is open
+    pub fn in_transaction(&self) -> bool {
+        self.depth > 0
+    }
+
+    /// Allow the open transaction to be merged with previous typing
+    pub fn set_mergeable(&mut self) {
+        if self.depth == 1 {
+            if let Some(pending) = self.pending.as_mut() {
+                pending.mergeable = true;
+            }
+        }
+    }
+
+    /// Record an edit into the open transaction
+    pub fn push_edit(&mut self, edit: Edit) {
+        if let Some(pending) = self.pending.as_mut() {
+            pending.edits.push(edit);
+        }
+    }
+
+    /// Close a transaction, committing it once the outermost one ends.
+    ///
+    /// Transactions without edits are discarded. Committing a transaction
+    /// clears the redo stack.
+    pub fn end_transaction(&mut self, selections: &Selections) {
+        if self.depth == 0 {
+            return;
+        }
+        self.depth -= 1;
+        if self.depth > 0 {
+            return;
+        }
+
+        let Some(mut transaction) = self.pending.take() else {
+            return;
+        };
+        if transaction.edits.is_empty() {
+            return;
+        }
+        transaction.selection_after = selections.clone();
+        transaction.last_edited_at = Instant::now();
+        self.redo_stack.clear();
+
+        if let Some(last) = self.undo_stack.last_mut() {
+            let within_interval = transaction
+                .last_edited_at
+                .saturating_duration_since(last.last_edited_at)
+                < self.group_interval;
+            if last.mergeable
+                && transaction.mergeable
+                && within_interval
+                && last.selection_after == transaction.selection_before
+            {
+                last.edits.append(&mut transaction.edits);
+                last.selection_after = transaction.selection_after;
+                last.last_edited_at = transaction.last_edited_at;
+                return;
+            }
+        }
+
+        transaction.id = self.next_id;
+        self.next_id += 1;
+        self.undo_stack.push(transaction);
+    }
+
+    /// Identifies the current state of the text: the id of the last
+    /// transaction that wasn't undone, or 0 if there is none. Undoing and
+    /// redoing back to a state gives the same version again.
+    ///
+    /// Typing may still be merged into the last transaction without changing
+    /// the version; call [`Self::finalize_last_transaction`] after reading a
+    /// version to compare against later.
+    pub fn version(&self) -> usize {
+        self.undo_stack
+            .last()
+            .map_or(0, |transaction| transaction.id)
+    }
+
+    /// Prevent the next transaction from merging into the last one
+    pub fn finalize_last_transaction(&mut self) {
+        if let Some(last) = self.undo_stack.last_mut() {
+            last.mergeable = false;
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Transaction> {
+        self.undo_stack.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Transaction> {
+        self.redo_stack.pop()
+    }
+
+    /// Record a transaction that has just been undone
+    pub fn push_redo(&mut self, mut transaction: Transaction) {
+        transaction.mergeable = false;
+        self.redo_stack.push(transaction);
+    }
+
+    /// Record a transaction that has just been redone
+    pub fn push_undo(&mut self, mut transaction: Transaction) {
+        transaction.mergeable = false;
+        self.undo_stack.push(transaction);
+    }
+
+    /// Drop all recorded history
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.pending = None;
+        self.depth = 0;
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::CursorPosition;
+
+    fn selection(col: usize) -> Selections {
+        Selections::new(CursorPosition::new(0, col))
+    }
+
+    fn insert(position: usize, text: &str) -> Edit {
+        Edit {
+            position,
+            deleted: String::new(),
+            inserted: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_empty_transaction_is_discarded() {
+        let mut history = History::new();
+        history.start_transaction(&selection(0));
+        history.end_transaction(&selection(0));
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn test_nested_transactions_commit_once() {
+        let mut history = History::new();
+        history.start_transaction(&selection(0));
+        history.push_edit(insert(0, "a"));
+        history.start_transaction(&selection(1));
+        history.push_edit(insert(1, "b"));
+        history.end_transaction(&selection(2));
+        assert!(!history.can_undo());
+        history.end_transaction(&selection(2));
+
+        let transaction = history.pop_undo().unwrap();
+        assert_eq!(transaction.edits.len(), 2);
+        assert_eq!(transaction.selection_before, selection(0));
+        assert_eq!(transaction.selection_after, selection(2));
+    }
+
+    #[test]
+    fn test_typing_is_grouped() {
+        let mut history = History::new();
+        history.set_group_interval(Duration::from_secs(60));
+        for col in 0..3 {
+            history.start_transaction(&selection(col));
+            history.set_mergeable();
+            history.push_edit(insert(col, "x"));
+            history.end_transaction(&selection(col + 1));
+        }
+
+        let transaction = history.pop_undo().unwrap();
+        assert_eq!(transaction.edits.len(), 3);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn test_typing_group_breaks() {
+        // Zero interval always breaks the group
+        let mut history = History::new();
+        history.set_group_interval(Duration::ZERO);
+        for col in 0..2 {
+            history.start_transaction(&selection(col));
+            history.set_mergeable();
+            history.push_edit(insert(col, "x"));
+            history.end_transaction(&selection(col + 1));
+        }
+        assert_eq!(history.undo_stack.len(), 2);
+
+        // Cursor movement between keystrokes breaks the group
+        let mut history = History::new();
+        history.set_group_interval(Duration::from_secs(60));
+        for col in [0, 5] {
+            history.start_transaction(&selection(col));
+            history.set_mergeable();
+            history.push_edit(insert(col, "x"));
+            history.end_transaction(&selection(col + 1));
+        }
+        assert_eq!(history.undo_stack.len(), 2);
+
+        // An explicit finalize breaks the group
+        let mut history = History::new();
+        history.set_group_interval(Duration::from_secs(60));
+        for col in 0..2 {
+            history.start_transaction(&selection(col));
+            history.set_mergeable();
+            history.push_edit(insert(col, "x"));
+            history.end_transaction(&selection(col + 1));
+            history.finalize_last_transaction();
+        }
+        assert_eq!(history.undo_stack.len(), 2);
+    }
+
+    #[test]
+    fn test_new_transaction_clears_redo() {
+        let mut history = History::new();
+        history.start_transaction(&selection(0));
+        history.push_edit(insert(0, "a"));
+        history.end_transaction(&selection(1));
+
+        let transaction = history.pop_undo().unwrap();
+        history.push_redo(transaction);
+        assert!(history.can_redo());
+
+        history.start_transaction(&selection(0));
+        history.push_edit(insert(0, "b"));
+        history.end_transaction(&selection(1));
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut history = History::new();
+        assert_eq!(history.version(), 0);
+        history.start_transaction(&selection(0));
+        history.push_edit(insert(0, "a"));
+        history.end_transaction(&selection(1));
+        let saved = history.version();
+        assert_ne!(saved, 0);
+
+        history.start_transaction(&selection(1));
+        history.push_edit(insert(1, "b"));
+        history.end_transaction(&selection(2));
+        assert_ne!(history.version(), saved);
+
+        let transaction = history.pop_undo().unwrap();
+        assert_eq!(history.version(), saved);
+        history.push_undo(transaction);
+        assert_ne!(history.version(), saved);
+    }
+}
@@ -0,0 +1,169 @@
+//! Indentation rules.
+//!
+//! Pressing enter keeps the indentation of the current line, and adds a level
+//! after a line that opens a block. What opens a block depends on the language:
+//! most languages open one with a trailing bracket, Python and YAML with a
+//! trailing colon, and Ruby and Lua with keywords. The rules are regexes in the
+//! style of TextMate's `increaseIndentPattern`, looked up by syntect language
+//! name. Languages without rules of their own use the bracket rule, except for
+//! plain text, which only keeps indentation.
+//!
+//! Offsets are in chars, like [`CursorPosition::col`](crate::editor::CursorPosition).
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Patterns matched against the text of a line before the caret
+const INCREASE_INDENT_PATTERNS: &[(&str, &str)] = &[
+    ("Python", r"(:|[\{\[\(])\s*(#.*)?$"),
+    ("YAML", r"(:|[\{\[])\s*(#.*)?$"),
+    (
+        "Ruby",
+        r"^\s*(def|class|module|if|unless|while|until|for|case|begin|else|elsif|when|rescue|ensure)\b|(\bdo(\s*\|[^|]*\|)?|[\{\[\(])\s*$",
+    ),
+    (
+        "Lua",
+        r"\b(then|do|repeat)\s*$|^\s*else\s*$|\bfunction\b.*\)\s*$|[\{\(]\s*$",
+    ),
+];
+
+/// Lines ending in an opening bracket, for languages without their own rule
+const BRACKET_PATTERN: &str = r"[\{\[\(]\s*$";
+
+fn compiled_patterns() -> &'static [(&'static str, Regex)] {
+    static PATTERNS: OnceLock<Vec<(&str, Regex)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        INCREASE_INDENT_PATTERNS
+            .iter()
+            .chain([&("", BRACKET_PATTERN)])
+            .map(|&(language, pattern)| (language, Regex::new(pattern).unwrap()))
+            .collect()
+    })
+}
+
+/// Whether a new line after `line` in `language` should be indented one level
+/// deeper than `line`
+pub fn increases_indent(language: &str, line: &str) -> bool {
+    if language == "Plain Text" {
+        return false;
+    }
+    let patterns = compiled_patterns();
+    let (_, pattern) = patterns
+        .iter()
+        .find(|(name, _)| *name == language)
+        .unwrap_or(&patterns[patterns.len() - 1]);
+    pattern.is_match(line)
+}
+
+/// The closing bracket for `open`, if it is an opening bracket
+pub fn closing_bracket(open: char) -> Option<char> {
+    match open {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        _ => None,
+    }
+}
+
+/// The opening bracket for `close`, if it is a closing bracket
+pub fn opening_bracket(close: char) -> Option<char> {
+    match close {
+        ')' => Some('('),
+        ']' => Some('['),
+        '}' => Some('{'),
+        _ => None,
+    }
+}
+
+pub fn is_closing_bracket(ch: char) -> bool {
+    matches!(ch, ')' | ']' | '}')
+}
+
+/// The whitespace a line starts with
+pub fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Width of `whitespace` in columns, with tabs advancing to the next multiple
+/// of `tab_size`
+pub fn indent_width(whitespace: &str, tab_size: usize) -> usize {
+    whitespace.chars().fold(0, |width, ch| match ch {
+        '\t' => (width / tab_size + 1) * tab_size,
+        _ => width + 1,
+    })
+}
+
+/// One level of indentation: a tab, or `tab_size` spaces
+pub fn indent_unit(tab_size: usize, hard_tabs: bool) -> String {
+    if hard_tabs {
+        "\t".to_string()
+    } else {
+        " ".repeat(tab_size)
+    }
+}
+
+/// Indentation `width` columns wide, using tabs as far as possible with hard
+/// tabs
+pub fn indent_of_width(width: usize, tab_size: usize, hard_tabs: bool) -> String {
+    if hard_tabs {
+        "\t".repeat(width / tab_size) + &" ".repeat(width % tab_size)
+    } else {
+        " ".repeat(width)
+    }
+}
+
+/// Number of leading chars of `line` to remove to take away one level of
+/// indentation: a tab, or spaces up to the previous tab stop
+pub fn outdent_len(line: &str, tab_size: usize) -> usize {
+    let whitespace = leading_whitespace(line);
+    if whitespace.starts_with('\t') {
+        return 1;
+    }
+    let spaces = whitespace.chars().take_while(|&ch| ch == ' ').count();
+    match spaces {
+        0 => 0,
+        _ if spaces % tab_size == 0 => tab_size,
+        _ => spaces % tab_size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_increases_indent() {
+        assert!(increases_indent("Rust", "fn main() {"));
+        assert!(increases_indent("Rust", "    let v = vec!["));
+        assert!(!increases_indent("Rust", "    let a = 1;"));
+        assert!(!increases_indent("Rust", "if x:"));
+
+        assert!(increases_indent("Python", "def f(x):"));
+        assert!(increases_indent("Python", "else:  # otherwise"));
+        assert!(increases_indent("YAML", "key:"));
+        assert!(increases_indent("Ruby", "def greet(name)"));
+        assert!(increases_indent("Ruby", "items.each do |item|"));
+        assert!(increases_indent("Lua", "if x then"));
+
+        // Unknown languages use brackets, plain text never indents
+        assert!(increases_indent("Go", "func main() {"));
+        assert!(!increases_indent("Plain Text", "{"));
+    }
+
+    #[test]
+    fn test_widths_and_units() {
+        assert_eq!(indent_width("\t  ", 4), 6);
+        assert_eq!(indent_width("  \t", 4), 4);
+        assert_eq!(indent_unit(2, false), "  ");
+        assert_eq!(indent_of_width(6, 4, true), "\t  ");
+        assert_eq!(indent_of_width(6, 4, false), "      ");
+    }
+
+    #[test]
+    fn test_outdent_len() {
+        assert_eq!(outdent_len("        x", 4), 4);
+        assert_eq!(outdent_len("      x", 4), 2);
+        assert_eq!(outdent_len("\t\tx", 4), 1);
+        assert_eq!(outdent_len("x", 4), 0);
+    }
+}
@@ -7,23 +7,68 @@
 //! The editor is structured in three layers:
 //!
 //! - **Editor**: The core data model and editing operations
-//! - **EditorElement**: The GPUI element that renders an Editor
-//! - **EditorView**: A complete view with keyboard handling (see examples)
+//! - **EditorElement**: The GPUI element that renders the Editor of an EditorView
+//! - **EditorView**: A complete view with keyboard, mouse and text input handling
+//!
+//! Most applications only need the last layer:
+//!
+//! ```ignore
+//! gpui_editor::bind_default_keys(cx);
+//! let view = cx.new(|cx| EditorView::new(Editor::new("editor", lines), cx));
+//! ```
 
+pub mod actions;
+pub mod anchor;
 pub mod buffer;
+pub mod completion;
+pub mod coordinates;
+pub mod diagnostics;
 pub mod editor;
 pub mod element;
+pub mod file;
+pub mod lsp;
+pub mod rope;
+pub mod search;
+pub mod search_bar;
+pub mod selections;
 pub mod syntax_highlighter;
+pub mod view;
 
 // Internal modules
+mod autoclose;
+mod comment;
+mod decorations;
+mod display_map;
+mod fold_map;
+mod highlight_cache;
+mod history;
+mod indent;
+mod line_ops;
 mod meta_line;
+mod movement;
+mod position_map;
+mod scroll;
 
 // Re-export main types
-pub use buffer::{GapBuffer, TextBuffer};
-pub use editor::{CursorPosition, Editor, EditorConfig};
+pub use actions::bind_default_keys;
+pub use anchor::{Anchor, Bias};
+pub use buffer::{BufferSnapshot, GapBuffer, TextBuffer};
+pub use comment::CommentTokens;
+pub use completion::{BufferWordsProvider, CompletionItem, CompletionMenu, CompletionProvider};
+pub use decorations::DecorationStyle;
+pub use diagnostics::{Diagnostic, DiagnosticCounts, DiagnosticSeverity};
+pub use editor::{CursorPosition, Editor, EditorConfig, TextChange, WrapMode};
 pub use element::EditorElement;
+pub use file::{Encoding, FileError, FileFormat, LineEnding, LineEndings};
+pub use line_ops::SortOptions;
+pub use lsp::{Formatting, LanguageServer, Location, LspError, ReplyFuture};
 pub use meta_line::{Language, MetaLine, Selection};
-pub use syntax_highlighter::SyntaxHighlighter;
+pub use rope::{Rope, TextSummary};
+pub use search::SearchQuery;
+pub use search_bar::SearchBar;
+pub use selections::{SelectionRange, Selections};
+pub use syntax_highlighter::{StyledRun, SyntaxHighlighter};
+pub use view::EditorView;
 
 // Re-export gpui for convenience
 pub use gpui;
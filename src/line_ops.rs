@@ -0,0 +1,126 @@
+//! Text transformations behind the line commands.
+//!
+//! The editor works out which rows a command acts on and keeps selections on
+//! their text; the functions here only transform lines.
+
+use std::cmp::Ordering;
+
+/// How [`Editor::sort_lines`](crate::editor::Editor::sort_lines) orders lines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SortOptions {
+    /// Compare lines without regard to letter case
+    pub ignore_case: bool,
+    /// Sort in descending order
+    pub reverse: bool,
+    /// Keep only the first of lines that compare equal
+    pub unique: bool,
+}
+
+impl SortOptions {
+    /// Ascending, case-sensitive, keeping duplicates
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    fn compare(&self, a: &str, b: &str) -> Ordering {
+        let ordering = if self.ignore_case {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        } else {
+            a.cmp(b)
+        };
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// `lines` sorted by `options`. The sort is stable, so equal lines keep their
+/// order, and only the first of them is kept with `unique`.
+pub fn sort(lines: &[String], options: SortOptions) -> Vec<String> {
+    let mut sorted = lines.to_vec();
+    sorted.sort_by(|a, b| options.compare(a, b));
+    if options.unique {
+        sorted.dedup_by(|b, a| options.compare(a, b) == Ordering::Equal);
+    }
+    sorted
+}
+
+/// `lines` joined into one, with the indentation of every line after the
+/// first replaced by a single space. No space is added after a line that is
+/// empty or already ends in whitespace, or before an empty line.
+///
+/// Also returns, for each input line, the column its text starts at in the
+/// joined line and how many leading chars of it were removed.
+pub fn join(lines: &[String]) -> (String, Vec<(usize, usize)>) {
+    let mut joined = String::new();
+    let mut len = 0;
+    let mut starts = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        let text = if index == 0 { line } else { line.trim_start() };
+        let removed = line.chars().count() - text.chars().count();
+        let separate = index > 0
+            && !text.is_empty()
+            && !joined.is_empty()
+            && !joined.ends_with(char::is_whitespace);
+        if separate {
+            joined.push(' ');
+            len += 1;
+        }
+        starts.push((len, removed));
+        joined.push_str(text);
+        len += text.chars().count();
+    }
+    (joined, starts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_sort() {
+        let input = lines("b\nB\na\nb");
+        assert_eq!(sort(&input, SortOptions::new()), lines("B\na\nb\nb"));
+        assert_eq!(
+            sort(&input, SortOptions::new().ignore_case(true).unique(true)),
+            lines("a\nb")
+        );
+        assert_eq!(
+            sort(&input, SortOptions::new().reverse(true)),
+            lines("b\nb\na\nB")
+        );
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join(&lines("fn f(\n    a,\n\n    b)")),
+            (
+                "fn f( a, b)".to_string(),
+                vec![(0, 0), (6, 4), (8, 0), (9, 4)]
+            )
+        );
+        assert_eq!(join(&lines("a \n  b")).0, "a b");
+    }
+}
@@ -0,0 +1,1033 @@
+//! A client for language servers.
+//!
+//! [`LanguageServer`] starts a server process and talks to it over its stdin
+//! and stdout with JSON-RPC, as the Language Server Protocol specifies.
+//! Opening an editor in the server with [`LanguageServer::open`] starts
+//! tracking the editor's changes, and [`LanguageServer::did_change`] sends the
+//! ones made since, incrementally when the server supports it. Requests for
+//! hover text, completions, definitions and formatting send any pending
+//! changes first, so the server always answers for the text the editor has.
+//!
+//! Requests return a [`ReplyFuture`] that resolves once the server replies
+//! or the timeout passes, so a view can await it from `cx.spawn` without
+//! blocking the UI thread. Anything that depends on the editor's text, like
+//! applying formatting, is done once the reply is back. Messages from the
+//! server are read on a background thread and messages to it are written on
+//! another, so a server that is busy writing never stops the client from
+//! reading. The diagnostics the server publishes are kept until
+//! [`LanguageServer::apply_diagnostics`] sets them on the editor. Requests the
+//! server makes of the client are answered with `null`, and messages that
+//! can't be parsed are skipped.
+//!
+//! Positions in the protocol count UTF-16 code units within a line; they are
+//! converted to and from the char columns of [`CursorPosition`].
+
+use crate::anchor::{Anchor, Bias};
+use crate::buffer::{GapBuffer, TextBuffer};
+use crate::completion::CompletionItem;
+use crate::diagnostics::{Diagnostic, DiagnosticSeverity};
+use crate::editor::{CursorPosition, Editor, TextChange};
+use futures::channel::oneshot;
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long requests wait for a reply by default
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why talking to a language server failed
+#[derive(Debug)]
+pub enum LspError {
+    Io(io::Error),
+    /// The server sent something the protocol doesn't allow
+    Protocol(String),
+    /// The server answered a request with an error
+    Server {
+        code: i64,
+        message: String,
+    },
+    /// The server didn't answer a request in time
+    Timeout,
+    /// The editor has no file, so the server has no URI for its text
+    NoPath,
+    /// The editor's file wasn't opened in the server
+    NotOpen,
+    /// The server exited
+    Closed,
+}
+
+impl fmt::Display for LspError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LspError::Io(error) => error.fmt(f),
+            LspError::Protocol(message) => write!(f, "invalid message from server: {message}"),
+            LspError::Server { code, message } => write!(f, "server error {code}: {message}"),
+            LspError::Timeout => write!(f, "server didn't reply in time"),
+            LspError::NoPath => write!(f, "editor has no file"),
+            LspError::NotOpen => write!(f, "file isn't open in the server"),
+            LspError::Closed => write!(f, "server exited"),
+        }
+    }
+}
+
+impl std::error::Error for LspError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LspError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LspError {
+    fn from(error: io::Error) -> Self {
+        LspError::Io(error)
+    }
+}
+
+/// What a request to the server comes back with, once it replies
+pub type ReplyFuture<T> = Pin<Box<dyn Future<Output = Result<T, LspError>> + Send>>;
+
+/// Where a definition is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub range: Range<CursorPosition>,
+}
+
+/// The edits a server suggested to format a document, to apply with
+/// [`LanguageServer::apply_formatting`]
+#[derive(Clone, Debug)]
+pub struct Formatting {
+    uri: String,
+    /// The buffer version the edits are for
+    version: usize,
+    edits: Vec<Value>,
+}
+
+impl Formatting {
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+/// How the server wants to be told about changes to a document
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SyncKind {
+    None,
+    Full,
+    Incremental,
+}
+
+/// A document open in the server
+struct Document {
+    version: i64,
+    /// The text as the server has it, for converting the positions of
+    /// changes before they are applied
+    text: GapBuffer,
+}
+
+impl Document {
+    fn apply(&mut self, change: &TextChange) {
+        let start = self
+            .text
+            .cursor_to_position(change.range.start.row, change.range.start.col);
+        let end = self
+            .text
+            .cursor_to_position(change.range.end.row, change.range.end.col);
+        self.text.delete_range(start, end);
+        self.text.insert(start, &change.text);
+    }
+}
+
+type Reply = Result<Value, LspError>;
+/// Senders waiting for replies by request id, or `None` once the server exits
+type PendingRequests = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<Reply>>>>>;
+/// The latest diagnostics published for each document not yet applied
+type PublishedDiagnostics = Arc<Mutex<HashMap<String, Vec<Value>>>>;
+/// Open documents by URI, shared with requests waiting for replies about them
+type OpenDocuments = Arc<Mutex<HashMap<String, Document>>>;
+
+/// A running language server
+pub struct LanguageServer {
+    /// `None` once [`Self::shutdown`] has taken it to wait for it to exit
+    process: Option<Child>,
+    /// Messages for the writer thread to send
+    outgoing: Sender<Value>,
+    /// When requests waiting for replies time out, for the thread that fails
+    /// them
+    deadlines: Sender<(Instant, u64)>,
+    next_id: AtomicU64,
+    pending: PendingRequests,
+    diagnostics: PublishedDiagnostics,
+    capabilities: Value,
+    documents: OpenDocuments,
+    timeout: Duration,
+}
+
+impl LanguageServer {
+    /// Start `command` with `args` and initialize it for the workspace at
+    /// `root`. The server is ready once the future resolves.
+    pub fn start<I, S>(command: impl AsRef<OsStr>, args: I, root: &Path) -> ReplyFuture<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let started = Self::spawn(command, args, root);
+        Box::pin(async move {
+            let (mut server, initialize) = started?;
+            let result = initialize.await?;
+            server.capabilities = result["capabilities"].clone();
+            server.notify("initialized", json!({}))?;
+            Ok(server)
+        })
+    }
+
+    /// Start the server process and the threads talking to it, and send the
+    /// `initialize` request
+    fn spawn<I, S>(
+        command: impl AsRef<OsStr>,
+        args: I,
+        root: &Path,
+    ) -> Result<(Self, ReplyFuture<Value>), LspError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut process = Command::new(command)
+            .args(args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = process.stdin.take().expect("stdin is piped");
+        let stdout = process.stdout.take().expect("stdout is piped");
+        let pending: PendingRequests = Arc::new(Mutex::new(Some(HashMap::new())));
+        let diagnostics = PublishedDiagnostics::default();
+        let (outgoing, messages) = mpsc::channel();
+        thread::spawn(move || write_messages(stdin, messages));
+        let (deadlines, expiring) = mpsc::channel();
+        {
+            let pending = pending.clone();
+            thread::spawn(move || expire_requests(expiring, pending));
+        }
+        {
+            let outgoing = outgoing.clone();
+            let pending = pending.clone();
+            let diagnostics = diagnostics.clone();
+            thread::spawn(move || read_messages(stdout, outgoing, pending, diagnostics));
+        }
+
+        let server = Self {
+            process: Some(process),
+            outgoing,
+            deadlines,
+            next_id: AtomicU64::new(0),
+            pending,
+            diagnostics,
+            capabilities: Value::Null,
+            documents: OpenDocuments::default(),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let root_uri = path_to_uri(&std::path::absolute(root)?);
+        let name = root
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let initialize = server.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": name }],
+                "capabilities": {
+                    "general": { "positionEncodings": ["utf-16"] },
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "publishDiagnostics": {},
+                        "hover": { "contentFormat": ["plaintext", "markdown"] },
+                        "completion": {
+                            "completionItem": {
+                                "documentationFormat": ["plaintext", "markdown"]
+                            }
+                        },
+                        "definition": { "linkSupport": true },
+                        "formatting": {}
+                    }
+                }
+            }),
+        );
+        Ok((server, initialize))
+    }
+
+    /// What the server said it can do when it was initialized
+    pub fn capabilities(&self) -> &Value {
+        &self.capabilities
+    }
+
+    /// How long requests wait for a reply
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Send a request. The future resolves with the server's reply, or fails
+    /// once the timeout passes. The request is sent right away, in order with
+    /// the notifications sent before and after it, whether or not the future
+    /// is polled.
+    pub fn request(&self, method: &str, params: Value) -> ReplyFuture<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        let registered = match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, sender).is_none(),
+            None => false,
+        };
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let sent = if registered {
+            self.send(message).and_then(|()| {
+                let deadline = (Instant::now() + self.timeout, id);
+                self.deadlines.send(deadline).map_err(|_| LspError::Closed)
+            })
+        } else {
+            Err(LspError::Closed)
+        };
+        Box::pin(async move {
+            sent?;
+            // The sender is dropped without a reply once the server exits
+            receiver.await.unwrap_or(Err(LspError::Closed))
+        })
+    }
+
+    /// Send a notification, which the server doesn't reply to
+    pub fn notify(&self, method: &str, params: Value) -> Result<(), LspError> {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        self.send(message)
+    }
+
+    /// Queue `message` for the writer thread. Fails once it has stopped
+    /// because the server closed its input.
+    fn send(&self, message: Value) -> Result<(), LspError> {
+        self.outgoing.send(message).map_err(|_| LspError::Closed)
+    }
+
+    fn sync_kind(&self) -> SyncKind {
+        let sync = &self.capabilities["textDocumentSync"];
+        match sync.as_u64().or_else(|| sync["change"].as_u64()) {
+            Some(1) => SyncKind::Full,
+            Some(2) => SyncKind::Incremental,
+            _ => SyncKind::None,
+        }
+    }
+
+    /// Open the editor's file in the server and start tracking its changes
+    pub fn open<B: TextBuffer>(&mut self, editor: &mut Editor<B>) -> Result<(), LspError> {
+        let uri = document_uri(editor)?;
+        if self.documents.lock().unwrap().contains_key(&uri) {
+            return self.did_change(editor);
+        }
+        let text = editor.get_buffer().text();
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": language_id(editor.language()),
+                    "version": 0,
+                    "text": text,
+                }
+            }),
+        )?;
+        editor.track_changes();
+        editor.take_changes();
+        let document = Document {
+            version: 0,
+            text: GapBuffer::from_text(&text),
+        };
+        self.documents.lock().unwrap().insert(uri, document);
+        Ok(())
+    }
+
+    /// Send the changes made to the editor since they were last sent
+    pub fn did_change<B: TextBuffer>(&mut self, editor: &mut Editor<B>) -> Result<(), LspError> {
+        let uri = document_uri(editor)?;
+        let sync = self.sync_kind();
+        let mut documents = self.documents.lock().unwrap();
+        let document = documents.get_mut(&uri).ok_or(LspError::NotOpen)?;
+        let changes = editor.take_changes();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut content_changes = Vec::new();
+        for change in &changes {
+            if sync == SyncKind::Incremental {
+                content_changes.push(json!({
+                    "range": lsp_range(&document.text, &change.range),
+                    "text": change.text,
+                }));
+            }
+            document.apply(change);
+        }
+        if sync == SyncKind::Full {
+            content_changes.push(json!({ "text": document.text.text() }));
+        }
+        document.version += 1;
+        let version = document.version;
+        drop(documents);
+        if sync == SyncKind::None {
+            return Ok(());
+        }
+        self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": version },
+                "contentChanges": content_changes,
+            }),
+        )
+    }
+
+    /// Tell the server the editor's file was saved
+    pub fn did_save<B: TextBuffer>(&mut self, editor: &mut Editor<B>) -> Result<(), LspError> {
+        self.did_change(editor)?;
+        let sync = &self.capabilities["textDocumentSync"];
+        let save = &sync["save"];
+        if !sync.is_u64() && !save.as_bool().unwrap_or(save.is_object()) {
+            return Ok(());
+        }
+        let mut params = json!({ "textDocument": { "uri": document_uri(editor)? } });
+        if save["includeText"].as_bool() == Some(true) {
+            params["text"] = editor.get_buffer().text().into();
+        }
+        self.notify("textDocument/didSave", params)
+    }
+
+    /// Close the editor's file in the server and stop tracking its changes
+    pub fn did_close<B: TextBuffer>(&mut self, editor: &mut Editor<B>) -> Result<(), LspError> {
+        let uri = document_uri(editor)?;
+        self.documents
+            .lock()
+            .unwrap()
+            .remove(&uri)
+            .ok_or(LspError::NotOpen)?;
+        self.diagnostics.lock().unwrap().remove(&uri);
+        editor.stop_tracking_changes();
+        self.notify(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+    }
+
+    /// Set the diagnostics the server published for the editor's file since
+    /// they were last applied. Returns whether there were any, so this can be
+    /// called whenever the app polls for updates.
+    pub fn apply_diagnostics<B: TextBuffer>(
+        &self,
+        editor: &mut Editor<B>,
+    ) -> Result<bool, LspError> {
+        let uri = document_uri(editor)?;
+        let Some(published) = self.diagnostics.lock().unwrap().remove(&uri) else {
+            return Ok(false);
+        };
+        let buffer = editor.get_buffer();
+        let diagnostics = published
+            .iter()
+            .map(|diagnostic| {
+                let severity = match diagnostic["severity"].as_u64() {
+                    Some(2) => DiagnosticSeverity::Warning,
+                    Some(3) => DiagnosticSeverity::Information,
+                    Some(4) => DiagnosticSeverity::Hint,
+                    _ => DiagnosticSeverity::Error,
+                };
+                let message = diagnostic["message"].as_str().unwrap_or_default();
+                let mut converted = Diagnostic::new(
+                    from_lsp_range(&diagnostic["range"], |row| buffer.get_line(row)),
+                    severity,
+                    message,
+                );
+                if let Some(source) = diagnostic["source"].as_str() {
+                    converted = converted.source(source);
+                }
+                match &diagnostic["code"] {
+                    Value::String(code) => converted = converted.code(code),
+                    Value::Number(code) => converted = converted.code(code.to_string()),
+                    _ => {}
+                }
+                converted
+            })
+            .collect();
+        editor.set_diagnostics(diagnostics);
+        Ok(true)
+    }
+
+    /// The hover text for `position` in the editor, if the server has any
+    pub fn hover<B: TextBuffer>(
+        &mut self,
+        editor: &mut Editor<B>,
+        position: CursorPosition,
+    ) -> ReplyFuture<Option<String>> {
+        let reply = self
+            .position_params(editor, position)
+            .map(|params| self.request("textDocument/hover", params));
+        Box::pin(async move {
+            let result = reply?.await?;
+            Ok(markup_text(&result["contents"]))
+        })
+    }
+
+    /// Completions for the word at `position` in the editor. Items with a
+    /// text edit replace its range, anchored so text typed while the reply
+    /// was on its way is replaced too.
+    pub fn completions<B: TextBuffer>(
+        &mut self,
+        editor: &mut Editor<B>,
+        position: CursorPosition,
+    ) -> ReplyFuture<Vec<CompletionItem>> {
+        let reply = self
+            .position_params(editor, position)
+            .map(|params| self.request("textDocument/completion", params));
+        // Text edits are on the line the completions were asked for, as it
+        // is now
+        let row = position.row;
+        let buffer = editor.get_buffer();
+        let line = buffer.get_line(position.row).unwrap_or_default();
+        let line_start = buffer.cursor_to_position(position.row, 0);
+        let version = buffer.version();
+        Box::pin(async move {
+            let result = reply?.await?;
+            // Either a list of items or a `CompletionList` holding them
+            let items = match &result {
+                Value::Array(items) => items.as_slice(),
+                _ => result["items"].as_array().map_or(&[][..], Vec::as_slice),
+            };
+            let anchor = |position: &Value, bias| {
+                if position["line"].as_u64()? != row as u64 {
+                    return None;
+                }
+                let col = from_lsp_character(&line, position["character"].as_u64()? as usize);
+                Some(Anchor::new(line_start + col, version, bias))
+            };
+            Ok(items
+                .iter()
+                .filter_map(|item| {
+                    let mut completion = CompletionItem::new(item["label"].as_str()?);
+                    completion.detail = item["detail"].as_str().map(str::to_string);
+                    completion.documentation = markup_text(&item["documentation"]);
+                    let edit = &item["textEdit"];
+                    if let Some(text) = edit["newText"]
+                        .as_str()
+                        .or_else(|| item["insertText"].as_str())
+                    {
+                        completion.insert_text = text.to_string();
+                    }
+                    // Typing at either end of the range stays inside it
+                    let start = anchor(&edit["range"]["start"], Bias::Left);
+                    let end = anchor(&edit["range"]["end"], Bias::Right);
+                    if let Some((start, end)) = start.zip(end) {
+                        completion.range = Some(start..end);
+                    }
+                    Some(completion)
+                })
+                .collect())
+        })
+    }
+
+    /// Find the definition of the symbol at the editor's caret. Move the
+    /// caret there when it is in the editor's own file.
+    pub fn go_to_definition<B: TextBuffer>(
+        &mut self,
+        editor: &mut Editor<B>,
+    ) -> ReplyFuture<Option<Location>> {
+        let reply = self
+            .position_params(editor, editor.cursor_position())
+            .map(|params| self.request("textDocument/definition", params));
+        let documents = self.documents.clone();
+        Box::pin(async move {
+            let result = reply?.await?;
+            // A location, a list of locations or a list of location links
+            let target = match &result {
+                Value::Array(targets) => targets.first(),
+                Value::Null => None,
+                target => Some(target),
+            };
+            let Some(target) = target else {
+                return Ok(None);
+            };
+            let (uri, target_range) = match target.get("targetUri") {
+                Some(uri) => (uri, &target["targetSelectionRange"]),
+                None => (&target["uri"], &target["range"]),
+            };
+            let uri = uri
+                .as_str()
+                .ok_or_else(|| LspError::Protocol("definition has no URI".to_string()))?;
+            let path = uri_to_path(uri)
+                .ok_or_else(|| LspError::Protocol(format!("{uri} isn't a file URI")))?;
+
+            // Columns are converted with the file's text as it is open in the
+            // server, or else as it is on disk
+            let open =
+                documents.lock().unwrap().get(uri).map(|document| {
+                    from_lsp_range(target_range, |row| document.text.get_line(row))
+                });
+            let range = match open {
+                Some(range) => range,
+                None => {
+                    let text = fs::read_to_string(&path).unwrap_or_default();
+                    let text = GapBuffer::from_text(&text);
+                    from_lsp_range(target_range, |row| text.get_line(row))
+                }
+            };
+            Ok(Some(Location { path, range }))
+        })
+    }
+
+    /// Ask the server how to format the editor's text. Apply the edits with
+    /// [`Self::apply_formatting`].
+    pub fn format<B: TextBuffer>(&mut self, editor: &mut Editor<B>) -> ReplyFuture<Formatting> {
+        let request = self.did_change(editor).and_then(|()| {
+            let uri = document_uri(editor)?;
+            let params = json!({
+                "textDocument": { "uri": uri },
+                "options": {
+                    "tabSize": editor.config().tab_size,
+                    "insertSpaces": !editor.config().hard_tabs,
+                }
+            });
+            Ok((uri, self.request("textDocument/formatting", params)))
+        });
+        let version = editor.get_buffer().version();
+        Box::pin(async move {
+            let (uri, reply) = request?;
+            let result = reply.await?;
+            let edits = result.as_array().cloned().unwrap_or_default();
+            Ok(Formatting {
+                uri,
+                version,
+                edits,
+            })
+        })
+    }
+
+    /// Apply `formatting` to the editor as a single undoable edit. Does
+    /// nothing if it is for another file, or the text has changed since it
+    /// was asked for. Returns whether anything changed.
+    pub fn apply_formatting<B: TextBuffer>(
+        &mut self,
+        editor: &mut Editor<B>,
+        formatting: Formatting,
+    ) -> Result<bool, LspError> {
+        if formatting.is_empty()
+            || formatting.uri != document_uri(editor)?
+            || formatting.version != editor.get_buffer().version()
+        {
+            return Ok(false);
+        }
+        let buffer = editor.get_buffer();
+        let edits: Vec<(Range<CursorPosition>, String)> = formatting
+            .edits
+            .iter()
+            .map(|edit| {
+                let text = edit["newText"].as_str().unwrap_or_default().to_string();
+                let range = from_lsp_range(&edit["range"], |row| buffer.get_line(row));
+                (range, text)
+            })
+            .collect();
+        editor.apply_text_edits(edits);
+        self.did_change(editor)?;
+        Ok(true)
+    }
+
+    /// Parameters for a request about `position` in the editor's file, after
+    /// sending the server any changes it doesn't have yet
+    fn position_params<B: TextBuffer>(
+        &mut self,
+        editor: &mut Editor<B>,
+        position: CursorPosition,
+    ) -> Result<Value, LspError> {
+        self.did_change(editor)?;
+        Ok(json!({
+            "textDocument": { "uri": document_uri(editor)? },
+            "position": lsp_position(editor.get_buffer(), position),
+        }))
+    }
+
+    /// Ask the server to shut down. The future resolves once it has exited,
+    /// or fails if it is still running after the timeout.
+    pub fn shutdown(mut self) -> ReplyFuture<()> {
+        let reply = self.request("shutdown", Value::Null);
+        Box::pin(async move {
+            reply.await?;
+            self.notify("exit", Value::Null)?;
+            let mut process = self
+                .process
+                .take()
+                .expect("only shutdown takes the process");
+            let timeout = self.timeout;
+            let (sender, exited) = oneshot::channel();
+            thread::spawn(move || {
+                let deadline = Instant::now() + timeout;
+                let result = loop {
+                    match process.try_wait() {
+                        Ok(Some(_)) => break Ok(()),
+                        Ok(None) if Instant::now() > deadline => {
+                            let _ = process.kill();
+                            let _ = process.wait();
+                            break Err(LspError::Timeout);
+                        }
+                        Ok(None) => thread::sleep(Duration::from_millis(10)),
+                        Err(error) => break Err(error.into()),
+                    }
+                };
+                let _ = sender.send(result);
+            });
+            exited.await.unwrap_or(Err(LspError::Closed))
+        })
+    }
+}
+
+impl Drop for LanguageServer {
+    fn drop(&mut self) {
+        if let Some(process) = &mut self.process {
+            if let Ok(None) = process.try_wait() {
+                let _ = process.kill();
+                let _ = process.wait();
+            }
+        }
+    }
+}
+
+/// Fail the requests still waiting for replies once their deadlines pass,
+/// until the client is dropped
+fn expire_requests(deadlines: Receiver<(Instant, u64)>, pending: PendingRequests) {
+    let mut queue = BinaryHeap::new();
+    loop {
+        let now = Instant::now();
+        while let Some(&Reverse((deadline, id))) = queue.peek() {
+            if deadline > now {
+                break;
+            }
+            queue.pop();
+            let sender = pending
+                .lock()
+                .unwrap()
+                .as_mut()
+                .and_then(|pending| pending.remove(&id));
+            if let Some(sender) = sender {
+                let _ = sender.send(Err(LspError::Timeout));
+            }
+        }
+        let received = match queue.peek() {
+            Some(Reverse((deadline, _))) => deadlines.recv_timeout(*deadline - now),
+            None => deadlines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(deadline) => queue.push(Reverse(deadline)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+/// Write the messages queued for the server until the client is dropped or
+/// the server closes its input
+fn write_messages(mut stdin: ChildStdin, messages: Receiver<Value>) {
+    for message in messages {
+        if write_message(&mut stdin, &message).is_err() {
+            break;
+        }
+    }
+}
+
+/// Read messages from the server until it exits, passing replies to the
+/// requests waiting for them. Messages that can't be parsed are skipped.
+fn read_messages(
+    stdout: ChildStdout,
+    outgoing: Sender<Value>,
+    pending: PendingRequests,
+    diagnostics: PublishedDiagnostics,
+) {
+    let mut reader = BufReader::new(stdout);
+    loop {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => continue,
+            Ok(None) | Err(_) => break,
+        };
+        match (message["method"].as_str(), message.get("id")) {
+            // A request from the server
+            (Some(_), Some(id)) => {
+                let reply = json!({ "jsonrpc": "2.0", "id": id, "result": null });
+                let _ = outgoing.send(reply);
+            }
+            (Some("textDocument/publishDiagnostics"), None) => {
+                let params = &message["params"];
+                if let Some(uri) = params["uri"].as_str() {
+                    let published = params["diagnostics"].as_array().cloned();
+                    diagnostics
+                        .lock()
+                        .unwrap()
+                        .insert(uri.to_string(), published.unwrap_or_default());
+                }
+            }
+            // A reply to one of our requests
+            (None, Some(id)) => {
+                let sender = id.as_u64().and_then(|id| {
+                    pending
+                        .lock()
+                        .unwrap()
+                        .as_mut()
+                        .and_then(|pending| pending.remove(&id))
+                });
+                let reply = match message.get("error") {
+                    Some(error) => Err(LspError::Server {
+                        code: error["code"].as_i64().unwrap_or_default(),
+                        message: error["message"].as_str().unwrap_or_default().to_string(),
+                    }),
+                    None => Ok(message["result"].clone()),
+                };
+                if let Some(sender) = sender {
+                    let _ = sender.send(reply);
+                }
+            }
+            _ => {}
+        }
+    }
+    // Dropping the senders fails the requests still waiting
+    pending.lock().unwrap().take();
+}
+
+/// Read one message, framed by a `Content-Length` header. Returns `None` at
+/// the end of the stream, and an `InvalidData` error for a message without a
+/// length or with a body that isn't JSON, after which the next message can
+/// still be read.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message has no Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let message = serde_json::from_slice(&body)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    Ok(Some(message))
+}
+
+fn write_message(stdin: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(stdin, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    stdin.flush()
+}
+
+/// The URI the server knows the editor's file by
+fn document_uri<B: TextBuffer>(editor: &Editor<B>) -> Result<String, LspError> {
+    let path = editor.path().ok_or(LspError::NoPath)?;
+    Ok(path_to_uri(&std::path::absolute(path)?))
+}
+
+/// A `file` URI for an absolute path, with everything but unreserved chars
+/// and separators percent-encoded
+pub fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(char::from(byte));
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+/// The path of a `file` URI
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = encoded
+            .get(i + 1..i + 3)
+            .filter(|_| encoded[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+/// The protocol's identifier for one of the editor's language names
+fn language_id(language: &str) -> String {
+    let id = match language {
+        "Plain Text" => "plaintext",
+        "C++" => "cpp",
+        "C#" => "csharp",
+        "Objective-C" => "objective-c",
+        "Objective-C++" => "objective-cpp",
+        "Bourne Again Shell (bash)" => "shellscript",
+        "JavaScript (Babel)" => "javascript",
+        "TypeScriptReact" => "typescriptreact",
+        other => return other.to_lowercase().replace(' ', ""),
+    };
+    id.to_string()
+}
+
+/// The protocol position of `position` in `text`, in UTF-16 code units
+fn lsp_position(text: &impl TextBuffer, position: CursorPosition) -> Value {
+    let line = text.get_line(position.row).unwrap_or_default();
+    let character: usize = line.chars().take(position.col).map(char::len_utf16).sum();
+    json!({ "line": position.row, "character": character })
+}
+
+fn lsp_range(text: &impl TextBuffer, range: &Range<CursorPosition>) -> Value {
+    json!({
+        "start": lsp_position(text, range.start),
+        "end": lsp_position(text, range.end),
+    })
+}
+
+/// The char column of `character` UTF-16 code units into `line`
+fn from_lsp_character(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    line.chars()
+        .take_while(|ch| {
+            units += ch.len_utf16();
+            units <= character
+        })
+        .count()
+}
+
+/// The position of a protocol position, with `line_at` returning the text of
+/// a row
+fn from_lsp_position(
+    position: &Value,
+    line_at: impl Fn(usize) -> Option<String>,
+) -> CursorPosition {
+    let row = position["line"].as_u64().unwrap_or_default() as usize;
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+    let line = line_at(row).unwrap_or_default();
+    CursorPosition::new(row, from_lsp_character(&line, character))
+}
+
+fn from_lsp_range(
+    range: &Value,
+    line_at: impl Fn(usize) -> Option<String>,
+) -> Range<CursorPosition> {
+    from_lsp_position(&range["start"], &line_at)..from_lsp_position(&range["end"], &line_at)
+}
+
+/// The text of hover contents or documentation, which can be a string, a
+/// `MarkupContent`, a `MarkedString` or a list of them
+fn markup_text(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(text) => text.clone(),
+        Value::Object(_) => value["value"].as_str()?.to_string(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(markup_text)
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uris() {
+        let path = Path::new("/tmp/my files/a#b.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/my%20files/a%23b.rs");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(
+            uri_to_path("file:///caf%C3%A9%2"),
+            Some(PathBuf::from("/café%2"))
+        );
+        assert_eq!(uri_to_path("https://example.com"), None);
+    }
+
+    #[test]
+    fn test_utf16_positions() {
+        let text = GapBuffer::from_text("a😀b\né");
+        let pos = CursorPosition::new;
+        assert_eq!(
+            lsp_position(&text, pos(0, 2)),
+            json!({ "line": 0, "character": 3 })
+        );
+        let line_at = |row| text.get_line(row);
+        assert_eq!(
+            from_lsp_position(&lsp_position(&text, pos(0, 2)), line_at),
+            pos(0, 2)
+        );
+        assert_eq!(
+            from_lsp_position(&json!({ "line": 1, "character": 1 }), line_at),
+            pos(1, 1)
+        );
+        // Past the end of the line
+        assert_eq!(
+            from_lsp_position(&json!({ "line": 0, "character": 9 }), line_at),
+            pos(0, 3)
+        );
+    }
+
+    #[test]
+    fn test_markup_text() {
+        assert_eq!(markup_text(&Value::Null), None);
+        assert_eq!(markup_text(&json!("")), None);
+        assert_eq!(
+            markup_text(&json!({ "kind": "markdown", "value": "**x**" })).as_deref(),
+            Some("**x**")
+        );
+        assert_eq!(
+            markup_text(&json!(["a", { "language": "rust", "value": "fn b()" }])).as_deref(),
+            Some("a\n\nfn b()")
+        );
+    }
+
+    #[test]
+    fn test_language_ids() {
+        assert_eq!(language_id("Rust"), "rust");
+        assert_eq!(language_id("Plain Text"), "plaintext");
+        assert_eq!(language_id("C++"), "cpp");
+    }
+}
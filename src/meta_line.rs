@@ -1,3 +1,4 @@
+use crate::diagnostics::DiagnosticCounts;
 use gpui::{
     div, prelude::FluentBuilder, px, rgb, IntoElement, ParentElement, Point, RenderOnce,
     SharedString, Styled,
@@ -31,6 +32,7 @@ pub struct MetaLine {
     cursor_position: Point<usize>,
     language: Language,
     selection: Option<Selection>,
+    diagnostics: Option<DiagnosticCounts>,
 }
 
 impl MetaLine {
@@ -43,8 +45,15 @@ impl MetaLine {
             cursor_position,
             language,
             selection,
+            diagnostics: None,
         }
     }
+
+    /// Show how many errors and warnings there are
+    pub fn diagnostics(mut self, counts: DiagnosticCounts) -> Self {
+        self.diagnostics = Some(counts);
+        self
+    }
 }
 
 impl RenderOnce for MetaLine {
@@ -62,6 +71,12 @@ impl RenderOnce for MetaLine {
                     .gap_2()
                     .text_sm()
                     .text_color(rgb(0xaaaaaa))
+                    .when_some(self.diagnostics, |this, counts| {
+                        this.child(SharedString::from(format!(
+                            "{} errors, {} warnings",
+                            counts.errors, counts.warnings
+                        )))
+                    })
                     .child(self.language.label())
                     .child(SharedString::from(format!(
                         "{}:{}",
@@ -0,0 +1,238 @@
+//! Word and subword boundaries within a line.
+//!
+//! Offsets are in chars, like [`CursorPosition::col`](crate::editor::CursorPosition).
+//! Characters fall into three classes: whitespace, word characters
+//! (alphanumerics and `_`) and punctuation. A word is a run of word characters
+//! or a run of punctuation, so `foo.bar()` has the words `foo`, `.`, `bar` and
+//! `()`. Subwords further split word characters at `_` and at case changes:
+//! `fooBar`, `foo_bar` and `HTTPServer` each have two.
+//!
+//! The functions step over whole graphemes, classified by their first char, so
+//! a caret never lands inside a grapheme such as a letter with a combining
+//! accent.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+fn char_class(ch: char) -> CharClass {
+    if ch.is_whitespace() {
+        CharClass::Whitespace
+    } else if ch.is_alphanumeric() || ch == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// A line split into graphemes, each classified by its first char
+struct Graphemes {
+    first_chars: Vec<char>,
+    /// Char offset where each grapheme starts, followed by the line length
+    offsets: Vec<usize>,
+}
+
+impl Graphemes {
+    fn new(line: &str) -> Self {
+        let mut first_chars = Vec::new();
+        let mut offsets = vec![0];
+        for grapheme in line.graphemes(true) {
+            first_chars.extend(grapheme.chars().next());
+            offsets.push(offsets[offsets.len() - 1] + grapheme.chars().count());
+        }
+        Self {
+            first_chars,
+            offsets,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.first_chars.len()
+    }
+
+    /// Index of the grapheme containing char offset `col`
+    fn index(&self, col: usize) -> usize {
+        self.offsets[1..].partition_point(|&end| end <= col)
+    }
+
+    fn class(&self, index: usize) -> CharClass {
+        char_class(self.first_chars[index])
+    }
+
+    fn is_underscore(&self, index: usize) -> bool {
+        self.first_chars[index] == '_'
+    }
+
+    /// Whether a subword starts at grapheme `index` inside a word
+    fn is_subword_start(&self, index: usize) -> bool {
+        let chars = &self.first_chars;
+        let (prev, ch) = (chars[index - 1], chars[index]);
+        let next = chars.get(index + 1).copied();
+        (prev == '_') != (ch == '_')
+            || ((prev.is_lowercase() || prev.is_numeric()) && ch.is_uppercase())
+            // The "S" in "HTTPServer"
+            || (prev.is_uppercase() && ch.is_uppercase() && next.is_some_and(char::is_lowercase))
+    }
+
+    fn skip_backward(&self, mut index: usize, skip: impl Fn(usize) -> bool) -> usize {
+        while index > 0 && skip(index - 1) {
+            index -= 1;
+        }
+        index
+    }
+
+    fn skip_forward(&self, mut index: usize, skip: impl Fn(usize) -> bool) -> usize {
+        while index < self.len() && skip(index) {
+            index += 1;
+        }
+        index
+    }
+}
+
+/// The first non-whitespace char of `line`, or its length if it is blank
+pub fn first_non_whitespace(line: &str) -> usize {
+    line.chars()
+        .position(|ch| !ch.is_whitespace())
+        .unwrap_or_else(|| line.chars().count())
+}
+
+/// The start of the word before `col`, skipping whitespace
+pub fn previous_word_start(line: &str, col: usize) -> usize {
+    let line = Graphemes::new(line);
+    let index = line.skip_backward(line.index(col), |i| line.class(i) == CharClass::Whitespace);
+    let index = match index.checked_sub(1) {
+        Some(prev) => line.skip_backward(index, |i| line.class(i) == line.class(prev)),
+        None => index,
+    };
+    line.offsets[index]
+}
+
+/// The end of the word after `col`, skipping whitespace
+pub fn next_word_end(line: &str, col: usize) -> usize {
+    let line = Graphemes::new(line);
+    let index = line.skip_forward(line.index(col), |i| line.class(i) == CharClass::Whitespace);
+    let index = if index < line.len() {
+        let class = line.class(index);
+        line.skip_forward(index, |i| line.class(i) == class)
+    } else {
+        index
+    };
+    line.offsets[index]
+}
+
+/// The start of the subword before `col`, skipping whitespace
+pub fn previous_subword_start(line: &str, col: usize) -> usize {
+    let graphemes = Graphemes::new(line);
+    let g = &graphemes;
+    let index = g.skip_backward(g.index(col), |i| g.class(i) == CharClass::Whitespace);
+    if index == 0 || g.class(index - 1) == CharClass::Punctuation {
+        return previous_word_start(line, g.offsets[index]);
+    }
+
+    let index = g.skip_backward(index, |i| g.is_underscore(i));
+    let index = match index.checked_sub(1) {
+        Some(index) => g.skip_backward(index, |i| {
+            g.class(i) == CharClass::Word && !g.is_subword_start(i + 1)
+        }),
+        None => index,
+    };
+    g.offsets[index]
+}
+
+/// The end of the subword after `col`, skipping whitespace
+pub fn next_subword_end(line: &str, col: usize) -> usize {
+    let graphemes = Graphemes::new(line);
+    let g = &graphemes;
+    let index = g.skip_forward(g.index(col), |i| g.class(i) == CharClass::Whitespace);
+    if index == g.len() || g.class(index) == CharClass::Punctuation {
+        return next_word_end(line, g.offsets[index]);
+    }
+
+    let index = g.skip_forward(index, |i| g.is_underscore(i));
+    let index = if index < g.len() {
+        g.skip_forward(index + 1, |i| {
+            g.class(i) == CharClass::Word && !g.is_subword_start(i)
+        })
+    } else {
+        index
+    };
+    g.offsets[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every stop from the start of `line` to its end
+    fn stops_forward(line: &str, next: fn(&str, usize) -> usize) -> Vec<usize> {
+        let mut stops = Vec::new();
+        let mut col = 0;
+        loop {
+            let next_col = next(line, col);
+            if next_col == col {
+                return stops;
+            }
+            stops.push(next_col);
+            col = next_col;
+        }
+    }
+
+    fn stops_backward(line: &str, previous: fn(&str, usize) -> usize) -> Vec<usize> {
+        let mut stops = Vec::new();
+        let mut col = line.chars().count();
+        loop {
+            let previous_col = previous(line, col);
+            if previous_col == col {
+                return stops;
+            }
+            stops.push(previous_col);
+            col = previous_col;
+        }
+    }
+
+    #[test]
+    fn test_words() {
+        let line = "  let foo_bar = baz.qux();";
+        assert_eq!(
+            stops_forward(line, next_word_end),
+            vec![5, 13, 15, 19, 20, 23, 26]
+        );
+        assert_eq!(
+            stops_backward(line, previous_word_start),
+            vec![23, 20, 19, 16, 14, 6, 2, 0]
+        );
+    }
+
+    #[test]
+    fn test_subwords() {
+        let line = "fooBar HTTPServer snake_case";
+        assert_eq!(
+            stops_forward(line, next_subword_end),
+            vec![3, 6, 11, 17, 23, 28]
+        );
+        assert_eq!(
+            stops_backward(line, previous_subword_start),
+            vec![24, 18, 11, 7, 3, 0]
+        );
+    }
+
+    #[test]
+    fn test_words_with_graphemes() {
+        // COMBINING ACUTE ACCENT isn't alphanumeric, but it belongs to the "e"
+        let line = "cafe\u{301} bar";
+        assert_eq!(next_word_end(line, 0), 5);
+        assert_eq!(previous_word_start(line, 5), 0);
+    }
+
+    #[test]
+    fn test_first_non_whitespace() {
+        assert_eq!(first_non_whitespace("    indented"), 4);
+        assert_eq!(first_non_whitespace("\tx"), 1);
+        assert_eq!(first_non_whitespace("   "), 3);
+    }
+}
@@ -0,0 +1,213 @@
+//! Maps window coordinates back to buffer positions.
+//!
+//! `EditorElement` records where it painted the text each frame, and mouse
+//! handlers on the owning view use that with the
+//! [`DisplayMap`](crate::display_map::DisplayMap)'s line layouts to turn a click
+//! into a [`CursorPosition`].
+
+use crate::display_map::DisplayMap;
+use crate::editor::CursorPosition;
+use gpui::{point, px, Pixels, Point};
+
+#[derive(Clone, Default)]
+pub(crate) struct PositionMap {
+    /// Where the text of row 0 would start with no scrolling
+    text_origin: Point<Pixels>,
+    line_height: Pixels,
+    /// First buffer row painted last frame
+    first_row: usize,
+}
+
+impl PositionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record where the text was painted this frame
+    pub fn update(&mut self, text_origin: Point<Pixels>, line_height: Pixels, first_row: usize) {
+        *self = Self {
+            text_origin,
+            line_height,
+            first_row,
+        };
+    }
+
+    /// The buffer position closest to `point`.
+    ///
+    /// Points above the document map to its start and points below it to its
+    /// end. Rows that haven't been laid out can't be measured, so a point on
+    /// one of them maps to the start of the row when it lies above the painted
+    /// lines and to the end of the row when it lies below them. `line_len`
+    /// returns the char length of a row.
+    pub fn position_for_point(
+        &self,
+        point: Point<Pixels>,
+        scroll_top: Pixels,
+        display_map: &DisplayMap,
+        line_count: usize,
+        line_len: impl Fn(usize) -> usize,
+    ) -> Option<CursorPosition> {
+        if self.line_height <= px(0.0) || line_count == 0 {
+            return None;
+        }
+
+        let y = point.y - self.text_origin.y + scroll_top;
+        if y < px(0.0) {
+            return Some(CursorPosition::new(0, 0));
+        }
+        let display_row = (y / self.line_height).floor() as usize;
+        if display_row >= display_map.display_row_count(line_count) {
+            let last_row = line_count - 1;
+            return Some(CursorPosition::new(last_row, line_len(last_row)));
+        }
+
+        let (row, wrap_row) = display_map.buffer_row(display_row);
+        let col = match display_map.layout(row) {
+            Some(layout) => layout.col_for_x(wrap_row, (point.x - self.text_origin.x).max(px(0.0))),
+            None if row < self.first_row => 0,
+            None => line_len(row),
+        };
+
+        Some(CursorPosition::new(row, col.min(line_len(row))))
+    }
+
+    /// Window coordinates of the top-left corner of the caret slot at
+    /// `position`, or `None` if its row hasn't been laid out
+    pub fn point_for_position(
+        &self,
+        position: CursorPosition,
+        scroll_top: Pixels,
+        display_map: &DisplayMap,
+    ) -> Option<Point<Pixels>> {
+        let layout = display_map.layout(position.row)?;
+        let display_row = display_map.display_row(position);
+        Some(point(
+            self.text_origin.x + layout.x_for_col(position.col),
+            self.text_origin.y + self.line_height * display_row as f32 - scroll_top,
+        ))
+    }
+
+    /// Where the text of row 0 would start with no scrolling
+    pub fn text_origin(&self) -> Point<Pixels> {
+        self.text_origin
+    }
+
+    pub fn line_height(&self) -> Pixels {
+        self.line_height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display_map::LineLayout;
+
+    fn position_map(first_row: usize) -> PositionMap {
+        let mut map = PositionMap::new();
+        map.update(point(px(60.0), px(0.0)), px(20.0), first_row);
+        map
+    }
+
+    #[test]
+    fn test_points_outside_document() {
+        let map = position_map(0);
+        let line_len = |_| 5;
+        assert_eq!(
+            map.position_for_point(
+                point(px(100.0), px(-10.0)),
+                px(0.0),
+                &DisplayMap::new(),
+                3,
+                line_len
+            ),
+            Some(CursorPosition::new(0, 0))
+        );
+        assert_eq!(
+            map.position_for_point(
+                point(px(100.0), px(500.0)),
+                px(0.0),
+                &DisplayMap::new(),
+                3,
+                line_len
+            ),
+            Some(CursorPosition::new(2, 5))
+        );
+    }
+
+    #[test]
+    fn test_unpainted_rows() {
+        // Rows 10 and up were painted, but no lines were laid out
+        let map = position_map(10);
+        let line_len = |_| 5;
+        assert_eq!(
+            map.position_for_point(
+                point(px(100.0), px(50.0)),
+                px(0.0),
+                &DisplayMap::new(),
+                100,
+                line_len
+            ),
+            Some(CursorPosition::new(2, 0))
+        );
+        assert_eq!(
+            map.position_for_point(
+                point(px(100.0), px(50.0)),
+                px(400.0),
+                &DisplayMap::new(),
+                100,
+                line_len
+            ),
+            Some(CursorPosition::new(22, 5))
+        );
+    }
+
+    #[test]
+    fn test_no_layout_yet() {
+        let map = PositionMap::new();
+        assert_eq!(
+            map.position_for_point(
+                point(px(0.0), px(0.0)),
+                px(0.0),
+                &DisplayMap::new(),
+                3,
+                |_| 0
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_wrapped_rows() {
+        let map = position_map(0);
+        let mut display_map = DisplayMap::new();
+        // Row 0 wraps after 5 of its 8 chars, 10px each
+        let xs = (0..=8).map(|col| px(10.0 * col as f32)).collect();
+        display_map.insert(0, LineLayout::new(vec![5], xs));
+        let line_len = |row| if row == 0 { 8 } else { 4 };
+
+        assert_eq!(
+            map.position_for_point(
+                point(px(81.0), px(25.0)),
+                px(0.0),
+                &display_map,
+                2,
+                line_len
+            ),
+            Some(CursorPosition::new(0, 7))
+        );
+        assert_eq!(
+            map.position_for_point(
+                point(px(60.0), px(45.0)),
+                px(0.0),
+                &display_map,
+                2,
+                line_len
+            ),
+            Some(CursorPosition::new(1, 4))
+        );
+        assert_eq!(
+            map.point_for_position(CursorPosition::new(0, 6), px(0.0), &display_map),
+            Some(point(px(70.0), px(20.0)))
+        );
+    }
+}
@@ -0,0 +1,922 @@
+//! Rope implementation for editing large documents.
+//!
+//! This module provides a [`Rope`], a balanced tree of small text chunks where
+//! every node caches a [`TextSummary`] of the text beneath it. Because the
+//! summaries carry char, byte, UTF-16 and newline counts, translating between
+//! linear offsets, (row, col) positions and the other units only has to walk a
+//! single root-to-leaf path instead of the whole document.
+//!
+//! # Design
+//!
+//! The rope is a B-tree: leaves hold one chunk of at most [`CHUNK_MAX_BYTES`]
+//! bytes and internal nodes hold at most [`NODE_MAX_CHILDREN`] children. All
+//! leaves sit at the same depth. Nodes are shared through `Arc`, so cloning a
+//! rope is O(1) and edits copy only the path they touch.
+//!
+//! # Trade-offs
+//!
+//! - Offset <-> (row, col) conversion: O(log n)
+//! - Char <-> byte and char <-> UTF-16 offset conversion: O(log n)
+//! - Line lookup and line length: O(log n + line length)
+//! - Insertions/deletions anywhere: O(log n + edit size)
+//! - Memory overhead: one summary per chunk, plus the tree nodes
+//!
+//! For tiny inputs the [`GapBuffer`](crate::buffer::GapBuffer) is simpler and
+//! just as fast; the rope pays off once documents reach thousands of lines.
+
+use crate::anchor::{Anchor, Bias, EditLog};
+use crate::buffer::{BufferSnapshot, TextBuffer};
+use crate::coordinates::{self, char_to_byte};
+use std::cmp::min;
+use std::ops::{Add, AddAssign};
+use std::sync::Arc;
+
+/// Maximum size of a single leaf chunk in bytes
+pub const CHUNK_MAX_BYTES: usize = 256;
+
+/// Maximum number of children held by an internal node
+pub const NODE_MAX_CHILDREN: usize = 16;
+
+/// Aggregated counts for a span of text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextSummary {
+    /// Length of the text in bytes
+    pub bytes: usize,
+    /// Length of the text in chars
+    pub chars: usize,
+    /// Length of the text in UTF-16 code units
+    pub utf16: usize,
+    /// Number of newline characters in the text
+    pub newlines: usize,
+}
+
+impl TextSummary {
+    /// Compute the summary of a string
+    pub fn from_text(text: &str) -> Self {
+        Self {
+            bytes: text.len(),
+            chars: text.chars().count(),
+            utf16: text.chars().map(char::len_utf16).sum(),
+            newlines: text.bytes().filter(|&b| b == b'\n').count(),
+        }
+    }
+}
+
+impl Add for TextSummary {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            bytes: self.bytes + other.bytes,
+            chars: self.chars + other.chars,
+            utf16: self.utf16 + other.utf16,
+            newlines: self.newlines + other.newlines,
+        }
+    }
+}
+
+impl AddAssign for TextSummary {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+#[derive(Clone)]
+enum Node {
+    Leaf {
+        text: String,
+        summary: TextSummary,
+    },
+    Internal {
+        children: Vec<Arc<Node>>,
+        summary: TextSummary,
+    },
+}
+
+impl Node {
+    fn leaf(text: String) -> Self {
+        let summary = TextSummary::from_text(&text);
+        Node::Leaf { text, summary }
+    }
+
+    fn internal(children: Vec<Arc<Node>>) -> Self {
+        let summary = children
+            .iter()
+            .fold(TextSummary::default(), |acc, child| acc + child.summary());
+        Node::Internal { children, summary }
+    }
+
+    fn summary(&self) -> TextSummary {
+        match self {
+            Node::Leaf { summary, .. } | Node::Internal { summary, .. } => *summary,
+        }
+    }
+
+    /// Insert `text` at `char_offset` within this node.
+    ///
+    /// Returns any new siblings that must be placed directly after this node
+    /// because it overflowed.
+    fn insert(&mut self, char_offset: usize, text: &str) -> Vec<Arc<Node>> {
+        match self {
+            Node::Leaf { text: chunk, .. } => {
+                let byte_idx = char_to_byte(chunk, char_offset);
+                chunk.insert_str(byte_idx, text);
+                if chunk.len() <= CHUNK_MAX_BYTES {
+                    *self = Node::leaf(std::mem::take(chunk));
+                    return Vec::new();
+                }
+
+                let mut pieces = split_into_chunks(chunk).into_iter();
+                *self = Node::leaf(pieces.next().unwrap_or_default());
+                pieces.map(|piece| Arc::new(Node::leaf(piece))).collect()
+            }
+            Node::Internal { children, .. } => {
+                let mut remaining = char_offset;
+                let mut child_idx = children.len() - 1;
+                for (i, child) in children.iter().enumerate() {
+                    let chars = child.summary().chars;
+                    if remaining <= chars {
+                        child_idx = i;
+                        break;
+                    }
+                    remaining -= chars;
+                }
+
+                let overflow = Arc::make_mut(&mut children[child_idx]).insert(remaining, text);
+                children.splice(child_idx + 1..child_idx + 1, overflow);
+
+                let mut groups = group_nodes(std::mem::take(children)).into_iter();
+                let first = groups
+                    .next()
+                    .unwrap_or_else(|| Arc::new(Node::internal(Vec::new())));
+                *self = Arc::unwrap_or_clone(first);
+                groups.collect()
+            }
+        }
+    }
+
+    /// Delete the chars in `start..end` (relative to this node)
+    fn delete(&mut self, start: usize, end: usize) {
+        match self {
+            Node::Leaf { text, .. } => {
+                let start_byte = char_to_byte(text, start);
+                let end_byte = char_to_byte(text, end);
+                text.replace_range(start_byte..end_byte, "");
+                *self = Node::leaf(std::mem::take(text));
+            }
+            Node::Internal { children, .. } => {
+                let mut offset = 0;
+                let mut kept = Vec::with_capacity(children.len());
+                for mut child in children.drain(..) {
+                    let chars = child.summary().chars;
+                    let child_start = offset;
+                    let child_end = offset + chars;
+                    offset = child_end;
+
+                    if child_end <= start || child_start >= end {
+                        kept.push(child);
+                    } else if start <= child_start && child_end <= end {
+                        // Fully covered, drop the whole subtree
+                    } else {
+                        let local_start = start.saturating_sub(child_start);
+                        let local_end = min(end, child_end) - child_start;
+                        Arc::make_mut(&mut child).delete(local_start, local_end);
+                        kept.push(child);
+                    }
+                }
+
+                kept.retain(|child| child.summary().chars > 0);
+                *self = Node::internal(merge_small_neighbors(kept));
+            }
+        }
+    }
+
+    /// Char offset of the start of `row`, which must exist within this node
+    fn row_start(&self, row: usize) -> usize {
+        if row == 0 {
+            return 0;
+        }
+        match self {
+            Node::Leaf { text, .. } => {
+                let mut newlines = 0;
+                for (i, ch) in text.chars().enumerate() {
+                    if ch == '\n' {
+                        newlines += 1;
+                        if newlines == row {
+                            return i + 1;
+                        }
+                    }
+                }
+                self.summary().chars
+            }
+            Node::Internal { children, .. } => {
+                let mut remaining = row;
+                let mut offset = 0;
+                for child in children {
+                    let summary = child.summary();
+                    if remaining <= summary.newlines {
+                        return offset + child.row_start(remaining);
+                    }
+                    remaining -= summary.newlines;
+                    offset += summary.chars;
+                }
+                offset
+            }
+        }
+    }
+
+    /// Number of newlines strictly before `char_offset`
+    fn newlines_before(&self, char_offset: usize) -> usize {
+        match self {
+            Node::Leaf { text, .. } => text
+                .chars()
+                .take(char_offset)
+                .filter(|&ch| ch == '\n')
+                .count(),
+            Node::Internal { children, .. } => {
+                let mut remaining = char_offset;
+                let mut newlines = 0;
+                for child in children {
+                    let summary = child.summary();
+                    if remaining <= summary.chars {
+                        return newlines + child.newlines_before(remaining);
+                    }
+                    remaining -= summary.chars;
+                    newlines += summary.newlines;
+                }
+                newlines
+            }
+        }
+    }
+
+    /// The leaf containing `offset`, measured in `unit`, along with the summary
+    /// of everything before that leaf
+    fn seek(&self, offset: usize, unit: fn(&TextSummary) -> usize) -> (TextSummary, &str) {
+        match self {
+            Node::Leaf { text, .. } => (TextSummary::default(), text),
+            Node::Internal { children, .. } => {
+                let mut before = TextSummary::default();
+                for (i, child) in children.iter().enumerate() {
+                    let summary = child.summary();
+                    if offset - unit(&before) <= unit(&summary) || i + 1 == children.len() {
+                        let (inner, text) = child.seek(offset - unit(&before), unit);
+                        return (before + inner, text);
+                    }
+                    before += summary;
+                }
+                (before, "")
+            }
+        }
+    }
+
+    /// Append the chars in `start..end` (relative to this node) to `out`
+    fn collect_range(&self, start: usize, end: usize, out: &mut String) {
+        match self {
+            Node::Leaf { text, .. } => {
+                let start_byte = char_to_byte(text, start);
+                let end_byte = char_to_byte(text, end);
+                out.push_str(&text[start_byte..end_byte]);
+            }
+            Node::Internal { children, .. } => {
+                let mut offset = 0;
+                for child in children {
+                    let chars = child.summary().chars;
+                    let child_start = offset;
+                    let child_end = offset + chars;
+                    offset = child_end;
+
+                    if child_end <= start {
+                        continue;
+                    }
+                    if child_start >= end {
+                        break;
+                    }
+                    let local_start = start.saturating_sub(child_start);
+                    let local_end = min(end, child_end) - child_start;
+                    child.collect_range(local_start, local_end, out);
+                }
+            }
+        }
+    }
+
+    fn push_chunks<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Node::Leaf { text, .. } => out.push(text),
+            Node::Internal { children, .. } => {
+                for child in children {
+                    child.push_chunks(out);
+                }
+            }
+        }
+    }
+}
+
+/// A balanced tree of text chunks with cached summaries.
+///
+/// Positions are expressed as char offsets, matching
+/// [`GapBuffer`](crate::buffer::GapBuffer), so the two are interchangeable
+/// behind the [`TextBuffer`] trait.
+#[derive(Clone)]
+pub struct Rope {
+    root: Arc<Node>,
+    /// Every edit, for resolving anchors
+    edits: EditLog,
+}
+
+impl Rope {
+    /// Create a new empty rope
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(Node::leaf(String::new())),
+            edits: EditLog::new(),
+        }
+    }
+
+    /// Create a rope from text
+    pub fn from_text(text: &str) -> Self {
+        let leaves = split_into_chunks(text)
+            .into_iter()
+            .map(|chunk| Arc::new(Node::leaf(chunk)))
+            .collect();
+        Self {
+            root: build_tree(leaves),
+            edits: EditLog::new(),
+        }
+    }
+
+    /// Create a rope from lines
+    pub fn from_lines(lines: Vec<String>) -> Self {
+        Self::from_text(&lines.join("\n"))
+    }
+
+    /// Get the summary of the whole rope
+    pub fn summary(&self) -> TextSummary {
+        self.root.summary()
+    }
+
+    /// Get the length of the content in chars
+    pub fn len(&self) -> usize {
+        self.summary().chars
+    }
+
+    /// Whether the rope contains no text
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Insert text at the specified char offset
+    pub fn insert(&mut self, position: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let position = min(position, self.len());
+        self.edits.insert(position, text.chars().count());
+        let overflow = Arc::make_mut(&mut self.root).insert(position, text);
+        if !overflow.is_empty() {
+            let mut level = vec![self.root.clone()];
+            level.extend(overflow);
+            self.root = build_tree(level);
+        }
+    }
+
+    /// Delete the chars in `start..end`
+    pub fn delete_range(&mut self, start: usize, end: usize) {
+        let end = min(end, self.len());
+        if start >= end {
+            return;
+        }
+        Arc::make_mut(&mut self.root).delete(start, end);
+        self.edits.delete(start, end);
+
+        // Collapse single-child roots so the tree stays as shallow as possible
+        loop {
+            let next = match self.root.as_ref() {
+                Node::Internal { children, .. } if children.len() == 1 => children[0].clone(),
+                Node::Internal { children, .. } if children.is_empty() => {
+                    Arc::new(Node::leaf(String::new()))
+                }
+                _ => break,
+            };
+            self.root = next;
+        }
+    }
+
+    /// Get the text in the char range `start..end`
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        let end = min(end, self.len());
+        let mut out = String::new();
+        if start < end {
+            self.root.collect_range(start, end, &mut out);
+        }
+        out
+    }
+
+    /// Iterate over the chunks that make up the rope, in order
+    pub fn chunks(&self) -> impl Iterator<Item = &str> {
+        let mut chunks = Vec::new();
+        self.root.push_chunks(&mut chunks);
+        chunks.into_iter()
+    }
+
+    /// Char offset of the first char of `row`, clamped to the end of the rope
+    fn row_start(&self, row: usize) -> usize {
+        if row > self.summary().newlines {
+            return self.len();
+        }
+        self.root.row_start(row)
+    }
+
+    /// Convert cursor position (row, col) to a char offset.
+    ///
+    /// Columns past the end of the line clamp to the end of that line.
+    pub fn cursor_to_position(&self, row: usize, col: usize) -> usize {
+        if row > self.summary().newlines {
+            return self.len();
+        }
+        self.row_start(row) + min(col, self.line_len(row))
+    }
+
+    /// Convert a char offset to cursor position (row, col)
+    pub fn position_to_cursor(&self, position: usize) -> (usize, usize) {
+        let position = min(position, self.len());
+        let row = self.root.newlines_before(position);
+        (row, position - self.row_start(row))
+    }
+
+    /// Convert `offset` from one unit to another, walking down to the leaf
+    /// that contains it and converting within that leaf's text
+    fn convert(
+        &self,
+        offset: usize,
+        from: fn(&TextSummary) -> usize,
+        to: fn(&TextSummary) -> usize,
+        within_leaf: fn(&str, usize) -> usize,
+    ) -> usize {
+        let offset = min(offset, from(&self.summary()));
+        let (before, text) = self.root.seek(offset, from);
+        to(&before) + within_leaf(text, offset - from(&before))
+    }
+}
+
+impl Default for Rope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for Rope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Rope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rope({:?})", self.to_string())
+    }
+}
+
+impl TextBuffer for Rope {
+    fn line_count(&self) -> usize {
+        self.summary().newlines + 1
+    }
+
+    fn get_line(&self, line_idx: usize) -> Option<String> {
+        if line_idx >= self.line_count() {
+            return None;
+        }
+        let start = self.row_start(line_idx);
+        Some(self.slice(start, start + self.line_len(line_idx)))
+    }
+
+    fn line_len(&self, line_idx: usize) -> usize {
+        if line_idx >= self.line_count() {
+            return 0;
+        }
+        let start = self.row_start(line_idx);
+        let end = if line_idx + 1 < self.line_count() {
+            self.row_start(line_idx + 1) - 1
+        } else {
+            self.len()
+        };
+        end - start
+    }
+
+    fn all_lines(&self) -> Vec<String> {
+        self.to_string()
+            .split('\n')
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    fn insert_at(&mut self, row: usize, col: usize, text: &str) {
+        let position = self.cursor_to_position(row, col);
+        self.insert(position, text);
+    }
+
+    fn delete_at(&mut self, row: usize, col: usize) {
+        let position = self.cursor_to_position(row, col);
+        self.delete_range(position, position + 1);
+    }
+
+    fn backspace_at(&mut self, row: usize, col: usize) {
+        let position = self.cursor_to_position(row, col);
+        if position > 0 {
+            self.delete_range(position - 1, position);
+        }
+    }
+
+    fn len(&self) -> usize {
+        Rope::len(self)
+    }
+
+    fn insert(&mut self, position: usize, text: &str) {
+        Rope::insert(self, position, text);
+    }
+
+    fn delete_range(&mut self, start: usize, end: usize) {
+        Rope::delete_range(self, start, end);
+    }
+
+    fn cursor_to_position(&self, row: usize, col: usize) -> usize {
+        Rope::cursor_to_position(self, row, col)
+    }
+
+    fn position_to_cursor(&self, position: usize) -> (usize, usize) {
+        Rope::position_to_cursor(self, position)
+    }
+
+    fn text_in_range(&self, start: usize, end: usize) -> String {
+        self.slice(start, end)
+    }
+
+    fn char_to_byte(&self, position: usize) -> usize {
+        self.convert(
+            position,
+            |s| s.chars,
+            |s| s.bytes,
+            coordinates::char_to_byte,
+        )
+    }
+
+    fn byte_to_char(&self, offset: usize) -> usize {
+        self.convert(offset, |s| s.bytes, |s| s.chars, coordinates::byte_to_char)
+    }
+
+    fn char_to_utf16(&self, position: usize) -> usize {
+        self.convert(
+            position,
+            |s| s.chars,
+            |s| s.utf16,
+            coordinates::char_to_utf16,
+        )
+    }
+
+    fn utf16_to_char(&self, offset: usize) -> usize {
+        self.convert(offset, |s| s.utf16, |s| s.chars, coordinates::utf16_to_char)
+    }
+
+    fn version(&self) -> usize {
+        self.edits.version()
+    }
+
+    fn anchor_at(&self, position: usize, bias: Bias) -> Anchor {
+        self.edits.anchor(min(position, self.len()), bias)
+    }
+
+    fn resolve_anchor(&self, anchor: &Anchor) -> Option<usize> {
+        self.edits.resolve(anchor)
+    }
+
+    fn first_anchor_version(&self) -> usize {
+        self.edits.first_version()
+    }
+
+    fn forget_edits_before(&mut self, version: usize) {
+        self.edits.forget_before(version);
+    }
+
+    /// Share the tree with the snapshot. It gets an edit log of its own, so
+    /// it doesn't hold on to this one's.
+    fn snapshot(&self) -> BufferSnapshot {
+        BufferSnapshot::from_rope(Rope {
+            root: self.root.clone(),
+            edits: EditLog::new(),
+        })
+    }
+}
+
+/// Split text into chunks of at most [`CHUNK_MAX_BYTES`], on char boundaries
+fn split_into_chunks(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return vec![String::new()];
+    }
+
+    // Aim for chunks about three quarters full so small edits don't
+    // immediately split them again
+    let target = CHUNK_MAX_BYTES * 3 / 4;
+    let mut chunks = Vec::with_capacity(text.len() / target + 1);
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = min(target, rest.len());
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk.to_string());
+        rest = tail;
+    }
+    chunks
+}
+
+/// Group sibling nodes into parents of at most [`NODE_MAX_CHILDREN`] each
+fn group_nodes(nodes: Vec<Arc<Node>>) -> Vec<Arc<Node>> {
+    if nodes.len() <= NODE_MAX_CHILDREN {
+        return vec![Arc::new(Node::internal(nodes))];
+    }
+
+    let group_count = nodes.len().div_ceil(NODE_MAX_CHILDREN);
+    let group_size = nodes.len().div_ceil(group_count);
+    let mut groups = Vec::with_capacity(group_count);
+    let mut nodes = nodes.into_iter().peekable();
+    while nodes.peek().is_some() {
+        let group: Vec<_> = nodes.by_ref().take(group_size).collect();
+        groups.push(Arc::new(Node::internal(group)));
+    }
+    groups
+}
+
+/// Build a balanced tree on top of a level of same-height nodes
+fn build_tree(mut level: Vec<Arc<Node>>) -> Arc<Node> {
+    while level.len() > 1 {
+        level = group_nodes(level);
+    }
+    level
+        .pop()
+        .unwrap_or_else(|| Arc::new(Node::leaf(String::new())))
+}
+
+/// Merge adjacent siblings that are small enough to share a single node
+fn merge_small_neighbors(children: Vec<Arc<Node>>) -> Vec<Arc<Node>> {
+    let mut merged: Vec<Arc<Node>> = Vec::with_capacity(children.len());
+    for child in children {
+        if let Some(last) = merged.last_mut() {
+            let combined = match (last.as_ref(), child.as_ref()) {
+                (Node::Leaf { text, .. }, Node::Leaf { text: next, .. })
+                    if text.len() + next.len() <= CHUNK_MAX_BYTES =>
+                {
+                    Some(Node::leaf(format!("{}{}", text, next)))
+                }
+                (Node::Internal { children, .. }, Node::Internal { children: next, .. })
+                    if children.len() + next.len() <= NODE_MAX_CHILDREN =>
+                {
+                    Some(Node::internal(
+                        children.iter().chain(next.iter()).cloned().collect(),
+                    ))
+                }
+                _ => None,
+            };
+            if let Some(combined) = combined {
+                *last = Arc::new(combined);
+                continue;
+            }
+        }
+        merged.push(child);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::GapBuffer;
+
+    fn check_invariants(rope: &Rope) {
+        fn depth(node: &Node) -> usize {
+            match node {
+                Node::Leaf { text, summary } => {
+                    assert!(text.len() <= CHUNK_MAX_BYTES);
+                    assert_eq!(*summary, TextSummary::from_text(text));
+                    0
+                }
+                Node::Internal { children, summary } => {
+                    assert!(!children.is_empty());
+                    assert!(children.len() <= NODE_MAX_CHILDREN);
+                    let depths: Vec<_> = children.iter().map(|c| depth(c)).collect();
+                    assert!(depths.windows(2).all(|w| w[0] == w[1]));
+                    let total = children
+                        .iter()
+                        .fold(TextSummary::default(), |acc, c| acc + c.summary());
+                    assert_eq!(*summary, total);
+                    depths[0] + 1
+                }
+            }
+        }
+        depth(&rope.root);
+    }
+
+    #[test]
+    fn test_new_empty_rope() {
+        let rope = Rope::new();
+        assert_eq!(rope.len(), 0);
+        assert!(rope.is_empty());
+        assert_eq!(rope.to_string(), "");
+        assert_eq!(rope.line_count(), 1);
+        assert_eq!(rope.get_line(0), Some(String::new()));
+    }
+
+    #[test]
+    fn test_from_text_and_lines() {
+        let rope = Rope::from_text("Hello\nWorld\nTest");
+        assert_eq!(rope.line_count(), 3);
+        assert_eq!(rope.all_lines(), vec!["Hello", "World", "Test"]);
+
+        let rope = Rope::from_lines(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(rope.to_string(), "a\nb");
+    }
+
+    #[test]
+    fn test_summary() {
+        let rope = Rope::from_text("héllo\nwörld\n");
+        assert_eq!(
+            rope.summary(),
+            TextSummary {
+                bytes: 14,
+                chars: 12,
+                utf16: 12,
+                newlines: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_line_access() {
+        let rope = Rope::from_text("Hello\nWorld!\n");
+        assert_eq!(rope.line_count(), 3);
+        assert_eq!(rope.line_len(0), 5);
+        assert_eq!(rope.line_len(1), 6);
+        assert_eq!(rope.line_len(2), 0);
+        assert_eq!(rope.get_line(1), Some("World!".to_string()));
+        assert_eq!(rope.get_line(3), None);
+    }
+
+    #[test]
+    fn test_cursor_position_conversion() {
+        let rope = Rope::from_text("Line1\nLine2\nLine3");
+
+        assert_eq!(rope.cursor_to_position(0, 0), 0);
+        assert_eq!(rope.cursor_to_position(0, 5), 5);
+        assert_eq!(rope.cursor_to_position(1, 0), 6);
+        assert_eq!(rope.cursor_to_position(2, 0), 12);
+        assert_eq!(rope.cursor_to_position(0, 100), 5);
+        assert_eq!(rope.cursor_to_position(10, 0), 17);
+
+        assert_eq!(rope.position_to_cursor(0), (0, 0));
+        assert_eq!(rope.position_to_cursor(5), (0, 5));
+        assert_eq!(rope.position_to_cursor(6), (1, 0));
+        assert_eq!(rope.position_to_cursor(11), (1, 5));
+        assert_eq!(rope.position_to_cursor(100), (2, 5));
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let mut rope = Rope::from_text("Hello");
+        rope.insert(5, " World");
+        rope.insert(0, "Say ");
+        assert_eq!(rope.to_string(), "Say Hello World");
+
+        rope.delete_range(0, 4);
+        assert_eq!(rope.to_string(), "Hello World");
+
+        rope.insert_at(0, 5, "\n");
+        assert_eq!(rope.all_lines(), vec!["Hello", " World"]);
+
+        rope.backspace_at(1, 0);
+        assert_eq!(rope.to_string(), "Hello World");
+
+        rope.delete_at(0, 5);
+        assert_eq!(rope.to_string(), "HelloWorld");
+    }
+
+    #[test]
+    fn test_unicode_characters() {
+        let mut rope = Rope::from_text("Hello 世界");
+        rope.insert(6, "🌍 ");
+        assert_eq!(rope.to_string(), "Hello 🌍 世界");
+        assert_eq!(rope.line_len(0), 10);
+
+        rope.delete_range(6, 8);
+        assert_eq!(rope.to_string(), "Hello 世界");
+        assert_eq!(rope.text_in_range(6, 8), "世界");
+    }
+
+    #[test]
+    fn test_offset_conversions_match_gap_buffer() {
+        // Long enough to span many chunks, with multi-byte chars and surrogate
+        // pairs landing on either side of chunk boundaries
+        let text: String = (0..400).map(|i| format!("{i} é日😀\n")).collect();
+        let rope = Rope::from_text(&text);
+        let mut gap = GapBuffer::from_text(&text);
+        gap.move_gap_to(1_234);
+        assert!(matches!(rope.root.as_ref(), Node::Internal { .. }));
+
+        let summary = rope.summary();
+        for position in 0..=summary.chars + 1 {
+            assert_eq!(rope.char_to_byte(position), gap.char_to_byte(position));
+            assert_eq!(rope.char_to_utf16(position), gap.char_to_utf16(position));
+        }
+        for offset in 0..=summary.bytes + 1 {
+            assert_eq!(rope.byte_to_char(offset), gap.byte_to_char(offset));
+        }
+        for offset in 0..=summary.utf16 + 1 {
+            assert_eq!(rope.utf16_to_char(offset), gap.utf16_to_char(offset));
+        }
+        assert_eq!(
+            rope.char_to_utf16(summary.chars),
+            text.encode_utf16().count()
+        );
+        assert_eq!(rope.utf16_to_char(summary.utf16 + 10), summary.chars);
+    }
+
+    #[test]
+    fn test_large_text_is_balanced() {
+        let mut text = String::new();
+        for i in 0..50_000 {
+            text.push_str(&format!("line {}: some configuration value\n", i));
+        }
+        let rope = Rope::from_text(&text);
+        check_invariants(&rope);
+
+        assert_eq!(rope.line_count(), 50_001);
+        assert_eq!(
+            rope.get_line(31_337),
+            Some("line 31337: some configuration value".to_string())
+        );
+        let position = rope.cursor_to_position(40_000, 5);
+        assert_eq!(rope.position_to_cursor(position), (40_000, 5));
+    }
+
+    #[test]
+    fn test_large_insert_and_delete_keep_invariants() {
+        let mut rope = Rope::from_text("start\nend");
+        let big: String = (0..2_000).map(|i| format!("{}\n", i)).collect();
+        rope.insert(6, &big);
+        check_invariants(&rope);
+        assert_eq!(rope.line_count(), 2_002);
+        assert_eq!(rope.get_line(1), Some("0".to_string()));
+
+        let len = rope.len();
+        rope.delete_range(6, len - 3);
+        check_invariants(&rope);
+        assert_eq!(rope.to_string(), "start\nend");
+    }
+
+    #[test]
+    fn test_snapshot_keeps_its_text() {
+        let mut rope = Rope::from_text("one\ntwo");
+        let snapshot = rope.snapshot();
+        rope.insert(3, "\nthree");
+        rope.delete_range(0, 2);
+        assert_eq!(snapshot.line_count(), 2);
+        assert_eq!(snapshot.line(0), Some("one".to_string()));
+        assert_eq!(snapshot.line(1), Some("two".to_string()));
+        assert_eq!(rope.to_string(), "e\nthree\ntwo");
+    }
+
+    #[test]
+    fn test_matches_gap_buffer() {
+        let mut rope = Rope::from_text("Initial\ntext");
+        let mut gap = GapBuffer::from_text("Initial\ntext");
+        let anchors: Vec<_> = (0..=rope.len())
+            .flat_map(|pos| [Bias::Left, Bias::Right].map(|bias| rope.anchor_at(pos, bias)))
+            .collect();
+
+        for i in 0..500usize {
+            let len = rope.len();
+            let pos = (i * 7919) % (len + 1);
+            if i % 3 == 0 && len > 0 {
+                let end = min(pos + (i % 11), len);
+                rope.delete_range(pos, end);
+                gap.delete_range(pos, end);
+            } else {
+                let text = if i % 5 == 0 { "\n" } else { "abc日本" };
+                rope.insert(pos, text);
+                gap.insert(pos, text);
+            }
+        }
+
+        check_invariants(&rope);
+        assert_eq!(rope.to_string(), gap.to_string());
+        assert_eq!(rope.line_count(), gap.line_count());
+        for row in 0..rope.line_count() {
+            assert_eq!(rope.get_line(row), gap.get_line(row));
+        }
+        assert_eq!(rope.version(), gap.version());
+        for anchor in &anchors {
+            assert_eq!(rope.resolve_anchor(anchor), gap.resolve_anchor(anchor));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Scroll state for the editor.
+//!
+//! The view changes the scroll position from scroll wheel events and
+//! autoscroll requests, and `EditorElement` records how much of the text
+//! fits in the viewport each time it lays the editor out.
+
+use gpui::{px, Pixels};
+
+#[derive(Clone)]
+pub(crate) struct ScrollState {
+    scroll_top: Pixels,
+    viewport_height: Pixels,
+    autoscroll_requested: bool,
+}
+
+impl ScrollState {
+    pub fn new() -> Self {
+        Self {
+            scroll_top: px(0.0),
+            viewport_height: px(0.0),
+            autoscroll_requested: false,
+        }
+    }
+
+    pub fn scroll_top(&self) -> Pixels {
+        self.scroll_top
+    }
+
+    /// Set the scroll offset, clamped to `0..=max_scroll_top`.
+    /// Returns true if the offset changed.
+    pub fn set_scroll_top(&mut self, scroll_top: Pixels, max_scroll_top: Pixels) -> bool {
+        let scroll_top = scroll_top.clamp(px(0.0), max_scroll_top.max(px(0.0)));
+        let changed = self.scroll_top != scroll_top;
+        self.scroll_top = scroll_top;
+        changed
+    }
+
+    pub fn scroll_by(&mut self, delta: Pixels, max_scroll_top: Pixels) -> bool {
+        self.set_scroll_top(self.scroll_top + delta, max_scroll_top)
+    }
+
+    pub fn viewport_height(&self) -> Pixels {
+        self.viewport_height
+    }
+
+    pub fn set_viewport_height(&mut self, height: Pixels) {
+        self.viewport_height = height;
+    }
+
+    pub fn request_autoscroll(&mut self) {
+        self.autoscroll_requested = true;
+    }
+
+    /// Consume a pending autoscroll request
+    pub fn take_autoscroll_request(&mut self) -> bool {
+        std::mem::take(&mut self.autoscroll_requested)
+    }
+}
+
+impl Default for ScrollState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
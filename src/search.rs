@@ -0,0 +1,219 @@
+//! Find and replace.
+//!
+//! A [`SearchQuery`] describes what to look for; [`SearchQuery::build`] turns
+//! it into a [`Searcher`] that finds matches in text. Plain queries are
+//! escaped and compiled to a regex as well, so every kind of query goes through
+//! the same matching code. [`Editor`](crate::editor::Editor) keeps the active
+//! search and its matches, and [`SearchBar`](crate::search_bar::SearchBar) is a
+//! ready-made UI for it.
+
+use crate::editor::CursorPosition;
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+/// What to search for
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub text: String,
+    /// Match letter case exactly
+    pub case_sensitive: bool,
+    /// Only match where the match is not part of a longer word
+    pub whole_word: bool,
+    /// Treat `text` as a regular expression
+    pub regex: bool,
+}
+
+impl SearchQuery {
+    /// A plain, case-insensitive query for `text`
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn whole_word(mut self, whole_word: bool) -> Self {
+        self.whole_word = whole_word;
+        self
+    }
+
+    pub fn regex(mut self, regex: bool) -> Self {
+        self.regex = regex;
+        self
+    }
+
+    /// Compile the query. Fails if `text` is an invalid regular expression.
+    pub fn build(&self) -> Result<Searcher, regex::Error> {
+        let pattern = if self.regex {
+            self.text.clone()
+        } else {
+            regex::escape(&self.text)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()?;
+        Ok(Searcher {
+            regex,
+            whole_word: self.whole_word,
+            expand_captures: self.regex,
+        })
+    }
+}
+
+/// A compiled [`SearchQuery`]
+#[derive(Clone, Debug)]
+pub struct Searcher {
+    regex: Regex,
+    whole_word: bool,
+    /// Expand `$1`-style references in replacements
+    expand_captures: bool,
+}
+
+impl Searcher {
+    /// Byte ranges of every match in `text`. Empty matches are skipped.
+    pub fn find_all(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(text)
+            .map(|found| found.range())
+            .filter(|range| !range.is_empty())
+            .filter(|range| !self.whole_word || is_whole_word(text, range))
+            .collect()
+    }
+
+    /// The text to put in place of the match at `range` in `text`.
+    ///
+    /// For regex queries, `$1`, `${name}` and so on in `replacement` are
+    /// replaced with the corresponding capture group.
+    pub fn replacement_for(&self, text: &str, range: Range<usize>, replacement: &str) -> String {
+        if !self.expand_captures {
+            return replacement.to_string();
+        }
+        let Some(captures) = self.regex.captures_at(text, range.start) else {
+            return replacement.to_string();
+        };
+        let mut expanded = String::new();
+        captures.expand(replacement, &mut expanded);
+        expanded
+    }
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Whether the text around `range` doesn't continue the word it starts or ends with
+fn is_whole_word(text: &str, range: &Range<usize>) -> bool {
+    let matched = &text[range.clone()];
+    let before = text[..range.start].chars().next_back();
+    let after = text[range.end..].chars().next();
+    let joins = |outside: Option<char>, inside: Option<char>| {
+        outside.is_some_and(is_word_char) && inside.is_some_and(is_word_char)
+    };
+    !joins(before, matched.chars().next()) && !joins(after, matched.chars().next_back())
+}
+
+/// Convert sorted byte ranges in `text` to (start, end) positions
+pub fn byte_ranges_to_positions(
+    text: &str,
+    ranges: &[Range<usize>],
+) -> Vec<(CursorPosition, CursorPosition)> {
+    let mut offsets = ranges
+        .iter()
+        .flat_map(|range| [range.start, range.end])
+        .peekable();
+    let mut positions = Vec::with_capacity(ranges.len() * 2);
+    let mut position = CursorPosition::new(0, 0);
+
+    for (index, ch) in text.char_indices().chain([(text.len(), '\0')]) {
+        while offsets.next_if(|&offset| offset <= index).is_some() {
+            positions.push(position);
+        }
+        if offsets.peek().is_none() {
+            break;
+        }
+        if ch == '\n' {
+            position = CursorPosition::new(position.row + 1, 0);
+        } else {
+            position.col += 1;
+        }
+    }
+
+    positions
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: SearchQuery, text: &str) -> Vec<&str> {
+        let searcher = query.build().unwrap();
+        searcher
+            .find_all(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_plain_and_case() {
+        let text = "Foo foo FOO f.o";
+        assert_eq!(
+            find(SearchQuery::new("foo"), text),
+            vec!["Foo", "foo", "FOO"]
+        );
+        assert_eq!(
+            find(SearchQuery::new("foo").case_sensitive(true), text),
+            vec!["foo"]
+        );
+        // Plain queries are not patterns
+        assert_eq!(find(SearchQuery::new("f.o"), text), vec!["f.o"]);
+    }
+
+    #[test]
+    fn test_whole_word() {
+        let text = "cat concat cat_ (cat) .cat";
+        let query = SearchQuery::new("cat").whole_word(true);
+        assert_eq!(find(query, text).len(), 3);
+
+        // Punctuation at the edge of the query doesn't need a boundary
+        let query = SearchQuery::new(".cat").whole_word(true);
+        assert_eq!(find(query, text), vec![".cat"]);
+    }
+
+    #[test]
+    fn test_regex_and_replacement() {
+        let text = "let a = 1;\nlet bb = 22;";
+        let query = SearchQuery::new(r"^let (\w+)").regex(true);
+        assert_eq!(find(query.clone(), text), vec!["let a", "let bb"]);
+
+        let searcher = query.build().unwrap();
+        let ranges = searcher.find_all(text);
+        assert_eq!(
+            searcher.replacement_for(text, ranges[1].clone(), "const ${1}_x"),
+            "const bb_x"
+        );
+        assert!(SearchQuery::new("(").regex(true).build().is_err());
+    }
+
+    #[test]
+    fn test_byte_ranges_to_positions() {
+        let text = "héllo\n世界 hé";
+        let searcher = SearchQuery::new("hé").build().unwrap();
+        assert_eq!(
+            byte_ranges_to_positions(text, &searcher.find_all(text)),
+            vec![
+                (CursorPosition::new(0, 0), CursorPosition::new(0, 2)),
+                (CursorPosition::new(1, 3), CursorPosition::new(1, 5)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! A find and replace bar for an [`EditorView`].
+//!
+//! The bar has its own single-line editors for the query and the replacement,
+//! toggles for case sensitivity, whole words and regular expressions, and
+//! buttons to step through and replace matches. Typing in the query updates the
+//! target editor's search as you go, so matches are highlighted immediately.
+//!
+//! Enter in the query selects the next match, enter in the replacement replaces
+//! the current one, and escape clears the search and emits [`DismissEvent`] so
+//! the owner can hide the bar.
+
+use crate::actions::{Cancel, Newline};
+use crate::buffer::{GapBuffer, TextBuffer};
+use crate::editor::Editor;
+use crate::search::SearchQuery;
+use crate::view::EditorView;
+use gpui::prelude::FluentBuilder;
+use gpui::*;
+
+/// A find and replace bar driving the search of a target [`EditorView`]
+pub struct SearchBar<B = GapBuffer> {
+    target: Entity<EditorView<B>>,
+    query_input: Entity<EditorView>,
+    replace_input: Entity<EditorView>,
+    query: SearchQuery,
+    /// Why the query couldn't be used, e.g. an invalid regex
+    error: Option<SharedString>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl<B: TextBuffer + Clone + 'static> SearchBar<B> {
+    pub fn new(target: Entity<EditorView<B>>, cx: &mut Context<Self>) -> Self {
+        let query_input = cx.new(|cx| EditorView::new(input_editor("search-query"), cx));
+        let replace_input = cx.new(|cx| EditorView::new(input_editor("search-replace"), cx));
+        let subscriptions = vec![
+            cx.observe(&query_input, |this, _, cx| this.query_changed(cx)),
+            // Keep the match count current as the target is edited
+            cx.observe(&target, |_, _, cx| cx.notify()),
+        ];
+
+        Self {
+            target,
+            query_input,
+            replace_input,
+            query: SearchQuery::default(),
+            error: None,
+            _subscriptions: subscriptions,
+        }
+    }
+
+    pub fn query(&self) -> &SearchQuery {
+        &self.query
+    }
+
+    /// Focus the query input
+    pub fn focus(&self, window: &mut Window, cx: &App) {
+        window.focus(&self.query_input.focus_handle(cx));
+    }
+
+    pub fn select_next_match(&mut self, cx: &mut Context<Self>) {
+        self.target.update(cx, |view, cx| {
+            if view.editor_mut().select_next_match() {
+                cx.notify();
+            }
+        });
+    }
+
+    pub fn select_previous_match(&mut self, cx: &mut Context<Self>) {
+        self.target.update(cx, |view, cx| {
+            if view.editor_mut().select_previous_match() {
+                cx.notify();
+            }
+        });
+    }
+
+    /// Replace the selected match and select the next one
+    pub fn replace_current(&mut self, cx: &mut Context<Self>) {
+        let replacement = input_text(&self.replace_input, cx);
+        self.target.update(cx, |view, cx| {
+            view.editor_mut().replace_current(&replacement);
+            cx.notify();
+        });
+    }
+
+    pub fn replace_all(&mut self, cx: &mut Context<Self>) {
+        let replacement = input_text(&self.replace_input, cx);
+        self.target.update(cx, |view, cx| {
+            if view.editor_mut().replace_all(&replacement) > 0 {
+                cx.notify();
+            }
+        });
+    }
+
+    fn query_changed(&mut self, cx: &mut Context<Self>) {
+        let text = input_text(&self.query_input, cx);
+        if text != self.query.text {
+            self.query.text = text;
+            self.apply_query(cx);
+        }
+    }
+
+    fn toggle(&mut self, toggle: fn(&mut SearchQuery), cx: &mut Context<Self>) {
+        toggle(&mut self.query);
+        self.apply_query(cx);
+    }
+
+    /// Send the current query to the target editor
+    fn apply_query(&mut self, cx: &mut Context<Self>) {
+        let query = self.query.clone();
+        let result = self.target.update(cx, |view, cx| {
+            let result = view.editor_mut().set_search_query(query);
+            cx.notify();
+            result
+        });
+        self.error = result.err().map(|error| error.to_string().into());
+        cx.notify();
+    }
+
+    /// Enter selects the next match from the query and replaces from the
+    /// replacement, instead of inserting a newline
+    fn confirm(&mut self, _: &Newline, window: &mut Window, cx: &mut Context<Self>) {
+        cx.stop_propagation();
+        if self.replace_input.focus_handle(cx).is_focused(window) {
+            self.replace_current(cx);
+        } else {
+            self.select_next_match(cx);
+        }
+    }
+
+    fn dismiss(&mut self, _: &Cancel, _window: &mut Window, cx: &mut Context<Self>) {
+        cx.stop_propagation();
+        self.target.update(cx, |view, cx| {
+            view.editor_mut().clear_search();
+            cx.notify();
+        });
+        cx.emit(DismissEvent);
+    }
+
+    /// "3 of 12", "No results" or the query error
+    fn status(&self, cx: &App) -> SharedString {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        if self.query.text.is_empty() {
+            return SharedString::default();
+        }
+
+        let editor = self.target.read(cx).editor();
+        let matches = editor.search_matches();
+        match editor.active_match_index() {
+            _ if matches.is_empty() => "No results".into(),
+            Some(index) => format!("{} of {}", index + 1, matches.len()).into(),
+            None => format!("{} matches", matches.len()).into(),
+        }
+    }
+}
+
+/// A borderless single-line editor for the bar's inputs
+fn input_editor(id: &'static str) -> Editor {
+    let mut editor = Editor::new(id, vec![String::new()]);
+    editor.set_language("Plain Text".to_string());
+    let config = editor.config_mut();
+    config.gutter_width = px(0.0);
+    config.gutter_padding = px(4.0);
+    config.show_line_numbers = false;
+    config.show_fold_indicators = false;
+    config.active_line_bg_color = config.editor_bg_color;
+    editor
+}
+
+fn input_text(input: &Entity<EditorView>, cx: &App) -> String {
+    // Pasting can bring in line breaks; the bar works on a single line
+    input
+        .read(cx)
+        .editor()
+        .get_buffer()
+        .text()
+        .replace('\n', " ")
+}
+
+fn input(editor: &Entity<EditorView>) -> Div {
+    div()
+        .w(px(220.0))
+        .h(px(22.0))
+        .border_1()
+        .border_color(rgb(0x454545))
+        .rounded_sm()
+        .overflow_hidden()
+        .child(editor.clone())
+}
+
+fn button(id: &'static str, label: &'static str, active: bool) -> Stateful<Div> {
+    div()
+        .id(id)
+        .px_1()
+        .rounded_sm()
+        .text_color(rgb(0xcccccc))
+        .hover(|style| style.bg(rgb(0x3a3a3a)))
+        .when(active, |button| button.bg(rgb(0x264f78)))
+        .child(label)
+}
+
+impl<B: TextBuffer + Clone + 'static> EventEmitter<DismissEvent> for SearchBar<B> {}
+
+impl<B: TextBuffer + Clone + 'static> Focusable for SearchBar<B> {
+    fn focus_handle(&self, cx: &App) -> FocusHandle {
+        self.query_input.focus_handle(cx)
+    }
+}
+
+impl<B: TextBuffer + Clone + 'static> Render for SearchBar<B> {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let query = &self.query;
+        let status = self.status(cx);
+
+        div()
+            .key_context("SearchBar")
+            .capture_action(cx.listener(Self::confirm))
+            .capture_action(cx.listener(Self::dismiss))
+            .flex()
+            .flex_col()
+            .gap_1()
+            .p_1()
+            .bg(rgb(0x252525))
+            .text_sm()
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .gap_1()
+                    .child(input(&self.query_input))
+                    .child(
+                        button("case-sensitive", "Aa", query.case_sensitive).on_click(cx.listener(
+                            |this, _, _, cx| this.toggle(|query| query.case_sensitive ^= true, cx),
+                        )),
+                    )
+                    .child(
+                        button("whole-word", "W", query.whole_word).on_click(cx.listener(
+                            |this, _, _, cx| this.toggle(|query| query.whole_word ^= true, cx),
+                        )),
+                    )
+                    .child(button("regex", ".*", query.regex).on_click(
+                        cx.listener(|this, _, _, cx| this.toggle(|query| query.regex ^= true, cx)),
+                    ))
+                    .child(
+                        button("previous-match", "↑", false)
+                            .on_click(cx.listener(|this, _, _, cx| this.select_previous_match(cx))),
+                    )
+                    .child(
+                        button("next-match", "↓", false)
+                            .on_click(cx.listener(|this, _, _, cx| this.select_next_match(cx))),
+                    )
+                    .child(div().text_color(rgb(0xaaaaaa)).child(status)),
+            )
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .gap_1()
+                    .child(input(&self.replace_input))
+                    .child(
+                        button("replace", "Replace", false)
+                            .on_click(cx.listener(|this, _, _, cx| this.replace_current(cx))),
+                    )
+                    .child(
+                        button("replace-all", "Replace All", false)
+                            .on_click(cx.listener(|this, _, _, cx| this.replace_all(cx))),
+                    ),
+            )
+    }
+}
@@ -0,0 +1,274 @@
+//! Multi-cursor selection model.
+//!
+//! An editor always has at least one [`SelectionRange`]. Each range has an
+//! anchor (where the selection started) and a head (where the caret is); when
+//! the two are equal the range is just a caret. [`Selections`] keeps the ranges
+//! sorted in document order and merges any that overlap, so editing code can
+//! walk them front to back without worrying about conflicts.
+
+use crate::editor::CursorPosition;
+use gpui::Pixels;
+
+/// A single caret or selection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelectionRange {
+    /// Where the selection started; stays put while the selection is extended
+    pub anchor: CursorPosition,
+    /// Where the caret is drawn; moves when the selection is extended
+    pub head: CursorPosition,
+    /// Horizontal position to aim for when moving vertically across shorter
+    /// lines, from the start of the visual row
+    pub goal_column: Option<Pixels>,
+}
+
+impl SelectionRange {
+    pub fn new(anchor: CursorPosition, head: CursorPosition) -> Self {
+        Self {
+            anchor,
+            head,
+            goal_column: None,
+        }
+    }
+
+    /// An empty selection at `position`
+    pub fn caret(position: CursorPosition) -> Self {
+        Self::new(position, position)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// Whether the head comes before the anchor
+    pub fn is_reversed(&self) -> bool {
+        self.head < self.anchor
+    }
+
+    pub fn start(&self) -> CursorPosition {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> CursorPosition {
+        self.anchor.max(self.head)
+    }
+
+    /// The (start, end) positions in document order
+    pub fn range(&self) -> (CursorPosition, CursorPosition) {
+        (self.start(), self.end())
+    }
+}
+
+/// The set of carets and selections in an editor.
+///
+/// Ranges are kept sorted by start position and never overlap. One of them
+/// is the primary selection, usually the most recently added one, which is what
+/// single-cursor APIs such as [`Editor::cursor_position`] report.
+///
+/// [`Editor::cursor_position`]: crate::editor::Editor::cursor_position
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selections {
+    ranges: Vec<SelectionRange>,
+    primary: usize,
+}
+
+impl Selections {
+    /// A single caret at `position`
+    pub fn new(position: CursorPosition) -> Self {
+        Self::single(SelectionRange::caret(position))
+    }
+
+    pub fn single(range: SelectionRange) -> Self {
+        Self {
+            ranges: vec![range],
+            primary: 0,
+        }
+    }
+
+    /// Build from a list of ranges, with `ranges[primary]` as the primary one.
+    ///
+    /// Falls back to a caret at the origin when `ranges` is empty.
+    pub fn from_ranges(ranges: Vec<SelectionRange>, primary: usize) -> Self {
+        if ranges.is_empty() {
+            return Self::new(CursorPosition::new(0, 0));
+        }
+        let primary = primary.min(ranges.len() - 1);
+        let mut selections = Self { ranges, primary };
+        selections.merge_overlapping();
+        selections
+    }
+
+    /// Number of selections, always at least one
+    pub fn count(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// All selections in document order
+    pub fn all(&self) -> &[SelectionRange] {
+        &self.ranges
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SelectionRange> {
+        self.ranges.iter()
+    }
+
+    pub fn primary(&self) -> &SelectionRange {
+        &self.ranges[self.primary]
+    }
+
+    pub fn primary_index(&self) -> usize {
+        self.primary
+    }
+
+    /// First selection in document order
+    pub fn first(&self) -> &SelectionRange {
+        &self.ranges[0]
+    }
+
+    /// Last selection in document order
+    pub fn last(&self) -> &SelectionRange {
+        &self.ranges[self.ranges.len() - 1]
+    }
+
+    /// Add a selection and make it the primary one
+    pub fn push(&mut self, range: SelectionRange) {
+        self.ranges.push(range);
+        self.primary = self.ranges.len() - 1;
+        self.merge_overlapping();
+    }
+
+    /// Replace the primary selection, keeping it primary
+    pub fn replace_primary(&mut self, range: SelectionRange) {
+        self.ranges[self.primary] = range;
+        self.merge_overlapping();
+    }
+
+    /// Replace every selection with `range`
+    pub fn set_single(&mut self, range: SelectionRange) {
+        *self = Self::single(range);
+    }
+
+    /// Drop every selection except the primary one
+    pub fn retain_primary(&mut self) {
+        let primary = *self.primary();
+        self.set_single(primary);
+    }
+
+    /// Update every selection in place, then merge any that now overlap
+    pub fn update(&mut self, mut f: impl FnMut(&mut SelectionRange)) {
+        for range in &mut self.ranges {
+            f(range);
+        }
+        self.merge_overlapping();
+    }
+
+    /// Sort selections and merge overlapping ones, keeping track of the primary
+    fn merge_overlapping(&mut self) {
+        let primary = self.primary;
+        let mut indexed: Vec<(SelectionRange, bool)> = self
+            .ranges
+            .drain(..)
+            .enumerate()
+            .map(|(i, range)| (range, i == primary))
+            .collect();
+        indexed.sort_by_key(|(range, _)| (range.start(), range.end()));
+
+        let mut merged: Vec<(SelectionRange, bool)> = Vec::with_capacity(indexed.len());
+        for (range, is_primary) in indexed {
+            if let Some((last, last_is_primary)) = merged.last_mut() {
+                let overlaps = range.start() < last.end() || range.start() == last.start();
+                if overlaps {
+                    let start = last.start();
+                    let end = last.end().max(range.end());
+                    let reversed = if *last_is_primary || !is_primary {
+                        last.is_reversed()
+                    } else {
+                        range.is_reversed()
+                    };
+                    *last = if reversed {
+                        SelectionRange::new(end, start)
+                    } else {
+                        SelectionRange::new(start, end)
+                    };
+                    *last_is_primary |= is_primary;
+                    continue;
+                }
+            }
+            merged.push((range, is_primary));
+        }
+
+        self.primary = merged
+            .iter()
+            .position(|(_, is_primary)| *is_primary)
+            .unwrap_or(0);
+        self.ranges = merged.into_iter().map(|(range, _)| range).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(row: usize, col: usize) -> CursorPosition {
+        CursorPosition::new(row, col)
+    }
+
+    #[test]
+    fn test_ranges_are_sorted() {
+        let selections = Selections::from_ranges(
+            vec![
+                SelectionRange::caret(pos(2, 0)),
+                SelectionRange::caret(pos(0, 3)),
+                SelectionRange::caret(pos(1, 1)),
+            ],
+            2,
+        );
+        let heads: Vec<_> = selections.iter().map(|s| s.head).collect();
+        assert_eq!(heads, vec![pos(0, 3), pos(1, 1), pos(2, 0)]);
+        assert_eq!(selections.primary().head, pos(1, 1));
+    }
+
+    #[test]
+    fn test_duplicate_carets_merge() {
+        let mut selections = Selections::new(pos(1, 2));
+        selections.push(SelectionRange::caret(pos(1, 2)));
+        assert_eq!(selections.count(), 1);
+    }
+
+    #[test]
+    fn test_overlapping_selections_merge() {
+        let mut selections = Selections::new(pos(0, 0));
+        selections.set_single(SelectionRange::new(pos(0, 0), pos(0, 5)));
+        selections.push(SelectionRange::new(pos(0, 8), pos(0, 3)));
+        assert_eq!(selections.count(), 1);
+
+        // The primary (newest) selection decides the direction
+        let merged = selections.primary();
+        assert_eq!(merged.range(), (pos(0, 0), pos(0, 8)));
+        assert!(merged.is_reversed());
+    }
+
+    #[test]
+    fn test_adjacent_selections_do_not_merge() {
+        let selections = Selections::from_ranges(
+            vec![
+                SelectionRange::new(pos(0, 0), pos(0, 3)),
+                SelectionRange::new(pos(0, 3), pos(0, 6)),
+            ],
+            1,
+        );
+        assert_eq!(selections.count(), 2);
+    }
+
+    #[test]
+    fn test_update_merges() {
+        let mut selections = Selections::from_ranges(
+            vec![
+                SelectionRange::caret(pos(0, 1)),
+                SelectionRange::caret(pos(0, 2)),
+            ],
+            0,
+        );
+        selections.update(|range| *range = SelectionRange::caret(pos(0, 0)));
+        assert_eq!(selections.count(), 1);
+    }
+}
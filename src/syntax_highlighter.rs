@@ -1,21 +1,314 @@
+//! Syntax highlighting backed by syntect.
+//!
+//! syntect parses line by line, carrying state from each line to the next.
+//! [`SyntaxHighlighter`] caches that state at the start of every line it has
+//! seen (a checkpoint), so any line can be highlighted by parsing just that
+//! line. Edits invalidate checkpoints from the edited line on; re-parsing
+//! stops as soon as the parser reaches a state it already had cached below the
+//! edit, since everything after that point is unchanged.
+//!
+//! The highlighter is `Send + Sync`, so [`SyntaxHighlighter::highlight_snapshot`]
+//! can run on a background thread against a [`BufferSnapshot`], through a handle
+//! from [`SyntaxHighlighter::share`], while the editor keeps painting. The results are [`StyledRun`]s, which don't depend on the
+//! font and are turned into gpui `TextRun`s with [`text_runs`] at paint time.
+
+use crate::buffer::BufferSnapshot;
+use crate::comment::CommentTokens;
 use gpui::{Font, FontStyle, FontWeight, Hsla, SharedString, TextRun};
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
-use syntect::highlighting::{HighlightIterator, HighlightState, Highlighter, Style, ThemeSet};
-use syntect::parsing::{ParseState, ScopeStack, SyntaxSet};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard};
+use syntect::highlighting::{
+    HighlightIterator, HighlightState, Highlighter, Style, Theme, ThemeSet,
+};
+use syntect::parsing::{ParseState, Scope, ScopeStack, ScopeStackOp, SyntaxReference, SyntaxSet};
+
+/// A span of a highlighted line: `len` bytes drawn with `style`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StyledRun {
+    pub len: usize,
+    pub style: Style,
+}
+
+/// The scopes in effect for a byte range of a line
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeSpan {
+    pub range: Range<usize>,
+    pub scopes: ScopeStack,
+}
+
+/// A highlighted line: runs to paint it with, and the scopes they came from
+#[derive(Clone, Debug)]
+pub struct HighlightedLine {
+    pub runs: Vec<TextRun>,
+    /// Covers the line in order. Empty if the language has no syntax.
+    pub scopes: Vec<ScopeSpan>,
+}
+
+/// Parser state at the start of a line.
+///
+/// Only the scope stack is kept rather than a full `HighlightState`, so the
+/// checkpoints stay valid when the theme changes.
+#[derive(Clone, PartialEq, Eq)]
+struct LineState {
+    parse_state: ParseState,
+    scopes: ScopeStack,
+}
+
+impl LineState {
+    fn new(syntax: &SyntaxReference) -> Self {
+        Self {
+            parse_state: ParseState::new(syntax),
+            scopes: ScopeStack::new(),
+        }
+    }
+}
+
+/// Per-line checkpoints for one language
+#[derive(Clone, Default)]
+struct LineStateCache {
+    language: String,
+    /// `states[row]` is the state at the start of `row`, if known
+    states: Vec<Option<LineState>>,
+    /// `states[..valid]` are known to be correct
+    valid: usize,
+    /// Stale checkpoints from this row on may be reused once the parser
+    /// reproduces one of them
+    converge_from: usize,
+}
+
+impl LineStateCache {
+    fn reset(&mut self, language: &str, syntax: &SyntaxReference) {
+        self.language = language.to_string();
+        self.states = vec![Some(LineState::new(syntax))];
+        self.valid = 1;
+        self.converge_from = 0;
+    }
+
+    /// The checkpoint for `row`, if it is known to be correct
+    fn get(&self, row: usize) -> Option<&LineState> {
+        if row < self.valid {
+            self.states[row].as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// The closest correct checkpoint at or before `row`
+    fn nearest(&self, row: usize) -> (usize, &LineState) {
+        let row = row.min(self.valid - 1);
+        let state = self.states[row]
+            .as_ref()
+            .expect("checkpoints before `valid` are always present");
+        (row, state)
+    }
+
+    /// Record `state` as the start of `row`, which follows a correct checkpoint
+    fn record(&mut self, row: usize, state: LineState) {
+        if row > self.valid {
+            return;
+        }
+        if row >= self.states.len() {
+            self.states.resize(row + 1, None);
+        }
+
+        let converged = row >= self.converge_from && self.states[row].as_ref() == Some(&state);
+        self.states[row] = Some(state);
+        self.valid = self.valid.max(row + 1);
+        if converged {
+            // The old checkpoints below this point are still correct
+            while self.valid < self.states.len() && self.states[self.valid].is_some() {
+                self.valid += 1;
+            }
+            self.converge_from = 0;
+        }
+    }
+
+    /// Account for `old_rows` rows starting at `start_row` being replaced by `new_rows` rows
+    fn edit_lines(&mut self, start_row: usize, old_rows: usize, new_rows: usize) {
+        if self.states.is_empty() {
+            return;
+        }
+        let len = self.states.len();
+        let removed = (start_row + 1).min(len)..(start_row + old_rows.max(1)).min(len);
+        self.states.splice(
+            removed,
+            std::iter::repeat_n(None, new_rows.saturating_sub(1)),
+        );
+
+        self.valid = self.valid.min(start_row + 1).min(self.states.len()).max(1);
+        if self.converge_from > start_row {
+            self.converge_from = (self.converge_from + new_rows).saturating_sub(old_rows);
+        }
+        self.converge_from = self.converge_from.max(start_row + new_rows.max(1));
+    }
+
+    /// Forget every checkpoint after `row`
+    fn truncate(&mut self, row: usize) {
+        self.states.truncate(row + 1);
+        self.valid = self.valid.min(row + 1).max(1).min(self.states.len());
+    }
+}
 
 struct SyntaxHighlighterInner {
-    syntax_set: SyntaxSet,
+    syntax_set: Arc<SyntaxSet>,
     theme_set: ThemeSet,
     current_theme: String,
-    parse_states: HashMap<String, ParseState>,
-    highlight_states: HashMap<String, HighlightState>,
+    line_states: LineStateCache,
+    /// Incremented whenever checkpoints are invalidated, so background work
+    /// started before the change can tell its rows no longer line up
+    version: usize,
+}
+
+impl Clone for SyntaxHighlighterInner {
+    fn clone(&self) -> Self {
+        Self {
+            syntax_set: self.syntax_set.clone(),
+            // `ThemeSet` isn't `Clone`, but its themes are
+            theme_set: ThemeSet {
+                themes: self.theme_set.themes.clone(),
+            },
+            current_theme: self.current_theme.clone(),
+            line_states: self.line_states.clone(),
+            version: self.version,
+        }
+    }
+}
+
+impl SyntaxHighlighterInner {
+    /// Make sure the checkpoints belong to `language`. Returns false if there
+    /// is no syntax for it.
+    fn select_language(&mut self, language: &str) -> bool {
+        let Some(syntax) = self.syntax_set.find_syntax_by_name(language) else {
+            return false;
+        };
+        if self.line_states.states.is_empty() || self.line_states.language != language {
+            self.line_states.reset(language, syntax);
+            self.version += 1;
+        }
+        true
+    }
+
+    /// The current theme, or any theme if it is missing
+    fn theme(&self) -> Option<&Theme> {
+        self.theme_set
+            .themes
+            .get(&self.current_theme)
+            .or_else(|| self.theme_set.themes.values().next())
+    }
+}
+
+/// Parse one line, returning the scope operations and the state at the next line
+fn parse_line(
+    state: &LineState,
+    line: &str,
+    syntax_set: &SyntaxSet,
+) -> (Vec<(usize, ScopeStackOp)>, LineState) {
+    let mut parse_state = state.parse_state.clone();
+    // The bundled syntaxes expect each line to end with a newline
+    let ops = parse_state
+        .parse_line(&format!("{line}\n"), syntax_set)
+        .unwrap_or_default();
+    let mut scopes = state.scopes.clone();
+    for (_, op) in &ops {
+        scopes.apply(op).ok();
+    }
+    (
+        ops,
+        LineState {
+            parse_state,
+            scopes,
+        },
+    )
+}
+
+/// Whether `scopes` are inside a string or a comment
+pub fn is_string_or_comment(scopes: &ScopeStack) -> bool {
+    let string = Scope::new("string").unwrap();
+    let comment = Scope::new("comment").unwrap();
+    scopes
+        .as_slice()
+        .iter()
+        .any(|scope| string.is_prefix_of(*scope) || comment.is_prefix_of(*scope))
+}
+
+/// Split a line of `len` bytes into spans with the same scopes, given the
+/// state at its start and its scope operations
+fn scope_spans(state: &LineState, ops: &[(usize, ScopeStackOp)], len: usize) -> Vec<ScopeSpan> {
+    let mut spans = Vec::new();
+    let mut scopes = state.scopes.clone();
+    let mut start = 0;
+    for (index, op) in ops {
+        let index = (*index).min(len);
+        if index > start {
+            spans.push(ScopeSpan {
+                range: start..index,
+                scopes: scopes.clone(),
+            });
+            start = index;
+        }
+        scopes.apply(op).ok();
+    }
+    if len > start {
+        spans.push(ScopeSpan {
+            range: start..len,
+            scopes,
+        });
+    }
+    spans
+}
+
+/// Highlight one line starting from `state`, returning its runs and the state
+/// at the next line
+fn highlight_runs(
+    state: &LineState,
+    line: &str,
+    syntax_set: &SyntaxSet,
+    highlighter: &Highlighter,
+) -> (Vec<StyledRun>, LineState) {
+    let (ops, next) = parse_line(state, line, syntax_set);
+    (styled_runs(state, &ops, line, highlighter), next)
+}
+
+/// Runs for `line` with scope operations `ops`, starting from `state`
+fn styled_runs(
+    state: &LineState,
+    ops: &[(usize, ScopeStackOp)],
+    line: &str,
+    highlighter: &Highlighter,
+) -> Vec<StyledRun> {
+    let mut highlight_state = HighlightState::new(highlighter, state.scopes.clone());
+    let line_with_newline = format!("{line}\n");
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for (style, text) in
+        HighlightIterator::new(&mut highlight_state, ops, &line_with_newline, highlighter)
+    {
+        // Leave out the newline that was added for parsing
+        let end = (start + text.len()).min(line.len());
+        if end > start {
+            runs.push(StyledRun {
+                len: end - start,
+                style,
+            });
+        }
+        start += text.len();
+    }
+    runs
 }
 
-#[derive(Clone)]
 pub struct SyntaxHighlighter {
-    inner: Rc<RefCell<SyntaxHighlighterInner>>,
+    inner: Arc<Mutex<SyntaxHighlighterInner>>,
+}
+
+/// A copy starts with the same checkpoints but keeps its own from then on,
+/// since it is usually highlighting a copy of the buffer. Use
+/// [`SyntaxHighlighter::share`] for a handle to the same checkpoints.
+impl Clone for SyntaxHighlighter {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(self.inner().clone())),
+        }
+    }
 }
 
 impl SyntaxHighlighter {
@@ -32,36 +325,53 @@ impl SyntaxHighlighter {
             .unwrap_or_else(|| "Default".to_string());
 
         Self {
-            inner: Rc::new(RefCell::new(SyntaxHighlighterInner {
-                syntax_set,
+            inner: Arc::new(Mutex::new(SyntaxHighlighterInner {
+                syntax_set: Arc::new(syntax_set),
                 theme_set,
                 current_theme,
-                parse_states: HashMap::new(),
-                highlight_states: HashMap::new(),
+                line_states: LineStateCache::default(),
+                version: 0,
             })),
         }
     }
 
+    fn inner(&self) -> MutexGuard<'_, SyntaxHighlighterInner> {
+        // Every update leaves the state consistent, so it is still usable
+        // after a panic on another thread
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Another handle to this highlighter and its checkpoints, for
+    /// [`Self::highlight_snapshot`] on a background thread. Edits made through
+    /// either handle invalidate the checkpoints of both.
+    pub fn share(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Changes whenever cached parse state is invalidated. Pass it to
+    /// [`Self::highlight_snapshot`] along with a snapshot taken at the same time.
+    pub fn version(&self) -> usize {
+        self.inner().version
+    }
+
     pub fn set_theme(&mut self, theme_name: &str) {
-        let mut inner = self.inner.borrow_mut();
+        let mut inner = self.inner();
         if inner.theme_set.themes.contains_key(theme_name) {
+            // Checkpoints don't depend on the theme, so they stay valid
             inner.current_theme = theme_name.to_string();
-            inner.highlight_states.clear();
         }
     }
 
     pub fn available_themes(&self) -> Vec<String> {
-        self.inner
-            .borrow()
-            .theme_set
-            .themes
-            .keys()
-            .cloned()
-            .collect()
+        self.inner().theme_set.themes.keys().cloned().collect()
     }
 
     pub fn detect_language(&self, text: &str, file_extension: Option<&str>) -> Option<String> {
-        let inner = self.inner.borrow();
+        let inner = self.inner();
         if let Some(ext) = file_extension {
             if let Some(syntax) = inner.syntax_set.find_syntax_by_extension(ext) {
                 return Some(syntax.name.clone());
@@ -74,208 +384,253 @@ impl SyntaxHighlighter {
             .map(|s| s.name.clone())
     }
 
-    /// Clear cached highlighting state from a specific line onward.
-    /// This is useful for incremental re-highlighting when text changes.
-    pub fn clear_state_from_line(&mut self, line_number: usize, language: &str) {
-        let mut inner = self.inner.borrow_mut();
-
-        // Clear parse states for this language from this line onward
-        // Since we don't track line numbers in parse_states directly,
-        // we need to clear it entirely for now
-        // TODO: Improve this to track line-specific states
-        if line_number == 0 {
-            inner.parse_states.remove(language);
+    /// The comment markers of `language`, from its syntax's metadata
+    pub fn comment_tokens(&self, language: &str) -> CommentTokens {
+        let inner = self.inner();
+        let Some(syntax) = inner.syntax_set.find_syntax_by_name(language) else {
+            return CommentTokens::default();
+        };
+        let metadata = inner
+            .syntax_set
+            .metadata()
+            .metadata_for_scope(&[syntax.scope]);
+        CommentTokens {
+            line: metadata.line_comment().map(str::to_string),
+            block: metadata
+                .block_comment()
+                .map(|(open, close)| (open.to_string(), close.to_string())),
         }
+    }
 
-        // Clear highlight states that might be affected
-        let cache_key = format!("{}-{}", language, inner.current_theme);
-        if line_number == 0 {
-            inner.highlight_states.remove(&cache_key);
+    /// Forget cached parse state after `line_number`.
+    ///
+    /// Use this when the text of `line_number` changed in a way the highlighter
+    /// wasn't told about through [`Self::edit_lines`].
+    pub fn clear_state_from_line(&mut self, line_number: usize, language: &str) {
+        let mut inner = self.inner();
+        if inner.line_states.language == language {
+            inner.line_states.truncate(line_number);
+            inner.version += 1;
         }
     }
 
+    /// Tell the highlighter that `old_rows` rows starting at `start_row` were
+    /// replaced by `new_rows` rows.
+    ///
+    /// Checkpoints after the edited rows are kept and reused once re-parsing
+    /// catches up with them in the same state.
+    pub fn edit_lines(&mut self, start_row: usize, old_rows: usize, new_rows: usize) {
+        let mut inner = self.inner();
+        inner.line_states.edit_lines(start_row, old_rows, new_rows);
+        inner.version += 1;
+    }
+
     /// Reset all cached highlighting state.
     /// Call this when the buffer content has significantly changed.
     pub fn reset_state(&mut self) {
-        let mut inner = self.inner.borrow_mut();
-        inner.parse_states.clear();
-        inner.highlight_states.clear();
+        let mut inner = self.inner();
+        inner.line_states = LineStateCache::default();
+        inner.version += 1;
+    }
+
+    /// Parse forward until the state at the start of `rows.end` is known.
+    ///
+    /// `line_at` returns the text of a row. Only rows between the last valid
+    /// checkpoint and `rows.end` are parsed, so calling this for the visible
+    /// rows of a large file is cheap once the rows above have been seen.
+    pub fn prepare_lines(
+        &mut self,
+        language: &str,
+        rows: Range<usize>,
+        line_at: impl Fn(usize) -> Option<String>,
+    ) {
+        let mut inner = self.inner();
+        if !inner.select_language(language) {
+            return;
+        }
+
+        let inner = &mut *inner;
+        while inner.line_states.valid <= rows.end.saturating_sub(1) {
+            let row = inner.line_states.valid - 1;
+            let Some(line) = line_at(row) else {
+                break;
+            };
+            let (row, state) = inner.line_states.nearest(row);
+            let (_, next) = parse_line(state, &line, &inner.syntax_set);
+            inner.line_states.record(row + 1, next);
+        }
+    }
+
+    /// The scopes in effect just before byte `index` of row `row`, whose text
+    /// is `line`.
+    ///
+    /// Parses the rows above as needed, with `line_at` returning the text of a
+    /// row. Returns `None` if there is no syntax for `language`.
+    pub fn scopes_at(
+        &mut self,
+        language: &str,
+        row: usize,
+        line: &str,
+        index: usize,
+        line_at: impl Fn(usize) -> Option<String>,
+    ) -> Option<ScopeStack> {
+        self.prepare_lines(language, row..row + 1, line_at);
+        let inner = self.inner();
+        if inner.line_states.language != language {
+            return None;
+        }
+        let state = inner.line_states.get(row)?;
+        let (ops, _) = parse_line(state, line, &inner.syntax_set);
+        let mut scopes = state.scopes.clone();
+        for (_, op) in ops.iter().take_while(|(op_index, _)| *op_index < index) {
+            scopes.apply(op).ok();
+        }
+        Some(scopes)
+    }
+
+    /// The scopes of row `row`, whose text is `line`, in spans covering the
+    /// line in order.
+    ///
+    /// Parses the rows above as needed, with `line_at` returning the text of a
+    /// row. Returns `None` if there is no syntax for `language`.
+    pub fn line_scopes(
+        &mut self,
+        language: &str,
+        row: usize,
+        line: &str,
+        line_at: impl Fn(usize) -> Option<String>,
+    ) -> Option<Vec<ScopeSpan>> {
+        self.prepare_lines(language, row..row + 1, line_at);
+        let inner = self.inner();
+        if inner.line_states.language != language {
+            return None;
+        }
+        let state = inner.line_states.get(row)?;
+        let (ops, _) = parse_line(state, line, &inner.syntax_set);
+        Some(scope_spans(state, &ops, line.len()))
     }
 
+    /// Highlight one line, returning its runs and scopes.
+    ///
+    /// Uses the checkpoint at `line_number`. Lines highlighted in order from the
+    /// top always have one; otherwise call [`Self::prepare_lines`] first, or the
+    /// nearest earlier checkpoint is used as an approximation.
     pub fn highlight_line(
         &mut self,
         line: &str,
         language: &str,
         line_number: usize,
         font_family: SharedString,
-        font_size: f32,
-    ) -> Vec<TextRun> {
-        let mut inner = self.inner.borrow_mut();
+        _font_size: f32,
+    ) -> HighlightedLine {
+        let mut inner = self.inner();
+        let plain = |font_family| HighlightedLine {
+            runs: vec![plain_run(
+                line.len(),
+                font_family,
+                gpui::rgb(DEFAULT_FOREGROUND).into(),
+            )],
+            scopes: Vec::new(),
+        };
 
         // First, check if we have the syntax
-        let has_syntax = inner.syntax_set.find_syntax_by_name(language).is_some();
-        if !has_syntax {
+        if !inner.select_language(language) {
             // Fallback to plain text
-            return vec![TextRun {
-                len: line.len(),
-                font: Font {
-                    family: font_family,
-                    features: Default::default(),
-                    weight: FontWeight::NORMAL,
-                    style: FontStyle::Normal,
-                    fallbacks: Default::default(),
-                },
-                color: gpui::rgb(0xcccccc).into(),
-                background_color: None,
-                underline: None,
-                strikethrough: None,
-            }];
+            return plain(font_family);
         }
 
-        let cache_key = format!("{}-{}", language, inner.current_theme);
-        let parse_state_key = language.to_string();
-
-        // Clear states if starting fresh
-        if line_number == 0 {
-            inner.parse_states.remove(&parse_state_key);
-            inner.highlight_states.remove(&cache_key);
-        }
-
-        // Get or create parse state - we already checked syntax exists above
-        let syntax = inner
-            .syntax_set
-            .find_syntax_by_name(language)
-            .expect("syntax should exist after check above");
-
-        let mut parse_state = if line_number == 0 {
-            ParseState::new(syntax)
-        } else if let Some(state) = inner.parse_states.get(&parse_state_key) {
-            state.clone()
-        } else {
-            ParseState::new(syntax)
-        };
-
         // Get the theme, with fallback to default colors if theme not found
-        let theme = inner
-            .theme_set
-            .themes
-            .get(&inner.current_theme)
-            .or_else(|| inner.theme_set.themes.values().next());
-
-        if theme.is_none() {
+        let inner = &mut *inner;
+        let Some(theme) = inner.theme() else {
             // No themes available at all, return plain text
-            return vec![TextRun {
-                len: line.len(),
-                font: Font {
-                    family: font_family,
-                    features: Default::default(),
-                    weight: FontWeight::NORMAL,
-                    style: FontStyle::Normal,
-                    fallbacks: Default::default(),
-                },
-                color: gpui::rgb(0xcccccc).into(),
-                background_color: None,
-                underline: None,
-                strikethrough: None,
-            }];
-        }
-
-        let theme = theme.expect("theme should exist after check above");
+            return plain(font_family);
+        };
         let highlighter = Highlighter::new(theme);
 
-        let ops = parse_state
-            .parse_line(line, &inner.syntax_set)
-            .unwrap_or_default();
-
-        let mut highlight_state = if line_number == 0 {
-            HighlightState::new(&highlighter, ScopeStack::new())
-        } else if let Some(state) = inner.highlight_states.get(&cache_key) {
-            state.clone()
-        } else {
-            HighlightState::new(&highlighter, ScopeStack::new())
+        let (_, state) = inner.line_states.nearest(line_number);
+        let exact = inner.line_states.get(line_number).is_some();
+        let (ops, next) = parse_line(state, line, &inner.syntax_set);
+        let runs = styled_runs(state, &ops, line, &highlighter);
+        let highlighted = HighlightedLine {
+            runs: text_runs(
+                &runs,
+                line,
+                font_family,
+                gpui::rgb(DEFAULT_FOREGROUND).into(),
+            ),
+            scopes: scope_spans(state, &ops, line.len()),
         };
 
-        let mut text_runs = Vec::new();
-        let mut current_pos = 0;
+        // The state after this line is the checkpoint for the next one
+        if exact {
+            inner.line_states.record(line_number + 1, next);
+        }
 
-        let ranges: Vec<(Style, usize, usize)> =
-            HighlightIterator::new(&mut highlight_state, &ops, line, &highlighter)
-                .map(|(style, text)| {
-                    let start = current_pos;
-                    let end = current_pos + text.len();
-                    current_pos = end;
-                    (style, start, end)
-                })
-                .collect();
+        highlighted
+    }
 
-        for (style, start, end) in ranges {
-            let len = end - start;
-            if len == 0 {
-                continue;
+    /// Highlight `rows` of `snapshot`, which may be done on any thread.
+    ///
+    /// `version` is [`Self::version`] from when the snapshot was taken. Parse
+    /// state is shared with the highlighter's other users, so if it has been
+    /// invalidated since (the buffer was edited, or the language changed) the
+    /// rows no longer match the snapshot and this gives up, returning `None`.
+    /// The lock is only held between lines, so the editor isn't blocked while
+    /// this runs.
+    pub fn highlight_snapshot(
+        &self,
+        snapshot: &BufferSnapshot,
+        language: &str,
+        version: usize,
+        rows: Range<usize>,
+    ) -> Option<Vec<Vec<StyledRun>>> {
+        let line_count = snapshot.line_count();
+        let rows = rows.start.min(line_count)..rows.end.min(line_count);
+        let mut version = version;
+        let (syntax_set, theme, mut row) = {
+            let mut inner = self.inner();
+            if inner.version != version || !inner.select_language(language) {
+                return None;
             }
+            // Switching to `language` may have reset the checkpoints, which
+            // doesn't affect the rows of the snapshot
+            version = inner.version;
+            let theme = inner.theme()?.clone();
+            let (row, _) = inner.line_states.nearest(rows.start);
+            (inner.syntax_set.clone(), theme, row)
+        };
+        let highlighter = Highlighter::new(&theme);
 
-            let color = style_to_hsla(style);
-            let (weight, font_style) = get_font_style(style);
-
-            text_runs.push(TextRun {
-                len,
-                font: Font {
-                    family: font_family.clone(),
-                    features: Default::default(),
-                    weight,
-                    style: font_style,
-                    fallbacks: Default::default(),
-                },
-                color,
-                background_color: if style.background != style.foreground {
-                    Some(style_color_to_hsla(style.background))
-                } else {
-                    None
-                },
-                underline: if style
-                    .font_style
-                    .contains(syntect::highlighting::FontStyle::UNDERLINE)
-                {
-                    Some(Default::default())
-                } else {
-                    None
-                },
-                strikethrough: None,
-            });
-        }
-
-        if text_runs.is_empty() {
-            text_runs.push(TextRun {
-                len: line.len(),
-                font: Font {
-                    family: font_family,
-                    features: Default::default(),
-                    weight: FontWeight::NORMAL,
-                    style: FontStyle::Normal,
-                    fallbacks: Default::default(),
-                },
-                color: gpui::rgb(0xcccccc).into(),
-                background_color: None,
-                underline: None,
-                strikethrough: None,
-            });
-        }
+        let mut lines = Vec::with_capacity(rows.len());
+        while row < rows.end {
+            let state = {
+                let inner = self.inner();
+                if inner.version != version {
+                    return None;
+                }
+                inner.line_states.get(row)?.clone()
+            };
 
-        // Store parse state for next line
-        let new_parse_state = parse_state
-            .parse_line(line, &inner.syntax_set)
-            .map(|_| parse_state.clone())
-            .unwrap_or_else(|_| ParseState::new(syntax));
-        inner.parse_states.insert(parse_state_key, new_parse_state);
+            let line = snapshot.line(row).unwrap_or_default();
+            let (runs, next) = highlight_runs(&state, &line, &syntax_set, &highlighter);
+            if row >= rows.start {
+                lines.push(runs);
+            }
 
-        // Store highlight state for next line - it was already mutated by the iterator
-        inner.highlight_states.insert(cache_key, highlight_state);
+            let mut inner = self.inner();
+            if inner.version != version {
+                return None;
+            }
+            inner.line_states.record(row + 1, next);
+            // Skip ahead if the rest of the way to `rows` is already known
+            row = (row + 1).max(inner.line_states.nearest(rows.start).0);
+        }
 
-        text_runs
+        Some(lines)
     }
 
     pub fn get_theme_background(&self) -> Hsla {
-        let inner = self.inner.borrow();
+        let inner = self.inner();
         inner
             .theme_set
             .themes
@@ -286,7 +641,7 @@ impl SyntaxHighlighter {
     }
 
     pub fn get_theme_foreground(&self) -> Hsla {
-        let inner = self.inner.borrow();
+        let inner = self.inner();
         inner
             .theme_set
             .themes
@@ -297,7 +652,7 @@ impl SyntaxHighlighter {
     }
 
     pub fn get_theme_gutter_background(&self) -> Hsla {
-        let inner = self.inner.borrow();
+        let inner = self.inner();
         inner
             .theme_set
             .themes
@@ -316,7 +671,7 @@ impl SyntaxHighlighter {
     }
 
     pub fn get_theme_line_highlight(&self) -> Hsla {
-        let inner = self.inner.borrow();
+        let inner = self.inner();
         inner
             .theme_set
             .themes
@@ -331,7 +686,7 @@ impl SyntaxHighlighter {
     }
 
     pub fn get_theme_selection(&self) -> Hsla {
-        let inner = self.inner.borrow();
+        let inner = self.inner();
         inner
             .theme_set
             .themes
@@ -364,7 +719,7 @@ impl SyntaxHighlighter {
             .unwrap_or("custom")
             .to_string();
 
-        let mut inner = self.inner.borrow_mut();
+        let mut inner = self.inner();
         inner.theme_set.themes.insert(theme_name.clone(), theme);
         inner.current_theme = theme_name;
 
@@ -375,25 +730,107 @@ impl SyntaxHighlighter {
     // Example: highlighter.load_syntax_from_file("./syntaxes/mylang.sublime-syntax")
     #[allow(dead_code)]
     pub fn load_syntax_from_file(&mut self, path: &str) -> Result<(), String> {
-        let mut inner = self.inner.borrow_mut();
+        let mut inner = self.inner();
         let mut builder = syntect::parsing::SyntaxSetBuilder::new();
         builder
             .add_from_folder(path, true)
             .map_err(|e| format!("Failed to load syntax: {}", e))?;
 
         // Merge with existing syntaxes
-        for syntax in inner.syntax_set.syntaxes() {
+        for _syntax in inner.syntax_set.syntaxes() {
             builder.add_plain_text_syntax();
         }
 
-        inner.syntax_set = builder.build();
-        inner.parse_states.clear();
-        inner.highlight_states.clear();
+        inner.syntax_set = Arc::new(builder.build());
+        inner.line_states = LineStateCache::default();
+        inner.version += 1;
 
         Ok(())
     }
 }
 
+/// Text color used when there is no theme to take it from
+const DEFAULT_FOREGROUND: u32 = 0xcccccc;
+
+/// Turn styled runs into gpui text runs for `line`.
+///
+/// The runs may have been computed for an older version of the line, so they
+/// are clipped to the line and their ends moved to char boundaries. Text they
+/// don't cover takes the style of the last run, or `color` if there are no
+/// runs at all.
+pub fn text_runs(
+    runs: &[StyledRun],
+    line: &str,
+    font_family: SharedString,
+    color: Hsla,
+) -> Vec<TextRun> {
+    let mut text_runs = Vec::with_capacity(runs.len());
+    let mut start = 0;
+    for run in runs {
+        let mut end = (start + run.len).min(line.len());
+        while !line.is_char_boundary(end) {
+            end += 1;
+        }
+        if end > start {
+            text_runs.push(styled_text_run(end - start, run.style, &font_family));
+        }
+        start = end;
+    }
+
+    match text_runs.last_mut() {
+        Some(last) => last.len += line.len() - start,
+        None => text_runs.push(plain_run(line.len(), font_family, color)),
+    }
+    text_runs
+}
+
+fn styled_text_run(len: usize, style: Style, font_family: &SharedString) -> TextRun {
+    let (weight, font_style) = get_font_style(style);
+    TextRun {
+        len,
+        font: Font {
+            family: font_family.clone(),
+            features: Default::default(),
+            weight,
+            style: font_style,
+            fallbacks: Default::default(),
+        },
+        color: style_to_hsla(style),
+        background_color: if style.background != style.foreground {
+            Some(style_color_to_hsla(style.background))
+        } else {
+            None
+        },
+        underline: if style
+            .font_style
+            .contains(syntect::highlighting::FontStyle::UNDERLINE)
+        {
+            Some(Default::default())
+        } else {
+            None
+        },
+        strikethrough: None,
+    }
+}
+
+/// A single unstyled run covering `len` bytes
+fn plain_run(len: usize, font_family: SharedString, color: Hsla) -> TextRun {
+    TextRun {
+        len,
+        font: Font {
+            family: font_family,
+            features: Default::default(),
+            weight: FontWeight::NORMAL,
+            style: FontStyle::Normal,
+            fallbacks: Default::default(),
+        },
+        color,
+        background_color: None,
+        underline: None,
+        strikethrough: None,
+    }
+}
+
 fn style_color_to_hsla(color: syntect::highlighting::Color) -> Hsla {
     gpui::rgba(
         ((color.r as u32) << 24)
@@ -469,3 +906,202 @@ impl Default for SyntaxHighlighter {
 //    - base16-ocean.dark, base16-ocean.light
 //    - base16-mocha.dark, base16-eighties.dark
 //    - InspiredGitHub, Solarized (dark), Solarized (light)
+
+#[cfg(test)]
+impl SyntaxHighlighter {
+    /// Number of leading rows with a checkpoint known to be correct
+    fn valid_rows(&self) -> usize {
+        self.inner().line_states.valid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors(highlighter: &mut SyntaxHighlighter, lines: &[String], row: usize) -> Vec<Hsla> {
+        highlighter.prepare_lines("Rust", 0..row + 1, |row| lines.get(row).cloned());
+        highlighter
+            .highlight_line(&lines[row], "Rust", row, "Mono".into(), 14.0)
+            .runs
+            .iter()
+            .map(|run| run.color)
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_changes_following_lines() {
+        let mut highlighter = SyntaxHighlighter::new();
+        let mut lines: Vec<String> = ["fn a() {}", "let x = 1;", "let y = 2;"]
+            .map(String::from)
+            .to_vec();
+        let before = colors(&mut highlighter, &lines, 2);
+        assert!(before.len() > 1);
+
+        // Opening a block comment turns everything after it into a comment
+        lines[0] = "/* fn a() {}".to_string();
+        highlighter.edit_lines(0, 1, 1);
+        let after = colors(&mut highlighter, &lines, 2);
+        assert_eq!(after.len(), 1);
+        assert_ne!(before, after);
+    }
+
+    #[test]
+    fn test_scopes_at_strings_and_comments() {
+        let mut highlighter = SyntaxHighlighter::new();
+        let lines = ["/* a", "b */ let s = \"x\";"].map(String::from);
+        let mut in_string_or_comment = |row: usize, index: usize| {
+            let scopes = highlighter
+                .scopes_at("Rust", row, &lines[row], index, |row| {
+                    lines.get(row).cloned()
+                })
+                .unwrap();
+            is_string_or_comment(&scopes)
+        };
+
+        // The block comment carries over from the line above
+        assert!(in_string_or_comment(1, 2));
+        assert!(!in_string_or_comment(1, 8));
+        assert!(in_string_or_comment(1, 15));
+        assert!(!in_string_or_comment(1, 17));
+    }
+
+    #[test]
+    fn test_reparse_stops_when_state_converges() {
+        let mut highlighter = SyntaxHighlighter::new();
+        let mut lines = vec!["let x = 1;".to_string(); 100];
+        colors(&mut highlighter, &lines, 99);
+        // Includes the state after the last line
+        assert_eq!(highlighter.valid_rows(), 101);
+
+        lines[50] = "let y = 2;".to_string();
+        highlighter.edit_lines(50, 1, 1);
+        assert_eq!(highlighter.valid_rows(), 51);
+
+        // Parsing the edited line reproduces the old state after it, so the
+        // rest of the checkpoints are reused without parsing them again
+        colors(&mut highlighter, &lines, 51);
+        assert_eq!(highlighter.valid_rows(), 101);
+
+        // Inserting lines shifts the old checkpoints down
+        lines.splice(10..11, ["let a = 1;".to_string(), "let b = 2;".to_string()]);
+        highlighter.edit_lines(10, 1, 2);
+        colors(&mut highlighter, &lines, 12);
+        assert_eq!(highlighter.valid_rows(), 102);
+    }
+
+    #[test]
+    fn test_highlight_snapshot_on_another_thread() {
+        let lines: Vec<String> = ["/* a", "b */", "let c = 1;"].map(String::from).to_vec();
+        let mut expected = Vec::new();
+        let mut sequential = SyntaxHighlighter::new();
+        for row in 0..lines.len() {
+            expected.push(colors(&mut sequential, &lines, row));
+        }
+
+        let highlighter = SyntaxHighlighter::new();
+        let snapshot = BufferSnapshot::new(&lines.join("\n"));
+        let version = highlighter.version();
+        let background = highlighter.share();
+        let highlighted = std::thread::spawn(move || {
+            background.highlight_snapshot(&snapshot, "Rust", version, 1..3)
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+
+        let highlighted: Vec<Vec<Hsla>> = highlighted
+            .iter()
+            .zip(&lines[1..])
+            .map(|(runs, line)| {
+                text_runs(runs, line, "Mono".into(), gpui::black())
+                    .iter()
+                    .map(|run| run.color)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(highlighted, expected[1..]);
+    }
+
+    #[test]
+    fn test_highlight_snapshot_after_edit() {
+        let mut highlighter = SyntaxHighlighter::new();
+        let snapshot = BufferSnapshot::new("let a = 1;");
+        let version = highlighter.version();
+
+        highlighter.edit_lines(0, 1, 1);
+        assert_eq!(
+            highlighter.highlight_snapshot(&snapshot, "Rust", version, 0..1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_text_runs_fit_changed_line() {
+        let run = |len| StyledRun {
+            len,
+            style: Style::default(),
+        };
+        let lens = |runs: Vec<TextRun>| runs.iter().map(|run| run.len).collect::<Vec<_>>();
+
+        // Runs for "ab" on a line that grew: the last run covers the rest
+        assert_eq!(
+            lens(text_runs(
+                &[run(1), run(1)],
+                "abcd",
+                "Mono".into(),
+                gpui::black()
+            )),
+            vec![1, 3]
+        );
+        // Runs for "abcd" on "a日": ends move off the middle of "日"
+        assert_eq!(
+            lens(text_runs(
+                &[run(2), run(2)],
+                "a日",
+                "Mono".into(),
+                gpui::black()
+            )),
+            vec![4]
+        );
+        // No runs yet
+        assert_eq!(
+            lens(text_runs(&[], "abc", "Mono".into(), gpui::black())),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn test_highlight_arbitrary_line() {
+        let mut lines = vec!["/* start".to_string()];
+        lines.extend((1..500).map(|i| format!("let x{i} = {i};")));
+
+        let mut sequential = SyntaxHighlighter::new();
+        let mut expected = Vec::new();
+        for row in 0..lines.len() {
+            expected = colors(&mut sequential, &lines, row);
+        }
+
+        // Jumping straight to the last line gives the same result
+        let mut highlighter = SyntaxHighlighter::new();
+        assert_eq!(colors(&mut highlighter, &lines, 499), expected);
+        assert_eq!(expected.len(), 1);
+    }
+
+    #[test]
+    fn test_comment_tokens() {
+        let highlighter = SyntaxHighlighter::new();
+        let tokens = highlighter.comment_tokens("Python");
+        assert_eq!(tokens.line.as_deref(), Some("# "));
+        let tokens = highlighter.comment_tokens("HTML");
+        assert_eq!(tokens.line, None);
+        assert_eq!(
+            tokens.block,
+            Some(("<!-- ".to_string(), " -->".to_string()))
+        );
+        assert_eq!(
+            highlighter.comment_tokens("Plain Text"),
+            CommentTokens::default()
+        );
+    }
+}
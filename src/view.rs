@@ -0,0 +1,856 @@
+//! A complete, focusable editor view.
+//!
+//! [`EditorView`] owns an [`Editor`], renders it with an [`EditorElement`] and
+//! handles the actions in [`crate::actions`], the clipboard, the mouse and
+//! platform text input. Call [`bind_default_keys`](crate::actions::bind_default_keys)
+//! once at startup to get the standard key bindings.
+
+use crate::actions::*;
+use crate::buffer::{GapBuffer, TextBuffer};
+use crate::completion;
+use crate::editor::Editor;
+use crate::element::EditorElement;
+use crate::line_ops::SortOptions;
+use gpui::*;
+use std::ops::Range;
+
+/// An [`Editor`] wrapped in a GPUI view with keyboard, mouse and IME handling
+pub struct EditorView<B = GapBuffer> {
+    focus_handle: FocusHandle,
+    editor: Editor<B>,
+}
+
+impl<B: TextBuffer + Clone + 'static> EditorView<B> {
+    pub fn new(editor: Editor<B>, cx: &mut Context<Self>) -> Self {
+        Self {
+            focus_handle: cx.focus_handle(),
+            editor,
+        }
+    }
+
+    pub fn editor(&self) -> &Editor<B> {
+        &self.editor
+    }
+
+    /// Get mutable access to the editor. Call `cx.notify()` after changing it.
+    pub fn editor_mut(&mut self) -> &mut Editor<B> {
+        &mut self.editor
+    }
+
+    // Action handlers
+
+    fn move_up(&mut self, _: &MoveUp, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.editor.select_previous_completion() {
+            cx.notify();
+            return;
+        }
+        self.editor.move_up(false);
+        cx.notify();
+    }
+
+    fn move_down(&mut self, _: &MoveDown, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.editor.select_next_completion() {
+            cx.notify();
+            return;
+        }
+        self.editor.move_down(false);
+        cx.notify();
+    }
+
+    fn move_left(&mut self, _: &MoveLeft, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.move_left(false);
+        cx.notify();
+    }
+
+    fn move_right(&mut self, _: &MoveRight, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.move_right(false);
+        cx.notify();
+    }
+
+    fn select_up(&mut self, _: &SelectUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.move_up(true);
+        cx.notify();
+    }
+
+    fn select_down(&mut self, _: &SelectDown, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.move_down(true);
+        cx.notify();
+    }
+
+    fn select_left(&mut self, _: &SelectLeft, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.move_left(true);
+        cx.notify();
+    }
+
+    fn select_right(&mut self, _: &SelectRight, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.move_right(true);
+        cx.notify();
+    }
+
+    fn move_to_previous_word_start(
+        &mut self,
+        _: &MoveToPreviousWordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_previous_word_start(false);
+        cx.notify();
+    }
+
+    fn move_to_next_word_end(
+        &mut self,
+        _: &MoveToNextWordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_next_word_end(false);
+        cx.notify();
+    }
+
+    fn move_to_previous_subword_start(
+        &mut self,
+        _: &MoveToPreviousSubwordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_previous_subword_start(false);
+        cx.notify();
+    }
+
+    fn move_to_next_subword_end(
+        &mut self,
+        _: &MoveToNextSubwordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_next_subword_end(false);
+        cx.notify();
+    }
+
+    fn move_to_line_start(
+        &mut self,
+        _: &MoveToLineStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_line_start(false);
+        cx.notify();
+    }
+
+    fn move_to_line_end(
+        &mut self,
+        _: &MoveToLineEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_line_end(false);
+        cx.notify();
+    }
+
+    fn move_to_document_start(
+        &mut self,
+        _: &MoveToDocumentStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_document_start(false);
+        cx.notify();
+    }
+
+    fn move_to_document_end(
+        &mut self,
+        _: &MoveToDocumentEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_document_end(false);
+        cx.notify();
+    }
+
+    fn page_up(&mut self, _: &PageUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.page_up(false);
+        cx.notify();
+    }
+
+    fn page_down(&mut self, _: &PageDown, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.page_down(false);
+        cx.notify();
+    }
+
+    fn select_to_previous_word_start(
+        &mut self,
+        _: &SelectToPreviousWordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_previous_word_start(true);
+        cx.notify();
+    }
+
+    fn select_to_next_word_end(
+        &mut self,
+        _: &SelectToNextWordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_next_word_end(true);
+        cx.notify();
+    }
+
+    fn select_to_previous_subword_start(
+        &mut self,
+        _: &SelectToPreviousSubwordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_previous_subword_start(true);
+        cx.notify();
+    }
+
+    fn select_to_next_subword_end(
+        &mut self,
+        _: &SelectToNextSubwordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_next_subword_end(true);
+        cx.notify();
+    }
+
+    fn select_to_line_start(
+        &mut self,
+        _: &SelectToLineStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_line_start(true);
+        cx.notify();
+    }
+
+    fn select_to_line_end(
+        &mut self,
+        _: &SelectToLineEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_line_end(true);
+        cx.notify();
+    }
+
+    fn select_to_document_start(
+        &mut self,
+        _: &SelectToDocumentStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_document_start(true);
+        cx.notify();
+    }
+
+    fn select_to_document_end(
+        &mut self,
+        _: &SelectToDocumentEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_document_end(true);
+        cx.notify();
+    }
+
+    fn select_page_up(&mut self, _: &SelectPageUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.page_up(true);
+        cx.notify();
+    }
+
+    fn select_page_down(
+        &mut self,
+        _: &SelectPageDown,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.page_down(true);
+        cx.notify();
+    }
+
+    fn select_all(&mut self, _: &SelectAll, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.select_all();
+        cx.notify();
+    }
+
+    fn tab(&mut self, _: &Tab, _window: &mut Window, cx: &mut Context<Self>) {
+        if !self.editor.confirm_completion() {
+            self.editor.tab();
+        }
+        cx.notify();
+    }
+
+    fn backtab(&mut self, _: &Backtab, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.backtab();
+        cx.notify();
+    }
+
+    fn indent(&mut self, _: &Indent, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.indent_selection();
+        cx.notify();
+    }
+
+    fn outdent(&mut self, _: &Outdent, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.outdent_selection();
+        cx.notify();
+    }
+
+    fn backspace(&mut self, _: &Backspace, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.backspace();
+        cx.notify();
+    }
+
+    fn delete(&mut self, _: &Delete, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.delete();
+        cx.notify();
+    }
+
+    fn delete_to_previous_word_start(
+        &mut self,
+        _: &DeleteToPreviousWordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.delete_to_previous_word_start();
+        cx.notify();
+    }
+
+    fn delete_to_next_word_end(
+        &mut self,
+        _: &DeleteToNextWordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.delete_to_next_word_end();
+        cx.notify();
+    }
+
+    fn delete_to_previous_subword_start(
+        &mut self,
+        _: &DeleteToPreviousSubwordStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.delete_to_previous_subword_start();
+        cx.notify();
+    }
+
+    fn delete_to_next_subword_end(
+        &mut self,
+        _: &DeleteToNextSubwordEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.delete_to_next_subword_end();
+        cx.notify();
+    }
+
+    fn delete_to_line_start(
+        &mut self,
+        _: &DeleteToLineStart,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.delete_to_line_start();
+        cx.notify();
+    }
+
+    fn delete_to_line_end(
+        &mut self,
+        _: &DeleteToLineEnd,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.delete_to_line_end();
+        cx.notify();
+    }
+
+    fn newline(&mut self, _: &Newline, _window: &mut Window, cx: &mut Context<Self>) {
+        if !self.editor.confirm_completion() {
+            self.editor.insert_newline();
+        }
+        cx.notify();
+    }
+
+    fn cancel(&mut self, _: &Cancel, _window: &mut Window, cx: &mut Context<Self>) {
+        // Escape closes the completion menu first
+        if !self.editor.cancel_completions() {
+            if self.editor.selections().count() > 1 {
+                self.editor.clear_extra_cursors();
+            } else {
+                self.editor.clear_selection();
+            }
+        }
+        cx.notify();
+    }
+
+    fn copy(&mut self, _: &Copy, _window: &mut Window, cx: &mut Context<Self>) {
+        let selected_text = self.editor.get_selected_text();
+        if !selected_text.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(selected_text));
+        }
+    }
+
+    fn cut(&mut self, _: &Cut, _window: &mut Window, cx: &mut Context<Self>) {
+        let selected_text = self.editor.get_selected_text();
+        if !selected_text.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(selected_text));
+            self.editor.delete_selection();
+            cx.notify();
+        }
+    }
+
+    fn paste(&mut self, _: &Paste, _window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(text) = cx.read_from_clipboard().and_then(|item| item.text()) {
+            self.editor.paste(&text);
+            cx.notify();
+        }
+    }
+
+    fn undo(&mut self, _: &Undo, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.editor.undo() {
+            cx.notify();
+        }
+    }
+
+    fn redo(&mut self, _: &Redo, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.editor.redo() {
+            cx.notify();
+        }
+    }
+
+    fn add_cursor_above(
+        &mut self,
+        _: &AddCursorAbove,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.add_cursor_above();
+        cx.notify();
+    }
+
+    fn add_cursor_below(
+        &mut self,
+        _: &AddCursorBelow,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.add_cursor_below();
+        cx.notify();
+    }
+
+    fn add_next_occurrence(
+        &mut self,
+        _: &AddNextOccurrence,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.add_next_occurrence();
+        cx.notify();
+    }
+
+    fn select_next_match(
+        &mut self,
+        _: &SelectNextMatch,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if self.editor.select_next_match() {
+            cx.notify();
+        }
+    }
+
+    fn select_previous_match(
+        &mut self,
+        _: &SelectPreviousMatch,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        if self.editor.select_previous_match() {
+            cx.notify();
+        }
+    }
+
+    fn fold(&mut self, _: &Fold, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.editor.fold() {
+            cx.notify();
+        }
+    }
+
+    fn unfold(&mut self, _: &Unfold, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.editor.unfold() {
+            cx.notify();
+        }
+    }
+
+    fn fold_all(&mut self, _: &FoldAll, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.editor.fold_all() {
+            cx.notify();
+        }
+    }
+
+    fn unfold_all(&mut self, _: &UnfoldAll, _window: &mut Window, cx: &mut Context<Self>) {
+        if self.editor.unfold_all() {
+            cx.notify();
+        }
+    }
+
+    fn move_lines_up(&mut self, _: &MoveLinesUp, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.move_lines_up();
+        cx.notify();
+    }
+
+    fn move_lines_down(&mut self, _: &MoveLinesDown, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.move_lines_down();
+        cx.notify();
+    }
+
+    fn duplicate_lines(
+        &mut self,
+        _: &DuplicateLines,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.duplicate_lines();
+        cx.notify();
+    }
+
+    fn delete_lines(&mut self, _: &DeleteLines, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.delete_lines();
+        cx.notify();
+    }
+
+    fn join_lines(&mut self, _: &JoinLines, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.join_lines();
+        cx.notify();
+    }
+
+    fn sort_lines(&mut self, _: &SortLines, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.sort_lines(SortOptions::new());
+        cx.notify();
+    }
+
+    fn sort_lines_case_insensitive(
+        &mut self,
+        _: &SortLinesCaseInsensitive,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.sort_lines(SortOptions::new().ignore_case(true));
+        cx.notify();
+    }
+
+    fn reverse_lines(&mut self, _: &ReverseLines, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.reverse_lines();
+        cx.notify();
+    }
+
+    fn trim_trailing_whitespace(
+        &mut self,
+        _: &TrimTrailingWhitespace,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.trim_trailing_whitespace();
+        cx.notify();
+    }
+
+    fn toggle_line_comment(
+        &mut self,
+        _: &ToggleLineComment,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.toggle_line_comment();
+        cx.notify();
+    }
+
+    fn toggle_block_comment(
+        &mut self,
+        _: &ToggleBlockComment,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.toggle_block_comment();
+        cx.notify();
+    }
+
+    fn move_to_matching_bracket(
+        &mut self,
+        _: &MoveToMatchingBracket,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.move_to_matching_bracket();
+        cx.notify();
+    }
+
+    fn select_to_enclosing_brackets(
+        &mut self,
+        _: &SelectToEnclosingBrackets,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.select_to_enclosing_brackets();
+        cx.notify();
+    }
+
+    fn next_diagnostic(
+        &mut self,
+        _: &NextDiagnostic,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.next_diagnostic();
+        cx.notify();
+    }
+
+    fn previous_diagnostic(
+        &mut self,
+        _: &PreviousDiagnostic,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.previous_diagnostic();
+        cx.notify();
+    }
+
+    fn show_completions(
+        &mut self,
+        _: &ShowCompletions,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.show_completions(cx);
+        cx.notify();
+    }
+
+    /// Ask for completions when a word starts being typed. While the menu is
+    /// open, typing more of the word narrows it instead.
+    fn complete_typed_text(&mut self, text: &str, cx: &mut Context<Self>) {
+        if !text.is_empty()
+            && text.chars().all(completion::is_word_char)
+            && self.editor.has_completion_provider()
+            && !self.editor.has_open_completions()
+        {
+            self.editor.show_completions(cx);
+        }
+    }
+
+    // Mouse handlers
+
+    fn mouse_down(&mut self, event: &MouseDownEvent, window: &mut Window, cx: &mut Context<Self>) {
+        window.focus(&self.focus_handle);
+        // Clicking a fold indicator toggles the fold instead of moving the caret
+        if let Some(row) = self.editor.gutter_row_for_point(event.position) {
+            if self.editor.toggle_fold(row) {
+                cx.notify();
+                return;
+            }
+        }
+        if let Some(position) = self.editor.position_for_point(event.position) {
+            self.editor
+                .mouse_down(position, event.click_count, event.modifiers.shift);
+            cx.notify();
+        }
+    }
+
+    fn mouse_move(&mut self, event: &MouseMoveEvent, _window: &mut Window, cx: &mut Context<Self>) {
+        if !self.editor.is_selecting_with_mouse() || event.pressed_button != Some(MouseButton::Left)
+        {
+            if self.editor.mouse_hover(event.position) {
+                cx.notify();
+            }
+            return;
+        }
+        if let Some(position) = self.editor.position_for_point(event.position) {
+            self.editor.mouse_drag(position);
+            cx.notify();
+        }
+    }
+
+    fn mouse_up(&mut self, _: &MouseUpEvent, _window: &mut Window, _cx: &mut Context<Self>) {
+        self.editor.mouse_up();
+    }
+}
+
+impl<B: TextBuffer + Clone + 'static> Focusable for EditorView<B> {
+    fn focus_handle(&self, _cx: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl<B: TextBuffer + Clone + 'static> EntityInputHandler for EditorView<B> {
+    fn text_for_range(
+        &mut self,
+        range: Range<usize>,
+        adjusted_range: &mut Option<Range<usize>>,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<String> {
+        let (text, range) = self.editor.text_for_utf16_range(range);
+        *adjusted_range = Some(range);
+        Some(text)
+    }
+
+    fn selected_text_range(
+        &mut self,
+        _ignore_disabled_input: bool,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<UTF16Selection> {
+        let (range, reversed) = self.editor.selected_utf16_range();
+        Some(UTF16Selection { range, reversed })
+    }
+
+    fn marked_text_range(
+        &self,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<Range<usize>> {
+        self.editor.marked_utf16_range()
+    }
+
+    fn unmark_text(&mut self, _window: &mut Window, cx: &mut Context<Self>) {
+        self.editor.unmark_text();
+        cx.notify();
+    }
+
+    fn replace_text_in_range(
+        &mut self,
+        range: Option<Range<usize>>,
+        text: &str,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor.replace_text_in_utf16_range(range, text);
+        self.complete_typed_text(text, cx);
+        cx.notify();
+    }
+
+    fn replace_and_mark_text_in_range(
+        &mut self,
+        range: Option<Range<usize>>,
+        new_text: &str,
+        new_selected_range: Option<Range<usize>>,
+        _window: &mut Window,
+        cx: &mut Context<Self>,
+    ) {
+        self.editor
+            .replace_and_mark_text_in_utf16_range(range, new_text, new_selected_range);
+        cx.notify();
+    }
+
+    fn bounds_for_range(
+        &mut self,
+        range_utf16: Range<usize>,
+        _element_bounds: Bounds<Pixels>,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<Bounds<Pixels>> {
+        self.editor.bounds_for_utf16_range(range_utf16)
+    }
+
+    fn character_index_for_point(
+        &mut self,
+        point: Point<Pixels>,
+        _window: &mut Window,
+        _cx: &mut Context<Self>,
+    ) -> Option<usize> {
+        self.editor.utf16_offset_for_point(point)
+    }
+}
+
+impl<B: TextBuffer + Clone + 'static> Render for EditorView<B> {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .key_context(KEY_CONTEXT)
+            .track_focus(&self.focus_handle)
+            .size_full()
+            .on_action(cx.listener(Self::move_up))
+            .on_action(cx.listener(Self::move_down))
+            .on_action(cx.listener(Self::move_left))
+            .on_action(cx.listener(Self::move_right))
+            .on_action(cx.listener(Self::select_up))
+            .on_action(cx.listener(Self::select_down))
+            .on_action(cx.listener(Self::select_left))
+            .on_action(cx.listener(Self::select_right))
+            .on_action(cx.listener(Self::move_to_previous_word_start))
+            .on_action(cx.listener(Self::move_to_next_word_end))
+            .on_action(cx.listener(Self::move_to_previous_subword_start))
+            .on_action(cx.listener(Self::move_to_next_subword_end))
+            .on_action(cx.listener(Self::move_to_line_start))
+            .on_action(cx.listener(Self::move_to_line_end))
+            .on_action(cx.listener(Self::move_to_document_start))
+            .on_action(cx.listener(Self::move_to_document_end))
+            .on_action(cx.listener(Self::page_up))
+            .on_action(cx.listener(Self::page_down))
+            .on_action(cx.listener(Self::select_to_previous_word_start))
+            .on_action(cx.listener(Self::select_to_next_word_end))
+            .on_action(cx.listener(Self::select_to_previous_subword_start))
+            .on_action(cx.listener(Self::select_to_next_subword_end))
+            .on_action(cx.listener(Self::select_to_line_start))
+            .on_action(cx.listener(Self::select_to_line_end))
+            .on_action(cx.listener(Self::select_to_document_start))
+            .on_action(cx.listener(Self::select_to_document_end))
+            .on_action(cx.listener(Self::select_page_up))
+            .on_action(cx.listener(Self::select_page_down))
+            .on_action(cx.listener(Self::select_all))
+            .on_action(cx.listener(Self::tab))
+            .on_action(cx.listener(Self::backtab))
+            .on_action(cx.listener(Self::indent))
+            .on_action(cx.listener(Self::outdent))
+            .on_action(cx.listener(Self::backspace))
+            .on_action(cx.listener(Self::delete))
+            .on_action(cx.listener(Self::delete_to_previous_word_start))
+            .on_action(cx.listener(Self::delete_to_next_word_end))
+            .on_action(cx.listener(Self::delete_to_previous_subword_start))
+            .on_action(cx.listener(Self::delete_to_next_subword_end))
+            .on_action(cx.listener(Self::delete_to_line_start))
+            .on_action(cx.listener(Self::delete_to_line_end))
+            .on_action(cx.listener(Self::newline))
+            .on_action(cx.listener(Self::cancel))
+            .on_action(cx.listener(Self::copy))
+            .on_action(cx.listener(Self::cut))
+            .on_action(cx.listener(Self::paste))
+            .on_action(cx.listener(Self::undo))
+            .on_action(cx.listener(Self::redo))
+            .on_action(cx.listener(Self::add_cursor_above))
+            .on_action(cx.listener(Self::add_cursor_below))
+            .on_action(cx.listener(Self::add_next_occurrence))
+            .on_action(cx.listener(Self::select_next_match))
+            .on_action(cx.listener(Self::select_previous_match))
+            .on_action(cx.listener(Self::fold))
+            .on_action(cx.listener(Self::unfold))
+            .on_action(cx.listener(Self::fold_all))
+            .on_action(cx.listener(Self::unfold_all))
+            .on_action(cx.listener(Self::move_lines_up))
+            .on_action(cx.listener(Self::move_lines_down))
+            .on_action(cx.listener(Self::duplicate_lines))
+            .on_action(cx.listener(Self::delete_lines))
+            .on_action(cx.listener(Self::join_lines))
+            .on_action(cx.listener(Self::sort_lines))
+            .on_action(cx.listener(Self::sort_lines_case_insensitive))
+            .on_action(cx.listener(Self::reverse_lines))
+            .on_action(cx.listener(Self::trim_trailing_whitespace))
+            .on_action(cx.listener(Self::toggle_line_comment))
+            .on_action(cx.listener(Self::toggle_block_comment))
+            .on_action(cx.listener(Self::move_to_matching_bracket))
+            .on_action(cx.listener(Self::select_to_enclosing_brackets))
+            .on_action(cx.listener(Self::next_diagnostic))
+            .on_action(cx.listener(Self::previous_diagnostic))
+            .on_action(cx.listener(Self::show_completions))
+            .on_mouse_down(MouseButton::Left, cx.listener(Self::mouse_down))
+            .on_mouse_move(cx.listener(Self::mouse_move))
+            .on_mouse_up(MouseButton::Left, cx.listener(Self::mouse_up))
+            .on_mouse_up_out(MouseButton::Left, cx.listener(Self::mouse_up))
+            .child(
+                EditorElement::new(self.editor.id().clone(), cx.entity())
+                    .with_input_handler(&self.focus_handle),
+            )
+    }
+}
@@ -0,0 +1,282 @@
+//! The LSP client against the fake server in `tests/support`.
+
+use futures::executor::block_on;
+use gpui_editor::lsp::{path_to_uri, LanguageServer, Location, LspError};
+use gpui_editor::{CursorPosition, DiagnosticSeverity, Editor};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn pos(row: usize, col: usize) -> CursorPosition {
+    CursorPosition::new(row, col)
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gpui-editor-lsp-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The fake server, built next to the tests as an example
+fn fake_server() -> PathBuf {
+    let deps = std::env::current_exe().unwrap();
+    let command = deps
+        .parent()
+        .and_then(Path::parent)
+        .unwrap()
+        .join("examples")
+        .join(format!("fake-lsp-server{}", std::env::consts::EXE_SUFFIX));
+    assert!(
+        command.exists(),
+        "{} is missing; build it with `cargo build --example fake-lsp-server`",
+        command.display()
+    );
+    command
+}
+
+fn start(root: &Path) -> LanguageServer {
+    block_on(LanguageServer::start(
+        fake_server(),
+        Vec::<&str>::new(),
+        root,
+    ))
+    .unwrap()
+}
+
+fn open(server: &mut LanguageServer, path: &Path, text: &str) -> Editor {
+    fs::write(path, text).unwrap();
+    let mut editor = Editor::open("editor", path).unwrap();
+    server.open(&mut editor).unwrap();
+    editor
+}
+
+/// The document as the server has it
+fn server_document(server: &LanguageServer, path: &Path) -> Value {
+    let params = json!({ "textDocument": { "uri": path_to_uri(path) } });
+    block_on(server.request("fake/text", params)).unwrap()
+}
+
+#[test]
+fn test_incremental_sync() {
+    let dir = temp_dir("sync");
+    let path = dir.join("main.rs");
+    let mut server = start(&dir);
+    let mut editor = open(&mut server, &path, "fn main() {\n    let 😀 = 1;\n}\n");
+
+    editor.set_cursor_position(pos(1, 13));
+    editor.insert_text("2");
+    editor.set_cursor_position(pos(1, 4));
+    editor.insert_newline();
+    editor.set_cursor_position(pos(0, 0));
+    editor.insert_text("// é\n");
+    editor.undo();
+    editor.set_cursor_position(pos(3, 1));
+    editor.backspace();
+    server.did_change(&mut editor).unwrap();
+
+    let document = server_document(&server, &path);
+    assert_eq!(document["text"], editor.get_buffer().to_string());
+    assert_eq!(document["version"], 1);
+
+    // Nothing to send leaves the version alone
+    server.did_change(&mut editor).unwrap();
+    assert_eq!(server_document(&server, &path)["version"], 1);
+
+    editor.save().unwrap();
+    server.did_save(&mut editor).unwrap();
+    assert_eq!(
+        server_document(&server, &path)["saved"],
+        editor.get_buffer().to_string()
+    );
+
+    server.did_close(&mut editor).unwrap();
+    assert_eq!(server_document(&server, &path), Value::Null);
+    assert!(matches!(
+        server.did_change(&mut editor),
+        Err(LspError::NotOpen)
+    ));
+    block_on(server.shutdown()).unwrap();
+}
+
+#[test]
+fn test_diagnostics() {
+    let dir = temp_dir("diagnostics");
+    let path = dir.join("main.rs");
+    let mut server = start(&dir);
+    let mut editor = open(&mut server, &path, "let 😀 = error;\n");
+
+    // Diagnostics published before a reply have been read once it arrives
+    server_document(&server, &path);
+    assert!(server.apply_diagnostics(&mut editor).unwrap());
+    assert!(!server.apply_diagnostics(&mut editor).unwrap());
+    let diagnostics = editor.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].range, pos(0, 8)..pos(0, 13));
+    assert_eq!(diagnostics[0].severity, DiagnosticSeverity::Error);
+    assert_eq!(diagnostics[0].display_message(), "found error (fake 1)");
+
+    editor.set_cursor_position(pos(1, 0));
+    editor.insert_text("todo");
+    server.did_change(&mut editor).unwrap();
+    server_document(&server, &path);
+    server.apply_diagnostics(&mut editor).unwrap();
+    let counts = editor.diagnostic_counts();
+    assert_eq!((counts.errors, counts.warnings), (1, 1));
+}
+
+#[test]
+fn test_requests() {
+    let dir = temp_dir("requests");
+    let path = dir.join("main.rs");
+    let mut server = start(&dir);
+    let mut editor = open(&mut server, &path, "fn main() {}\n");
+
+    // Requests send the changes made since the last one first
+    editor.set_cursor_position(pos(1, 0));
+    editor.insert_text("let 😀 = main_loop + main + mai");
+    assert_eq!(
+        block_on(server.hover(&mut editor, pos(1, 11)))
+            .unwrap()
+            .as_deref(),
+        Some("hover: main_loop")
+    );
+    assert_eq!(
+        block_on(server.hover(&mut editor, pos(1, 6))).unwrap(),
+        None
+    );
+
+    let completions = block_on(server.completions(&mut editor, pos(1, 30))).unwrap();
+    let labels: Vec<&str> = completions.iter().map(|item| item.label.as_str()).collect();
+    assert_eq!(labels, vec!["main", "main_loop"]);
+    assert_eq!(completions[1].insert_text, "main_loop");
+    assert_eq!(completions[1].detail.as_deref(), Some("word"));
+    assert_eq!(
+        completions[1].documentation.as_deref(),
+        Some("`main_loop` is in the document")
+    );
+
+    // The definition is the first occurrence of the word
+    editor.set_cursor_position(pos(1, 22));
+    let location = block_on(server.go_to_definition(&mut editor)).unwrap();
+    let expected = Location {
+        path: path.clone(),
+        range: pos(0, 3)..pos(0, 7),
+    };
+    assert_eq!(location, Some(expected));
+
+    let error = block_on(server.request("fake/unknown", Value::Null)).unwrap_err();
+    assert!(matches!(error, LspError::Server { code: -32601, .. }));
+}
+
+#[test]
+fn test_format() {
+    let dir = temp_dir("format");
+    let path = dir.join("main.rs");
+    let mut server = start(&dir);
+    let mut editor = open(&mut server, &path, "fn a() {  \n    b();\t\n}\n");
+    editor.set_cursor_position(pos(1, 8));
+
+    let formatting = block_on(server.format(&mut editor)).unwrap();
+    assert!(server.apply_formatting(&mut editor, formatting).unwrap());
+    assert_eq!(editor.get_buffer().to_string(), "fn a() {\n    b();\n}\n");
+    assert_eq!(editor.cursor_position(), pos(1, 8));
+    // The server has the formatted text
+    assert_eq!(
+        server_document(&server, &path)["text"],
+        editor.get_buffer().to_string()
+    );
+    let formatting = block_on(server.format(&mut editor)).unwrap();
+    assert!(formatting.is_empty());
+    assert!(!server.apply_formatting(&mut editor, formatting).unwrap());
+
+    editor.undo();
+    assert_eq!(
+        editor.get_buffer().to_string(),
+        "fn a() {  \n    b();\t\n}\n"
+    );
+}
+
+#[test]
+fn test_no_path() {
+    let dir = temp_dir("no-path");
+    let mut server = start(&dir);
+    let mut editor = Editor::new("editor", vec!["text".to_string()]);
+    assert!(matches!(server.open(&mut editor), Err(LspError::NoPath)));
+}
+
+#[test]
+fn test_bad_messages_are_skipped() {
+    let dir = temp_dir("garbage");
+    let server = start(&dir);
+    assert_eq!(
+        block_on(server.request("fake/garbage", Value::Null)).unwrap(),
+        "ok"
+    );
+    // Reading carries on after them
+    assert_eq!(
+        block_on(server.request("shutdown", Value::Null)).unwrap(),
+        Value::Null
+    );
+}
+
+#[test]
+fn test_server_requests_while_replying() {
+    let dir = temp_dir("flood");
+    let mut server = start(&dir);
+    server.set_timeout(Duration::from_secs(5));
+    // More replies than fit in the pipe to the server, which reads none of
+    // them until it has sent every request
+    let count = 5000;
+    assert_eq!(
+        block_on(server.request("fake/flood", json!(count))).unwrap(),
+        count
+    );
+    assert_eq!(
+        block_on(server.request("shutdown", Value::Null)).unwrap(),
+        Value::Null
+    );
+}
+
+#[test]
+fn test_completion_ranges() {
+    let dir = temp_dir("completion-ranges");
+    let path = dir.join("main.rs");
+    let mut server = start(&dir);
+    let mut editor = open(&mut server, &path, "fn main() {}\nlet x = mazz;");
+
+    // The server replaces the whole word, including the text after the caret,
+    // and what is typed before the reply arrives is replaced too
+    let completions = block_on(server.completions(&mut editor, pos(1, 10))).unwrap();
+    editor.set_cursor_position(pos(1, 10));
+    editor.insert_text("i");
+    editor.show_completion_items(completions);
+    assert!(editor.confirm_completion());
+    assert_eq!(
+        editor.get_buffer().to_string(),
+        "fn main() {}\nlet x = main;"
+    );
+}
+
+#[test]
+fn test_requests_dont_wait_for_each_other() {
+    let dir = temp_dir("concurrent");
+    let path = dir.join("main.rs");
+    let mut server = start(&dir);
+    let mut editor = open(&mut server, &path, "fn main() {}\n");
+
+    // Requests go out as they are made, and replies can be awaited in any order
+    let hover = server.hover(&mut editor, pos(0, 4));
+    editor.set_cursor_position(pos(1, 0));
+    editor.insert_text("main");
+    server.did_change(&mut editor).unwrap();
+    let document = server_document(&server, &path);
+    assert_eq!(document["text"], "fn main() {}\nmain");
+    assert_eq!(block_on(hover).unwrap().as_deref(), Some("hover: main"));
+
+    // A request the server never answers fails once the timeout passes
+    server.set_timeout(Duration::from_millis(100));
+    let unanswered = server.request("fake/silent", Value::Null);
+    assert!(matches!(block_on(unanswered), Err(LspError::Timeout)));
+    assert_eq!(server_document(&server, &path)["version"], 1);
+}
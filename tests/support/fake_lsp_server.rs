@@ -0,0 +1,316 @@
+//! A tiny language server for testing the LSP client.
+//!
+//! It keeps the text of open documents by applying the changes it is sent,
+//! and answers from that text:
+//!
+//! - diagnostics: an error on every `error` and a warning on every `todo`
+//! - hover: the word under the position
+//! - completion: words in the document starting with the word before the
+//!   position, each with an edit replacing the whole word at the position
+//! - definition: the first occurrence of the word under the position
+//! - formatting: removes trailing whitespace
+//! - `fake/text`: the document's text and version, and the text it was last
+//!   saved with, so tests can check what the server has
+//! - `fake/garbage`: a message with a body that isn't JSON and one without a
+//!   length before replying `"ok"`
+//! - `fake/flood`: as many requests of the client as the params say, without
+//!   reading its replies, before replying with the count
+//! - `fake/silent`: never replies
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+struct Document {
+    version: i64,
+    text: String,
+    saved: Option<String>,
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut documents: HashMap<String, Document> = HashMap::new();
+    while let Some(message) = read_message(&mut reader) {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let Some(method) = message["method"].as_str() else {
+            // A reply to our own request
+            continue;
+        };
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": {
+                        "openClose": true,
+                        "change": 2,
+                        "save": { "includeText": true }
+                    },
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                    "definitionProvider": true,
+                    "documentFormattingProvider": true
+                }
+            }),
+            "initialized" => {
+                // Servers can make requests of the client at any time
+                send(&json!({
+                    "jsonrpc": "2.0",
+                    "id": "progress",
+                    "method": "window/workDoneProgress/create",
+                    "params": { "token": "indexing" }
+                }));
+                continue;
+            }
+            "textDocument/didOpen" => {
+                let document = Document {
+                    version: params["textDocument"]["version"].as_i64().unwrap(),
+                    text: params["textDocument"]["text"].as_str().unwrap().to_string(),
+                    saved: None,
+                };
+                publish_diagnostics(&uri, &document.text);
+                documents.insert(uri, document);
+                continue;
+            }
+            "textDocument/didChange" => {
+                let document = documents.get_mut(&uri).unwrap();
+                document.version = params["textDocument"]["version"].as_i64().unwrap();
+                for change in params["contentChanges"].as_array().unwrap() {
+                    let text = change["text"].as_str().unwrap();
+                    if change["range"].is_null() {
+                        document.text = text.to_string();
+                    } else {
+                        let start = offset(&document.text, &change["range"]["start"]);
+                        let end = offset(&document.text, &change["range"]["end"]);
+                        document.text.replace_range(start..end, text);
+                    }
+                }
+                publish_diagnostics(&uri, &document.text);
+                continue;
+            }
+            "textDocument/didSave" => {
+                let document = documents.get_mut(&uri).unwrap();
+                document.saved = params["text"].as_str().map(str::to_string);
+                continue;
+            }
+            "textDocument/didClose" => {
+                documents.remove(&uri);
+                publish_diagnostics(&uri, "");
+                continue;
+            }
+            "exit" => return,
+            "fake/silent" => continue,
+            "fake/garbage" => {
+                let mut stdout = io::stdout().lock();
+                write!(stdout, "Content-Length: 9\r\n\r\nnot json!").unwrap();
+                write!(stdout, "Content-Type: text/plain\r\n\r\n").unwrap();
+                stdout.flush().unwrap();
+                json!("ok")
+            }
+            "fake/flood" => {
+                let count = params.as_u64().unwrap();
+                for id in 0..count {
+                    send(&json!({
+                        "jsonrpc": "2.0",
+                        "id": format!("flood-{id}"),
+                        "method": "workspace/configuration",
+                        "params": { "items": [{ "section": "fake" }] }
+                    }));
+                }
+                json!(count)
+            }
+            "shutdown" => Value::Null,
+            "fake/text" => match documents.get(&uri) {
+                Some(document) => json!({
+                    "text": document.text,
+                    "version": document.version,
+                    "saved": document.saved,
+                }),
+                None => Value::Null,
+            },
+            "textDocument/hover" => {
+                let text = &documents[&uri].text;
+                match word_at(text, offset(text, &params["position"])) {
+                    Some((_, word)) => json!({
+                        "contents": { "kind": "plaintext", "value": format!("hover: {word}") }
+                    }),
+                    None => Value::Null,
+                }
+            }
+            "textDocument/completion" => {
+                let text = &documents[&uri].text;
+                let at = offset(text, &params["position"]);
+                let (start, typed) = word_at(text, at).unwrap_or((at, ""));
+                let prefix = &text[start..at];
+                let mut labels: Vec<&str> = words(text)
+                    .map(|(_, word)| word)
+                    .filter(|word| word.starts_with(prefix) && *word != prefix && *word != typed)
+                    .collect();
+                labels.sort();
+                labels.dedup();
+                let items: Vec<Value> = labels
+                    .into_iter()
+                    .map(|label| {
+                        json!({
+                            "label": label,
+                            "textEdit": {
+                                "range": {
+                                    "start": position(text, start),
+                                    "end": position(text, start + typed.len()),
+                                },
+                                "newText": label
+                            },
+                            "detail": "word",
+                            "documentation": {
+                                "kind": "markdown",
+                                "value": format!("`{label}` is in the document")
+                            }
+                        })
+                    })
+                    .collect();
+                json!({ "isIncomplete": false, "items": items })
+            }
+            "textDocument/definition" => {
+                let text = &documents[&uri].text;
+                match word_at(text, offset(text, &params["position"])) {
+                    Some((_, word)) => {
+                        let (start, _) = words(text).find(|(_, found)| *found == word).unwrap();
+                        json!({
+                            "uri": uri,
+                            "range": {
+                                "start": position(text, start),
+                                "end": position(text, start + word.len()),
+                            }
+                        })
+                    }
+                    None => Value::Null,
+                }
+            }
+            "textDocument/formatting" => {
+                let text = &documents[&uri].text;
+                let mut edits = Vec::new();
+                let mut line_start = 0;
+                for line in text.split('\n') {
+                    let trimmed = line.trim_end();
+                    if trimmed.len() < line.len() {
+                        edits.push(json!({
+                            "range": {
+                                "start": position(text, line_start + trimmed.len()),
+                                "end": position(text, line_start + line.len()),
+                            },
+                            "newText": ""
+                        }));
+                    }
+                    line_start += line.len() + 1;
+                }
+                Value::Array(edits)
+            }
+            _ => {
+                send(&json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": { "code": -32601, "message": format!("unknown method {method}") }
+                }));
+                continue;
+            }
+        };
+        send(&json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }));
+    }
+}
+
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(message: &Value) {
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+    stdout.flush().unwrap();
+}
+
+fn publish_diagnostics(uri: &str, text: &str) {
+    let diagnostics: Vec<Value> = words(text)
+        .filter_map(|(start, word)| {
+            let (severity, message) = match word {
+                "error" => (1, "found error"),
+                "todo" => (2, "found todo"),
+                _ => return None,
+            };
+            Some(json!({
+                "range": {
+                    "start": position(text, start),
+                    "end": position(text, start + word.len()),
+                },
+                "severity": severity,
+                "message": message,
+                "source": "fake",
+                "code": severity,
+            }))
+        })
+        .collect();
+    send(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics }
+    }));
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Every word in `text` with its byte offset
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split(|ch: char| !is_word_char(ch))
+        .filter(|word| !word.is_empty())
+        .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+}
+
+/// The word containing or ending at byte `offset`
+fn word_at(text: &str, offset: usize) -> Option<(usize, &str)> {
+    words(text).find(|(start, word)| (*start..=start + word.len()).contains(&offset))
+}
+
+/// The byte offset of a protocol position, which counts UTF-16 code units
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap() as usize;
+    let character = position["character"].as_u64().unwrap() as usize;
+    let line_start: usize = text.split('\n').take(line).map(|line| line.len() + 1).sum();
+    let mut units = 0;
+    for (i, ch) in text[line_start..].char_indices() {
+        if units >= character || ch == '\n' {
+            return line_start + i;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+/// The protocol position of byte `offset`
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}